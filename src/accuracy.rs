@@ -0,0 +1,135 @@
+use std::{fmt, str::FromStr};
+
+///How the PPU draws a scanline
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PpuBackend {
+    Dot,      //One pixel per dot, mid-scanline register writes show up where they happen
+    Scanline, //The whole line at dot 256 with one fetch per tile, mid-scanline writes land on the next line
+}
+
+///Emulation details that cost speed or that only a few games (and the test ROMs) depend on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AccuracySettings {
+    pub ppu_backend: PpuBackend,
+    ///Indexed reads (abs,X abs,Y (ind),Y) first read the address before the page carry is fixed,
+    ///which can hit $2007 or $4016 twice
+    pub dummy_reads: bool,
+    ///The PPU I/O latch (what write only registers read back) fades to 0 when it isn't refreshed for about 600ms
+    pub open_bus_decay: bool,
+    ///Turning rendering off during sprite evaluation corrupts a row of OAM the next time rendering starts
+    pub oam_corruption: bool,
+    ///A $2007 access while the dot renderer is drawing bumps v through the PPU's own scroll counters (coarse X and
+    ///Y at once) instead of adding 1 or 32, the glitch some games and test ROMs rely on
+    pub ppudata_glitch: bool,
+    ///A DMC sample fetch repeats the CPU's last register read, clocking the controllers or $2007 once more
+    pub dmc_conflicts: bool,
+    ///The triangle keeps stepping at timer periods below 2 like the hardware: an ultrasonic tone that the
+    ///filters turn into pops and a dip in volume. Off, the sequencer holds its step instead
+    pub ultrasonic_triangle: bool,
+}
+
+impl Default for AccuracySettings {
+    fn default() -> Self {
+        AccuracyProfile::default().settings()
+    }
+}
+
+///Presets that set every AccuracySettings field at once
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AccuracyProfile {
+    Performance, //Scanline renderer, no hardware quirks
+    #[default]
+    Balanced, //Dot renderer, the quirks that are cheap and that commercial games rely on
+    Accuracy, //Everything, for test ROMs and the odd game that trips over the rare quirks
+}
+
+impl AccuracyProfile {
+    pub const ALL: [AccuracyProfile; 3] = [AccuracyProfile::Performance, AccuracyProfile::Balanced, AccuracyProfile::Accuracy];
+
+    pub fn settings(&self) -> AccuracySettings {
+        match self {
+            AccuracyProfile::Performance => AccuracySettings {
+                ppu_backend: PpuBackend::Scanline,
+                dummy_reads: false,
+                open_bus_decay: false,
+                oam_corruption: false,
+                ppudata_glitch: false,
+                dmc_conflicts: false,
+                ultrasonic_triangle: false,
+            },
+            AccuracyProfile::Balanced => AccuracySettings {
+                ppu_backend: PpuBackend::Dot,
+                dummy_reads: true,
+                open_bus_decay: true,
+                oam_corruption: false,
+                ppudata_glitch: true,
+                dmc_conflicts: false,
+                ultrasonic_triangle: false,
+            },
+            AccuracyProfile::Accuracy => AccuracySettings {
+                ppu_backend: PpuBackend::Dot,
+                dummy_reads: true,
+                open_bus_decay: true,
+                oam_corruption: true,
+                ppudata_glitch: true,
+                dmc_conflicts: true,
+                ultrasonic_triangle: true,
+            },
+        }
+    }
+
+    ///The preset the settings match, None once a setting was changed on its own
+    pub fn matching(settings: AccuracySettings) -> Option<AccuracyProfile> {
+        return Self::ALL.into_iter().find(|profile| profile.settings() == settings);
+    }
+}
+
+impl fmt::Display for AccuracyProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccuracyProfile::Performance => write!(f, "performance"),
+            AccuracyProfile::Balanced => write!(f, "balanced"),
+            AccuracyProfile::Accuracy => write!(f, "accuracy"),
+        }
+    }
+}
+
+impl FromStr for AccuracyProfile {
+    type Err = String;
+
+    ///Accepts the names printed by Display, in any case
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        return Self::ALL
+            .into_iter()
+            .find(|profile| profile.to_string().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown accuracy profile {} (performance, balanced or accuracy)", name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for profile in AccuracyProfile::ALL {
+            assert_eq!(profile.to_string().parse::<AccuracyProfile>(), Ok(profile));
+        }
+
+        assert_eq!("ACCURACY".parse::<AccuracyProfile>(), Ok(AccuracyProfile::Accuracy));
+        assert!("fast".parse::<AccuracyProfile>().is_err());
+    }
+
+    #[test]
+    fn presets_are_told_apart() {
+        for profile in AccuracyProfile::ALL {
+            assert_eq!(AccuracyProfile::matching(profile.settings()), Some(profile));
+        }
+
+        let settings = AccuracySettings {
+            dmc_conflicts: true,
+            ..AccuracySettings::default()
+        };
+        assert_eq!(AccuracyProfile::matching(settings), None);
+    }
+}
@@ -0,0 +1,1353 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{accuracy::AccuracySettings, audio::ChannelLevels, region::Region, rng::Rng};
+
+///Length counter values loaded by the upper 5 bits of $4003/$4007 (and the other channels' length registers)
+pub const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+///Pulse waveforms for the four duty settings (12.5%, 25%, 50%, 25% negated)
+pub const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+///Triangle waveform, one step per timer clock
+pub const TRIANGLE_SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+///CPU cycles a DMC sample fetch halts the CPU for
+pub const DMC_DMA_STALL: u8 = 4;
+
+///Length counter of the pulse, triangle and noise channels: the channel goes silent when it reaches 0<br>
+///A register write on the same CPU cycle as a half frame clock races it: the clock still sees the old halt flag,
+///and it wins over a reload of a running counter (the reload of a stopped one goes through)
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LengthCounter {
+    pub counter: u8,
+    pub halt: bool,
+    previous_halt: Option<bool>,  //Before a write on this cycle
+    previous_counter: Option<u8>, //Before a reload on this cycle
+}
+
+impl LengthCounter {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            counter: 0,
+            halt: false,
+            previous_halt: None,
+            previous_counter: None,
+        }
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.previous_halt.get_or_insert(self.halt);
+        self.halt = halt;
+    }
+
+    ///Loads LENGTH_TABLE[index], only written while the channel is enabled
+    pub fn reload(&mut self, index: u8) {
+        self.previous_counter.get_or_insert(self.counter);
+        self.counter = LENGTH_TABLE[index as usize];
+    }
+
+    ///Disabling the channel through $4015 stops it right away
+    pub fn clear(&mut self) {
+        self.counter = 0;
+        self.previous_counter = None;
+    }
+
+    ///Clocked by the frame counter half frames
+    pub fn clock(&mut self) {
+        if self.previous_halt.unwrap_or(self.halt) {
+            return;
+        }
+
+        match self.previous_counter {
+            //Reloaded on this cycle: a running counter is clocked and the reload is lost
+            Some(0) => {}
+            Some(previous) => self.counter = previous - 1,
+            None if self.counter > 0 => self.counter -= 1,
+            None => {}
+        }
+    }
+
+    ///Writes from now on land after the next clock
+    pub fn end_cycle(&mut self) {
+        self.previous_halt = None;
+        self.previous_counter = None;
+    }
+}
+
+///Volume envelope shared by the pulse and noise channels<br>
+///Either a constant volume or a sawtooth decaying from 15 that can loop
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub start: bool,
+    pub loop_flag: bool,
+    pub constant_volume: bool,
+    pub volume: u8, //Constant volume or the divider period
+    pub divider: u8,
+    pub decay: u8,
+}
+
+impl Envelope {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            start: false,
+            loop_flag: false,
+            constant_volume: false,
+            volume: 0,
+            divider: 0,
+            decay: 0,
+        }
+    }
+
+    ///Clocked by the frame counter quarter frames
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.loop_flag {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant_volume {
+            return self.volume;
+        } else {
+            return self.decay;
+        }
+    }
+}
+
+///Sweep unit, periodically bends the pulse period up or down
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Sweep {
+    pub enabled: bool,
+    pub period: u8,
+    pub negate: bool,
+    pub shift: u8,
+    pub reload: bool,
+    pub divider: u8,
+}
+
+impl Sweep {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            period: 0,
+            negate: false,
+            shift: 0,
+            reload: false,
+            divider: 0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PulseChannel {
+    ones_complement: bool, //Pulse 1 negates with one's complement, pulse 2 with two's complement
+
+    pub enabled: bool,
+    pub duty: u8,
+    pub sequence_step: u8,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub length: LengthCounter,
+
+    pub envelope: Envelope,
+    pub sweep: Sweep,
+}
+
+impl PulseChannel {
+    //Constructor
+    pub fn new(ones_complement: bool) -> Self {
+        Self {
+            ones_complement,
+
+            enabled: false,
+            duty: 0,
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            length: LengthCounter::new(),
+
+            envelope: Envelope::new(),
+            sweep: Sweep::new(),
+        }
+    }
+
+    ///Writes one of the four channel registers ($4000 - $4003 or $4004 - $4007)
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register & 0x03 {
+            //DDLC VVVV: duty, length halt / envelope loop, constant volume, volume / envelope period
+            0 => {
+                self.duty = (data >> 6) & 0x03;
+                self.length.set_halt((data & 0x20) != 0);
+                self.envelope.loop_flag = (data & 0x20) != 0;
+                self.envelope.constant_volume = (data & 0x10) != 0;
+                self.envelope.volume = data & 0x0F;
+            }
+            //EPPP NSSS: sweep enable, period, negate, shift
+            1 => {
+                self.sweep.enabled = (data & 0x80) != 0;
+                self.sweep.period = (data >> 4) & 0x07;
+                self.sweep.negate = (data & 0x08) != 0;
+                self.sweep.shift = data & 0x07;
+                self.sweep.reload = true;
+            }
+            //Timer low byte
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | data as u16;
+            }
+            //LLLL LTTT: length counter load, timer high bits
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0x07) << 8);
+
+                if self.enabled {
+                    self.length.reload(data >> 3);
+                }
+
+                self.sequence_step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length.clear();
+        }
+    }
+
+    ///Clocked every APU cycle (every second CPU cycle)
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_step = (self.sequence_step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    ///Clocked by the frame counter half frames
+    pub fn clock_length_counter(&mut self) {
+        self.length.clock();
+    }
+
+    ///Clocked by the frame counter half frames
+    pub fn clock_sweep(&mut self) {
+        let target = self.target_period();
+
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.is_muted(target) {
+            self.timer_period = target;
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    ///Period the sweep unit is continuously calculating, even when disabled
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+
+        if self.sweep.negate {
+            let change = if self.ones_complement { change + 1 } else { change };
+
+            return self.timer_period.saturating_sub(change);
+        } else {
+            return self.timer_period + change;
+        }
+    }
+
+    ///Periods below 8 or a sweep target above $7FF silence the channel
+    fn is_muted(&self, target: u16) -> bool {
+        return self.timer_period < 8 || target > 0x07FF;
+    }
+
+    ///Current output level (0 - 15)
+    pub fn output(&self) -> u8 {
+        if self.length.counter == 0
+            || self.is_muted(self.target_period())
+            || DUTY_TABLE[self.duty as usize][self.sequence_step as usize] == 0
+        {
+            return 0;
+        }
+
+        return self.envelope.output();
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TriangleChannel {
+    pub enabled: bool,
+    pub sequence_step: u8,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub length: LengthCounter,
+    pub control: bool, //Halts the length counter and keeps reloading the linear counter
+
+    //Linear counter: a finer grained length counter clocked by the quarter frames
+    pub linear_reload_value: u8,
+    pub linear_counter: u8,
+    pub linear_reload: bool,
+}
+
+impl TriangleChannel {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            sequence_step: 0,
+            timer_period: 0,
+            timer: 0,
+            length: LengthCounter::new(),
+            control: false,
+
+            linear_reload_value: 0,
+            linear_counter: 0,
+            linear_reload: false,
+        }
+    }
+
+    ///Writes one of the channel registers ($4008 - $400B, $4009 is unused)
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register & 0x03 {
+            //CRRR RRRR: control / length halt, linear counter reload value
+            0 => {
+                self.control = (data & 0x80) != 0;
+                self.length.set_halt(self.control);
+                self.linear_reload_value = data & 0x7F;
+            }
+            1 => {}
+            //Timer low byte
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | data as u16;
+            }
+            //LLLL LTTT: length counter load, timer high bits
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0x07) << 8);
+
+                if self.enabled {
+                    self.length.reload(data >> 3);
+                }
+
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length.clear();
+        }
+    }
+
+    ///Clocked every CPU cycle, the sequencer only moves while both counters are running<br>
+    ///Periods below 2 play an ultrasonic tone that pops through the mixer, unless `ultrasonic` is set the
+    ///sequencer holds instead
+    pub fn clock_timer(&mut self, ultrasonic: bool) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+
+            if self.length.counter > 0 && self.linear_counter > 0 && (ultrasonic || self.timer_period >= 2) {
+                self.sequence_step = (self.sequence_step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    ///Clocked by the frame counter quarter frames
+    pub fn clock_linear_counter(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    ///Clocked by the frame counter half frames
+    pub fn clock_length_counter(&mut self) {
+        self.length.clock();
+    }
+
+    ///Current output level (0 - 15), a stopped sequencer keeps its last level
+    pub fn output(&self) -> u8 {
+        return TRIANGLE_SEQUENCE[self.sequence_step as usize];
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NoiseChannel {
+    pub enabled: bool,
+    pub mode: bool,           //Short mode: feedback from bit 6 instead of bit 1, a 93 step metallic loop
+    pub shift_register: u16,  //15 bit LFSR, 1 at power on
+    pub timer_period: u16,
+    pub timer: u16,
+    pub length: LengthCounter,
+
+    pub envelope: Envelope,
+}
+
+impl NoiseChannel {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            mode: false,
+            shift_register: 1,
+            timer_period: 0,
+            timer: 0,
+            length: LengthCounter::new(),
+
+            envelope: Envelope::new(),
+        }
+    }
+
+    ///Writes one of the channel registers ($400C - $400F, $400D is unused)
+    pub fn write(&mut self, register: u16, data: u8, region: Region) {
+        match register & 0x03 {
+            //--LC VVVV: length halt / envelope loop, constant volume, volume / envelope period
+            0 => {
+                self.length.set_halt((data & 0x20) != 0);
+                self.envelope.loop_flag = (data & 0x20) != 0;
+                self.envelope.constant_volume = (data & 0x10) != 0;
+                self.envelope.volume = data & 0x0F;
+            }
+            1 => {}
+            //M--- PPPP: mode, period index
+            2 => {
+                self.mode = (data & 0x80) != 0;
+                self.timer_period = region.noise_periods()[(data & 0x0F) as usize] - 1;
+            }
+            //LLLL L---: length counter load
+            _ => {
+                if self.enabled {
+                    self.length.reload(data >> 3);
+                }
+
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length.clear();
+        }
+    }
+
+    ///Clocked every CPU cycle, every timer reload shifts the LFSR once
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x01;
+
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    ///Clocked by the frame counter half frames
+    pub fn clock_length_counter(&mut self) {
+        self.length.clock();
+    }
+
+    ///Current output level (0 - 15), silent while bit 0 of the LFSR is set
+    pub fn output(&self) -> u8 {
+        if self.length.counter == 0 || (self.shift_register & 0x01) != 0 {
+            return 0;
+        }
+
+        return self.envelope.output();
+    }
+}
+
+///Delta modulation channel: plays 1 bit delta samples fetched from CPU memory ($C000 - $FFFF) by DMA
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DmcChannel {
+    pub irq_enabled: bool,
+    pub loop_flag: bool,
+    pub timer_period: u16,
+    pub timer: u16,
+    pub output_level: u8, //7 bit DAC, moved by 2 for every sample bit
+
+    //Memory reader
+    pub sample_address: u16, //$4012: $C000 + A * 64
+    pub sample_length: u16,  //$4013: L * 16 + 1 bytes
+    pub current_address: u16,
+    pub bytes_remaining: u16,
+    pub sample_buffer: Option<u8>,
+
+    //Output unit
+    pub shift_register: u8,
+    pub bits_remaining: u8,
+    pub silence: bool, //The buffer was empty when the last output cycle started
+
+    pub irq: bool,
+    pub stall: u8, //CPU cycles left on the current sample fetch
+}
+
+impl DmcChannel {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            irq_enabled: false,
+            loop_flag: false,
+            timer_period: Region::Ntsc.dmc_rates()[0] - 1,
+            timer: 0,
+            output_level: 0,
+
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+
+            irq: false,
+            stall: 0,
+        }
+    }
+
+    ///Writes one of the channel registers ($4010 - $4013)
+    pub fn write(&mut self, register: u16, data: u8, region: Region) {
+        match register & 0x03 {
+            //IL-- RRRR: IRQ enable, loop, rate index
+            0 => {
+                self.irq_enabled = (data & 0x80) != 0;
+                self.loop_flag = (data & 0x40) != 0;
+                self.timer_period = region.dmc_rates()[(data & 0x0F) as usize] - 1;
+
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            //-DDD DDDD: direct load of the output level
+            1 => {
+                self.output_level = data & 0x7F;
+            }
+            2 => {
+                self.sample_address = 0xC000 | ((data as u16) << 6);
+            }
+            _ => {
+                self.sample_length = ((data as u16) << 4) | 0x0001;
+            }
+        }
+    }
+
+    ///$4015 bit 4: stops the sample, or starts it over when it had ended
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    ///Address the memory reader wants to fetch, it fills the sample buffer as soon as it is emptied
+    pub fn dma_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            return Some(self.current_address);
+        }
+
+        return None;
+    }
+
+    ///The fetched byte, the address wraps from $FFFF to $8000
+    pub fn dma_complete(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        self.stall = DMC_DMA_STALL;
+
+        self.current_address = if self.current_address == 0xFFFF { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    ///Clocked every CPU cycle, every timer reload plays one bit of the shift register
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period;
+
+        if !self.silence {
+            //The level saturates instead of wrapping around
+            if (self.shift_register & 0x01) != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+
+            match self.sample_buffer.take() {
+                Some(data) => {
+                    self.shift_register = data;
+                    self.silence = false;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        return self.output_level;
+    }
+}
+
+///Channels and frame counter of the APU, as stored in save states
+#[derive(Serialize, Deserialize)]
+pub struct ApuState {
+    pulse_1: PulseChannel,
+    pulse_2: PulseChannel,
+    triangle: TriangleChannel,
+    noise: NoiseChannel,
+    dmc: DmcChannel,
+
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_clock_counter: u32,
+
+    clock_count: u64,
+}
+
+///2A03 Audio Processing Unit
+pub struct APU {
+    pub pulse_1: PulseChannel,
+    pub pulse_2: PulseChannel,
+    pub triangle: TriangleChannel,
+    pub noise: NoiseChannel,
+    pub dmc: DmcChannel,
+
+    //Frame Counter ($4017)
+    pub five_step_mode: bool,
+    pub irq_inhibit: bool,
+    pub frame_irq: bool,
+    frame_clock_counter: u32,
+    region: Region, //Frame counter step timing
+
+    ultrasonic_triangle: bool, //See AccuracySettings
+
+    pub clock_count: u64, //CPU cycles since power on
+}
+
+impl APU {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            pulse_1: PulseChannel::new(true),
+            pulse_2: PulseChannel::new(false),
+            triangle: TriangleChannel::new(),
+            noise: NoiseChannel::new(),
+            dmc: DmcChannel::new(),
+
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_clock_counter: 0,
+            region: Region::Ntsc,
+
+            ultrasonic_triangle: false,
+
+            clock_count: 0,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub(crate) fn set_accuracy(&mut self, settings: &AccuracySettings) {
+        self.ultrasonic_triangle = settings.ultrasonic_triangle;
+    }
+
+    ///Silences every channel like writing 0 to $4015
+    pub fn reset(&mut self) {
+        self.pulse_1.set_enabled(false);
+        self.pulse_2.set_enabled(false);
+        self.triangle.set_enabled(false);
+        self.noise.set_enabled(false);
+        self.dmc.set_enabled(false);
+
+        self.frame_irq = false;
+        self.frame_clock_counter = 0;
+    }
+
+    ///Startup fuzzing: the frame counter and the CPU/APU cycle alignment are arbitrary at power on
+    pub fn randomize_power_on_state(&mut self, rng: &mut Rng) {
+        self.frame_clock_counter = (rng.next_u64() % self.region.frame_counter_steps()[0] as u64) as u32;
+        self.clock_count = rng.next_u64() & 0x01;
+    }
+
+    //Save States
+
+    pub fn save_state(&self) -> ApuState {
+        ApuState {
+            pulse_1: self.pulse_1.clone(),
+            pulse_2: self.pulse_2.clone(),
+            triangle: self.triangle.clone(),
+            noise: self.noise.clone(),
+            dmc: self.dmc.clone(),
+
+            five_step_mode: self.five_step_mode,
+            irq_inhibit: self.irq_inhibit,
+            frame_irq: self.frame_irq,
+            frame_clock_counter: self.frame_clock_counter,
+
+            clock_count: self.clock_count,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &ApuState) {
+        self.pulse_1 = state.pulse_1.clone();
+        self.pulse_2 = state.pulse_2.clone();
+        self.triangle = state.triangle.clone();
+        self.noise = state.noise.clone();
+        self.dmc = state.dmc.clone();
+
+        self.five_step_mode = state.five_step_mode;
+        self.irq_inhibit = state.irq_inhibit;
+        self.frame_irq = state.frame_irq;
+        self.frame_clock_counter = state.frame_clock_counter;
+
+        self.clock_count = state.clock_count;
+    }
+
+    //CPU Interface
+
+    pub fn cpu_write(&mut self, address: u16, data: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulse_1.write(address, data),
+            0x4004..=0x4007 => self.pulse_2.write(address, data),
+            0x4008..=0x400B => self.triangle.write(address, data),
+            0x400C..=0x400F => self.noise.write(address, data, self.region),
+            0x4010..=0x4013 => self.dmc.write(address, data, self.region),
+            //Status: channel enables, also acknowledges the DMC interrupt
+            0x4015 => {
+                self.pulse_1.set_enabled((data & 0x01) != 0);
+                self.pulse_2.set_enabled((data & 0x02) != 0);
+                self.triangle.set_enabled((data & 0x04) != 0);
+                self.noise.set_enabled((data & 0x08) != 0);
+                self.dmc.set_enabled((data & 0x10) != 0);
+            }
+            //Frame counter: MI-- ----, mode and IRQ inhibit
+            0x4017 => {
+                self.five_step_mode = (data & 0x80) != 0;
+                self.irq_inhibit = (data & 0x40) != 0;
+
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+
+                self.frame_clock_counter = 0;
+
+                //Selecting the 5-step mode clocks the units immediately
+                if self.five_step_mode {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ///$4015 read: which length counters are running, the DMC and frame interrupt flags (only the frame one is
+    ///cleared by the read)
+    pub fn cpu_read_status(&mut self) -> u8 {
+        let data = self.peek_status();
+
+        self.frame_irq = false;
+
+        return data;
+    }
+
+    ///$4015 without acknowledging the frame interrupt
+    pub fn peek_status(&self) -> u8 {
+        let mut data = 0x00;
+
+        if self.pulse_1.length.counter > 0 {
+            data |= 0x01;
+        }
+
+        if self.pulse_2.length.counter > 0 {
+            data |= 0x02;
+        }
+
+        if self.triangle.length.counter > 0 {
+            data |= 0x04;
+        }
+
+        if self.noise.length.counter > 0 {
+            data |= 0x08;
+        }
+
+        if self.dmc.bytes_remaining > 0 {
+            data |= 0x10;
+        }
+
+        if self.frame_irq {
+            data |= 0x40;
+        }
+
+        if self.dmc.irq {
+            data |= 0x80;
+        }
+
+        return data;
+    }
+
+    ///True while the frame counter or the DMC is requesting an interrupt
+    pub fn irq(&self) -> bool {
+        return self.frame_irq || self.dmc.irq;
+    }
+
+    //DMC DMA
+
+    ///Address the DMC wants read, the System fetches it and passes it to dmc_dma_complete()
+    pub fn dmc_dma_request(&self) -> Option<u16> {
+        return self.dmc.dma_request();
+    }
+
+    pub fn dmc_dma_complete(&mut self, data: u8) {
+        self.dmc.dma_complete(data);
+    }
+
+    ///Counts down the CPU cycles a sample fetch takes, returns true while the CPU is halted
+    pub fn cpu_stalled(&mut self) -> bool {
+        if self.dmc.stall == 0 {
+            return false;
+        }
+
+        self.dmc.stall -= 1;
+
+        return true;
+    }
+
+    //Timing
+
+    ///Advances the APU by one CPU cycle
+    pub fn clock(&mut self) {
+        //The pulse timers run at half the CPU rate
+        if self.clock_count % 2 == 1 {
+            self.pulse_1.clock_timer();
+            self.pulse_2.clock_timer();
+        }
+
+        self.triangle.clock_timer(self.ultrasonic_triangle);
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        self.clock_frame_counter();
+
+        self.pulse_1.length.end_cycle();
+        self.pulse_2.length.end_cycle();
+        self.triangle.length.end_cycle();
+        self.noise.length.end_cycle();
+
+        self.clock_count += 1;
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_clock_counter += 1;
+
+        let [step_1, step_2, step_3, step_4, step_5] = self.region.frame_counter_steps();
+
+        match self.frame_clock_counter {
+            step if step == step_1 || step == step_3 => {
+                self.clock_quarter_frame();
+            }
+            step if step == step_2 => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            step if step == step_4 && !self.five_step_mode => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+
+                if !self.irq_inhibit {
+                    self.frame_irq = true;
+                }
+
+                self.frame_clock_counter = 0;
+            }
+            step if step == step_5 => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+
+                self.frame_clock_counter = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_1.envelope.clock();
+        self.pulse_2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear_counter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse_1.clock_length_counter();
+        self.pulse_2.clock_length_counter();
+        self.triangle.clock_length_counter();
+        self.noise.clock_length_counter();
+
+        self.pulse_1.clock_sweep();
+        self.pulse_2.clock_sweep();
+    }
+
+    //Output
+
+    ///Current output of every channel, mixed by the audio pipeline
+    pub fn levels(&self) -> ChannelLevels {
+        return ChannelLevels {
+            pulse_1: self.pulse_1.output(),
+            pulse_2: self.pulse_2.output(),
+            triangle: self.triangle.output(),
+            noise: self.noise.output(),
+            dmc: self.dmc.output(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::System;
+
+    ///Runs the channel timers for the given CPU cycles
+    fn run(apu: &mut APU, cycles: u32) {
+        for _ in 0..cycles {
+            apu.clock();
+        }
+    }
+
+    #[test]
+    fn triangle_needs_the_linear_counter() {
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x04);
+
+        //Control set: the linear counter reload stays on, the sequencer runs
+        apu.cpu_write(0x4008, 0x81);
+        apu.cpu_write(0x400A, 0x10);
+        apu.cpu_write(0x400B, 0x08);
+        assert_eq!(apu.peek_status() & 0x04, 0x04);
+
+        apu.triangle.clock_linear_counter();
+        run(&mut apu, 0x11 * 4);
+        assert_ne!(apu.triangle.sequence_step, 0);
+
+        //Without the control flag the linear counter runs out and the sequencer holds its step
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x04);
+        apu.cpu_write(0x4008, 0x01);
+        apu.cpu_write(0x400A, 0x10);
+        apu.cpu_write(0x400B, 0x08);
+
+        apu.triangle.clock_linear_counter();
+        apu.triangle.clock_linear_counter();
+        assert_eq!(apu.triangle.linear_counter, 0);
+
+        run(&mut apu, 0x11 * 4);
+        assert_eq!(apu.triangle.sequence_step, 0);
+        assert_eq!(apu.triangle.output(), 15);
+    }
+
+    #[test]
+    fn length_reloads_race_the_half_frame_clock() {
+        let half_frame = Region::Ntsc.frame_counter_steps()[1];
+
+        //Written on the cycle of the clock: a running counter is clocked and the reload is lost
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x01);
+        apu.cpu_write(0x4003, 0x18);
+        run(&mut apu, 1);
+
+        apu.frame_clock_counter = half_frame - 1;
+        apu.cpu_write(0x4003, 0x08);
+        apu.clock();
+        assert_eq!(apu.pulse_1.length.counter, 1);
+
+        //A stopped counter takes the reload and isn't clocked
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x01);
+
+        apu.frame_clock_counter = half_frame - 1;
+        apu.cpu_write(0x4003, 0x08);
+        apu.clock();
+        assert_eq!(apu.pulse_1.length.counter, 254);
+
+        //A cycle earlier the reload lands first and gets clocked
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x01);
+        apu.cpu_write(0x4003, 0x18);
+        run(&mut apu, 1);
+
+        apu.frame_clock_counter = half_frame - 2;
+        apu.cpu_write(0x4003, 0x08);
+        run(&mut apu, 2);
+        assert_eq!(apu.pulse_1.length.counter, 253);
+    }
+
+    #[test]
+    fn halt_changes_land_after_the_half_frame_clock() {
+        let half_frame = Region::Ntsc.frame_counter_steps()[1];
+
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x08);
+        apu.cpu_write(0x400F, 0x08);
+        run(&mut apu, 1);
+
+        //Halted on the cycle of the clock: it still counts down once
+        apu.frame_clock_counter = half_frame - 1;
+        apu.cpu_write(0x400C, 0x20);
+        apu.clock();
+        assert_eq!(apu.noise.length.counter, 253);
+
+        apu.clock_half_frame();
+        assert_eq!(apu.noise.length.counter, 253);
+
+        //Released on the cycle of the clock: it is still halted for that clock
+        apu.frame_clock_counter = half_frame - 1;
+        apu.cpu_write(0x400C, 0x00);
+        apu.clock();
+        assert_eq!(apu.noise.length.counter, 253);
+
+        apu.clock_half_frame();
+        assert_eq!(apu.noise.length.counter, 252);
+    }
+
+    #[test]
+    fn envelope_restarts_on_the_next_quarter_frame() {
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x01);
+
+        //Decaying volume, divider period 2 (three quarter frames per step), no loop
+        apu.cpu_write(0x4000, 0x02);
+        apu.cpu_write(0x4003, 0x08);
+        assert_eq!(apu.pulse_1.envelope.output(), 0);
+
+        apu.clock_quarter_frame();
+        assert_eq!(apu.pulse_1.envelope.output(), 15);
+
+        for _ in 0..3 * 15 {
+            apu.clock_quarter_frame();
+        }
+        assert_eq!(apu.pulse_1.envelope.output(), 0);
+
+        apu.clock_quarter_frame();
+        assert_eq!(apu.pulse_1.envelope.output(), 0);
+
+        //Rewriting the length register starts over from 15 mid decay, the loop flag wraps it around
+        apu.cpu_write(0x4000, 0x22);
+        apu.cpu_write(0x4003, 0x08);
+        apu.clock_quarter_frame();
+        assert_eq!(apu.pulse_1.envelope.output(), 15);
+
+        for _ in 0..3 * 16 {
+            apu.clock_quarter_frame();
+        }
+        assert_eq!(apu.pulse_1.envelope.output(), 15);
+    }
+
+    #[test]
+    fn sweep_mutes_even_when_disabled() {
+        let mut pulse_1 = PulseChannel::new(true);
+        let mut pulse_2 = PulseChannel::new(false);
+
+        //Disabled with a shift of 0: the target is twice the period and mutes from $400 on
+        pulse_1.timer_period = 0x0400;
+        assert!(pulse_1.is_muted(pulse_1.target_period()));
+        pulse_1.timer_period = 0x03FF;
+        assert!(!pulse_1.is_muted(pulse_1.target_period()));
+
+        //Periods below 8 mute whatever the sweep does
+        pulse_1.timer_period = 0x0007;
+        assert!(pulse_1.is_muted(pulse_1.target_period()));
+
+        //Negated targets never mute, pulse 1 subtracts one more
+        for pulse in [&mut pulse_1, &mut pulse_2] {
+            pulse.write(0x4001, 0x89);
+            pulse.timer_period = 0x0100;
+        }
+        assert_eq!(pulse_1.target_period(), 0x007F);
+        assert_eq!(pulse_2.target_period(), 0x0080);
+
+        pulse_1.timer_period = 0x0700;
+        assert!(!pulse_1.is_muted(pulse_1.target_period()));
+
+        //A muting target leaves the period alone
+        pulse_2.write(0x4005, 0x81);
+        pulse_2.timer_period = 0x0600;
+        pulse_2.clock_sweep();
+        assert_eq!(pulse_2.timer_period, 0x0600);
+
+        pulse_2.timer_period = 0x0100;
+        pulse_2.clock_sweep();
+        assert_eq!(pulse_2.timer_period, 0x0180);
+    }
+
+    #[test]
+    fn ultrasonic_triangle_periods_can_hold_the_sequencer() {
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x04);
+        apu.cpu_write(0x4008, 0x81);
+        apu.cpu_write(0x400A, 0x00);
+        apu.cpu_write(0x400B, 0x08);
+        apu.triangle.clock_linear_counter();
+
+        //Period 0: held by default
+        run(&mut apu, 10);
+        assert_eq!(apu.triangle.sequence_step, 0);
+
+        //The hardware steps on every cycle
+        apu.set_accuracy(&AccuracySettings {
+            ultrasonic_triangle: true,
+            ..AccuracySettings::default()
+        });
+        run(&mut apu, 10);
+        assert_eq!(apu.triangle.sequence_step, 10);
+    }
+
+    ///Timer reloads until the LFSR is back to its power on value
+    fn lfsr_period(mode: bool) -> u32 {
+        let mut noise = NoiseChannel::new();
+        noise.mode = mode;
+
+        let mut steps = 0;
+        loop {
+            noise.clock_timer();
+            steps += 1;
+
+            if noise.shift_register == 1 {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn noise_lfsr_modes() {
+        assert_eq!(lfsr_period(false), 32767);
+        assert_eq!(lfsr_period(true), 93);
+    }
+
+    #[test]
+    fn noise_is_silent_while_bit_0_is_set() {
+        let mut apu = APU::new();
+        apu.cpu_write(0x4015, 0x08);
+        apu.cpu_write(0x400C, 0x1F);
+        apu.cpu_write(0x400F, 0x08);
+
+        apu.noise.shift_register = 0x0002;
+        assert_eq!(apu.noise.output(), 15);
+
+        apu.noise.shift_register = 0x0001;
+        assert_eq!(apu.noise.output(), 0);
+    }
+
+    #[test]
+    fn dmc_plays_fetched_bytes_and_raises_its_irq() {
+        let mut apu = APU::new();
+
+        //IRQ enabled, fastest rate, one byte sample at $C040
+        apu.cpu_write(0x4010, 0x8F);
+        apu.cpu_write(0x4011, 0x40);
+        apu.cpu_write(0x4012, 0x01);
+        apu.cpu_write(0x4013, 0x00);
+        apu.cpu_write(0x4015, 0x10);
+
+        assert_eq!(apu.peek_status() & 0x10, 0x10);
+        assert_eq!(apu.dmc_dma_request(), Some(0xC040));
+
+        apu.dmc_dma_complete(0xFF);
+        assert_eq!(apu.dmc_dma_request(), None);
+        assert!(apu.irq());
+        assert_eq!(apu.peek_status() & 0x90, 0x80);
+
+        //Reading $4015 doesn't acknowledge the DMC interrupt, writing it does
+        apu.cpu_read_status();
+        assert!(apu.irq());
+        apu.cpu_write(0x4015, 0x00);
+        assert!(!apu.irq());
+
+        //The first 8 bits drain the empty shift register, then every 1 bit adds 2
+        let rate = Region::Ntsc.dmc_rates()[15] as u32;
+        run(&mut apu, rate * 8);
+        assert_eq!(apu.dmc.output(), 0x40);
+        run(&mut apu, rate * 8);
+        assert_eq!(apu.dmc.output(), 0x40 + 16);
+    }
+
+    #[test]
+    fn dmc_loop_restarts_without_an_irq() {
+        let mut apu = APU::new();
+        apu.cpu_write(0x4010, 0xC0);
+        apu.cpu_write(0x4012, 0x00);
+        apu.cpu_write(0x4013, 0x00);
+        apu.cpu_write(0x4015, 0x10);
+
+        apu.dmc_dma_complete(0x00);
+        assert!(!apu.irq());
+        assert_eq!(apu.dmc.current_address, 0xC000);
+        assert_eq!(apu.dmc.bytes_remaining, 1);
+    }
+
+    ///Plays `bits` DMC output bits, serving its fetches from `sample` (read from $C000 on), and returns the output
+    ///level after each of them
+    fn dmc_levels(apu: &mut APU, sample: &[u8], bits: usize) -> Vec<u8> {
+        let mut levels = Vec::new();
+
+        while levels.len() < bits {
+            if let Some(address) = apu.dmc_dma_request() {
+                apu.dmc_dma_complete(sample[(address - 0xC000) as usize % sample.len()]);
+            }
+
+            apu.clock();
+
+            if apu.dmc.timer == apu.dmc.timer_period {
+                levels.push(apu.dmc.output());
+            }
+        }
+
+        return levels;
+    }
+
+    ///One byte sample at $C000, the fastest rate, `level` loaded into the DAC
+    fn start_dmc(apu: &mut APU, looped: bool, level: u8) {
+        apu.cpu_write(0x4010, if looped { 0x4F } else { 0x0F });
+        apu.cpu_write(0x4011, level);
+        apu.cpu_write(0x4012, 0x00);
+        apu.cpu_write(0x4013, 0x00);
+        apu.cpu_write(0x4015, 0x10);
+    }
+
+    #[test]
+    fn dmc_level_clamps_and_holds_while_silent() {
+        //Bits that would take the level past 127 or below 0 leave it alone, the first 8 bits drain the empty
+        //shift register
+        let mut apu = APU::new();
+        start_dmc(&mut apu, false, 125);
+
+        let levels = dmc_levels(&mut apu, &[0xFF], 24);
+        assert_eq!(levels[..8], [125; 8]);
+        assert_eq!(levels[8..16], [127; 8]);
+
+        //The sample is over and the buffer stays empty: the output cycle after it is silent and holds the level
+        assert_eq!(levels[16..], [127; 8]);
+        assert!(apu.dmc.silence);
+        assert_eq!(apu.peek_status() & 0x10, 0);
+
+        let mut apu = APU::new();
+        start_dmc(&mut apu, false, 3);
+        assert_eq!(dmc_levels(&mut apu, &[0x00], 16)[8..], [1, 1, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn looped_dmc_sample_plays_without_a_gap() {
+        //The sample restarts when its last byte is fetched, so the buffer is full again before the output cycle
+        //that needs it: no silent cycle at the loop point
+        let mut apu = APU::new();
+        start_dmc(&mut apu, true, 0x40);
+
+        let levels = dmc_levels(&mut apu, &[0x55], 8 + 8 * 8);
+
+        for (bit, &level) in levels[8..].iter().enumerate() {
+            assert_eq!(level, if bit % 2 == 0 { 0x42 } else { 0x40 }, "bit {}", bit);
+        }
+
+        assert!(!apu.dmc.silence);
+        assert_eq!(apu.peek_status() & 0x90, 0x10);
+    }
+
+    #[test]
+    fn dmc_fetch_stalls_the_cpu() {
+        let mut system = System::new();
+        let bus = system.get_bus();
+        let apu = bus.borrow().get_apu();
+
+        system.step_frame();
+
+        bus.borrow_mut().write(0x4013, 0x00);
+        bus.borrow_mut().write(0x4015, 0x10);
+
+        //CPU cycles taken by the fetch: the one that reads the sample and the ones stalled after it
+        let mut stalled = 0;
+
+        for _ in 0..300 {
+            let stalling = apu.borrow().dmc.stall != 0 || apu.borrow().dmc_dma_request().is_some();
+
+            if system.clock() && stalling {
+                stalled += 1;
+            }
+        }
+
+        assert_eq!(stalled, DMC_DMA_STALL);
+    }
+}
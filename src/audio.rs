@@ -0,0 +1,513 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+///Output levels of the five APU channels for one CPU cycle
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ChannelLevels {
+    pub pulse_1: u8,  //0 - 15
+    pub pulse_2: u8,  //0 - 15
+    pub triangle: u8, //0 - 15
+    pub noise: u8,    //0 - 15
+    pub dmc: u8,      //0 - 127
+}
+
+///Non-linear 2A03 mixer (0.0 - 1.0): the pulses share one DAC, the triangle, noise and DMC another
+pub fn mix(levels: ChannelLevels) -> f32 {
+    let pulse = levels.pulse_1 as f32 + levels.pulse_2 as f32;
+
+    let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+    let tnd = levels.triangle as f32 / 8227.0 + levels.noise as f32 / 12241.0 + levels.dmc as f32 / 22638.0;
+
+    let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+    return pulse_out + tnd_out;
+}
+
+///First order RC filter
+#[derive(Clone, Copy)]
+struct Filter {
+    high_pass: bool,
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl Filter {
+    fn new(high_pass: bool, cutoff: f64, sample_rate: f64) -> Self {
+        let rc = 1.0 / (std::f64::consts::TAU * cutoff);
+        let dt = 1.0 / sample_rate;
+
+        let alpha = if high_pass { rc / (rc + dt) } else { dt / (rc + dt) };
+
+        Self {
+            high_pass,
+            alpha: alpha as f32,
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = if self.high_pass {
+            self.alpha * (self.previous_output + input - self.previous_input)
+        } else {
+            self.previous_output + self.alpha * (input - self.previous_output)
+        };
+
+        self.previous_input = input;
+        self.previous_output = output;
+
+        return output;
+    }
+}
+
+///Cutoffs of the console's output stage: two high-pass filters (90Hz, 440Hz) remove the DC offset,
+///a 14kHz low-pass smooths the steps and keeps most of the aliasing out of the resampled stream
+pub const HIGH_PASS_CUTOFFS: [f64; 2] = [90.0, 440.0];
+pub const LOW_PASS_CUTOFF: f64 = 14_000.0;
+
+///Takes the APU levels at the CPU rate and turns them into filtered samples at the output rate<br>
+///Resampling averages the (already low-passed) input over every output period
+pub struct AudioPipeline {
+    input_rate: f64,
+    output_rate: u32,
+
+    filters: Vec<Filter>,
+
+    step: f64,  //Input samples per output sample
+    phase: f64, //Input samples accumulated toward the next output sample
+    sum: f64,
+
+    output: Vec<f32>,
+}
+
+impl AudioPipeline {
+    //Constructor
+    pub fn new(input_rate: f64, output_rate: u32) -> Self {
+        let mut pipeline = Self {
+            input_rate,
+            output_rate: output_rate.max(1),
+
+            filters: Vec::new(),
+
+            step: 0.0,
+            phase: 0.0,
+            sum: 0.0,
+
+            output: Vec::new(),
+        };
+
+        pipeline.configure();
+
+        return pipeline;
+    }
+
+    fn configure(&mut self) {
+        self.filters = HIGH_PASS_CUTOFFS
+            .iter()
+            .map(|&cutoff| Filter::new(true, cutoff, self.input_rate))
+            .chain(std::iter::once(Filter::new(false, LOW_PASS_CUTOFF, self.input_rate)))
+            .collect();
+
+        self.step = self.input_rate / self.output_rate as f64;
+        self.clear();
+    }
+
+    ///The CPU clock, changes with the region
+    pub fn set_input_rate(&mut self, input_rate: f64) {
+        self.input_rate = input_rate;
+        self.configure();
+    }
+
+    pub fn get_output_rate(&self) -> u32 {
+        return self.output_rate;
+    }
+
+    pub fn set_output_rate(&mut self, output_rate: u32) {
+        self.output_rate = output_rate.max(1);
+        self.configure();
+    }
+
+    ///Forgets the filter history and the pending samples (reset, save state load, ...)
+    pub fn clear(&mut self) {
+        for filter in &mut self.filters {
+            filter.previous_input = 0.0;
+            filter.previous_output = 0.0;
+        }
+
+        self.phase = 0.0;
+        self.sum = 0.0;
+        self.output.clear();
+    }
+
+    ///One CPU cycle of APU output
+    pub fn push(&mut self, levels: ChannelLevels) {
+        let mut sample = mix(levels);
+
+        for filter in &mut self.filters {
+            sample = filter.process(sample);
+        }
+
+        self.sum += sample as f64;
+        self.phase += 1.0;
+
+        //The input sample straddling the boundary is split between the two output samples
+        if self.phase >= self.step {
+            let overshoot = self.phase - self.step;
+            let carried = sample as f64 * overshoot;
+
+            self.output.push(((self.sum - carried) / self.step) as f32);
+
+            self.sum = carried;
+            self.phase = overshoot;
+        }
+    }
+
+    ///Takes the samples (about -1.0 - 1.0) produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        return std::mem::take(&mut self.output);
+    }
+}
+
+///Fixed size single producer, single consumer sample queue between the emulation thread and an audio callback<br>
+///Neither side ever blocks or allocates: a full ring drops the newest samples, an empty one plays silence
+pub fn sample_ring(capacity: usize) -> (RingProducer, RingConsumer) {
+    let ring = Arc::new(Ring {
+        samples: (0..capacity.max(1) + 1).map(|_| AtomicU32::new(0)).collect(),
+        read: AtomicUsize::new(0),
+        write: AtomicUsize::new(0),
+        underruns: AtomicU64::new(0),
+    });
+
+    return (RingProducer { ring: ring.clone() }, RingConsumer { ring });
+}
+
+///One slot is always left empty to tell a full ring from an empty one
+struct Ring {
+    samples: Box<[AtomicU32]>, //f32 bits
+    read: AtomicUsize,
+    write: AtomicUsize,
+    underruns: AtomicU64,
+}
+
+impl Ring {
+    fn len(&self) -> usize {
+        let read = self.read.load(Ordering::Acquire);
+        let write = self.write.load(Ordering::Acquire);
+
+        return (write + self.samples.len() - read) % self.samples.len();
+    }
+
+    fn capacity(&self) -> usize {
+        return self.samples.len() - 1;
+    }
+}
+
+///Emulation side of the ring
+pub struct RingProducer {
+    ring: Arc<Ring>,
+}
+
+impl RingProducer {
+    ///Queues as many samples as fit, returns how many did
+    pub fn push(&mut self, samples: &[f32]) -> usize {
+        let ring = &self.ring;
+        let count = samples.len().min(ring.capacity() - ring.len());
+
+        let mut write = ring.write.load(Ordering::Relaxed);
+
+        for &sample in &samples[..count] {
+            ring.samples[write].store(sample.to_bits(), Ordering::Relaxed);
+            write = (write + 1) % ring.samples.len();
+        }
+
+        ring.write.store(write, Ordering::Release);
+
+        return count;
+    }
+
+    ///Samples queued and not played yet
+    pub fn len(&self) -> usize {
+        return self.ring.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn capacity(&self) -> usize {
+        return self.ring.capacity();
+    }
+
+    ///Callbacks that ran out of samples since the last call
+    pub fn take_underruns(&self) -> u64 {
+        return self.ring.underruns.swap(0, Ordering::Relaxed);
+    }
+}
+
+///Audio callback side of the ring
+pub struct RingConsumer {
+    ring: Arc<Ring>,
+}
+
+impl RingConsumer {
+    ///Fills the whole buffer, with silence once the queued samples run out (counted as an underrun)
+    pub fn fill(&mut self, output: &mut [f32]) {
+        let ring = &self.ring;
+        let count = output.len().min(ring.len());
+
+        let mut read = ring.read.load(Ordering::Relaxed);
+
+        for sample in &mut output[..count] {
+            *sample = f32::from_bits(ring.samples[read].load(Ordering::Relaxed));
+            read = (read + 1) % ring.samples.len();
+        }
+
+        ring.read.store(read, Ordering::Release);
+
+        if count < output.len() {
+            output[count..].fill(0.0);
+            ring.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+///Where a frontend sends the samples of every frame: a sound device, a file, nowhere<br>
+///The emulator is switched to sample_rate() before the first samples are queued
+pub trait AudioSink {
+    ///Output rate the samples are expected at
+    fn sample_rate(&self) -> u32;
+
+    ///The mono samples (about -1.0 - 1.0) of one frame
+    fn queue(&mut self, samples: &[f32]);
+
+    ///Times the output ran out of samples since the last call, always 0 for sinks without a device
+    fn take_underruns(&self) -> u64 {
+        return 0;
+    }
+
+    ///The stream is about to be interrupted (pause, save state load, rewind): fade out what is buffered, the
+    ///samples queued after it start a new stream
+    fn discontinuity(&mut self) {}
+}
+
+///Discards the samples: headless runs and builds without a sound device
+pub struct NullSink {
+    sample_rate: u32,
+}
+
+impl NullSink {
+    //Constructor
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    fn queue(&mut self, _samples: &[f32]) {}
+}
+
+///Size of the RIFF and fmt headers in front of the samples
+const WAV_HEADER_SIZE: u32 = 44;
+
+///Writes the samples to a 16 bit mono WAV file as they come<br>
+///The sizes in the header are filled in by finish() (or when the sink is dropped), a write error stops the writing
+///and is returned by finish()
+pub struct WavSink<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    samples: u32,
+    error: Option<io::Error>,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        return Self::new(BufWriter::new(File::create(path)?), sample_rate);
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    //Constructor
+    pub fn new(writer: W, sample_rate: u32) -> io::Result<Self> {
+        let mut sink = Self {
+            writer,
+            sample_rate: sample_rate.max(1),
+            samples: 0,
+            error: None,
+        };
+
+        sink.write_header()?;
+
+        Ok(sink)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let data_size = self.samples * 2;
+
+        let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        header.extend(b"RIFF");
+        header.extend((WAV_HEADER_SIZE - 8 + data_size).to_le_bytes());
+        header.extend(b"WAVEfmt ");
+        header.extend(16u32.to_le_bytes()); //fmt chunk size
+        header.extend(1u16.to_le_bytes()); //PCM
+        header.extend(1u16.to_le_bytes()); //Mono
+        header.extend(self.sample_rate.to_le_bytes());
+        header.extend((self.sample_rate * 2).to_le_bytes()); //Bytes per second
+        header.extend(2u16.to_le_bytes()); //Bytes per sample
+        header.extend(16u16.to_le_bytes()); //Bits per sample
+        header.extend(b"data");
+        header.extend(data_size.to_le_bytes());
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::End(0))?;
+
+        Ok(())
+    }
+
+    ///Fills in the header and flushes, returns the first error the sink ran into
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.write_header()?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    fn queue(&mut self, samples: &[f32]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+            .collect();
+
+        match self.writer.write_all(&bytes) {
+            Ok(()) => self.samples += samples.len() as u32,
+            Err(error) => self.error = Some(error),
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CPU_RATE: f64 = 1_789_773.0;
+
+    ///Pulse 1 as a square wave at the frequency, for the given CPU cycles
+    fn square(pipeline: &mut AudioPipeline, frequency: f64, cycles: usize) {
+        let period = CPU_RATE / frequency;
+
+        for cycle in 0..cycles {
+            let high = (cycle as f64 / period).fract() < 0.5;
+            pipeline.push(ChannelLevels {
+                pulse_1: if high { 15 } else { 0 },
+                ..ChannelLevels::default()
+            });
+        }
+    }
+
+    #[test]
+    fn mixer_matches_the_reference_levels() {
+        assert_eq!(mix(ChannelLevels::default()), 0.0);
+
+        let full = ChannelLevels { pulse_1: 15, pulse_2: 15, triangle: 15, noise: 15, dmc: 127 };
+        assert!((mix(full) - 1.0).abs() < 0.02, "{}", mix(full));
+    }
+
+    #[test]
+    fn resamples_to_the_output_rate() {
+        let mut pipeline = AudioPipeline::new(CPU_RATE, 44_100);
+        square(&mut pipeline, 440.0, CPU_RATE as usize);
+
+        let samples = pipeline.take_samples();
+        assert!((44_099..=44_101).contains(&samples.len()), "{}", samples.len());
+
+        pipeline.set_output_rate(48_000);
+        square(&mut pipeline, 440.0, CPU_RATE as usize / 10);
+        assert!((4_799..=4_801).contains(&pipeline.take_samples().len()));
+    }
+
+    #[test]
+    fn high_pass_removes_the_dc_offset() {
+        let mut pipeline = AudioPipeline::new(CPU_RATE, 44_100);
+        square(&mut pipeline, 440.0, CPU_RATE as usize);
+
+        //The last 100ms swing around 0 even though the mixer output is never negative
+        let samples = pipeline.take_samples();
+        let tail = &samples[samples.len() - 4410..];
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+
+        assert!(mean.abs() < 0.01, "{}", mean);
+        assert!(tail.iter().any(|&sample| sample < -0.05));
+    }
+
+    #[test]
+    fn ring_drops_when_full_and_plays_silence_when_empty() {
+        let (mut producer, mut consumer) = sample_ring(4);
+
+        assert_eq!(producer.push(&[0.1, 0.2, 0.3]), 3);
+        assert_eq!(producer.push(&[0.4, 0.5]), 1);
+        assert_eq!(producer.len(), 4);
+
+        let mut output = [1.0; 6];
+        consumer.fill(&mut output);
+
+        assert_eq!(output, [0.1, 0.2, 0.3, 0.4, 0.0, 0.0]);
+        assert!(producer.is_empty());
+        assert_eq!(producer.take_underruns(), 1);
+        assert_eq!(producer.take_underruns(), 0);
+
+        //Wraps around the end of the storage
+        assert_eq!(producer.push(&[0.6, 0.7, 0.8]), 3);
+        let mut output = [0.0; 3];
+        consumer.fill(&mut output);
+        assert_eq!(output, [0.6, 0.7, 0.8]);
+    }
+
+    #[test]
+    fn wav_sink_writes_the_samples_and_sizes() {
+        let mut sink = WavSink::new(io::Cursor::new(Vec::new()), 44_100).unwrap();
+        sink.queue(&[0.0, 1.0]);
+        sink.queue(&[-1.0]);
+        sink.finish().unwrap();
+
+        let wav = sink.writer.get_ref().clone();
+        assert_eq!(wav.len(), WAV_HEADER_SIZE as usize + 6);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav[4..8], (WAV_HEADER_SIZE - 8 + 6).to_le_bytes());
+        assert_eq!(wav[24..28], 44_100u32.to_le_bytes());
+        assert_eq!(wav[40..44], 6u32.to_le_bytes());
+        assert_eq!(wav[44..], [0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]);
+    }
+}
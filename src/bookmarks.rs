@@ -0,0 +1,65 @@
+///A save state taken on a frame under a name (practice points, TAS editing), see Emulator::add_bookmark()
+#[derive(Clone)]
+pub struct Bookmark {
+    pub name: String,
+    pub frame: u64, //Emulator::frame_number() when the state was taken
+    state: Vec<u8>,
+}
+
+impl Bookmark {
+    ///The save state, in the Emulator::save_state() format
+    pub fn state(&self) -> &[u8] {
+        return &self.state;
+    }
+}
+
+///The bookmarks of the loaded game, ordered by frame
+pub(crate) struct Bookmarks {
+    list: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    //Constructor
+    pub fn new() -> Self {
+        Self { list: Vec::new() }
+    }
+
+    ///Adds the bookmark, replacing the one with the same name
+    pub fn set(&mut self, name: &str, frame: u64, state: Vec<u8>) {
+        self.remove(name);
+
+        //After the bookmarks of the same frame, so they keep the order they were added in
+        let index = self.list.partition_point(|bookmark| bookmark.frame <= frame);
+
+        self.list.insert(index, Bookmark { name: name.to_string(), frame, state });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Bookmark> {
+        return self.list.iter().find(|bookmark| bookmark.name == name);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let count = self.list.len();
+        self.list.retain(|bookmark| bookmark.name != name);
+
+        return self.list.len() != count;
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    pub fn list(&self) -> &[Bookmark] {
+        return &self.list;
+    }
+
+    ///First bookmark after the frame
+    pub fn next(&self, frame: u64) -> Option<&Bookmark> {
+        return self.list.iter().find(|bookmark| bookmark.frame > frame);
+    }
+
+    ///Last bookmark before the frame
+    pub fn previous(&self, frame: u64) -> Option<&Bookmark> {
+        return self.list.iter().rev().find(|bookmark| bookmark.frame < frame);
+    }
+}
@@ -1,30 +1,634 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::{Cell, Ref, RefCell}, ops::RangeInclusive, rc::Rc};
 
-use crate::cpu::CPU;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apu::APU,
+    cartridge::Cartridge,
+    cheats::CheatCode,
+    controller::{Button, Controller},
+    cpu::CPU,
+    debugger::Debugger,
+    diagnostics::{Diagnostic, Diagnostics},
+    input::{InputDevice, InputPorts},
+    memory_map::{MemoryKind, MemoryRegion},
+    ppu::PPU,
+    rng::Rng,
+};
+
+///Address range left unused by the console where embedders may map their own devices
+pub const HANDLER_RANGE: RangeInclusive<u16> = 0x4018..=0x5FFF;
+
+///Identifier returned when a handler is registered, used to remove it later
+pub type HandlerId = usize;
+
+///A user supplied memory-mapped device (print ports, test harness exits, ...)
+pub(crate) struct BusHandler {
+    pub id: HandlerId,
+    pub range: RangeInclusive<u16>,
+    pub read: Box<dyn FnMut(u16) -> u8>,
+    pub write: Box<dyn FnMut(u16, u8)>,
+}
+
+///Identifier returned when a write interceptor is registered
+pub type InterceptorId = usize;
+
+///What happens to a CPU write after a write interceptor has seen it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WriteAction {
+    Allow,
+    Veto,        //The write is dropped, the memory or register keeps its value
+    Replace(u8), //The value is written instead of the game's
+}
+
+///Trainers and practice hacks: observes (and may veto or change) the game's own writes to a range
+pub(crate) struct WriteInterceptor {
+    pub id: InterceptorId,
+    pub range: RangeInclusive<u16>,
+    pub callback: Box<dyn FnMut(u16, u8, u8) -> WriteAction>, //(address, current value, new value)
+}
+
+///Internal RAM, the open bus value and the OAM DMA progress, as stored in save states
+#[derive(Serialize, Deserialize)]
+pub struct BusState {
+    ram: Vec<u8>,
+    open_bus: u8,
+
+    dma_transfer: bool,
+    dma_dummy: bool,
+    dma_page: u8,
+    dma_address: u8,
+    dma_data: u8,
+}
 
 pub(crate) struct BUS {
     cpu: Rc<RefCell<CPU>>,
-    ram:[u8;2048]
+    ppu: Rc<RefCell<PPU>>,
+    apu: Rc<RefCell<APU>>,
+    cartridge: Option<Rc<RefCell<Cartridge>>>,
+    ram:[u8;2048],
+    input_ports: RefCell<InputPorts>,
+
+    //Last value driven on the CPU data bus, returned by reads nothing responds to
+    open_bus: Cell<u8>,
+    last_read: Cell<u16>, //Address of the last read, repeated by DMC fetch conflicts
+    instruction_address: Cell<u16>, //Of the last opcode fetch, the context of diagnostics
+
+    //OAM DMA ($4014): the CPU is halted while 256 bytes are copied from page dma_page into OAM
+    dma_transfer: bool,
+    dma_dummy: bool, //Waiting for the alignment cycle before the first read
+    dma_page: u8,
+    dma_address: u8,
+    dma_data: u8,
+
+    handlers: RefCell<Vec<BusHandler>>,
+    next_handler_id: HandlerId,
+
+    interceptors: Vec<WriteInterceptor>,
+    next_interceptor_id: InterceptorId,
+
+    cheats: Vec<CheatCode>, //Enabled Game Genie / raw codes, patch the bytes CPU reads return
+
+    debugger: RefCell<Debugger>, //Watchpoints see every read and write
+    diagnostics: RefCell<Diagnostics>,
 }
 
 impl BUS {
     pub fn new() -> Rc<RefCell<Self>> {
-        let mut bus = Rc::new(RefCell::new(BUS{
+        let bus = Rc::new(RefCell::new(BUS{
             cpu: Rc::new(RefCell::new(CPU::new())),
-            ram: [Default::default();2048]
+            ppu: Rc::new(RefCell::new(PPU::new())),
+            apu: Rc::new(RefCell::new(APU::new())),
+            cartridge: None,
+            ram: [Default::default();2048],
+            input_ports: RefCell::new(InputPorts::new()),
+
+            open_bus: Cell::new(0),
+            last_read: Cell::new(0),
+            instruction_address: Cell::new(0),
+
+            dma_transfer: false,
+            dma_dummy: true,
+            dma_page: 0x00,
+            dma_address: 0x00,
+            dma_data: 0x00,
+
+            handlers: RefCell::new(Vec::new()),
+            next_handler_id: 0,
+
+            interceptors: Vec::new(),
+            next_interceptor_id: 0,
+
+            cheats: Vec::new(),
+
+            debugger: RefCell::new(Debugger::new()),
+            diagnostics: RefCell::new(Diagnostics::new()),
         }));
 
         bus.borrow_mut().cpu.borrow_mut().connect_bus(Rc::downgrade(&bus));
 
+        //Both ports start with a standard controller plugged in
+        bus.borrow_mut().connect_input_device(0, Box::new(Controller::new()));
+        bus.borrow_mut().connect_input_device(1, Box::new(Controller::new()));
+
         bus
     }
 
+    ///CPU Memory Map
+    // $0000 - $07FF  2KB internal RAM
+    // $0800 - $1FFF  Mirrors of $0000 - $07FF
+    // $2000 - $2007  PPU registers
+    // $2008 - $3FFF  Mirrors of $2000 - $2007 (repeats every 8 bytes)
+    // $4000 - $4017  APU and I/O registers
+    // $4018 - $401F  APU and I/O functionality that is normally disabled
+    // $4020 - $FFFF  Cartridge space: PRG-ROM, PRG-RAM and mapper registers
     pub fn write(&mut self,address:u16,data:u8) {
-        self.ram[address as usize] = data;
+        self.open_bus.set(data);
+
+        let Some(data) = self.intercept_write(address, data) else {
+            return;
+        };
+
+        self.debugger.get_mut().check_access(address, data, true);
+
+        if let Some(handler) = self.handlers.get_mut().iter_mut().find(|handler| handler.range.contains(&address)) {
+            (handler.write)(address, data);
+            return;
+        }
+
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram[(address & 0x07FF) as usize] = data;
+            }
+            0x2000..=0x3FFF => {
+                self.ppu.borrow_mut().cpu_write(address & 0x0007, data);
+            }
+            //Channels, status and frame counter
+            0x4000..=0x4013 | 0x4015 | 0x4017 => {
+                self.apu.borrow_mut().cpu_write(address, data);
+            }
+            //OAM DMA, the transfer itself runs in clock_dma()
+            0x4014 => {
+                self.dma_page = data;
+                self.dma_address = 0x00;
+                self.dma_transfer = true;
+                self.dma_dummy = true;
+            }
+            0x4016 => {
+                self.input_ports.get_mut().write(data);
+            }
+            //APU and I/O test registers, disabled on retail consoles
+            0x4018..=0x401F => {}
+            0x4020..=0xFFFF => {
+                if let Some(cartridge) = &self.cartridge {
+                    let mut cartridge = cartridge.borrow_mut();
+
+                    if let Some(register) = cartridge.unsupported_write(address, data) {
+                        self.diagnostics.get_mut().report(Diagnostic::UnsupportedRegister {
+                            mapper: cartridge.mapper_id,
+                            register,
+                            address,
+                            data,
+                            program_counter: self.instruction_address.get(),
+                        });
+                    }
+
+                    cartridge.cpu_write(address, data);
+                }
+            }
+        }
+    }
+
+    ///Runs the write interceptors covering the address in registration order<br>
+    ///Returns the value to write, or None if one of them vetoed the write
+    fn intercept_write(&mut self, address: u16, data: u8) -> Option<u8> {
+        if !self.interceptors.iter().any(|interceptor| interceptor.range.contains(&address)) {
+            return Some(data);
+        }
+
+        let current = self.peek(address);
+        let mut data = data;
+
+        for interceptor in self.interceptors.iter_mut().filter(|interceptor| interceptor.range.contains(&address)) {
+            match (interceptor.callback)(address, current, data) {
+                WriteAction::Allow => {}
+                WriteAction::Veto => return None,
+                WriteAction::Replace(value) => data = value,
+            }
+        }
+
+        return Some(data);
     }
 
     pub fn read(&self,address:u16) -> u8 {
-        self.ram[address as usize]
+        let data = self.read_data(address);
+        let data = self.cheats.iter().find_map(|cheat| cheat.apply(address, data)).unwrap_or(data);
+
+        self.open_bus.set(data);
+        self.last_read.set(address);
+        self.debugger.borrow_mut().check_access(address, data, false);
+
+        return data;
+    }
+
+    ///Read of an opcode by the CPU, remembers where the instruction is
+    pub fn fetch_opcode(&self, address: u16) -> u8 {
+        self.instruction_address.set(address);
+
+        return self.read(address);
+    }
+
+    pub fn last_read_address(&self) -> u16 {
+        return self.last_read.get();
+    }
+
+    fn read_data(&self, address: u16) -> u8 {
+        if let Some(handler) = self.handlers.borrow_mut().iter_mut().find(|handler| handler.range.contains(&address)) {
+            return (handler.read)(address);
+        }
+
+        match address {
+            0x0000..=0x1FFF => {
+                return self.ram[(address & 0x07FF) as usize];
+            }
+            0x2000..=0x3FFF => {
+                return self.ppu.borrow_mut().cpu_read(address & 0x0007);
+            }
+            //Bit 5 isn't driven by the APU and keeps the open bus value
+            0x4015 => {
+                let data = self.apu.borrow_mut().cpu_read_status();
+
+                return data | (self.open_bus.get() & 0x20);
+            }
+            //The controllers only drive D0-D4, the upper bits keep the open bus value
+            0x4016 | 0x4017 => {
+                let data = self.input_ports.borrow_mut().read((address - 0x4016) as usize);
+
+                return (data & 0x1F) | (self.open_bus.get() & 0xE0);
+            }
+            //$4000 - $4014 are write only and nothing drives the bus when they are read
+            0x4000..=0x401F => {
+                return self.open_bus.get();
+            }
+            0x4020..=0xFFFF => {
+                if let Some(cartridge) = &self.cartridge {
+                    if let Some(data) = cartridge.borrow().cpu_read(address) {
+                        return data;
+                    }
+                }
+
+                return self.open_bus.get();
+            }
+        }
+    }
+
+    //OAM DMA
+
+    ///True while an OAM DMA is running, the CPU must not be clocked
+    pub fn dma_active(&self) -> bool {
+        return self.dma_transfer;
+    }
+
+    ///Runs one CPU cycle of the OAM DMA, called instead of clocking the CPU<br>
+    ///After the halt cycle (and one more if it landed on an odd cycle) it alternates reads on even cycles with
+    ///writes to $2004 on odd cycles, stalling the CPU for 513 or 514 cycles in total
+    pub fn clock_dma(&mut self, odd_cycle: bool) {
+        if self.dma_dummy {
+            if odd_cycle {
+                self.dma_dummy = false;
+            }
+
+            return;
+        }
+
+        if !odd_cycle {
+            self.dma_data = self.read(((self.dma_page as u16) << 8) | self.dma_address as u16);
+        } else {
+            self.ppu.borrow_mut().cpu_write(0x0004, self.dma_data);
+            self.dma_address = self.dma_address.wrapping_add(1);
+
+            if self.dma_address == 0x00 {
+                self.dma_transfer = false;
+                self.dma_dummy = true;
+            }
+        }
+    }
+
+    //Save States
+
+    ///Only the BUS itself, the components are saved separately
+    pub fn save_state(&self) -> BusState {
+        BusState {
+            ram: self.ram.to_vec(),
+            open_bus: self.open_bus.get(),
+
+            dma_transfer: self.dma_transfer,
+            dma_dummy: self.dma_dummy,
+            dma_page: self.dma_page,
+            dma_address: self.dma_address,
+            dma_data: self.dma_data,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &BusState) {
+        if state.ram.len() == self.ram.len() {
+            self.ram.copy_from_slice(&state.ram);
+        }
+
+        self.open_bus.set(state.open_bus);
+
+        self.dma_transfer = state.dma_transfer;
+        self.dma_dummy = state.dma_dummy;
+        self.dma_page = state.dma_page;
+        self.dma_address = state.dma_address;
+        self.dma_data = state.dma_data;
+    }
+
+    pub fn get_cartridge(&self) -> Option<Rc<RefCell<Cartridge>>> {
+        return self.cartridge.clone();
+    }
+
+    ///Clocks the boards that count CPU cycles
+    pub fn clock_cartridge(&self) {
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().cpu_clock();
+        }
+    }
+
+    ///IRQ line of the cartridge board
+    pub fn cartridge_irq(&self) -> bool {
+        if let Some(cartridge) = &self.cartridge {
+            return cartridge.borrow().irq();
+        }
+
+        return false;
+    }
+
+    //Debug Access
+
+    ///Reads like the CPU would but without side effects: no register acknowledges, no controller shifts,
+    ///no virtual device callbacks (which read as open bus) and the open bus value is left untouched
+    pub fn peek(&self, address: u16) -> u8 {
+        if self.handlers.borrow().iter().any(|handler| handler.range.contains(&address)) {
+            return self.open_bus.get();
+        }
+
+        match address {
+            0x0000..=0x1FFF => {
+                return self.ram[(address & 0x07FF) as usize];
+            }
+            0x2000..=0x3FFF => {
+                return self.ppu.borrow().cpu_peek(address & 0x0007);
+            }
+            0x4015 => {
+                return self.apu.borrow().peek_status() | (self.open_bus.get() & 0x20);
+            }
+            0x4000..=0x401F => {
+                return self.open_bus.get();
+            }
+            0x4020..=0xFFFF => {
+                if let Some(cartridge) = &self.cartridge {
+                    if let Some(data) = cartridge.borrow().cpu_read(address) {
+                        return data;
+                    }
+                }
+
+                return self.open_bus.get();
+            }
+        }
+    }
+
+    ///Changes memory without side effects: RAM, or the PRG byte mapped at the address (ROM included)<br>
+    ///Registers aren't memory and are left alone, returns false when nothing was written
+    pub fn poke(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram[(address & 0x07FF) as usize] = data;
+                return true;
+            }
+            0x4020..=0xFFFF => {
+                if let Some(cartridge) = &self.cartridge {
+                    return cartridge.borrow_mut().poke(address, data);
+                }
+
+                return false;
+            }
+            _ => {
+                return false;
+            }
+        }
+    }
+
+    ///The CPU address space as it is mapped right now (see the CPU Memory Map above), in address order
+    pub fn memory_map(&self) -> Vec<MemoryRegion> {
+        let mut map = vec![
+            MemoryRegion::new(0x0000, 0x07FF, "Internal RAM", MemoryKind::Ram),
+            MemoryRegion::new(0x0800, 0x1FFF, "Internal RAM mirrors", MemoryKind::Ram),
+            MemoryRegion::new(0x2000, 0x2007, "PPU registers", MemoryKind::Registers),
+            MemoryRegion::new(0x2008, 0x3FFF, "PPU register mirrors", MemoryKind::Registers),
+            MemoryRegion::new(0x4000, 0x4017, "APU and I/O registers", MemoryKind::Registers),
+            MemoryRegion::new(0x4018, 0x401F, "Disabled APU and I/O", MemoryKind::OpenBus),
+            MemoryRegion::new(0x4020, 0x5FFF, "Expansion", MemoryKind::OpenBus),
+        ];
+
+        match &self.cartridge {
+            Some(cartridge) => map.extend(cartridge.borrow().prg_memory_map()),
+            None => map.push(MemoryRegion::new(0x6000, 0xFFFF, "Open bus", MemoryKind::OpenBus)),
+        }
+
+        return map;
+    }
+
+    ///The PPU address space as it is mapped right now, in address order
+    pub fn ppu_memory_map(&self) -> Vec<MemoryRegion> {
+        let mut map = match &self.cartridge {
+            Some(cartridge) => cartridge.borrow().chr_memory_map(),
+            None => vec![MemoryRegion::new(0x0000, 0x1FFF, "Open bus", MemoryKind::OpenBus)],
+        };
+
+        map.extend([
+            MemoryRegion::new(0x2000, 0x2FFF, "Nametables", MemoryKind::Ram),
+            MemoryRegion::new(0x3000, 0x3EFF, "Nametable mirrors", MemoryKind::Ram),
+            MemoryRegion::new(0x3F00, 0x3FFF, "Palettes", MemoryKind::Ram),
+        ]);
+
+        return map;
+    }
+
+    ///Connects the cartridge to both the CPU and the PPU buses
+    pub fn insert_cartridge(&mut self, cartridge: Rc<RefCell<Cartridge>>) {
+        self.ppu.borrow_mut().connect_cartridge(cartridge.clone());
+        self.cartridge = Some(cartridge);
+    }
+
+    ///Resets the cartridge, the CPU (which reloads the program counter from the reset vector), the PPU and the APU
+    pub fn reset(&self) {
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().reset();
+        }
+
+        self.cpu.borrow_mut().reset();
+        self.ppu.borrow_mut().reset();
+        self.apu.borrow_mut().reset();
+    }
+
+    ///Power cycle: clears the internal RAM and the PPU memories, call reset() after it<br>
+    ///The cartridge keeps its RAM, battery backed or not
+    pub fn clear_memory(&mut self) {
+        self.ram.fill(0);
+        self.ppu.borrow_mut().clear_memory();
+    }
+
+    ///Startup fuzzing: fills everything the hardware leaves undefined at power on (RAM, PPU memories,
+    ///APU phase and CPU registers) from the generator, call it after reset()<br>
+    ///Games that read memory before initializing it behave differently between seeds
+    pub fn randomize_power_on_state(&mut self, rng: &mut Rng) {
+        rng.fill(&mut self.ram);
+
+        self.cpu.borrow_mut().randomize_power_on_state(rng);
+        self.ppu.borrow_mut().randomize_power_on_state(rng);
+        self.apu.borrow_mut().randomize_power_on_state(rng);
+    }
+
+    pub fn get_cpu(&self) -> Rc<RefCell<CPU>> {
+        return self.cpu.clone();
+    }
+
+    pub fn get_ppu(&self) -> Rc<RefCell<PPU>> {
+        return self.ppu.clone();
+    }
+
+    pub fn get_apu(&self) -> Rc<RefCell<APU>> {
+        return self.apu.clone();
+    }
+
+    ///The last frame rendered by the PPU as 256x240 0x00RRGGBB pixels
+    pub fn get_screen(&self) -> Ref<'_, [u32]> {
+        return Ref::map(self.ppu.borrow(), |ppu| ppu.get_screen());
+    }
+
+    //Input Ports
+
+    ///Plugs a device into controller port 0 ($4016) or 1 ($4017), returns false when the port doesn't exist
+    pub fn connect_input_device(&mut self, port: usize, device: Box<dyn InputDevice>) -> bool {
+        return self.input_ports.get_mut().connect(port, device);
+    }
+
+    pub fn disconnect_input_device(&mut self, port: usize) -> bool {
+        return self.input_ports.get_mut().disconnect(port);
+    }
+
+    ///Presses or releases a button of the controller in port 0 or 1 (2 and 3 for players 3 and 4 of a Four Score),
+    ///returns false if nothing is plugged in
+    pub fn set_button_state(&mut self, port: usize, button: Button, pressed: bool) -> bool {
+        return self.input_ports.get_mut().set_button_state(port, button, pressed);
     }
 
-}
\ No newline at end of file
+    ///Plugs a device into the Famicom expansion port, it sees the whole $4016 output latch and both read ports
+    pub fn connect_expansion_device(&mut self, device: Box<dyn InputDevice>) {
+        self.input_ports.get_mut().connect_expansion(device);
+    }
+
+    pub fn disconnect_expansion_device(&mut self) -> bool {
+        return self.input_ports.get_mut().disconnect_expansion();
+    }
+
+    ///Writes to $4016 that raised the strobe since power on
+    pub fn input_strobes(&self) -> u64 {
+        return self.input_ports.borrow().strobes();
+    }
+
+    //Virtual Devices
+
+    ///Maps a custom device into an unused address range (inside HANDLER_RANGE)<br>
+    ///Reads and writes in the range are forwarded to the callbacks instead of the console hardware<br>
+    ///Returns None if the range is outside HANDLER_RANGE or overlaps an already registered handler
+    pub fn register_handler(
+        &mut self,
+        range: RangeInclusive<u16>,
+        read: Box<dyn FnMut(u16) -> u8>,
+        write: Box<dyn FnMut(u16, u8)>,
+    ) -> Option<HandlerId> {
+        if range.is_empty()
+            || !HANDLER_RANGE.contains(range.start())
+            || !HANDLER_RANGE.contains(range.end())
+        {
+            return None;
+        }
+
+        let overlaps = self.handlers.get_mut().iter().any(|handler| {
+            range.start() <= handler.range.end() && handler.range.start() <= range.end()
+        });
+
+        if overlaps {
+            return None;
+        }
+
+        let id = self.next_handler_id;
+        self.next_handler_id += 1;
+
+        self.handlers.get_mut().push(BusHandler { id, range, read, write });
+
+        Some(id)
+    }
+
+    ///Removes a previously registered handler, returns false if the id is unknown
+    pub fn remove_handler(&mut self, id: HandlerId) -> bool {
+        let handlers = self.handlers.get_mut();
+        let count = handlers.len();
+
+        handlers.retain(|handler| handler.id != id);
+
+        return handlers.len() != count;
+    }
+
+    pub fn get_debugger(&self) -> &RefCell<Debugger> {
+        return &self.debugger;
+    }
+
+    pub fn get_diagnostics(&self) -> &RefCell<Diagnostics> {
+        return &self.diagnostics;
+    }
+
+    //Write Interceptors
+
+    ///Calls the callback with (address, current value, new value) before every CPU write in the range,
+    ///its WriteAction decides whether the write happens<br>
+    ///Interceptors may overlap, they run in the order they were registered
+    pub fn register_write_interceptor(
+        &mut self,
+        range: RangeInclusive<u16>,
+        callback: Box<dyn FnMut(u16, u8, u8) -> WriteAction>,
+    ) -> InterceptorId {
+        let id = self.next_interceptor_id;
+        self.next_interceptor_id += 1;
+
+        self.interceptors.push(WriteInterceptor { id, range, callback });
+
+        return id;
+    }
+
+    pub fn has_write_interceptors(&self) -> bool {
+        return !self.interceptors.is_empty();
+    }
+
+    ///Removes a previously registered interceptor, returns false if the id is unknown
+    pub fn remove_write_interceptor(&mut self, id: InterceptorId) -> bool {
+        let count = self.interceptors.len();
+
+        self.interceptors.retain(|interceptor| interceptor.id != id);
+
+        return self.interceptors.len() != count;
+    }
+
+    //Cheats
+
+    ///Replaces the codes applied to CPU reads
+    pub fn set_cheats(&mut self, cheats: Vec<CheatCode>) {
+        self.cheats = cheats;
+    }
+
+    pub fn has_cheats(&self) -> bool {
+        return !self.cheats.is_empty();
+    }
+}
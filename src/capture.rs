@@ -0,0 +1,164 @@
+use std::io::{self, Write};
+
+use crate::{
+    checksum::crc32,
+    palette::Palette,
+    ppu::{SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+///Largest block of stored (uncompressed) deflate data
+const STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+///What the pixels of a screenshot or recorded frame hold
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CaptureColors {
+    ///The colors the window shows: the palette with color emphasis, as frame_buffer() holds them
+    #[default]
+    Display,
+    ///The 2C02 color (0 - 63) of every pixel without emphasis, stored with the 64 base colors of the palette,
+    ///for tools that apply their own palette or filter
+    Indexed,
+    ///The frame run through an NTSC composite encoder and decoder, with the color fringes and emphasis of a TV
+    Ntsc,
+}
+
+///A frame taken by Emulator::capture(), written out with write_png()
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Capture {
+    pub colors: CaptureColors,
+    pub pixels: Vec<u8>,  //Row by row: R, G, B bytes (Display, Ntsc) or one color index (Indexed)
+    pub palette: Vec<u8>, //R, G, B bytes of the 64 base colors for Indexed captures, empty otherwise
+}
+
+impl Capture {
+    ///From the 0x00RRGGBB frame the window shows
+    pub(crate) fn display(frame: &[u32]) -> Self {
+        Self {
+            colors: CaptureColors::Display,
+            pixels: frame.iter().flat_map(|pixel| pixel.to_be_bytes()[1..].to_vec()).collect(),
+            palette: Vec::new(),
+        }
+    }
+
+    ///From the 0x00RRGGBB frame the NTSC decoder produced
+    pub(crate) fn ntsc(frame: &[u32]) -> Self {
+        Self { colors: CaptureColors::Ntsc, ..Self::display(frame) }
+    }
+
+    ///From the frame as palette indexes with the emphasis bits above them
+    pub(crate) fn indexed(indices: &[u16], palette: &Palette) -> Self {
+        Self {
+            colors: CaptureColors::Indexed,
+            pixels: indices.iter().map(|&index| (index & 0x3F) as u8).collect(),
+            palette: palette.colors()[..64].iter().flat_map(|color| color.to_be_bytes()[1..].to_vec()).collect(),
+        }
+    }
+
+    ///Writes a 256x240 PNG: 8 bit RGB for Display and Ntsc captures, 8 bit indexed with the palette for Indexed<br>
+    ///The image data isn't compressed, so a frame is written in no time while recording
+    pub fn write_png<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let (color_type, bytes_per_pixel) = match self.colors {
+            CaptureColors::Display | CaptureColors::Ntsc => (2, 3),
+            CaptureColors::Indexed => (3, 1),
+        };
+
+        let mut header = Vec::new();
+        header.extend((SCREEN_WIDTH as u32).to_be_bytes());
+        header.extend((SCREEN_HEIGHT as u32).to_be_bytes());
+        header.extend([8, color_type, 0, 0, 0]); //Bit depth, color type, compression, filter, interlace
+
+        //Every row starts with its filter type, 0 for none
+        let mut image = Vec::with_capacity(self.pixels.len() + SCREEN_HEIGHT);
+        for row in self.pixels.chunks(SCREEN_WIDTH * bytes_per_pixel) {
+            image.push(0);
+            image.extend_from_slice(row);
+        }
+
+        writer.write_all(&PNG_SIGNATURE)?;
+        write_chunk(writer, b"IHDR", &header)?;
+
+        if self.colors == CaptureColors::Indexed {
+            write_chunk(writer, b"PLTE", &self.palette)?;
+        }
+
+        write_chunk(writer, b"IDAT", &zlib_stored(&image))?;
+        write_chunk(writer, b"IEND", &[])?;
+
+        Ok(())
+    }
+}
+
+///Length, type, data and the CRC-32 of the type and data
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc_data = kind.to_vec();
+    crc_data.extend_from_slice(data);
+
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(&crc_data)?;
+    writer.write_all(&crc32(&crc_data).to_be_bytes())?;
+
+    Ok(())
+}
+
+///zlib stream of stored deflate blocks: a header, blocks of up to 64KB and the Adler-32 of the data
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks = data.len().div_ceil(STORED_BLOCK_SIZE).max(1);
+
+    for (index, block) in data.chunks(STORED_BLOCK_SIZE).chain(data.is_empty().then_some(&[][..])).enumerate() {
+        let length = block.len() as u16;
+
+        stream.push((index + 1 == blocks) as u8); //BFINAL on the last block, BTYPE 00 (stored)
+        stream.extend(length.to_le_bytes());
+        stream.extend((!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend(adler32(data).to_be_bytes());
+
+    return stream;
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    return (b << 16) | a;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zlib_stream_splits_into_stored_blocks() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        assert_eq!(zlib_stored(&[]), [0x78, 0x01, 0x01, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x01]);
+
+        let data = vec![0xAB; STORED_BLOCK_SIZE + 1];
+        let stream = zlib_stored(&data);
+
+        assert_eq!(stream.len(), 2 + 5 + STORED_BLOCK_SIZE + 5 + 1 + 4);
+        assert_eq!(&stream[2..7], &[0x00, 0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(&stream[7 + STORED_BLOCK_SIZE..12 + STORED_BLOCK_SIZE], &[0x01, 0x01, 0x00, 0xFE, 0xFF]);
+    }
+
+    #[test]
+    fn png_chunks_are_laid_out_in_order() {
+        let capture = Capture::indexed(&vec![0x21; SCREEN_WIDTH * SCREEN_HEIGHT], &Palette::default());
+        let mut png = Vec::new();
+        capture.write_png(&mut png).unwrap();
+
+        assert_eq!(&png[..8], &PNG_SIGNATURE);
+        assert_eq!(&png[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(&png[16..29], &[0, 0, 1, 0, 0, 0, 0, 240, 8, 3, 0, 0, 0]);
+        assert_eq!(&png[33..41], &[0, 0, 0, 192, b'P', b'L', b'T', b'E']);
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+    }
+}
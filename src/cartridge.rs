@@ -0,0 +1,927 @@
+use std::{fmt, fs, io, ops::Range, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::crc32,
+    mapper::{create_mapper, Mapper},
+    memory_map::{bank_regions, MemoryKind, MemoryRegion},
+    ppu::Mirroring,
+    region::Region,
+};
+
+///Size of one PRG-ROM bank
+pub const PRG_BANK_SIZE: usize = 16 * 1024;
+
+///Size of one CHR-ROM bank
+pub const CHR_BANK_SIZE: usize = 8 * 1024;
+
+///Work RAM at $6000 - $7FFF, kept in a .sav file when the board has a battery
+pub const PRG_RAM_SIZE: usize = 8 * 1024;
+
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+
+///Hardware the game was made for, from the console type bits of flags 7
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes, //NES and Famicom
+    ///VS. System arcade board: PPU model (RP2C03B, RP2C04-0001, ...) and hardware type (Unisystem, Dual System, ...)
+    ///as numbered in byte 13 of NES 2.0 headers, both 0 for iNES headers
+    VsSystem { ppu: u8, hardware: u8 },
+    PlayChoice10,
+    Extended(u8), //NES 2.0 extended console type from byte 13 (3: Famiclone with decimal mode, 4: EPSM, ...)
+}
+
+///Size of the PlayChoice-10 INST-ROM and PROM (key data and counter output) that follow the CHR-ROM
+const INST_ROM_SIZE: usize = 8 * 1024;
+const PROM_SIZE: usize = 32;
+
+///The PlayChoice-10 chips of an image, which drive the arcade's instruction (hint) screen instead of the game
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlayChoiceRoms {
+    pub inst_rom: Vec<u8>, //Hint screen tiles and text, shorter than 8KB in cut short dumps
+    pub prom: Vec<u8>,     //Empty when the dump leaves it out
+}
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    InvalidHeader, //The file doesn't start with "NES" followed by 0x1A
+    Truncated,     //The file is shorter than the sizes declared in the header
+    UnsupportedMapper(u16),
+    UnsupportedSize, //PRG/CHR-ROM that isn't a whole number of banks, or more than 255 of them
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(error) => write!(f, "could not read the ROM file: {}", error),
+            CartridgeError::InvalidHeader => write!(f, "not an iNES ROM (missing NES<EOF> header)"),
+            CartridgeError::Truncated => write!(f, "ROM file is smaller than its header declares"),
+            CartridgeError::UnsupportedMapper(id) => write!(f, "mapper {} is not supported", id),
+            CartridgeError::UnsupportedSize => write!(f, "PRG/CHR-ROM size is not supported"),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+impl From<io::Error> for CartridgeError {
+    fn from(error: io::Error) -> Self {
+        CartridgeError::Io(error)
+    }
+}
+
+///iNES Header
+// Bytes  Description
+// 0-3    Constant $4E $45 $53 $1A ("NES" followed by MS-DOS end-of-file)
+// 4      Size of PRG ROM in 16 KB units
+// 5      Size of CHR ROM in 8 KB units (0 means the board uses CHR RAM)
+// 6      Flags 6: mirroring, battery, trainer, four screen, lower nybble of the mapper number
+// 7      Flags 7: VS/PlayChoice, NES 2.0 identifier, upper nybble of the mapper number
+// 8-15   Flags 8-10 and padding (iNES), extended sizes and settings (NES 2.0)
+//
+///NES 2.0 Extension
+// Bytes  Description
+// 8      Mapper bits 8-11 (low nybble), submapper (high nybble)
+// 9      PRG ROM size MSB (low nybble), CHR ROM size MSB (high nybble), $F selects the exponent notation
+// 10     PRG-RAM (low nybble) and PRG-NVRAM (high nybble) sizes, 64 << n bytes, 0 for none
+// 11     CHR-RAM (low nybble) and CHR-NVRAM (high nybble) sizes, same encoding
+// 12     CPU/PPU timing: 0 NTSC, 1 PAL, 2 multiple regions, 3 Dendy
+// 13     VS. System PPU (low nybble) and hardware (high nybble) types, or the extended console type (low nybble)
+// 14     Number of miscellaneous ROMs (bits 0-1), stored after CHR-ROM
+pub struct Header {
+    pub prg_banks: u8, //PRG ROM size LSB
+    pub chr_banks: u8, //CHR ROM size LSB
+    pub flags_6: u8,
+    pub flags_7: u8,
+    pub flags_8: u8,
+    pub flags_9: u8,
+    pub flags_10: u8,
+    pub flags_11: u8,
+    pub flags_12: u8,
+    pub flags_13: u8,
+    pub flags_14: u8,
+}
+
+impl Header {
+    pub fn parse(data: &[u8]) -> Result<Self, CartridgeError> {
+        if data.len() < HEADER_SIZE || &data[0..4] != b"NES\x1A" {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        Ok(Self {
+            prg_banks: data[4],
+            chr_banks: data[5],
+            flags_6: data[6],
+            flags_7: data[7],
+            flags_8: data[8],
+            flags_9: data[9],
+            flags_10: data[10],
+            flags_11: data[11],
+            flags_12: data[12],
+            flags_13: data[13],
+            flags_14: data[14],
+        })
+    }
+
+    ///NES 2.0 headers have bits 2-3 of flags 7 set to 10
+    pub fn is_nes2(&self) -> bool {
+        return (self.flags_7 & 0x0C) == 0x08;
+    }
+
+    ///iNES mapper number (0 - 255), NES 2.0 extends it to 12 bits
+    pub fn mapper_id(&self) -> u16 {
+        let mapper = ((self.flags_7 & 0xF0) | (self.flags_6 >> 4)) as u16;
+
+        if !self.is_nes2() {
+            return mapper;
+        }
+
+        return ((self.flags_8 & 0x0F) as u16) << 8 | mapper;
+    }
+
+    ///Board variant of the mapper, always 0 for iNES headers
+    pub fn submapper(&self) -> u8 {
+        if !self.is_nes2() {
+            return 0;
+        }
+
+        return self.flags_8 >> 4;
+    }
+
+    pub fn prg_rom_size(&self) -> Result<usize, CartridgeError> {
+        return rom_size(self.prg_banks, self.flags_9 & 0x0F, PRG_BANK_SIZE, self.is_nes2());
+    }
+
+    pub fn chr_rom_size(&self) -> Result<usize, CartridgeError> {
+        return rom_size(self.chr_banks, self.flags_9 >> 4, CHR_BANK_SIZE, self.is_nes2());
+    }
+
+    ///Volatile PRG-RAM in bytes, iNES headers don't tell: the 8KB every supported board can map is assumed
+    pub fn prg_ram_size(&self) -> usize {
+        if !self.is_nes2() {
+            return PRG_RAM_SIZE;
+        }
+
+        return ram_size(self.flags_10 & 0x0F);
+    }
+
+    ///Battery backed PRG-RAM (or EEPROM) in bytes, for iNES headers the whole PRG-RAM keeps its contents with a battery
+    pub fn prg_nvram_size(&self) -> usize {
+        if !self.is_nes2() {
+            return 0;
+        }
+
+        return ram_size(self.flags_10 >> 4);
+    }
+
+    ///CHR-RAM in bytes, iNES boards without CHR-ROM have 8KB
+    pub fn chr_ram_size(&self) -> usize {
+        if !self.is_nes2() {
+            return if self.chr_banks == 0 { CHR_BANK_SIZE } else { 0 };
+        }
+
+        return ram_size(self.flags_11 & 0x0F);
+    }
+
+    pub fn chr_nvram_size(&self) -> usize {
+        if !self.is_nes2() {
+            return 0;
+        }
+
+        return ram_size(self.flags_11 >> 4);
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        if (self.flags_6 & 0x01) != 0 {
+            return Mirroring::Vertical;
+        } else {
+            return Mirroring::Horizontal;
+        }
+    }
+
+    ///The PRG-RAM is battery backed and keeps the game saves
+    pub fn has_battery(&self) -> bool {
+        return (self.flags_6 & 0x02) != 0;
+    }
+
+    pub fn has_trainer(&self) -> bool {
+        return (self.flags_6 & 0x04) != 0;
+    }
+
+    ///Bits 0-1 of flags 7, iNES headers only tell VS. System and PlayChoice-10 apart from the NES
+    pub fn console_type(&self) -> ConsoleType {
+        match self.flags_7 & 0x03 {
+            0 => return ConsoleType::Nes,
+            1 if self.is_nes2() => {
+                return ConsoleType::VsSystem {
+                    ppu: self.flags_13 & 0x0F,
+                    hardware: self.flags_13 >> 4,
+                };
+            }
+            1 => return ConsoleType::VsSystem { ppu: 0, hardware: 0 },
+            2 => return ConsoleType::PlayChoice10,
+            //iNES headers never set both bits, some dumps have junk there
+            _ if self.is_nes2() => return ConsoleType::Extended(self.flags_13 & 0x0F),
+            _ => return ConsoleType::Nes,
+        }
+    }
+
+    ///ROMs after CHR-ROM that belong to neither the PRG nor the CHR space, only NES 2.0 headers count them
+    pub fn misc_rom_count(&self) -> u8 {
+        if !self.is_nes2() {
+            return 0;
+        }
+
+        return self.flags_14 & 0x03;
+    }
+
+    ///Console timing the game was made for, None for iNES headers and games that run on every region
+    pub fn region(&self) -> Option<Region> {
+        if !self.is_nes2() {
+            return None;
+        }
+
+        match self.flags_12 & 0x03 {
+            0 => return Some(Region::Ntsc),
+            1 => return Some(Region::Pal),
+            3 => return Some(Region::Dendy),
+            _ => return None,
+        }
+    }
+}
+
+///ROM size from the LSB and (NES 2.0) MSB nybble, in units of bank_size<br>
+///An MSB of $F switches to the exponent notation: the LSB is EEEEEEMM and the size 2^E * (MM * 2 + 1) bytes,
+///sizes that don't fit in a usize are UnsupportedSize
+fn rom_size(lsb: u8, msb: u8, bank_size: usize, nes2: bool) -> Result<usize, CartridgeError> {
+    if !nes2 {
+        return Ok(lsb as usize * bank_size);
+    }
+
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+
+        return 1usize
+            .checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .ok_or(CartridgeError::UnsupportedSize);
+    }
+
+    return Ok(((msb as usize) << 8 | lsb as usize) * bank_size);
+}
+
+///NES 2.0 RAM size field: 64 << shift bytes, 0 means there is none
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        return 0;
+    }
+
+    return 64 << shift;
+}
+
+///Everything the header tells about a ROM, without loading it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CartridgeInfo {
+    pub nes2: bool,
+    pub mapper: u16,
+    pub submapper: u8,
+
+    //Sizes in bytes
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub region: Option<Region>, //None when the header doesn't say or the game runs on every region
+    pub console_type: ConsoleType,
+    pub misc_roms: u8, //Number of miscellaneous ROMs, see Emulator::misc_rom()
+}
+
+impl CartridgeInfo {
+    ///Reads the header of an iNES or NES 2.0 image
+    pub fn parse(data: &[u8]) -> Result<Self, CartridgeError> {
+        let header = Header::parse(data)?;
+
+        return Self::from_header(&header);
+    }
+
+    fn from_header(header: &Header) -> Result<Self, CartridgeError> {
+        Ok(Self {
+            nes2: header.is_nes2(),
+            mapper: header.mapper_id(),
+            submapper: header.submapper(),
+
+            prg_rom_size: header.prg_rom_size()?,
+            chr_rom_size: header.chr_rom_size()?,
+            prg_ram_size: header.prg_ram_size(),
+            prg_nvram_size: header.prg_nvram_size(),
+            chr_ram_size: header.chr_ram_size(),
+            chr_nvram_size: header.chr_nvram_size(),
+
+            mirroring: header.mirroring(),
+            battery: header.has_battery(),
+            trainer: header.has_trainer(),
+            region: header.region(),
+            console_type: header.console_type(),
+            misc_roms: header.misc_rom_count(),
+        })
+    }
+}
+
+///Mapper registers and cartridge RAM, as stored in save states<br>
+///The ROM itself isn't saved, only its size and mapper to check the state belongs to the same game
+#[derive(Serialize, Deserialize)]
+pub struct CartridgeState {
+    mapper_id: u16,
+    prg_size: usize,
+    mapper: Vec<u8>,
+    chr_ram: Vec<u8>, //Empty for boards with CHR-ROM
+    prg_ram: Vec<u8>,
+}
+
+pub struct Cartridge {
+    pub prg_memory: Vec<u8>,
+    pub chr_memory: Vec<u8>,
+    pub prg_ram: Vec<u8>, //Volatile and battery backed PRG-RAM, mirrored through $6000 - $7FFF
+    pub misc_rom: Vec<u8>, //Miscellaneous ROM area, kept for the devices that will read it (VS. System, ...)
+    pub playchoice: Option<PlayChoiceRoms>, //Instead of misc_rom on PlayChoice-10 images
+
+    pub mapper_id: u16,
+    pub chr_banks: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+
+    info: CartridgeInfo,
+    mapper: Box<dyn Mapper>,
+    crc32: u32, //PRG-ROM and CHR-ROM, what game databases identify dumps by
+}
+
+impl Cartridge {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, CartridgeError> {
+        let data = fs::read(path)?;
+
+        return Self::from_bytes(&data);
+    }
+
+    ///Parses an iNES or NES 2.0 image already loaded in memory
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CartridgeError> {
+        let header = Header::parse(data)?;
+        let info = CartridgeInfo::from_header(&header)?;
+
+        let prg_size = info.prg_rom_size;
+        let chr_size = info.chr_rom_size;
+
+        //The mappers count whole 16KB/8KB banks
+        let prg_banks = bank_count(prg_size, PRG_BANK_SIZE)?;
+        let chr_banks = bank_count(chr_size, CHR_BANK_SIZE)?;
+
+        let mapper = create_mapper(info.mapper, info.submapper, prg_banks, chr_banks)
+            .ok_or(CartridgeError::UnsupportedMapper(info.mapper))?;
+
+        //The 512 byte trainer (if present) sits between the header and PRG-ROM and is not used
+        let mut offset = HEADER_SIZE;
+        if info.trainer {
+            offset += TRAINER_SIZE;
+        }
+
+        if data.len() < offset + prg_size + chr_size {
+            return Err(CartridgeError::Truncated);
+        }
+
+        let crc32 = crc32(&data[offset..offset + prg_size + chr_size]);
+
+        let prg_memory = data[offset..offset + prg_size].to_vec();
+        offset += prg_size;
+
+        //Boards without CHR-ROM have CHR-RAM instead, 8KB when the header doesn't give a size
+        let chr_memory = if chr_banks == 0 {
+            let chr_ram_size = info.chr_ram_size + info.chr_nvram_size;
+            vec![0; if chr_ram_size == 0 { CHR_BANK_SIZE } else { chr_ram_size }]
+        } else {
+            data[offset..offset + chr_size].to_vec()
+        };
+        offset += chr_size;
+
+        //The miscellaneous ROM area runs to the end of the file. On PlayChoice-10 images it holds the INST-ROM and
+        //PROM, which only drive the arcade's instruction screen: the game runs without them, but they are kept apart
+        //(even when cut short, iNES dumps don't count them as misc ROMs)
+        let rest = &data[offset..];
+        let playchoice = if info.console_type == ConsoleType::PlayChoice10 && !rest.is_empty() {
+            let inst_rom_size = rest.len().min(INST_ROM_SIZE);
+            let prom_size = (rest.len() - inst_rom_size).min(PROM_SIZE);
+
+            Some(PlayChoiceRoms {
+                inst_rom: rest[..inst_rom_size].to_vec(),
+                prom: rest[inst_rom_size..inst_rom_size + prom_size].to_vec(),
+            })
+        } else {
+            None
+        };
+
+        let misc_rom = if info.misc_roms != 0 && info.console_type != ConsoleType::PlayChoice10 {
+            rest.to_vec()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            prg_memory,
+            chr_memory,
+            prg_ram: if mapper.has_prg_ram() {
+                vec![0; info.prg_ram_size + info.prg_nvram_size]
+            } else {
+                Vec::new()
+            },
+            misc_rom,
+            playchoice,
+
+            mapper_id: info.mapper,
+            chr_banks,
+            mirroring: info.mirroring,
+            battery: info.battery,
+
+            info,
+            mapper,
+            crc32,
+        })
+    }
+
+    ///What the header says about the game
+    pub fn info(&self) -> &CartridgeInfo {
+        return &self.info;
+    }
+
+    ///CRC-32 of the PRG-ROM and CHR-ROM, the same for every header and trainer a dump is found with
+    pub fn rom_crc32(&self) -> u32 {
+        return self.crc32;
+    }
+
+    //CPU Bus ($4020 - $FFFF)
+
+    ///Returns None when the address isn't handled by the cartridge
+    pub fn cpu_read(&self, address: u16) -> Option<u8> {
+        if let Some(offset) = self.prg_ram_offset(address) {
+            return Some(self.prg_ram[offset]);
+        }
+
+        if let Some(data) = self.mapper.read_register(address) {
+            return Some(data);
+        }
+
+        let offset = self.mapper.cpu_map_read(address)?;
+
+        return self.prg_memory.get(offset).copied();
+    }
+
+    ///Returns false when the address isn't handled by the cartridge<br>
+    ///Everything from $8000 belongs to the cartridge, even when the board ignores the write<br>
+    ///Boards that decode registers inside the PRG-RAM (NINA-001 at $7FFD - $7FFF) see the RAM writes too
+    pub fn cpu_write(&mut self, address: u16, data: u8) -> bool {
+        if let Some(offset) = self.prg_ram_offset(address) {
+            self.prg_ram[offset] = data;
+            self.mapper.cpu_map_write(address, data);
+            return true;
+        }
+
+        //Bus conflicts: the ROM byte at the address fights the written value, 0 wins
+        let data = match self.mapper.cpu_map_read(address) {
+            Some(offset) if self.mapper.bus_conflicts() => data & self.prg_memory.get(offset).copied().unwrap_or(0xFF),
+            _ => data,
+        };
+
+        if let Some(offset) = self.mapper.cpu_map_write(address, data) {
+            if let Some(byte) = self.prg_memory.get_mut(offset) {
+                *byte = data;
+            }
+
+            return true;
+        }
+
+        return address >= 0x8000;
+    }
+
+    ///Patches the PRG byte currently mapped at the address (ROM included), without reaching the mapper registers<br>
+    ///Returns false when nothing is mapped there
+    pub fn poke(&mut self, address: u16, data: u8) -> bool {
+        if let Some(offset) = self.prg_ram_offset(address) {
+            self.prg_ram[offset] = data;
+            return true;
+        }
+
+        let Some(offset) = self.mapper.cpu_map_read(address) else {
+            return false;
+        };
+
+        if let Some(byte) = self.prg_memory.get_mut(offset) {
+            *byte = data;
+            return true;
+        }
+
+        return false;
+    }
+
+    ///The feature a CPU write asks for when the board doesn't emulate it, see Mapper::unsupported_write()
+    pub fn unsupported_write(&self, address: u16, data: u8) -> Option<&'static str> {
+        return self.mapper.unsupported_write(address, data);
+    }
+
+    //PPU Bus ($0000 - $1FFF)
+
+    pub fn ppu_read(&self, address: u16) -> Option<u8> {
+        let offset = self.mapper.ppu_map_read(address)?;
+
+        return self.chr_memory.get(offset).copied();
+    }
+
+    ///Returns true when the address belongs to the pattern tables, only CHR-RAM is actually written
+    pub fn ppu_write(&mut self, address: u16, data: u8) -> bool {
+        if let Some(offset) = self.mapper.ppu_map_write(address) {
+            if let Some(byte) = self.chr_memory.get_mut(offset) {
+                *byte = data;
+            }
+        }
+
+        return address <= 0x1FFF;
+    }
+
+    pub fn reset(&mut self) {
+        self.mapper.reset();
+    }
+
+    ///One CPU cycle went by
+    pub fn cpu_clock(&mut self) {
+        self.mapper.cpu_clock();
+    }
+
+    ///Forwards an address seen on the PPU bus to the board
+    pub fn ppu_address(&mut self, address: u16) {
+        self.mapper.ppu_address(address);
+    }
+
+    ///The board's IRQ line (MMC3 scanline counter, ...)
+    pub fn irq(&self) -> bool {
+        return self.mapper.irq();
+    }
+
+    ///The board's current mirroring, falls back to the header for hard-wired boards
+    pub fn get_mirroring(&self) -> Mirroring {
+        return self.mapper.mirroring().unwrap_or(self.mirroring);
+    }
+
+    //Memory Map
+
+    ///$6000 - $FFFF as the board maps it right now, PRG-ROM split into the board's current banks
+    pub fn prg_memory_map(&self) -> Vec<MemoryRegion> {
+        let work_ram = if let Some(offset) = self.prg_ram_offset(0x6000) {
+            //Boards with more than 8KB show the bank, the battery only covers the NVRAM banks
+            MemoryRegion {
+                bank: (self.prg_ram.len() > PRG_RAM_SIZE).then_some(offset / PRG_RAM_SIZE),
+                battery: self.battery && self.mapper.save_memory().is_none() && self.nvram_range().contains(&offset),
+                ..MemoryRegion::new(0x6000, 0x7FFF, "PRG-RAM", MemoryKind::Ram)
+            }
+        } else if !self.mapper.has_prg_ram() {
+            //The board's own registers (and EEPROM, kept in the .sav file) live there instead
+            MemoryRegion {
+                battery: self.mapper.save_memory().is_some(),
+                ..MemoryRegion::new(0x6000, 0x7FFF, "Board registers", MemoryKind::Registers)
+            }
+        } else {
+            MemoryRegion::new(0x6000, 0x7FFF, "Open bus", MemoryKind::OpenBus)
+        };
+
+        let mut map = vec![work_ram];
+        map.extend(bank_regions(0x8000, 0xFFFF, self.mapper.prg_bank_size(), "PRG-ROM", MemoryKind::Rom, |address| {
+            self.mapper.cpu_map_read(address)
+        }));
+
+        return map;
+    }
+
+    ///The pattern tables ($0000 - $1FFF) split into the board's current CHR banks
+    pub fn chr_memory_map(&self) -> Vec<MemoryRegion> {
+        let (name, kind) = if self.chr_banks == 0 { ("CHR-RAM", MemoryKind::Ram) } else { ("CHR-ROM", MemoryKind::Rom) };
+
+        let mut map = bank_regions(0x0000, 0x1FFF, self.mapper.chr_bank_size(), name, kind, |address| {
+            self.mapper.ppu_map_read(address)
+        });
+
+        for region in &mut map {
+            region.battery = kind == MemoryKind::Ram && self.info.chr_nvram_size != 0;
+        }
+
+        return map;
+    }
+
+    ///Offset in PRG-RAM for CPU addresses in $6000 - $7FFF, RAM smaller than 8KB is mirrored and RAM larger than
+    ///8KB is switched in 8KB banks by the mapper<br>
+    ///None for boards without PRG-RAM
+    fn prg_ram_offset(&self, address: u16) -> Option<usize> {
+        if self.prg_ram.is_empty() {
+            return None;
+        }
+
+        let banks = self.prg_ram.len().div_ceil(PRG_RAM_SIZE);
+        let bank = self.mapper.prg_ram_bank(banks) % banks;
+
+        match address {
+            0x6000..=0x7FFF => Some((bank * PRG_RAM_SIZE + (address - 0x6000) as usize) % self.prg_ram.len()),
+            _ => None,
+        }
+    }
+
+    ///Part of the PRG-RAM kept in the .sav file: the PRG-NVRAM after the volatile PRG-RAM when the NES 2.0 header
+    ///splits them (SOROM's second 8KB), all of it for iNES headers
+    fn nvram_range(&self) -> Range<usize> {
+        if self.info.prg_nvram_size == 0 {
+            return 0..self.prg_ram.len();
+        }
+
+        return self.info.prg_ram_size.min(self.prg_ram.len())..self.prg_ram.len();
+    }
+
+    //Battery RAM
+
+    ///The PRG-RAM (or the board's EEPROM) to write to the .sav file, None when the board has no battery
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        if let Some(memory) = self.mapper.save_memory() {
+            return Some(memory);
+        }
+
+        if !self.battery {
+            return None;
+        }
+
+        return Some(&self.prg_ram[self.nvram_range()]);
+    }
+
+    ///Restores a .sav file, returns false (and changes nothing) when the board has no battery or the size doesn't match
+    pub fn load_battery_ram(&mut self, data: &[u8]) -> bool {
+        if self.mapper.save_memory().is_some() {
+            return self.mapper.load_save_memory(data);
+        }
+
+        let range = self.nvram_range();
+
+        if !self.battery || data.len() != range.len() {
+            return false;
+        }
+
+        self.prg_ram[range].copy_from_slice(data);
+
+        return true;
+    }
+
+    //Save States
+
+    pub fn save_state(&self) -> CartridgeState {
+        CartridgeState {
+            mapper_id: self.mapper_id,
+            prg_size: self.prg_memory.len(),
+            mapper: self.mapper.save_state(),
+            chr_ram: if self.chr_banks == 0 { self.chr_memory.clone() } else { Vec::new() },
+            prg_ram: self.prg_ram.clone(),
+        }
+    }
+
+    ///Returns false (and changes nothing) when the state was saved with a different game
+    pub fn load_state(&mut self, state: &CartridgeState) -> bool {
+        if state.mapper_id != self.mapper_id || state.prg_size != self.prg_memory.len() {
+            return false;
+        }
+
+        self.mapper.load_state(&state.mapper);
+
+        if self.chr_banks == 0 && state.chr_ram.len() == self.chr_memory.len() {
+            self.chr_memory.copy_from_slice(&state.chr_ram);
+        }
+
+        if state.prg_ram.len() == self.prg_ram.len() {
+            self.prg_ram.copy_from_slice(&state.prg_ram);
+        }
+
+        return true;
+    }
+}
+
+///Whole 16KB/8KB banks in a ROM size, the mappers take at most 255
+fn bank_count(size: usize, bank_size: usize) -> Result<u8, CartridgeError> {
+    if !size.is_multiple_of(bank_size) {
+        return Err(CartridgeError::UnsupportedSize);
+    }
+
+    return u8::try_from(size / bank_size).map_err(|_| CartridgeError::UnsupportedSize);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(bytes: [u8; 12]) -> Vec<u8> {
+        let mut data = b"NES\x1A".to_vec();
+        data.extend(bytes);
+
+        return data;
+    }
+
+    #[test]
+    fn ines_headers_keep_the_classic_meaning() {
+        //Mapper 4, 128KB PRG, no CHR-ROM, battery, junk in the padding bytes
+        let info = CartridgeInfo::parse(&header([8, 0, 0x42, 0x00, 0x0F, 0xFF, 0xFF, 0xFF, 0x01, 0, 0, 0])).unwrap();
+
+        assert!(!info.nes2);
+        assert_eq!(info.mapper, 4);
+        assert_eq!(info.submapper, 0);
+        assert_eq!(info.prg_rom_size, 128 * 1024);
+        assert_eq!(info.chr_rom_size, 0);
+        assert_eq!(info.prg_ram_size, PRG_RAM_SIZE);
+        assert_eq!(info.chr_ram_size, CHR_BANK_SIZE);
+        assert!(info.battery);
+        assert_eq!(info.region, None);
+    }
+
+    #[test]
+    fn nes2_headers_extend_the_mapper_and_sizes() {
+        //Mapper $1A4 submapper 3, PRG MSB 1 (256 + 2 banks), CHR 16KB, 8KB PRG-RAM + 32KB NVRAM, 8KB CHR-RAM, PAL
+        let info = CartridgeInfo::parse(&header([2, 2, 0x40, 0xA8, 0x31, 0x01, 0x97, 0x07, 0x01, 0, 0, 0])).unwrap();
+
+        assert!(info.nes2);
+        assert_eq!(info.mapper, 0x1A4);
+        assert_eq!(info.submapper, 3);
+        assert_eq!(info.prg_rom_size, 258 * PRG_BANK_SIZE);
+        assert_eq!(info.chr_rom_size, 2 * CHR_BANK_SIZE);
+        assert_eq!(info.prg_ram_size, 8 * 1024);
+        assert_eq!(info.prg_nvram_size, 32 * 1024);
+        assert_eq!(info.chr_ram_size, 8 * 1024);
+        assert_eq!(info.chr_nvram_size, 0);
+        assert_eq!(info.region, Some(Region::Pal));
+    }
+
+    #[test]
+    fn nes2_exponent_notation() {
+        //PRG: 2^15 * 3 = 96KB, CHR: 2^13 * 1 = 8KB
+        let info = CartridgeInfo::parse(&header([(15 << 2) | 1, 13 << 2, 0x00, 0x08, 0, 0xFF, 0, 0, 0, 0, 0, 0])).unwrap();
+
+        assert_eq!(info.prg_rom_size, 96 * 1024);
+        assert_eq!(info.chr_rom_size, 8 * 1024);
+    }
+
+    #[test]
+    fn oversized_exponent_notation_is_rejected() {
+        //PRG: 2^63 * 7 bytes
+        let data = header([(63 << 2) | 3, 0, 0x00, 0x08, 0, 0x0F, 0, 0, 0, 0, 0, 0]);
+
+        assert!(matches!(CartridgeInfo::parse(&data), Err(CartridgeError::UnsupportedSize)));
+        assert!(matches!(Cartridge::from_bytes(&data), Err(CartridgeError::UnsupportedSize)));
+    }
+
+    #[test]
+    fn misc_rom_area_is_kept_and_playchoice_inst_rom_skipped() {
+        //NES 2.0 VS. System (RP2C04-0002 PPU, Dual System), NROM-128 with CHR-RAM and one misc ROM
+        let mut data = header([1, 0, 0x00, 0x09, 0, 0, 0, 0x07, 0, 0x62, 0x01, 0]);
+        data.resize(HEADER_SIZE + PRG_BANK_SIZE, 0);
+        data.extend([0xDE, 0xAD, 0xBE, 0xEF]);
+
+        let cartridge = Cartridge::from_bytes(&data).unwrap();
+        assert_eq!(cartridge.info().console_type, ConsoleType::VsSystem { ppu: 2, hardware: 6 });
+        assert_eq!(cartridge.info().misc_roms, 1);
+        assert_eq!(cartridge.misc_rom, [0xDE, 0xAD, 0xBE, 0xEF]);
+
+        //The same image as PlayChoice-10, then as an iNES PlayChoice-10 dump with a cut short INST-ROM
+        data[7] = 0x0A;
+        let cartridge = Cartridge::from_bytes(&data).unwrap();
+        assert_eq!(cartridge.info().console_type, ConsoleType::PlayChoice10);
+        assert!(cartridge.misc_rom.is_empty());
+        assert_eq!(cartridge.playchoice.unwrap().inst_rom, [0xDE, 0xAD, 0xBE, 0xEF]);
+
+        data[7] = 0x02;
+        let cartridge = Cartridge::from_bytes(&data).unwrap();
+        assert_eq!(cartridge.info().console_type, ConsoleType::PlayChoice10);
+        assert_eq!(cartridge.info().misc_roms, 0);
+        assert!(cartridge.misc_rom.is_empty());
+
+        let playchoice = cartridge.playchoice.unwrap();
+        assert_eq!(playchoice.inst_rom, [0xDE, 0xAD, 0xBE, 0xEF]);
+        assert!(playchoice.prom.is_empty());
+
+        //Extended console types only exist in NES 2.0
+        data[7] = 0x0B;
+        data[13] = 0x03;
+        assert_eq!(CartridgeInfo::parse(&data).unwrap().console_type, ConsoleType::Extended(3));
+    }
+
+    #[test]
+    fn playchoice_inst_rom_and_prom_are_split() {
+        //iNES PlayChoice-10 NROM-128 with CHR-RAM, a full INST-ROM and PROM
+        let mut data = header([1, 0, 0x00, 0x02, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.resize(HEADER_SIZE + PRG_BANK_SIZE, 0);
+        data.extend(std::iter::repeat_n(0x11, INST_ROM_SIZE));
+        data.extend(std::iter::repeat_n(0x22, PROM_SIZE));
+
+        let cartridge = Cartridge::from_bytes(&data).unwrap();
+        assert_eq!(cartridge.info().console_type, ConsoleType::PlayChoice10);
+
+        let playchoice = cartridge.playchoice.unwrap();
+        assert_eq!(playchoice.inst_rom, [0x11; INST_ROM_SIZE]);
+        assert_eq!(playchoice.prom, [0x22; PROM_SIZE]);
+
+        //An NES image has none
+        data[7] = 0x00;
+        assert_eq!(Cartridge::from_bytes(&data).unwrap().playchoice, None);
+    }
+
+    #[test]
+    fn rom_crc_leaves_out_the_header_and_trainer() {
+        //NROM-128 with CHR-RAM, then the same ROM as NES 2.0 with a trainer in front
+        let mut data = header([1, 0, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend((0..PRG_BANK_SIZE).map(|index| index as u8));
+
+        let crc = crc32(&data[HEADER_SIZE..]);
+        assert_eq!(Cartridge::from_bytes(&data).unwrap().rom_crc32(), crc);
+
+        let mut trained = header([1, 0, 0x04, 0x08, 0, 0, 0x07, 0x07, 0, 0, 0, 0]);
+        trained.extend([0xEA; TRAINER_SIZE]);
+        trained.extend_from_slice(&data[HEADER_SIZE..]);
+        assert_eq!(Cartridge::from_bytes(&trained).unwrap().rom_crc32(), crc);
+    }
+
+    #[test]
+    fn small_prg_ram_is_mirrored_and_missing_prg_ram_is_open_bus() {
+        //NROM-128 with CHR-RAM, NES 2.0: 2KB PRG-RAM
+        let mut data = header([1, 0, 0x00, 0x08, 0, 0, 0x05, 0x07, 0, 0, 0, 0]);
+        data.resize(HEADER_SIZE + PRG_BANK_SIZE, 0);
+
+        let mut cartridge = Cartridge::from_bytes(&data).unwrap();
+        assert!(cartridge.cpu_write(0x6001, 0x42));
+        assert_eq!(cartridge.cpu_read(0x6801), Some(0x42));
+
+        //No PRG-RAM at all
+        data[10] = 0x00;
+        let cartridge = Cartridge::from_bytes(&data).unwrap();
+        assert_eq!(cartridge.cpu_read(0x6000), None);
+    }
+
+    #[test]
+    fn banked_prg_ram_saves_only_the_nvram() {
+        //SOROM: MMC1, 32KB PRG, CHR-RAM, NES 2.0 8KB PRG-RAM + 8KB battery backed PRG-NVRAM
+        let mut data = header([2, 0, 0x12, 0x08, 0, 0, 0x77, 0x07, 0, 0, 0, 0]);
+        data.resize(HEADER_SIZE + 2 * PRG_BANK_SIZE, 0);
+
+        let mut cartridge = Cartridge::from_bytes(&data).unwrap();
+        let select_bank = |cartridge: &mut Cartridge, bank: u8| {
+            for bit in 0..5 {
+                cartridge.cpu_write(0xA000, ((bank << 3) >> bit) & 0x01);
+            }
+        };
+
+        cartridge.cpu_write(0x6000, 0x11);
+        select_bank(&mut cartridge, 1);
+        cartridge.cpu_write(0x6000, 0x22);
+        assert_eq!(cartridge.prg_memory_map()[0].to_string(), "$6000-$7FFF PRG-RAM bank 1 (battery)");
+
+        select_bank(&mut cartridge, 0);
+        assert_eq!(cartridge.cpu_read(0x6000), Some(0x11));
+        assert_eq!(cartridge.prg_memory_map()[0].to_string(), "$6000-$7FFF PRG-RAM bank 0");
+
+        let save = cartridge.battery_ram().unwrap().to_vec();
+        assert_eq!(save.len(), PRG_RAM_SIZE);
+        assert_eq!(save[0], 0x22);
+
+        assert!(!cartridge.load_battery_ram(&[0; 2 * PRG_RAM_SIZE]));
+        assert!(cartridge.load_battery_ram(&[0x33; PRG_RAM_SIZE]));
+        assert_eq!(cartridge.cpu_read(0x6000), Some(0x11));
+        select_bank(&mut cartridge, 1);
+        assert_eq!(cartridge.cpu_read(0x6000), Some(0x33));
+    }
+
+    #[test]
+    fn memory_map_follows_the_banks() {
+        //UxROM, 64KB PRG, CHR-RAM, battery
+        let mut data = header([4, 0, 0x22, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.resize(HEADER_SIZE + 4 * PRG_BANK_SIZE, 0);
+
+        let mut cartridge = Cartridge::from_bytes(&data).unwrap();
+        cartridge.cpu_write(0x8000, 2);
+
+        let map = cartridge.prg_memory_map();
+        let labels: Vec<String> = map.iter().map(|region| region.to_string()).collect();
+
+        assert_eq!(
+            labels,
+            ["$6000-$7FFF PRG-RAM (battery)", "$8000-$BFFF PRG-ROM bank 2", "$C000-$FFFF PRG-ROM bank 3"]
+        );
+        assert_eq!(map[1].kind, MemoryKind::Rom);
+
+        let chr = cartridge.chr_memory_map();
+        assert_eq!(chr.len(), 1);
+        assert_eq!((chr[0].name, chr[0].kind, chr[0].bank), ("CHR-RAM", MemoryKind::Ram, Some(0)));
+    }
+}
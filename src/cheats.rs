@@ -0,0 +1,527 @@
+use std::{fmt, str::FromStr};
+
+///Game Genie letters in the order of the values they stand for (0 - 15)
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+///Marks a disabled code in cheat files
+const DISABLED_PREFIX: char = '!';
+
+///Cheat file layouts read by CheatList::load_text() and written by CheatList::to_text()
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CheatFormat {
+    ///One code per line followed by its description, disabled codes start with !: `!GOSSIP Infinite lives`
+    Rnes,
+    ///FCEUX .cht: `[S][C][:]AAAA:VV[:CC]:Description` in hex, S patches reads (the only kind here), C adds the
+    ///compare byte and : marks a disabled code. Game Genie codes are written decoded
+    Fceux,
+    ///Nestopia XML: `<cheat enabled="1">` with a `<genie>` code or `<address>`/`<value>`/`<compare>`, and a
+    ///`<description>`
+    Nestopia,
+}
+
+impl CheatFormat {
+    ///Tells the layouts apart: Nestopia files are XML, FCEUX lines have 2 or 3 colons before the description
+    pub fn detect(text: &str) -> CheatFormat {
+        if text.trim_start().starts_with('<') {
+            return CheatFormat::Nestopia;
+        }
+
+        let fceux = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .any(|line| line.split_whitespace().next().is_some_and(|code| code.matches(':').count() >= 2));
+
+        if fceux {
+            return CheatFormat::Fceux;
+        }
+
+        return CheatFormat::Rnes;
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CheatError {
+    InvalidLength(usize), //Game Genie codes have 6 or 8 letters
+    InvalidLetter(char),
+    InvalidFormat(String), //Neither a Game Genie code nor AAAA:VV / AAAA?CC:VV
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::InvalidLength(length) => write!(f, "Game Genie codes have 6 or 8 letters, not {}", length),
+            CheatError::InvalidLetter(letter) => write!(f, "{} is not a Game Genie letter", letter),
+            CheatError::InvalidFormat(code) => {
+                write!(f, "{} is not a cheat code (Game Genie letters, AAAA:VV or AAAA?CC:VV)", code)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+///What a code does: CPU reads of the address return the value, if the compare byte is set only while the
+///original byte matches it (8 letter Game Genie codes, so the patch only hits the right PRG bank)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CheatCode {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl CheatCode {
+    ///The byte a read of the address returns with the code applied, None if the code doesn't apply
+    pub fn apply(&self, address: u16, data: u8) -> Option<u8> {
+        if address != self.address || self.compare.is_some_and(|compare| compare != data) {
+            return None;
+        }
+
+        return Some(self.value);
+    }
+
+    ///6 or 8 letters, every letter carries 4 bits scattered over the address, value and compare byte
+    fn from_game_genie(code: &str) -> Result<Self, CheatError> {
+        let letters = code
+            .chars()
+            .map(|letter| {
+                GAME_GENIE_LETTERS
+                    .find(letter.to_ascii_uppercase())
+                    .map(|value| value as u16)
+                    .ok_or(CheatError::InvalidLetter(letter))
+            })
+            .collect::<Result<Vec<u16>, CheatError>>()?;
+
+        if letters.len() != 6 && letters.len() != 8 {
+            return Err(CheatError::InvalidLength(letters.len()));
+        }
+
+        let n = |index: usize| letters[index];
+
+        let address = 0x8000
+            | ((n(3) & 7) << 12)
+            | ((n(5) & 7) << 8)
+            | ((n(4) & 8) << 8)
+            | ((n(2) & 7) << 4)
+            | ((n(1) & 8) << 4)
+            | (n(4) & 7)
+            | (n(3) & 8);
+
+        //The last letter's high bit goes to the value for 6 letter codes and to the compare byte for 8 letter ones
+        let (value_high, compare) = if letters.len() == 6 {
+            (n(5) & 8, None)
+        } else {
+            let compare = ((n(7) & 7) << 4) | ((n(6) & 8) << 4) | (n(6) & 7) | (n(5) & 8);
+            (n(7) & 8, Some(compare as u8))
+        };
+
+        let value = ((n(1) & 7) << 4) | ((n(0) & 8) << 4) | (n(0) & 7) | value_high;
+
+        Ok(Self {
+            address,
+            value: value as u8,
+            compare,
+        })
+    }
+
+    ///Pro Action Replay style raw codes: AAAA:VV, or AAAA?CC:VV with a compare byte, in hex
+    fn from_raw(code: &str) -> Option<Self> {
+        let (target, value) = code.split_once(':')?;
+
+        let (address, compare) = match target.split_once('?') {
+            Some((address, compare)) => (address, Some(u8::from_str_radix(compare, 16).ok()?)),
+            None => (target, None),
+        };
+
+        Some(Self {
+            address: u16::from_str_radix(address.trim_start_matches('$'), 16).ok()?,
+            value: u8::from_str_radix(value, 16).ok()?,
+            compare,
+        })
+    }
+}
+
+impl FromStr for CheatCode {
+    type Err = CheatError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let code = code.trim();
+
+        if code.contains(':') {
+            return Self::from_raw(code).ok_or_else(|| CheatError::InvalidFormat(code.to_string()));
+        }
+
+        return Self::from_game_genie(code);
+    }
+}
+
+///Identifier returned when a code is added, used to remove or toggle it later
+pub type CheatId = usize;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cheat {
+    pub id: CheatId,
+    pub code: String, //As it was entered
+    pub cheat: CheatCode,
+    pub enabled: bool,
+    pub description: String, //From the cheat file, empty for codes added by hand
+}
+
+///The codes of the loaded game, in the order they were added
+#[derive(Clone, Default, Debug)]
+pub struct CheatList {
+    cheats: Vec<Cheat>,
+    next_id: CheatId,
+}
+
+impl CheatList {
+    //Constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Decodes and adds an enabled code
+    pub fn add(&mut self, code: &str) -> Result<CheatId, CheatError> {
+        let cheat = code.parse()?;
+
+        return Ok(self.push(code.trim().to_string(), cheat, true, String::new()));
+    }
+
+    fn push(&mut self, code: String, cheat: CheatCode, enabled: bool, description: String) -> CheatId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.cheats.push(Cheat {
+            id,
+            code,
+            cheat,
+            enabled,
+            description,
+        });
+
+        return id;
+    }
+
+    ///Returns false if the id is unknown
+    pub fn remove(&mut self, id: CheatId) -> bool {
+        let count = self.cheats.len();
+
+        self.cheats.retain(|cheat| cheat.id != id);
+
+        return self.cheats.len() != count;
+    }
+
+    ///Returns false if the id is unknown
+    pub fn set_enabled(&mut self, id: CheatId, enabled: bool) -> bool {
+        let Some(cheat) = self.cheats.iter_mut().find(|cheat| cheat.id == id) else {
+            return false;
+        };
+
+        cheat.enabled = enabled;
+
+        return true;
+    }
+
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        return &self.cheats;
+    }
+
+    ///The enabled codes, what the BUS applies
+    pub fn active(&self) -> Vec<CheatCode> {
+        return self.cheats.iter().filter(|cheat| cheat.enabled).map(|cheat| cheat.cheat).collect();
+    }
+
+    ///The codes as a cheat file in the format
+    pub fn to_text(&self, format: CheatFormat) -> String {
+        let mut text = String::new();
+
+        if format == CheatFormat::Nestopia {
+            text.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<cheats version=\"1.0\">\n");
+        }
+
+        for cheat in &self.cheats {
+            match format {
+                CheatFormat::Rnes => {
+                    if !cheat.enabled {
+                        text.push(DISABLED_PREFIX);
+                    }
+
+                    text.push_str(&cheat.code);
+
+                    if !cheat.description.is_empty() {
+                        text.push(' ');
+                        text.push_str(&cheat.description);
+                    }
+                }
+                CheatFormat::Fceux => {
+                    let CheatCode { address, value, compare } = cheat.cheat;
+
+                    text.push('S');
+                    text.push_str(if compare.is_some() { "C" } else { "" });
+                    text.push_str(if cheat.enabled { "" } else { ":" });
+                    text.push_str(&format!("{:04x}:{:02x}:", address, value));
+
+                    if let Some(compare) = compare {
+                        text.push_str(&format!("{:02x}:", compare));
+                    }
+
+                    text.push_str(&cheat.description);
+                }
+                CheatFormat::Nestopia => {
+                    text.push_str(&format!("  <cheat enabled=\"{}\">\n", cheat.enabled as u8));
+
+                    //Game Genie codes are kept as they were entered, raw codes are written field by field
+                    if !cheat.code.contains(':') {
+                        text.push_str(&format!("    <genie>{}</genie>\n", cheat.code.to_ascii_uppercase()));
+                    } else {
+                        let CheatCode { address, value, compare } = cheat.cheat;
+
+                        text.push_str(&format!("    <address>0x{:04X}</address>\n", address));
+                        text.push_str(&format!("    <value>0x{:02X}</value>\n", value));
+
+                        if let Some(compare) = compare {
+                            text.push_str(&format!("    <compare>0x{:02X}</compare>\n", compare));
+                        }
+                    }
+
+                    text.push_str(&format!("    <description>{}</description>\n", escape_xml(&cheat.description)));
+                    text.push_str("  </cheat>");
+                }
+            }
+
+            text.push('\n');
+        }
+
+        if format == CheatFormat::Nestopia {
+            text.push_str("</cheats>\n");
+        }
+
+        return text;
+    }
+
+    ///Adds the codes of a cheat file in any CheatFormat (see CheatFormat::detect()), blank lines and lines starting
+    ///with # are skipped<br>
+    ///Nothing is added if a code is invalid
+    pub fn load_text(&mut self, text: &str) -> Result<(), CheatError> {
+        let mut loaded = self.clone();
+
+        match CheatFormat::detect(text) {
+            CheatFormat::Nestopia => loaded.load_nestopia(text)?,
+            format => {
+                for line in text.lines().map(str::trim) {
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+
+                    if format == CheatFormat::Fceux {
+                        loaded.load_fceux_line(line)?;
+                        continue;
+                    }
+
+                    let (line, enabled) = match line.strip_prefix(DISABLED_PREFIX) {
+                        Some(line) => (line, false),
+                        None => (line, true),
+                    };
+
+                    let (code, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+                    loaded.push(code.to_string(), code.parse()?, enabled, description.trim().to_string());
+                }
+            }
+        }
+
+        *self = loaded;
+
+        Ok(())
+    }
+
+    ///`[S][C][:]AAAA:VV[:CC]:Description`, kept as an AAAA?CC:VV raw code
+    fn load_fceux_line(&mut self, line: &str) -> Result<(), CheatError> {
+        let invalid = || CheatError::InvalidFormat(line.to_string());
+
+        let fields = line.strip_prefix('S').unwrap_or(line);
+        let (fields, has_compare) = match fields.strip_prefix('C') {
+            Some(fields) => (fields, true),
+            None => (fields, false),
+        };
+        let (fields, enabled) = match fields.strip_prefix(':') {
+            Some(fields) => (fields, false),
+            None => (fields, true),
+        };
+
+        let count = if has_compare { 4 } else { 3 };
+        let fields: Vec<&str> = fields.splitn(count, ':').collect();
+        if fields.len() != count {
+            return Err(invalid());
+        }
+
+        let code = match has_compare {
+            true => format!("{}?{}:{}", fields[0], fields[2], fields[1]),
+            false => format!("{}:{}", fields[0], fields[1]),
+        };
+        let cheat = code.parse().map_err(|_| invalid())?;
+
+        self.push(code.to_ascii_uppercase(), cheat, enabled, fields[count - 1].trim().to_string());
+
+        Ok(())
+    }
+
+    ///Every `<cheat>` element, Pro Action Rocky codes aren't supported
+    fn load_nestopia(&mut self, text: &str) -> Result<(), CheatError> {
+        for element in text.split("</cheat>") {
+            let Some(start) = element.rfind("<cheat ").or_else(|| element.rfind("<cheat>")) else {
+                continue;
+            };
+            let element = &element[start..];
+
+            let enabled = !element.split('>').next().unwrap_or("").contains("enabled=\"0\"");
+            let description = unescape_xml(xml_tag(element, "description").unwrap_or(""));
+
+            let (code, cheat) = if let Some(genie) = xml_tag(element, "genie") {
+                (genie.to_string(), genie.parse()?)
+            } else {
+                let hex = |name: &str| -> Result<Option<String>, CheatError> {
+                    match xml_tag(element, name) {
+                        Some(value) => {
+                            let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+                            return Ok(Some(digits.to_ascii_uppercase()));
+                        }
+                        None if name == "compare" => return Ok(None),
+                        None => return Err(CheatError::InvalidFormat(element.trim().to_string())),
+                    }
+                };
+
+                let (address, value) = (hex("address")?.unwrap_or_default(), hex("value")?.unwrap_or_default());
+
+                let code = match hex("compare")? {
+                    Some(compare) => format!("{}?{}:{}", address, compare, value),
+                    None => format!("{}:{}", address, value),
+                };
+
+                let cheat = code.parse()?;
+                (code, cheat)
+            };
+
+            self.push(code, cheat, enabled, description);
+        }
+
+        Ok(())
+    }
+}
+
+///Text of the first `<name>...</name>` element
+fn xml_tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+
+    return Some(xml[start..end].trim());
+}
+
+fn escape_xml(text: &str) -> String {
+    return text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+}
+
+fn unescape_xml(text: &str) -> String {
+    return text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(address: u16, value: u8, compare: Option<u8>) -> CheatCode {
+        return CheatCode { address, value, compare };
+    }
+
+    #[test]
+    fn game_genie_codes_decode() {
+        assert_eq!("GOSSIP".parse(), Ok(code(0xD1DD, 0x14, None)));
+        assert_eq!("zexpygla".parse(), Ok(code(0x94A7, 0x02, Some(0x03))));
+
+        assert_eq!("GOSSI".parse::<CheatCode>(), Err(CheatError::InvalidLength(5)));
+        assert_eq!("GOSSIB".parse::<CheatCode>(), Err(CheatError::InvalidLetter('B')));
+    }
+
+    #[test]
+    fn raw_codes_decode() {
+        assert_eq!("0075:09".parse(), Ok(code(0x0075, 0x09, None)));
+        assert_eq!("$C123?4A:EA".parse(), Ok(code(0xC123, 0xEA, Some(0x4A))));
+        assert!(matches!("0075:".parse::<CheatCode>(), Err(CheatError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn compare_byte_limits_the_patch() {
+        let cheat = code(0x94A7, 0x02, Some(0x03));
+
+        assert_eq!(cheat.apply(0x94A7, 0x03), Some(0x02));
+        assert_eq!(cheat.apply(0x94A7, 0x04), None);
+        assert_eq!(cheat.apply(0x94A8, 0x03), None);
+    }
+
+    #[test]
+    fn cheat_files_round_trip() {
+        let mut list = CheatList::new();
+        let id = list.add("GOSSIP").unwrap();
+        list.add("0075:09").unwrap();
+        list.set_enabled(id, false);
+
+        let text = list.to_text(CheatFormat::Rnes);
+        assert_eq!(text, "!GOSSIP\n0075:09\n");
+
+        let mut loaded = CheatList::new();
+        loaded.load_text(&format!("# lives\n\n{}", text)).unwrap();
+        assert_eq!(loaded.active(), vec![code(0x0075, 0x09, None)]);
+        assert_eq!(loaded.to_text(CheatFormat::Rnes), text);
+
+        //A bad line leaves the list as it was
+        assert!(loaded.load_text("SXIOPO\nnot a code").is_err());
+        assert_eq!(loaded.cheats().len(), 2);
+    }
+
+    #[test]
+    fn fceux_and_nestopia_lists_convert() {
+        let mut list = CheatList::new();
+        list.load_text("GOSSIP Infinite lives\n!C123?4A:EA Skip the intro\n").unwrap();
+        assert_eq!(list.cheats()[0].description, "Infinite lives");
+
+        let fceux = list.to_text(CheatFormat::Fceux);
+        assert_eq!(fceux, "Sd1dd:14:Infinite lives\nSC:c123:ea:4a:Skip the intro\n");
+
+        let nestopia = list.to_text(CheatFormat::Nestopia);
+        assert!(nestopia.contains("<genie>GOSSIP</genie>\n    <description>Infinite lives</description>"));
+
+        for text in [fceux, nestopia] {
+            let mut loaded = CheatList::new();
+            loaded.load_text(&text).unwrap();
+
+            let cheats: Vec<_> = loaded
+                .cheats()
+                .iter()
+                .map(|cheat| (cheat.cheat, cheat.enabled, cheat.description.as_str()))
+                .collect();
+            assert_eq!(
+                cheats,
+                [
+                    (code(0xD1DD, 0x14, None), true, "Infinite lives"),
+                    (code(0xC123, 0xEA, Some(0x4A)), false, "Skip the intro"),
+                ]
+            );
+        }
+
+        //RAM cheats of FCEUX (no S) patch the reads as well, descriptions may hold colons
+        assert_eq!(CheatFormat::detect("0075:09:Lives: 9"), CheatFormat::Fceux);
+        let mut loaded = CheatList::new();
+        loaded.load_text("0075:09:Lives: 9\n").unwrap();
+        assert_eq!(loaded.active(), vec![code(0x0075, 0x09, None)]);
+        assert_eq!(loaded.cheats()[0].description, "Lives: 9");
+    }
+}
@@ -0,0 +1,140 @@
+use crate::input::InputDevice;
+
+///Standard joypad buttons, in the order they are shifted out (bit 0 first)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Button {
+    A = 1 << 0,
+    B = 1 << 1,
+    Select = 1 << 2,
+    Start = 1 << 3,
+    Up = 1 << 4,
+    Down = 1 << 5,
+    Left = 1 << 6,
+    Right = 1 << 7,
+}
+
+///Standard NES controller, a 4021 8-bit shift register latched by the strobe (OUT0)
+pub struct Controller {
+    buttons: u8,
+    shift_register: u8,
+    strobe: bool,
+}
+
+impl Controller {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            buttons: 0x00,
+            shift_register: 0x00,
+            strobe: false,
+        }
+    }
+
+    pub fn get_buttons(&self) -> u8 {
+        return self.buttons;
+    }
+
+    ///Sets every button at once, one bit per Button
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+    }
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for Controller {
+    fn write_output(&mut self, output: u8) {
+        self.strobe = (output & 0x01) != 0;
+
+        if self.strobe {
+            self.shift_register = self.buttons;
+        }
+    }
+
+    fn read(&mut self, _port: usize) -> u8 {
+        //While the strobe is high the register keeps reloading, so only A is ever returned
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+
+        let data = self.shift_register & 0x01;
+
+        //Official controllers shift in 1s, so every read after the 8th returns 1
+        self.shift_register = (self.shift_register >> 1) | 0x80;
+
+        return data;
+    }
+
+    fn set_button_state(&mut self, button: Button, pressed: bool) {
+        set_bit(&mut self.buttons, button, pressed);
+    }
+}
+
+///Four Score multitap, one in each controller port: port 0 reads players 1 and 3, port 1 players 2 and 4<br>
+///A read returns the 8 buttons of the first player, then those of the second player, then a signature that tells
+///games the adapter is plugged in
+pub struct FourScore {
+    buttons: [u8; 2],
+    signature: u8, //$08 in port 0, $04 in port 1: the 20th or 19th read returns 1
+    shift_register: u32,
+    strobe: bool,
+}
+
+impl FourScore {
+    //Constructor
+    pub fn new(port: usize) -> Self {
+        Self {
+            buttons: [0x00; 2],
+            signature: if port == 0 { 0x08 } else { 0x04 },
+            shift_register: 0,
+            strobe: false,
+        }
+    }
+
+    fn latch(&self) -> u32 {
+        return u32::from_le_bytes([self.buttons[0], self.buttons[1], self.signature, 0x00]);
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write_output(&mut self, output: u8) {
+        self.strobe = (output & 0x01) != 0;
+
+        if self.strobe {
+            self.shift_register = self.latch();
+        }
+    }
+
+    fn read(&mut self, _port: usize) -> u8 {
+        if self.strobe {
+            return self.buttons[0] & 0x01;
+        }
+
+        let data = (self.shift_register & 0x01) as u8;
+
+        //Every read after the 24th returns 1
+        self.shift_register = (self.shift_register >> 1) | 0x0080_0000;
+
+        return data;
+    }
+
+    fn set_button_state(&mut self, button: Button, pressed: bool) {
+        set_bit(&mut self.buttons[0], button, pressed);
+    }
+
+    fn set_second_button_state(&mut self, button: Button, pressed: bool) {
+        set_bit(&mut self.buttons[1], button, pressed);
+    }
+}
+
+fn set_bit(buttons: &mut u8, button: Button, pressed: bool) {
+    if pressed {
+        *buttons |= button as u8;
+    } else {
+        *buttons &= !(button as u8);
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::{self, Write};
+
+///First address of the cartridge PRG space tracked by the coverage map
+pub const PRG_START: u16 = 0x8000;
+
+///Number of bytes between PRG_START and 0xFFFF
+pub const PRG_SIZE: usize = 0x8000;
+
+///Executed opcode histogram and PRG address coverage collected while the CPU runs
+#[derive(Clone)]
+pub struct Coverage {
+    opcode_counts: [u64; 256],
+    prg_executed: Vec<bool>,
+}
+
+impl Coverage {
+    //Constructor
+    pub(crate) fn new() -> Self {
+        Self {
+            opcode_counts: [0; 256],
+            prg_executed: vec![false; PRG_SIZE],
+        }
+    }
+
+    ///Records an instruction executed at the given address, marking its opcode and operand bytes as covered
+    pub fn record(&mut self, address: u16, opcode: u8, length: u16) {
+        self.opcode_counts[opcode as usize] += 1;
+
+        for offset in 0..length.max(1) {
+            let byte_address = address.wrapping_add(offset);
+
+            if byte_address >= PRG_START {
+                self.prg_executed[(byte_address - PRG_START) as usize] = true;
+            }
+        }
+    }
+
+    ///Clears every counter without releasing the coverage map
+    pub fn clear(&mut self) {
+        self.opcode_counts = [0; 256];
+        self.prg_executed.iter_mut().for_each(|executed| *executed = false);
+    }
+
+    ///How many times the opcode was executed
+    pub fn opcode_count(&self, opcode: u8) -> u64 {
+        return self.opcode_counts[opcode as usize];
+    }
+
+    ///Every opcode that was executed at least once together with its count, sorted by opcode
+    pub fn executed_opcodes(&self) -> Vec<(u8, u64)> {
+        return (0..=255u8)
+            .map(|opcode| (opcode, self.opcode_counts[opcode as usize]))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+    }
+
+    ///Total number of instructions recorded
+    pub fn instruction_count(&self) -> u64 {
+        return self.opcode_counts.iter().sum();
+    }
+
+    ///Returns true if the byte at the given PRG address was executed as part of an instruction
+    pub fn is_executed(&self, address: u16) -> bool {
+        if address < PRG_START {
+            return false;
+        }
+
+        return self.prg_executed[(address - PRG_START) as usize];
+    }
+
+    ///Number of distinct PRG bytes executed as part of an instruction
+    pub fn executed_address_count(&self) -> usize {
+        return self.prg_executed.iter().filter(|executed| **executed).count();
+    }
+
+    ///Fraction (0.0 - 1.0) of the PRG space that was executed
+    pub fn prg_coverage(&self) -> f32 {
+        return self.executed_address_count() as f32 / PRG_SIZE as f32;
+    }
+
+    ///Writes the histogram as "opcode,count" CSV lines followed by the executed PRG ranges
+    pub fn export<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "opcode,count")?;
+        for (opcode, count) in self.executed_opcodes() {
+            writeln!(writer, "${:02X},{}", opcode, count)?;
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "start,end")?;
+
+        //Join adjacent executed addresses into ranges to keep the output readable
+        let mut range_start: Option<usize> = None;
+        for index in 0..=PRG_SIZE {
+            let executed = index < PRG_SIZE && self.prg_executed[index];
+
+            match (executed, range_start) {
+                (true, None) => range_start = Some(index),
+                (false, Some(start)) => {
+                    writeln!(
+                        writer,
+                        "${:04X},${:04X}",
+                        start + PRG_START as usize,
+                        index - 1 + PRG_START as usize
+                    )?;
+                    range_start = None;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
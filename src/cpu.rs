@@ -1,24 +1,38 @@
-use std::{borrow::Borrow, cell::RefCell, rc::Weak};
+use std::{cell::RefCell, fmt, rc::Weak};
 
-use crate::{bus::BUS, opcode::{lookup_table, imp}};
+use serde::{Deserialize, Serialize};
 
+use crate::{
+    bus::BUS,
+    coverage::Coverage,
+    diagnostics::Diagnostic,
+    opcode::{is_implied, AddressingMode, LOOKUP_TABLE},
+    rng::Rng,
+};
+
+///The fields are private: other modules go through the register getters/setters,
+///the opcode module through the crate internal interface at the end of this impl
 pub struct CPU {
     //CPU Registers
-    pub regx: u8,             //X REGISTER
-    pub regy: u8,             //Y REGISTER
-    pub acu: u8,              //ACCUMULATOR REGISTER
-    pub stack_pointer: u8,    //STACK POINTER
-    pub program_counter: u16, //PROGRAM COUNTER
-    pub status: u8,           //STATUS REGISTER
+    regx: u8,             //X REGISTER
+    regy: u8,             //Y REGISTER
+    acu: u8,              //ACCUMULATOR REGISTER
+    stack_pointer: u8,    //STACK POINTER
+    program_counter: u16, //PROGRAM COUNTER
+    status: u8,           //STATUS REGISTER
 
     //Assist Variables
-    pub fetched: u8,
-    pub abs_addr: u16,
-    pub rel_addr: u16,
-    pub temp_op: u16,
-    pub cur_opcode: u8,
-    pub cycles: u8,
-    pub clock_count: u32,
+    fetched: u8,
+    abs_addr: u16,
+    rel_addr: u16,
+    temp_op: u16,
+    cur_opcode: u8,
+    cycles: u8,
+
+    //Opcode histogram and PRG coverage, only collected when enabled
+    coverage: Option<Coverage>,
+
+    dummy_reads: bool, //See AccuracySettings::dummy_reads
 
     bus: Option<Weak<RefCell<BUS>>>,
 }
@@ -36,6 +50,46 @@ pub enum StatusFlags {
     N = 1 << 7, //Negative
 }
 
+///Snapshot of the CPU registers, as shown in trace logs ("A:00 X:00 Y:00 P:24 SP:FD")
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+    pub stack_pointer: u8,
+    pub program_counter: u16,
+}
+
+impl fmt::Display for CpuRegisters {
+    ///"A:00 X:00 Y:00 P:24 SP:FD", the program counter is left to the caller
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            self.a, self.x, self.y, self.status, self.stack_pointer
+        )
+    }
+}
+
+///CPU registers and the state of the instruction in progress, as stored in save states
+#[derive(Serialize, Deserialize)]
+pub struct CpuState {
+    regx: u8,
+    regy: u8,
+    acu: u8,
+    stack_pointer: u8,
+    program_counter: u16,
+    status: u8,
+
+    fetched: u8,
+    abs_addr: u16,
+    rel_addr: u16,
+    temp_op: u16,
+    cur_opcode: u8,
+    cycles: u8,
+}
+
 impl CPU {
     //Constructor
     pub fn new() -> Self {
@@ -53,7 +107,10 @@ impl CPU {
             temp_op: 0,
             cur_opcode: 0,
             cycles: 0,
-            clock_count: 0,
+
+            coverage: None,
+
+            dummy_reads: false,
 
             bus: None,
         }
@@ -69,6 +126,25 @@ impl CPU {
         0
     }
 
+    ///Opcode read, the BUS keeps the address as the context of diagnostics
+    fn fetch_opcode(&self, address: u16) -> u8 {
+        if let Some(bus) = &self.bus {
+            if let Some(bus) = bus.upgrade() {
+                return bus.as_ref().borrow().fetch_opcode(address);
+            }
+        }
+
+        0
+    }
+
+    fn report(&self, diagnostic: Diagnostic) {
+        if let Some(bus) = &self.bus {
+            if let Some(bus) = bus.upgrade() {
+                bus.as_ref().borrow().get_diagnostics().borrow_mut().report(diagnostic);
+            }
+        }
+    }
+
     pub fn write(&mut self, address: u16, data: u8) {
         if let Some(bus) = &mut self.bus {
             if let Some(bus) = bus.upgrade() {
@@ -78,7 +154,47 @@ impl CPU {
     }
 
     pub fn get_stack_address(&self) -> u16 {
-        0x0100 as u16 + self.stack_pointer as u16
+        0x0100 + self.stack_pointer as u16
+    }
+
+    //Stack
+    //The stack lives in page 1 ($0100-$01FF), the pointer wraps around inside it like the hardware
+
+    pub(crate) fn push8(&mut self, data: u8) {
+        self.write(self.get_stack_address(), data);
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+    }
+
+    ///High byte first, so the low byte ends up at the lower address
+    pub(crate) fn push16(&mut self, data: u16) {
+        self.push8((data >> 8) as u8);
+        self.push8((data & 0x00FF) as u8);
+    }
+
+    ///Pushes the status register, B (Break) is set only when pushed by BRK or PHP and G (Unused) is always set<br>
+    ///B isn't a real flag: the register never holds it, set_status() drops it when P is pulled back
+    pub(crate) fn push_status(&mut self, brk: bool) {
+        let mut status = self.status | StatusFlags::G as u8;
+
+        if brk {
+            status |= StatusFlags::B as u8;
+        } else {
+            status &= !(StatusFlags::B as u8);
+        }
+
+        self.push8(status);
+    }
+
+    pub(crate) fn pop8(&mut self) -> u8 {
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
+        return self.read(self.get_stack_address());
+    }
+
+    pub(crate) fn pop16(&mut self) -> u16 {
+        let low_byte = self.pop8() as u16;
+        let high_byte = self.pop8() as u16;
+
+        return (high_byte << 8) | low_byte;
     }
 
     pub fn get_accumulator(&self) -> u8 {
@@ -97,56 +213,129 @@ impl CPU {
         return self.program_counter;
     }
 
+    pub fn get_stack_pointer(&self) -> u8 {
+        return self.stack_pointer;
+    }
+
+    pub fn get_registers(&self) -> CpuRegisters {
+        return CpuRegisters {
+            a: self.acu,
+            x: self.regx,
+            y: self.regy,
+            status: self.status,
+            stack_pointer: self.stack_pointer,
+            program_counter: self.program_counter,
+        };
+    }
+
+    pub fn set_accumulator(&mut self, value: u8) {
+        self.acu = value;
+    }
+
+    pub fn set_register_x(&mut self, value: u8) {
+        self.regx = value;
+    }
+
+    pub fn set_register_y(&mut self, value: u8) {
+        self.regy = value;
+    }
+
+    pub fn set_stack_pointer(&mut self, value: u8) {
+        self.stack_pointer = value;
+    }
+
+    ///The next instruction is fetched from here, an instruction in progress still finishes its cycles
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    ///B (Break) only exists in the copies pushed on the stack and G (Unused) always reads as set,
+    ///so both are fixed up whatever the value holds
+    pub fn set_status(&mut self, value: u8) {
+        self.status = (value | StatusFlags::G as u8) & !(StatusFlags::B as u8);
+    }
+
     //Interface Signals
 
     ///Executes every update but will only trigger when the cycles are off
     pub fn clock(&mut self) {
         if self.cycles == 0 {
-            self.cur_opcode = self.read(self.program_counter);
+            let opcode_address = self.program_counter;
+            self.cur_opcode = self.fetch_opcode(self.program_counter);
 
             self.set_flag(StatusFlags::G, true);
 
-            self.program_counter += 1;
+            self.program_counter = self.program_counter.wrapping_add(1);
 
-            self.cycles = lookup_table[self.cur_opcode as usize].cycles;
+            self.cycles = LOOKUP_TABLE[self.cur_opcode as usize].cycles;
 
-            let addr_mode_cycles = (lookup_table[self.cur_opcode as usize].addr_mode)(self);
+            let instruction = &LOOKUP_TABLE[self.cur_opcode as usize];
 
-            let operate_cycles = (lookup_table[self.cur_opcode as usize].operate)(self);
+            if instruction.name == "XXX" {
+                self.report(Diagnostic::UnimplementedOpcode {
+                    opcode: self.cur_opcode,
+                    address: opcode_address,
+                });
+            }
 
-            self.cycles += addr_mode_cycles & operate_cycles;
+            let page_crossed = instruction.addr_mode.resolve(self);
+
+            if self.dummy_reads {
+                self.dummy_read(instruction.addr_mode, page_crossed == 1 || !instruction.page_penalty);
+            }
+
+            //The addressing mode has consumed the operands, so the instruction length is known here
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record(
+                    opcode_address,
+                    self.cur_opcode,
+                    self.program_counter.wrapping_sub(opcode_address),
+                );
+            }
+
+            (instruction.operate)(self);
+
+            if instruction.page_penalty {
+                self.cycles += page_crossed;
+            }
 
             self.set_flag(StatusFlags::G, true);
         }
 
-        self.clock_count += 1;
         self.cycles -= 1
     }
 
-    ///The interrupt will execute when the "disable interrupt" (I status Flag) is off
-    pub fn interrupt_request(&mut self) {
+    ///Indexed modes add the index to the low byte first and read from there while the high byte is fixed up<br>
+    ///Reads skip that read unless the page was crossed, stores and read-modify-writes always do it
+    fn dummy_read(&mut self, addr_mode: AddressingMode, long_path: bool) {
+        let index = match addr_mode {
+            AddressingMode::AbsoluteX => self.regx,
+            AddressingMode::AbsoluteY | AddressingMode::IndirectY => self.regy,
+            _ => return,
+        };
+
+        if !long_path {
+            return;
+        }
+
+        let base = self.abs_addr.wrapping_sub(index as u16);
+        self.read((base & 0xFF00) | (self.abs_addr & 0x00FF));
+    }
+
+    pub(crate) fn set_dummy_reads(&mut self, enabled: bool) {
+        self.dummy_reads = enabled;
+    }
+
+    ///The interrupt will execute when the "disable interrupt" (I status Flag) is off<br>
+    ///Returns true when it was taken
+    pub fn interrupt_request(&mut self) -> bool {
         if self.get_flag(StatusFlags::I) == 0 {
-            //Save the program counter high byte into the stack
-            self.write(
-                self.get_stack_address(),
-                ((self.program_counter >> 8) & 0x00FF) as u8,
-            );
-            self.stack_pointer -= 1;
-
-            //Save the program counter low byte into the stack
-            self.write(
-                self.get_stack_address(),
-                (self.program_counter & 0x00FF) as u8,
-            );
-            self.stack_pointer -= 1;
-
-            //Sets the status register into the stack
-            self.set_flag(StatusFlags::B, false);
-            self.set_flag(StatusFlags::G, true);
-            self.set_flag(StatusFlags::I, true);
+            //Save the program counter into the stack, high byte first
+            self.push16(self.program_counter);
 
-            self.write(self.get_stack_address(), self.status);
-            self.stack_pointer -= 1;
+            //The pushed status has B clear, interrupts are masked once it is saved
+            self.push_status(false);
+            self.set_flag(StatusFlags::I, true);
 
             //The program counter is equal to the low_byte in the 0xFFFE RAM address and to the high_byte in the 0xFFFF RAM address
             let low_byte = self.read(0xFFFE) as u16;
@@ -156,33 +345,22 @@ impl CPU {
             self.program_counter = (high_byte << 8) | low_byte;
 
             self.cycles = 7;
+
+            return true;
         }
+
+        return false;
     }
 
     ///The non maskable input can't be ignored in contrary to the interrput request but they do the same thing execept
     ///for the program address is 0xFFFA for low byte and 0xFFFB for high byte
     pub fn non_maskable_input(&mut self) {
-        //Save the program counter high byte into the stack
-        self.write(
-            self.get_stack_address(),
-            ((self.program_counter >> 8) & 0x00FF) as u8,
-        );
-        self.stack_pointer -= 1;
-
-        //Save the program counter low byte into the stack
-        self.write(
-            self.get_stack_address(),
-            (self.program_counter & 0x00FF) as u8,
-        );
-        self.stack_pointer -= 1;
-
-        //Sets the status register into the stack
-        self.set_flag(StatusFlags::B, false);
-        self.set_flag(StatusFlags::G, true);
-        self.set_flag(StatusFlags::I, true);
+        //Save the program counter into the stack, high byte first
+        self.push16(self.program_counter);
 
-        self.write(self.get_stack_address(), self.status);
-        self.stack_pointer -= 1;
+        //The pushed status has B clear, interrupts are masked once it is saved
+        self.push_status(false);
+        self.set_flag(StatusFlags::I, true);
 
         //The program counter is equal to the low_byte in the 0xFFFA RAM address and to the high_byte in the 0xFFFB RAM address
         let low_byte = self.read(0xFFFA) as u16;
@@ -196,7 +374,8 @@ impl CPU {
 
     ///Resets the registers and pointers and status and sets the program counter to the low_byte in the 0xFFFC RAM address and to the high_byte in the 0xFFFD RAM address 
     pub fn reset(&mut self) {
-        self.status = 0x00 | StatusFlags::G as u8;
+        //Interrupts start masked, the APU frame IRQ is enabled at power on
+        self.status = StatusFlags::G as u8 | StatusFlags::I as u8;
 
         self.stack_pointer = 0xFD;
         self.regx = 0;
@@ -209,7 +388,7 @@ impl CPU {
         
         //The program counter is equal to the low_byte in the 0xFFFC RAM address and to the high_byte in the 0xFFFD RAM address
         let low_byte = self.read(0xFFFC) as u16;
-        let high_byte = self.read(0xFFFd) as u16;
+        let high_byte = self.read(0xFFFD) as u16;
 
         //Execute the same thing to join two bytes into one opcocde/uint_16
         self.program_counter = (high_byte << 8) | low_byte;
@@ -217,6 +396,13 @@ impl CPU {
         self.cycles = 8;
     }
 
+    ///Startup fuzzing: A, X and Y hold garbage at power on instead of the zeros reset() leaves
+    pub fn randomize_power_on_state(&mut self, rng: &mut Rng) {
+        self.acu = rng.next_u8();
+        self.regx = rng.next_u8();
+        self.regy = rng.next_u8();
+    }
+
     pub fn complete(&self) -> bool{
         return self.cycles == 0;
     }
@@ -225,6 +411,64 @@ impl CPU {
         self.bus = Some(bus)
     }
 
+    //Instruction Coverage
+
+    ///Starts collecting the executed opcode histogram and PRG address coverage
+    pub fn enable_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(Coverage::new());
+        }
+    }
+
+    ///Stops collecting coverage and returns what was collected so far
+    pub fn disable_coverage(&mut self) -> Option<Coverage> {
+        return self.coverage.take();
+    }
+
+    pub fn get_coverage(&self) -> Option<&Coverage> {
+        return self.coverage.as_ref();
+    }
+
+    pub fn get_coverage_mut(&mut self) -> Option<&mut Coverage> {
+        return self.coverage.as_mut();
+    }
+
+    //Save States
+
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            regx: self.regx,
+            regy: self.regy,
+            acu: self.acu,
+            stack_pointer: self.stack_pointer,
+            program_counter: self.program_counter,
+            status: self.status,
+
+            fetched: self.fetched,
+            abs_addr: self.abs_addr,
+            rel_addr: self.rel_addr,
+            temp_op: self.temp_op,
+            cur_opcode: self.cur_opcode,
+            cycles: self.cycles,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.regx = state.regx;
+        self.regy = state.regy;
+        self.acu = state.acu;
+        self.stack_pointer = state.stack_pointer;
+        self.program_counter = state.program_counter;
+        self.status = state.status;
+
+        self.fetched = state.fetched;
+        self.abs_addr = state.abs_addr;
+        self.rel_addr = state.rel_addr;
+        self.temp_op = state.temp_op;
+        self.cur_opcode = state.cur_opcode;
+        self.cycles = state.cycles;
+    }
+
     //Set/Get Status Flags
     pub fn get_flag(&self, flag: StatusFlags) -> u8 {
         let bit = flag as u8;
@@ -249,10 +493,54 @@ impl CPU {
         self.status &= !flags;
     }
 
+    ///Loads the operand into "fetched", implied instructions already have it set by the addressing mode
     pub fn fetch(&mut self) {
-        let imp: Box<dyn Fn(&mut CPU) -> u8> = Box::new(imp);
-        if std::ptr::eq(&*lookup_table[self.cur_opcode as usize].addr_mode,&*imp) {
+        if !is_implied(self) {
             self.fetched = self.read(self.abs_addr)
         }
-    } 
+    }
+
+    //Opcode Interface
+    //Addressing modes and instructions only reach the CPU state through these
+
+    ///Reads the byte at the program counter and moves past it
+    pub(crate) fn read_program_byte(&mut self) -> u8 {
+        let data = self.read(self.program_counter);
+        self.program_counter = self.program_counter.wrapping_add(1);
+
+        return data;
+    }
+
+    pub(crate) fn get_opcode(&self) -> u8 {
+        return self.cur_opcode;
+    }
+
+    pub(crate) fn get_fetched(&self) -> u8 {
+        return self.fetched;
+    }
+
+    pub(crate) fn set_fetched(&mut self, data: u8) {
+        self.fetched = data;
+    }
+
+    pub(crate) fn get_abs_addr(&self) -> u16 {
+        return self.abs_addr;
+    }
+
+    pub(crate) fn set_abs_addr(&mut self, address: u16) {
+        self.abs_addr = address;
+    }
+
+    pub(crate) fn get_rel_addr(&self) -> u16 {
+        return self.rel_addr;
+    }
+
+    pub(crate) fn set_rel_addr(&mut self, offset: u16) {
+        self.rel_addr = offset;
+    }
+
+    ///Extra cycles taken by the instruction in progress (branches taken, page crossings)
+    pub(crate) fn add_cycles(&mut self, cycles: u8) {
+        self.cycles += cycles;
+    }
 }
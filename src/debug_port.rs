@@ -0,0 +1,86 @@
+use std::{cell::RefCell, rc::Rc};
+
+///Homebrew writes text here one byte at a time, a newline ends the line
+pub const DEFAULT_PRINT_ADDRESS: u16 = 0x4018;
+
+///A write here ends a headless run, the value is the exit code
+pub const DEFAULT_EXIT_ADDRESS: u16 = 0x401A;
+
+///Where the debug port is mapped, both addresses must be inside the unused $4018 - $5FFF range
+#[derive(Clone, Copy, Debug)]
+pub struct DebugPortConfig {
+    pub print_address: u16,
+    pub exit_address: u16,
+    pub echo: bool, //Also print every completed line to stderr
+}
+
+impl Default for DebugPortConfig {
+    fn default() -> Self {
+        Self {
+            print_address: DEFAULT_PRINT_ADDRESS,
+            exit_address: DEFAULT_EXIT_ADDRESS,
+            echo: true,
+        }
+    }
+}
+
+///What the game wrote to the debug port, shared with the two bus handlers
+pub(crate) struct DebugPort {
+    echo: bool,
+    line: Vec<u8>,     //Text since the last newline
+    output: String,    //Completed lines not taken yet
+    exit_code: Option<u8>,
+}
+
+impl DebugPort {
+    //Constructor
+    pub fn new(echo: bool) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            echo,
+            line: Vec::new(),
+            output: String::new(),
+            exit_code: None,
+        }))
+    }
+
+    ///Print port write: carriage returns are dropped, a newline completes the line
+    pub fn print(&mut self, data: u8) {
+        match data {
+            b'\r' => {}
+            b'\n' => {
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                self.line.clear();
+
+                if self.echo {
+                    eprintln!("{}", line);
+                }
+
+                self.output.push_str(&line);
+                self.output.push('\n');
+            }
+            _ => self.line.push(data),
+        }
+    }
+
+    ///Exit port write, the first code wins
+    pub fn exit(&mut self, code: u8) {
+        if self.exit_code.is_none() {
+            self.exit_code = Some(code);
+        }
+    }
+
+    pub fn get_exit_code(&self) -> Option<u8> {
+        return self.exit_code;
+    }
+
+    ///Takes the completed lines, a line without its newline yet stays until it is finished
+    pub fn take_output(&mut self) -> String {
+        return std::mem::take(&mut self.output);
+    }
+
+    ///A reset starts a new run: the exit code and the unfinished line are forgotten
+    pub fn reset(&mut self) {
+        self.line.clear();
+        self.exit_code = None;
+    }
+}
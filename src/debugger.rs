@@ -0,0 +1,178 @@
+use std::ops::RangeInclusive;
+
+use crate::watch::{WatchExpression, WatchId};
+
+///Identifier returned when a breakpoint or watchpoint is added, used to remove it later
+pub type BreakpointId = usize;
+
+///Which accesses a watchpoint stops on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+///Why Emulator::run_until_break() stopped
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DebugHit {
+    ///The next instruction is at a breakpoint
+    Breakpoint { id: BreakpointId, address: u16 },
+    ///The last instruction accessed a watched address (the first matching access is reported)
+    Watchpoint { id: BreakpointId, address: u16, value: u8, write: bool },
+    ///The next instruction is at the address given to run_to()
+    RunTo { address: u16 },
+}
+
+///Called with every hit before the run loop returns it
+type HitCallback = Box<dyn FnMut(&DebugHit)>;
+
+struct Watchpoint {
+    id: BreakpointId,
+    range: RangeInclusive<u16>,
+    kind: WatchKind,
+}
+
+///Breakpoints, watchpoints, watch expressions and the hit callback<br>
+///The BUS reports every CPU access to the watchpoints, the run loop checks the breakpoints between instructions
+pub(crate) struct Debugger {
+    breakpoints: Vec<(BreakpointId, u16)>,
+    watchpoints: Vec<Watchpoint>,
+    next_id: BreakpointId,
+
+    watches: Vec<(WatchId, WatchExpression)>,
+    next_watch_id: WatchId,
+
+    watch_hit: Option<DebugHit>, //First watchpoint hit since the last take_watch_hit()
+    callback: Option<HitCallback>,
+}
+
+impl Debugger {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 0,
+
+            watches: Vec::new(),
+            next_watch_id: 0,
+
+            watch_hit: None,
+            callback: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) -> BreakpointId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.breakpoints.push((id, address));
+
+        return id;
+    }
+
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, kind: WatchKind) -> BreakpointId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.watchpoints.push(Watchpoint { id, range, kind });
+
+        return id;
+    }
+
+    ///Removes a breakpoint or a watchpoint, returns false if the id is unknown
+    pub fn remove(&mut self, id: BreakpointId) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+
+        self.breakpoints.retain(|&(breakpoint, _)| breakpoint != id);
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+
+        return self.breakpoints.len() + self.watchpoints.len() != count;
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.watch_hit = None;
+    }
+
+    //Watch Expressions
+
+    pub fn add_watch(&mut self, expression: WatchExpression) -> WatchId {
+        let id = self.next_watch_id;
+        self.next_watch_id += 1;
+
+        self.watches.push((id, expression));
+
+        return id;
+    }
+
+    ///Returns false if the id is unknown
+    pub fn remove_watch(&mut self, id: WatchId) -> bool {
+        let count = self.watches.len();
+
+        self.watches.retain(|&(watch, _)| watch != id);
+
+        return self.watches.len() != count;
+    }
+
+    pub fn clear_watches(&mut self) {
+        self.watches.clear();
+    }
+
+    ///The watch expressions in the order they were added
+    pub fn watches(&self) -> &[(WatchId, WatchExpression)] {
+        return &self.watches;
+    }
+
+    pub fn set_callback(&mut self, callback: Option<HitCallback>) {
+        self.callback = callback;
+    }
+
+    ///Breakpoint at the address of the next instruction
+    pub fn breakpoint_at(&self, address: u16) -> Option<DebugHit> {
+        return self
+            .breakpoints
+            .iter()
+            .find(|&&(_, breakpoint)| breakpoint == address)
+            .map(|&(id, address)| DebugHit::Breakpoint { id, address });
+    }
+
+    ///Called by the BUS on every CPU read and write
+    pub fn check_access(&mut self, address: u16, value: u8, write: bool) {
+        if self.watchpoints.is_empty() || self.watch_hit.is_some() {
+            return;
+        }
+
+        let watchpoint = self.watchpoints.iter().find(|watchpoint| {
+            let kind = match watchpoint.kind {
+                WatchKind::Read => !write,
+                WatchKind::Write => write,
+                WatchKind::ReadWrite => true,
+            };
+
+            kind && watchpoint.range.contains(&address)
+        });
+
+        if let Some(watchpoint) = watchpoint {
+            self.watch_hit = Some(DebugHit::Watchpoint {
+                id: watchpoint.id,
+                address,
+                value,
+                write,
+            });
+        }
+    }
+
+    pub fn take_watch_hit(&mut self) -> Option<DebugHit> {
+        return self.watch_hit.take();
+    }
+
+    ///Tells the callback (if any) that execution stopped
+    pub fn notify(&mut self, hit: &DebugHit) {
+        if let Some(callback) = &mut self.callback {
+            callback(hit);
+        }
+    }
+}
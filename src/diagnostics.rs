@@ -0,0 +1,96 @@
+use std::fmt;
+
+///Something the game did that the emulator doesn't emulate, with where it happened
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Diagnostic {
+    ///The CPU ran one of the "XXX" opcodes (unstable undocumented ones and the JAMs), which do nothing here
+    UnimplementedOpcode { opcode: u8, address: u16 },
+    ///A write asked the board for a feature it doesn't emulate, the rest of the write still took effect
+    UnsupportedRegister {
+        mapper: u16,
+        register: &'static str, //The feature, "PRG-RAM write protection", ...
+        address: u16,
+        data: u8,
+        program_counter: u16, //Of the instruction that wrote
+    },
+}
+
+impl Diagnostic {
+    ///The same gap hit again: same opcode, or same feature of the board
+    fn same_gap(&self, other: &Diagnostic) -> bool {
+        match (self, other) {
+            (Diagnostic::UnimplementedOpcode { opcode, .. }, Diagnostic::UnimplementedOpcode { opcode: other, .. }) => {
+                opcode == other
+            }
+            (
+                Diagnostic::UnsupportedRegister { register, .. },
+                Diagnostic::UnsupportedRegister { register: other, .. },
+            ) => register == other,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::UnimplementedOpcode { opcode, address } => {
+                write!(f, "unimplemented opcode ${:02X} at ${:04X}, ran as a NOP", opcode, address)
+            }
+            Diagnostic::UnsupportedRegister {
+                mapper,
+                register,
+                address,
+                data,
+                program_counter,
+            } => write!(
+                f,
+                "mapper {}: {} is not emulated (${:02X} written to ${:04X} by the instruction at ${:04X})",
+                mapper, register, data, address, program_counter
+            ),
+        }
+    }
+}
+
+///Every gap the game ran into since the ROM was loaded, each one logged the first time it happened
+pub(crate) struct Diagnostics {
+    log: Vec<Diagnostic>,
+    taken: usize, //Entries already handed out by take_new()
+}
+
+impl Diagnostics {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            log: Vec::new(),
+            taken: 0,
+        }
+    }
+
+    ///Logs the diagnostic unless the same gap was already logged
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        if self.log.iter().any(|logged| logged.same_gap(&diagnostic)) {
+            return;
+        }
+
+        self.log.push(diagnostic);
+    }
+
+    pub fn log(&self) -> &[Diagnostic] {
+        return &self.log;
+    }
+
+    ///The entries logged since the last call
+    pub fn take_new(&mut self) -> Vec<Diagnostic> {
+        let new = self.log[self.taken..].to_vec();
+        self.taken = self.log.len();
+
+        return new;
+    }
+
+    ///Forgets the log, so every gap is reported again
+    pub fn clear(&mut self) {
+        self.log.clear();
+        self.taken = 0;
+    }
+}
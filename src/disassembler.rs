@@ -0,0 +1,82 @@
+use std::fmt;
+
+use crate::opcode::{decode, AddressingMode};
+
+///One decoded instruction
+pub struct DisasmLine {
+    pub address: u16,
+    pub bytes: Vec<u8>, //Opcode and operands
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub operand: String, //Formatted operand, empty for implied instructions
+}
+
+impl fmt::Display for DisasmLine {
+    ///"$C000: LDA #$10"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.operand.is_empty() {
+            write!(f, "${:04X}: {}", self.address, self.mnemonic)
+        } else {
+            write!(f, "${:04X}: {} {}", self.address, self.mnemonic, self.operand)
+        }
+    }
+}
+
+///Decodes the instructions from start up to end (inclusive) through the lookup table<br>
+///Memory is read through the callback, which must not have side effects (use BUS::peek)
+pub fn disassemble<F: Fn(u16) -> u8>(read: F, start: u16, end: u16) -> Vec<DisasmLine> {
+    let mut lines = Vec::new();
+
+    //u32 so an instruction at $FFFF doesn't wrap around and loop forever
+    let mut address = start as u32;
+
+    while address <= end as u32 {
+        let line = disassemble_instruction(&read, address as u16);
+
+        address += line.bytes.len() as u32;
+        lines.push(line);
+    }
+
+    return lines;
+}
+
+///Decodes the single instruction at the address
+pub fn disassemble_instruction<F: Fn(u16) -> u8>(read: F, address: u16) -> DisasmLine {
+    let opcode = read(address);
+    let (mnemonic, mode) = decode(opcode);
+
+    let bytes: Vec<u8> = (0..mode.length())
+        .map(|offset| read(address.wrapping_add(offset)))
+        .collect();
+
+    let low = bytes.get(1).copied().unwrap_or(0);
+    let high = bytes.get(2).copied().unwrap_or(0);
+    let word = ((high as u16) << 8) | low as u16;
+
+    let operand = match mode {
+        AddressingMode::Implied => String::new(),
+        AddressingMode::Immediate => format!("#${:02X}", low),
+        AddressingMode::ZeroPage => format!("${:02X}", low),
+        AddressingMode::ZeroPageX => format!("${:02X},X", low),
+        AddressingMode::ZeroPageY => format!("${:02X},Y", low),
+        //Branches show their destination
+        AddressingMode::Relative => {
+            let target = address.wrapping_add(2).wrapping_add(low as i8 as u16);
+            format!("${:04X}", target)
+        }
+        AddressingMode::Absolute => format!("${:04X}", word),
+        AddressingMode::AbsoluteX => format!("${:04X},X", word),
+        AddressingMode::AbsoluteY => format!("${:04X},Y", word),
+        AddressingMode::Indirect => format!("(${:04X})", word),
+        AddressingMode::IndirectX => format!("(${:02X},X)", low),
+        AddressingMode::IndirectY => format!("(${:02X}),Y", low),
+    };
+
+    DisasmLine {
+        address,
+        bytes,
+        mnemonic,
+        mode,
+        operand,
+    }
+}
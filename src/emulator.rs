@@ -0,0 +1,1239 @@
+use std::{
+    cell::{Ref, RefCell},
+    io::{self, Write},
+    ops::RangeInclusive,
+    path::Path,
+    rc::Rc,
+    time::Instant,
+};
+
+use crate::{
+    accuracy::{AccuracyProfile, AccuracySettings},
+    bookmarks::{Bookmark, Bookmarks},
+    bus::{HandlerId, InterceptorId, WriteAction, BUS},
+    capture::{Capture, CaptureColors},
+    cartridge::{Cartridge, CartridgeError, CartridgeInfo, PlayChoiceRoms},
+    cheats::{Cheat, CheatError, CheatFormat, CheatId, CheatList},
+    controller::Button,
+    coverage::Coverage,
+    cpu::CpuRegisters,
+    debug_port::{DebugPort, DebugPortConfig},
+    debugger::{BreakpointId, DebugHit, WatchKind},
+    diagnostics::Diagnostic,
+    disassembler::{self, DisasmLine},
+    events::{CycleStop, CyclesRun, EmulatorEvent, PauseReason, RunState, StepSize},
+    frame::{self, PixelFormat},
+    games,
+    input::InputDevice,
+    input_history::InputHistory,
+    memory_map::MemoryRegion,
+    ntsc,
+    opcode::is_jam,
+    palette::Palette,
+    ppu::{Layer, ScanlineScroll, SpriteLimit, PPU, SCREEN_HEIGHT, SCREEN_WIDTH},
+    region::Region,
+    rewind::RewindBuffer,
+    savestate::{SaveState, SaveStateError},
+    stats::Stats,
+    system::System,
+    timing::TimingTrace,
+    watch::{WatchExpression, WatchId, WatchValue},
+};
+
+///Called at the chosen dot of every scanline with the scanline (-1 for the pre-render one)
+type ScanlineCallback = Box<dyn FnMut(i16, &mut Emulator)>;
+
+///A complete NES: the entry point for embedding RNES in another program
+pub struct Emulator {
+    system: System,
+    bus: Rc<RefCell<BUS>>,
+    ppu: Rc<RefCell<PPU>>,
+
+    debug_port: Option<(Rc<RefCell<DebugPort>>, Vec<HandlerId>)>,
+    cheats: CheatList,
+    frozen: Vec<(InterceptorId, u16, u8)>, //Interceptors added by freeze() with their address and value
+
+    //Pause
+    pause_reason: Option<PauseReason>,
+    queued_step: Option<StepSize>, //Only kept while paused
+    auto_pause: bool,
+    pause_on_diagnostic: bool,
+    events: Vec<EmulatorEvent>,
+
+    timing: Option<TimingTrace>,
+    input_history: InputHistory,
+    rewind: Option<RewindBuffer>,
+    bookmarks: Bookmarks,
+    scanline_callback: Option<ScanlineCallback>,
+}
+
+impl Emulator {
+    //Constructor
+    pub fn new() -> Self {
+        let system = System::new();
+        let bus = system.get_bus();
+        let ppu = system.get_ppu();
+
+        Self {
+            system,
+            bus,
+            ppu,
+
+            debug_port: None,
+            cheats: CheatList::new(),
+            frozen: Vec::new(),
+
+            pause_reason: None,
+            queued_step: None,
+            auto_pause: false,
+            pause_on_diagnostic: false,
+            events: Vec::new(),
+
+            timing: None,
+            input_history: InputHistory::new(),
+            rewind: None,
+            bookmarks: Bookmarks::new(),
+            scanline_callback: None,
+        }
+    }
+
+    ///Loads an iNES file and resets the console
+    pub fn load_rom<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CartridgeError> {
+        let cartridge = Cartridge::from_file(path)?;
+        self.insert_cartridge(cartridge);
+
+        Ok(())
+    }
+
+    ///Loads an iNES image already in memory and resets the console
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), CartridgeError> {
+        let cartridge = Cartridge::from_bytes(data)?;
+        self.insert_cartridge(cartridge);
+
+        Ok(())
+    }
+
+    ///NES 2.0 headers that name a region switch the console to it, otherwise the current region is kept
+    fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        if let Some(region) = cartridge.info().region {
+            self.system.set_region(region);
+        }
+
+        self.bus.borrow_mut().insert_cartridge(Rc::new(RefCell::new(cartridge)));
+        self.reset();
+
+        //Codes are made for one game
+        self.clear_cheats();
+        self.clear_diagnostics();
+
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+
+        self.bookmarks.clear();
+    }
+
+    pub fn region(&self) -> Region {
+        return self.system.get_region();
+    }
+
+    ///Switches between NTSC, PAL and Dendy timing (CPU clock, scanlines per frame, vertical blank, APU frame counter)<br>
+    ///Loading a ROM with a NES 2.0 header picks the region it asks for
+    pub fn set_region(&mut self, region: Region) {
+        self.system.set_region(region);
+    }
+
+    ///Runtime region switch: changes every region table (timing, APU periods, color emphasis) and power cycles the
+    ///console, since a game only checks the region at startup<br>
+    ///The rewind snapshots of the old region are dropped
+    pub fn switch_region(&mut self, region: Region) {
+        self.set_region(region);
+        self.power_cycle();
+
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+    }
+
+    ///Applies a preset (Balanced by default), every setting can still be changed with set_accuracy_settings()
+    pub fn set_accuracy_profile(&mut self, profile: AccuracyProfile) {
+        self.system.set_accuracy(profile.settings());
+    }
+
+    ///The preset in use, None when the settings were changed one by one and match none of them
+    pub fn accuracy_profile(&self) -> Option<AccuracyProfile> {
+        return AccuracyProfile::matching(self.system.get_accuracy());
+    }
+
+    pub fn accuracy_settings(&self) -> AccuracySettings {
+        return self.system.get_accuracy();
+    }
+
+    pub fn set_accuracy_settings(&mut self, settings: AccuracySettings) {
+        self.system.set_accuracy(settings);
+    }
+
+    ///Clears the internal RAM and PPU memories and resets, like turning the console off and on
+    pub fn power_cycle(&mut self) {
+        self.system.power_cycle();
+
+        if let Some((port, _)) = &self.debug_port {
+            port.borrow_mut().reset();
+        }
+    }
+
+    ///Seeds the console's random generator, which the save states include<br>
+    ///With the same seed and inputs every random behavior (randomize_power_on_state(), ...) replays the same way
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.system.set_rng_seed(seed);
+    }
+
+    ///Fills the memories and registers the hardware leaves undefined at power on from the random generator,
+    ///call it after load_rom() or power_cycle() to catch games that read memory before setting it
+    pub fn randomize_power_on_state(&mut self) {
+        self.system.randomize_power_on_state();
+    }
+
+    ///Presses the reset button
+    pub fn reset(&mut self) {
+        self.system.reset();
+
+        if let Some((port, _)) = &self.debug_port {
+            port.borrow_mut().reset();
+        }
+    }
+
+    ///Runs the console until the CPU completes the current instruction
+    pub fn step_instruction(&mut self) {
+        self.run_system(System::step_instruction);
+        self.check_diagnostics();
+    }
+
+    ///Runs the console until the PPU starts the next scanline
+    pub fn step_scanline(&mut self) {
+        self.run_system(System::step_scanline);
+        self.check_diagnostics();
+    }
+
+    ///Runs the console until the PPU completes the next frame
+    pub fn step_frame(&mut self) {
+        let start = self.timing.as_ref().map(|_| Instant::now());
+
+        self.run_system(System::step_frame);
+        self.frame_completed(start);
+    }
+
+    ///Runs up to `cycles` master clock ticks (PPU dots: three per CPU cycle on NTSC, 3.2 on PAL) for schedulers
+    ///that budget time themselves, returns how many ran and why it stopped<br>
+    ///It stops early at the end of a frame, at a breakpoint or watchpoint (see add_breakpoint()) and before a JAM
+    ///opcode; calling it again carries on from there. Like the step functions it runs while paused
+    pub fn run_cycles(&mut self, cycles: u64) -> CyclesRun {
+        let start = self.timing.as_ref().map(|_| Instant::now());
+
+        //Accesses made before this run don't count
+        self.bus.borrow().get_debugger().borrow_mut().take_watch_hit();
+        self.ppu.borrow_mut().frame_complete = false;
+
+        let mut run = 0;
+
+        while run < cycles {
+            let boundary = self.system.clock() && self.system.instruction_complete();
+            run += 1;
+
+            self.call_scanline_callback();
+
+            let frame_complete = self.ppu.borrow().frame_complete;
+
+            let stop = if boundary {
+                self.debug_hit(None).map(CycleStop::Debug).or_else(|| self.jam())
+            } else {
+                None
+            };
+
+            if let Some(stop) = stop.or(frame_complete.then_some(CycleStop::FrameComplete)) {
+                //A debug stop on the last cycle of a frame still completes it
+                if frame_complete {
+                    self.frame_completed(start);
+                } else {
+                    self.check_diagnostics();
+                }
+
+                return CyclesRun { cycles: run, stop };
+            }
+        }
+
+        self.check_diagnostics();
+
+        return CyclesRun {
+            cycles: run,
+            stop: CycleStop::Budget,
+        };
+    }
+
+    ///The CPU is about to run a JAM opcode
+    fn jam(&self) -> Option<CycleStop> {
+        let address = self.get_program_counter();
+        let opcode = self.peek(address);
+
+        return is_jam(opcode).then_some(CycleStop::Jam { opcode, address });
+    }
+
+    ///Input history, rewind snapshots and the timing trace are kept per frame
+    fn frame_completed(&mut self, start: Option<Instant>) {
+        self.input_history.record_frame();
+        self.check_diagnostics();
+
+        if self.rewind.as_mut().is_some_and(|rewind| rewind.frame_completed()) {
+            if let Ok(state) = self.save_state() {
+                self.rewind.as_mut().unwrap().push(state);
+            }
+        }
+
+        if let (Some(trace), Some(start)) = (&mut self.timing, start) {
+            trace.record_frame(start, Instant::now(), self.system.take_subsystem_times());
+        }
+    }
+
+    //Scanline Callback
+
+    ///Calls the callback whenever the PPU reaches the dot (0 - 340) of a scanline, before it draws that dot<br>
+    ///It gets the scanline (-1 for the pre-render one) and the emulator, for raster effects, probes and filters
+    ///that need per-scanline state: writes made from it take effect from that dot on<br>
+    ///Replaces the previous callback, stepping the emulator from inside it runs without the callback
+    pub fn set_scanline_callback(&mut self, dot: u16, callback: impl FnMut(i16, &mut Emulator) + 'static) {
+        self.scanline_callback = Some(Box::new(callback));
+        self.system.set_scanline_dot(Some(dot));
+    }
+
+    pub fn clear_scanline_callback(&mut self) {
+        self.scanline_callback = None;
+        self.system.set_scanline_dot(None);
+    }
+
+    ///Runs a System step function, calling the scanline callback every time it stops at the dot
+    fn run_system(&mut self, step: fn(&mut System) -> bool) {
+        loop {
+            let finished = step(&mut self.system);
+            self.call_scanline_callback();
+
+            if finished {
+                return;
+            }
+        }
+    }
+
+    fn call_scanline_callback(&mut self) {
+        let Some(scanline) = self.system.take_scanline_reached() else {
+            return;
+        };
+
+        if let Some(mut callback) = self.scanline_callback.take() {
+            callback(scanline, self);
+
+            //Unless it was replaced or cleared from inside
+            if self.scanline_callback.is_none() && self.system.get_scanline_dot().is_some() {
+                self.scanline_callback = Some(callback);
+            }
+        }
+    }
+
+    //Pause
+
+    ///The frontend main loop: runs one frame unless the emulation is paused, returns false when nothing ran<br>
+    ///While paused only a queued step runs, the step_* functions ignore the pause altogether
+    pub fn run_frame(&mut self) -> bool {
+        if self.pause_reason.is_some() {
+            match self.queued_step.take() {
+                Some(size) => self.step(size),
+                None => return false,
+            }
+
+            return true;
+        }
+
+        self.step_frame();
+
+        return true;
+    }
+
+    ///Runs one instruction, scanline or frame, paused or not
+    pub fn step(&mut self, size: StepSize) {
+        match size {
+            StepSize::Instruction => self.step_instruction(),
+            StepSize::Scanline => self.step_scanline(),
+            StepSize::Frame => self.step_frame(),
+        }
+    }
+
+    ///Frame advance: the next run_frame() of a paused emulation runs this step, a running one ignores it
+    pub fn queue_step(&mut self, size: StepSize) {
+        if self.pause_reason.is_some() {
+            self.queued_step = Some(size);
+        }
+    }
+
+    pub fn run_state(&self) -> RunState {
+        match (self.pause_reason, self.queued_step) {
+            (None, _) => RunState::Running,
+            (Some(_), Some(size)) => RunState::Stepping(size),
+            (Some(reason), None) => RunState::Paused(reason),
+        }
+    }
+
+    ///Pauses with a reason, an emulation that is already paused keeps its first reason
+    pub fn pause(&mut self, reason: PauseReason) {
+        if self.pause_reason.is_none() {
+            self.pause_reason = Some(reason);
+            self.events.push(EmulatorEvent::Paused(reason));
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.queued_step = None;
+
+        if self.pause_reason.take().is_some() {
+            self.events.push(EmulatorEvent::Resumed);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        return self.pause_reason.is_some();
+    }
+
+    pub fn pause_reason(&self) -> Option<PauseReason> {
+        return self.pause_reason;
+    }
+
+    ///Auto-pause (off by default): pause when the window loses focus or a controller goes away
+    pub fn set_auto_pause(&mut self, enabled: bool) {
+        self.auto_pause = enabled;
+    }
+
+    ///Reported by the frontend when its window gains or loses focus<br>
+    ///Getting the focus back only resumes a pause that losing it caused
+    pub fn focus_changed(&mut self, focused: bool) {
+        if !focused && self.auto_pause {
+            self.pause(PauseReason::FocusLost);
+        } else if focused && self.pause_reason == Some(PauseReason::FocusLost) {
+            self.resume();
+        }
+    }
+
+    ///Reported by the frontend when the gamepad assigned to a port disconnects, the player resumes by hand
+    pub fn controller_disconnected(&mut self, port: usize) {
+        if self.auto_pause {
+            self.pause(PauseReason::ControllerDisconnected { port });
+        }
+    }
+
+    ///Pause on diagnostic (off by default): pause after the step in which the game first hits an opcode or board
+    ///register that isn't emulated, before it runs any further
+    pub fn set_pause_on_diagnostic(&mut self, enabled: bool) {
+        self.pause_on_diagnostic = enabled;
+    }
+
+    ///Takes the events since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<EmulatorEvent> {
+        return std::mem::take(&mut self.events);
+    }
+
+    //Save States
+
+    ///Snapshots the whole console in the versioned save state format
+    pub fn save_state(&self) -> Result<Vec<u8>, SaveStateError> {
+        return self.system.save_state()?.to_bytes();
+    }
+
+    ///Restores a snapshot taken with save_state() while the same game was loaded<br>
+    ///Cheats, breakpoints and the debug port stay as they are, frozen addresses get their value back right away
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let state = SaveState::from_bytes(data)?;
+
+        self.system.load_state(&state)?;
+
+        for &(_, address, value) in &self.frozen {
+            self.bus.borrow_mut().poke(address, value);
+        }
+
+        Ok(())
+    }
+
+    ///Whether write interceptors (cheats, frozen addresses) were active when the state was saved<br>
+    ///For movie playback and netplay, which need states that any console without cheats can reproduce
+    pub fn state_has_cheats(data: &[u8]) -> Result<bool, SaveStateError> {
+        return Ok(SaveState::from_bytes(data)?.cheats_active);
+    }
+
+    //Rewind
+
+    ///Snapshots the console every `interval` frames run by step_frame()/run_frame(), keeping the last `capacity`
+    ///snapshots (older ones are stored as deltas, a few hundred bytes each), replaces the snapshots already taken
+    pub fn enable_rewind(&mut self, interval: u32, capacity: usize) {
+        self.rewind = Some(RewindBuffer::new(interval, capacity));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    ///Goes back to the newest snapshot at least `frames` frames old and forgets the newer ones, returns how many
+    ///frames back it went (0 if rewind is off or no snapshot is that old)<br>
+    ///The frame buffer keeps the last frame drawn until the next one
+    pub fn rewind(&mut self, frames: u32) -> u32 {
+        let Some((rewound, state)) = self.rewind.as_mut().and_then(|rewind| rewind.pop(frames)) else {
+            return 0;
+        };
+
+        if self.load_state(&state).is_err() {
+            return 0;
+        }
+
+        return rewound as u32;
+    }
+
+    //Bookmarks
+
+    ///Frames the PPU finished since power on, part of save states: loading a state brings back its frame number
+    pub fn frame_number(&self) -> u64 {
+        return self.ppu.borrow().frame_count;
+    }
+
+    ///Saves the console under the name with the current frame number, replacing the bookmark with the same name<br>
+    ///Bookmarks are kept until another game is loaded, returns the frame number
+    pub fn add_bookmark(&mut self, name: &str) -> Result<u64, SaveStateError> {
+        let frame = self.frame_number();
+        self.bookmarks.set(name, frame, self.save_state()?);
+
+        Ok(frame)
+    }
+
+    pub fn remove_bookmark(&mut self, name: &str) -> bool {
+        return self.bookmarks.remove(name);
+    }
+
+    ///Every bookmark of the loaded game, ordered by frame
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        return self.bookmarks.list();
+    }
+
+    ///Loads the bookmark's state, returns false when there is no bookmark with that name
+    pub fn jump_to_bookmark(&mut self, name: &str) -> Result<bool, SaveStateError> {
+        let Some(bookmark) = self.bookmarks.get(name) else {
+            return Ok(false);
+        };
+
+        let state = bookmark.state().to_vec();
+        self.load_state(&state)?;
+
+        Ok(true)
+    }
+
+    ///Loads the first bookmark after the current frame, returns its name (None when there is none)
+    pub fn next_bookmark(&mut self) -> Result<Option<String>, SaveStateError> {
+        let Some(bookmark) = self.bookmarks.next(self.frame_number()) else {
+            return Ok(None);
+        };
+
+        let (name, state) = (bookmark.name.clone(), bookmark.state().to_vec());
+        self.load_state(&state)?;
+
+        Ok(Some(name))
+    }
+
+    ///Loads the last bookmark before the current frame, returns its name (None when there is none)
+    pub fn previous_bookmark(&mut self) -> Result<Option<String>, SaveStateError> {
+        let Some(bookmark) = self.bookmarks.previous(self.frame_number()) else {
+            return Ok(None);
+        };
+
+        let (name, state) = (bookmark.name.clone(), bookmark.state().to_vec());
+        self.load_state(&state)?;
+
+        Ok(Some(name))
+    }
+
+    ///Header metadata of the loaded game, None when no game is loaded
+    pub fn cartridge_info(&self) -> Option<CartridgeInfo> {
+        let cartridge = self.bus.borrow().get_cartridge()?;
+
+        return Some(cartridge.borrow().info().clone());
+    }
+
+    ///CRC-32 of the loaded game's PRG-ROM and CHR-ROM, None when no game is loaded
+    pub fn rom_crc32(&self) -> Option<u32> {
+        let cartridge = self.bus.borrow().get_cartridge()?;
+
+        return Some(cartridge.borrow().rom_crc32());
+    }
+
+    ///Name of the loaded game when the game table knows its dump, looked up by rom_crc32()
+    pub fn game_title(&self) -> Option<&'static str> {
+        return self.rom_crc32().and_then(games::title);
+    }
+
+    ///The NES 2.0 miscellaneous ROM area (VS. System and other boards' extra chips), None when the game has none
+    ///(or no game is loaded)<br>
+    ///Nothing reads it yet, it is kept for the devices that will
+    pub fn misc_rom(&self) -> Option<Vec<u8>> {
+        let cartridge = self.bus.borrow().get_cartridge()?;
+        let cartridge = cartridge.borrow();
+
+        if cartridge.misc_rom.is_empty() {
+            return None;
+        }
+
+        return Some(cartridge.misc_rom.clone());
+    }
+
+    ///The INST-ROM and PROM of a PlayChoice-10 image, None for other games (or when no game is loaded)<br>
+    ///The game itself runs as an NES cartridge, the arcade's instruction screen isn't emulated
+    pub fn playchoice_roms(&self) -> Option<PlayChoiceRoms> {
+        let cartridge = self.bus.borrow().get_cartridge()?;
+
+        return cartridge.borrow().playchoice.clone();
+    }
+
+    //Battery RAM
+
+    ///The battery backed PRG-RAM (or the EEPROM of Bandai boards) to keep in a .sav file, None when the game has no
+    ///battery (or no game is loaded)
+    pub fn battery_ram(&self) -> Option<Vec<u8>> {
+        let cartridge = self.bus.borrow().get_cartridge()?;
+        let cartridge = cartridge.borrow();
+
+        return cartridge.battery_ram().map(|ram| ram.to_vec());
+    }
+
+    ///Restores a .sav file, returns false when the game has no battery or the file has the wrong size
+    pub fn load_battery_ram(&mut self, data: &[u8]) -> bool {
+        let Some(cartridge) = self.bus.borrow().get_cartridge() else {
+            return false;
+        };
+
+        return cartridge.borrow_mut().load_battery_ram(data);
+    }
+
+    //Debug Access
+
+    ///Reads CPU memory without side effects ($2002 keeps VBlank, $2007 doesn't increment, ...)
+    pub fn peek(&self, address: u16) -> u8 {
+        return self.bus.borrow().peek(address);
+    }
+
+    ///Writes CPU memory without side effects: RAM or the mapped PRG byte, registers are left alone<br>
+    ///Returns false when nothing was written
+    pub fn poke(&mut self, address: u16, data: u8) -> bool {
+        return self.bus.borrow_mut().poke(address, data);
+    }
+
+    ///Reads PPU memory (pattern tables, nametables, palettes) without touching the VRAM address or read buffer
+    pub fn ppu_peek(&self, address: u16) -> u8 {
+        return self.ppu.borrow().ppu_read(address & 0x3FFF);
+    }
+
+    ///Writes PPU memory without touching the VRAM address, CHR-ROM stays read only
+    pub fn ppu_poke(&mut self, address: u16, data: u8) {
+        self.ppu.borrow_mut().ppu_write(address & 0x3FFF, data);
+    }
+
+    ///Scroll (v and fine X) every scanline of the last frame was drawn with, to inspect raster splits
+    pub fn scanline_scroll(&self) -> Vec<ScanlineScroll> {
+        return self.ppu.borrow().get_scanline_scroll().to_vec();
+    }
+
+    ///What is mapped where in the CPU address space right now, with the banks the mapper has switched in<br>
+    ///For labelling the hex view and the disassembly, ask again after the game runs since the banks change
+    pub fn memory_map(&self) -> Vec<MemoryRegion> {
+        return self.bus.borrow().memory_map();
+    }
+
+    ///Same as memory_map() for the PPU address space (pattern tables, nametables, palettes)
+    pub fn ppu_memory_map(&self) -> Vec<MemoryRegion> {
+        return self.bus.borrow().ppu_memory_map();
+    }
+
+    ///Region of the CPU address space the address falls in
+    pub fn memory_region(&self, address: u16) -> Option<MemoryRegion> {
+        return self.memory_map().into_iter().find(|region| region.contains(address));
+    }
+
+    ///Decodes the instructions between two addresses (inclusive) without side effects
+    pub fn disassemble(&self, start: u16, end: u16) -> Vec<DisasmLine> {
+        let bus = self.bus.borrow();
+
+        return disassembler::disassemble(|address| bus.peek(address), start, end);
+    }
+
+    ///Address of the next instruction the CPU will run
+    pub fn get_program_counter(&self) -> u16 {
+        return self.system.get_cpu().borrow().get_program_counter();
+    }
+
+    ///Jumps to an address, takes effect at the next instruction (call it between step_instruction() calls)<br>
+    ///Used to start test ROMs like nestest in automation mode ($C000)
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.system.get_cpu().borrow_mut().set_program_counter(address);
+    }
+
+    pub fn cpu_registers(&self) -> CpuRegisters {
+        return self.system.get_cpu().borrow().get_registers();
+    }
+
+    ///CPU cycles since the last reset
+    pub fn cpu_cycles(&self) -> u64 {
+        return self.system.get_cpu_cycle_count();
+    }
+
+    //Trace Logging
+
+    ///Logs every instruction the CPU starts to the writer in the nestest.log layout
+    ///(PC, bytes, disassembly, A/X/Y/P/SP, PPU scanline and dot, CPU cycles)<br>
+    ///Can be turned on and off at any time, a running trace is finished first
+    pub fn start_trace(&mut self, writer: impl Write + 'static) -> io::Result<()> {
+        return self.system.start_trace(Box::new(writer));
+    }
+
+    ///Stops the trace and flushes its writer, returns the first write error of the trace
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        return self.system.stop_trace();
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        return self.system.is_tracing();
+    }
+
+    //Instruction Coverage
+
+    ///Starts counting the executed opcodes and marking the PRG bytes ($8000 - $FFFF) run as instructions, to see how
+    ///much of a game a run exercises and which unofficial opcodes it uses<br>
+    ///Keeps collecting into the current coverage if it is already on
+    pub fn enable_coverage(&mut self) {
+        self.system.get_cpu().borrow_mut().enable_coverage();
+    }
+
+    ///Stops collecting and returns the coverage, see Coverage::export() to write it as CSV
+    pub fn disable_coverage(&mut self) -> Option<Coverage> {
+        return self.system.get_cpu().borrow_mut().disable_coverage();
+    }
+
+    ///A copy of the coverage collected so far, None when it is off
+    pub fn coverage(&self) -> Option<Coverage> {
+        return self.system.get_cpu().borrow().get_coverage().cloned();
+    }
+
+    ///Starts the coverage over without turning it off
+    pub fn clear_coverage(&mut self) {
+        if let Some(coverage) = self.system.get_cpu().borrow_mut().get_coverage_mut() {
+            coverage.clear();
+        }
+    }
+
+    //Timing Trace
+
+    ///Starts recording how long every frame takes and how much of it went to the CPU, PPU and APU<br>
+    ///Replaces a trace that is already running
+    pub fn start_timing_trace(&mut self) {
+        self.timing = Some(TimingTrace::new());
+        self.system.set_subsystem_timing(true);
+    }
+
+    ///Stops recording and returns the trace, to be written with TimingTrace::write_json()
+    pub fn stop_timing_trace(&mut self) -> Option<TimingTrace> {
+        self.system.set_subsystem_timing(false);
+
+        return self.timing.take();
+    }
+
+    ///Adds a span the host measured (timing::AUDIO_SPAN, timing::PRESENT_SPAN, ...), ignored without a trace
+    pub fn record_span(&mut self, name: &str, start: Instant, end: Instant) {
+        if let Some(trace) = &mut self.timing {
+            trace.record(name, start, end);
+        }
+    }
+
+    //Write Interceptors
+
+    ///Calls the callback with (address, current value, new value) before each write the game makes in the range<br>
+    ///Returning WriteAction::Veto or Replace lets trainers keep values from changing (lives, timers, ...)
+    pub fn add_write_interceptor(
+        &mut self,
+        range: RangeInclusive<u16>,
+        callback: impl FnMut(u16, u8, u8) -> WriteAction + 'static,
+    ) -> InterceptorId {
+        return self.bus.borrow_mut().register_write_interceptor(range, Box::new(callback));
+    }
+
+    ///Returns false if the id is unknown, also removes freeze() cheats
+    pub fn remove_write_interceptor(&mut self, id: InterceptorId) -> bool {
+        self.frozen.retain(|&(frozen, _, _)| frozen != id);
+
+        return self.bus.borrow_mut().remove_write_interceptor(id);
+    }
+
+    ///Writes the value and keeps the game from changing it (a RAM cheat), remove it with remove_write_interceptor()<br>
+    ///Unlike other interceptors the value is written again after load_state()
+    pub fn freeze(&mut self, address: u16, value: u8) -> InterceptorId {
+        self.bus.borrow_mut().poke(address, value);
+
+        let id = self.add_write_interceptor(address..=address, move |_, _, _| WriteAction::Replace(value));
+        self.frozen.push((id, address, value));
+
+        return id;
+    }
+
+    //Cheats
+
+    ///Adds an enabled Game Genie (6 or 8 letters) or raw (AAAA:VV, AAAA?CC:VV) code, see cheats::CheatCode<br>
+    ///Loading another ROM removes every code
+    pub fn add_cheat(&mut self, code: &str) -> Result<CheatId, CheatError> {
+        let id = self.cheats.add(code)?;
+        self.apply_cheats();
+
+        Ok(id)
+    }
+
+    ///Returns false if the id is unknown
+    pub fn remove_cheat(&mut self, id: CheatId) -> bool {
+        let removed = self.cheats.remove(id);
+        self.apply_cheats();
+
+        return removed;
+    }
+
+    ///Returns false if the id is unknown
+    pub fn set_cheat_enabled(&mut self, id: CheatId, enabled: bool) -> bool {
+        let found = self.cheats.set_enabled(id, enabled);
+        self.apply_cheats();
+
+        return found;
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+        self.apply_cheats();
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        return self.cheats.cheats();
+    }
+
+    ///The codes as a cheat file for the game (game.cht), in the RNES format or for FCEUX or Nestopia
+    pub fn export_cheats(&self, format: CheatFormat) -> String {
+        return self.cheats.to_text(format);
+    }
+
+    ///Adds the codes of a cheat file written by export_cheats(), FCEUX or Nestopia (the format is detected),
+    ///none are added if one is invalid
+    pub fn import_cheats(&mut self, text: &str) -> Result<(), CheatError> {
+        self.cheats.load_text(text)?;
+        self.apply_cheats();
+
+        Ok(())
+    }
+
+    fn apply_cheats(&mut self) {
+        self.bus.borrow_mut().set_cheats(self.cheats.active());
+    }
+
+    //Debugger
+
+    ///Stops run_until_break() before the instruction at the address runs
+    pub fn add_breakpoint(&mut self, address: u16) -> BreakpointId {
+        return self.bus.borrow().get_debugger().borrow_mut().add_breakpoint(address);
+    }
+
+    ///Stops run_until_break() after the instruction that read or wrote an address in the range
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, kind: WatchKind) -> BreakpointId {
+        return self.bus.borrow().get_debugger().borrow_mut().add_watchpoint(range, kind);
+    }
+
+    ///Removes a breakpoint or watchpoint, returns false if the id is unknown
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> bool {
+        return self.bus.borrow().get_debugger().borrow_mut().remove(id);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.bus.borrow().get_debugger().borrow_mut().clear();
+    }
+
+    ///Called with every hit before run_until_break(), run_to() or run_cycles() returns it
+    pub fn set_debug_callback(&mut self, callback: impl FnMut(&DebugHit) + 'static) {
+        self.bus.borrow().get_debugger().borrow_mut().set_callback(Some(Box::new(callback)));
+    }
+
+    ///Runs instruction by instruction until a breakpoint or watchpoint is hit or the frames run out (None)<br>
+    ///The instruction at the current PC always runs first, so calling it again resumes from a breakpoint
+    pub fn run_until_break(&mut self, frames: u32) -> Option<DebugHit> {
+        return self.run_debugger(None, frames);
+    }
+
+    ///Like run_until_break() with a one-shot breakpoint at the address
+    pub fn run_to(&mut self, address: u16, frames: u32) -> Option<DebugHit> {
+        return self.run_debugger(Some(address), frames);
+    }
+
+    fn run_debugger(&mut self, run_to: Option<u16>, frames: u32) -> Option<DebugHit> {
+        //Accesses made before this run don't count
+        self.bus.borrow().get_debugger().borrow_mut().take_watch_hit();
+
+        let last_frame = self.system.get_stats().frames + frames as u64;
+
+        while self.system.get_stats().frames < last_frame {
+            self.run_system(System::step_instruction);
+
+            if let Some(hit) = self.debug_hit(run_to) {
+                return Some(hit);
+            }
+        }
+
+        return None;
+    }
+
+    ///Checked between instructions: the watchpoint hit by the last one, or a breakpoint at the next one
+    fn debug_hit(&self, run_to: Option<u16>) -> Option<DebugHit> {
+        let address = self.get_program_counter();
+
+        let bus = self.bus.borrow();
+        let mut debugger = bus.get_debugger().borrow_mut();
+
+        let hit = debugger
+            .take_watch_hit()
+            .or_else(|| debugger.breakpoint_at(address))
+            .or_else(|| (run_to == Some(address)).then_some(DebugHit::RunTo { address }));
+
+        if let Some(hit) = &hit {
+            debugger.notify(hit);
+        }
+
+        return hit;
+    }
+
+    //Watch Expressions
+
+    ///Adds a value to show after every frame or step (a byte, a 16 bit pair, registers), see WatchExpression
+    ///for the syntax: `emulator.add_watch("$0010.w,s".parse()?)`
+    pub fn add_watch(&mut self, expression: WatchExpression) -> WatchId {
+        return self.bus.borrow().get_debugger().borrow_mut().add_watch(expression);
+    }
+
+    ///Returns false if the id is unknown
+    pub fn remove_watch(&mut self, id: WatchId) -> bool {
+        return self.bus.borrow().get_debugger().borrow_mut().remove_watch(id);
+    }
+
+    pub fn clear_watches(&mut self) {
+        self.bus.borrow().get_debugger().borrow_mut().clear_watches();
+    }
+
+    ///Evaluates every watch expression now, in the order they were added, without side effects on the console<br>
+    ///Call it after each frame or step to refresh a watch panel
+    pub fn watches(&self) -> Vec<WatchValue> {
+        let registers = self.cpu_registers();
+        let bus = self.bus.borrow();
+        let debugger = bus.get_debugger().borrow();
+
+        return debugger
+            .watches()
+            .iter()
+            .map(|&(id, expression)| {
+                let value = expression.evaluate(|address| bus.peek(address), &registers);
+
+                WatchValue {
+                    id,
+                    expression,
+                    value,
+                    text: expression.format_value(value),
+                }
+            })
+            .collect();
+    }
+
+    //Diagnostics
+
+    ///Every opcode and board register the game used that isn't emulated, in the order they were first hit<br>
+    ///Each one is logged once, with the address of its first use
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        return self.bus.borrow().get_diagnostics().borrow().log().to_vec();
+    }
+
+    ///Forgets the logged diagnostics, so they are reported (and pause) again
+    pub fn clear_diagnostics(&mut self) {
+        self.bus.borrow().get_diagnostics().borrow_mut().clear();
+    }
+
+    ///Turns the diagnostics logged during the last step into events, and pauses if asked to
+    fn check_diagnostics(&mut self) {
+        let diagnostics = self.bus.borrow().get_diagnostics().borrow_mut().take_new();
+
+        for diagnostic in diagnostics {
+            self.events.push(EmulatorEvent::Diagnostic(diagnostic));
+
+            if self.pause_on_diagnostic {
+                self.pause(PauseReason::Diagnostic(diagnostic));
+            }
+        }
+    }
+
+    //Virtual Devices
+
+    ///Maps a device of the host into an unused address range inside HANDLER_RANGE ($4018 - $5FFF): the game's reads
+    ///of the range return `read(address)` and its writes call `write(address, data)`<br>
+    ///Returns None when the range leaves HANDLER_RANGE or overlaps another device (the debug port's included)
+    pub fn register_bus_handler(
+        &mut self,
+        range: RangeInclusive<u16>,
+        read: impl FnMut(u16) -> u8 + 'static,
+        write: impl FnMut(u16, u8) + 'static,
+    ) -> Option<HandlerId> {
+        return self.bus.borrow_mut().register_handler(range, Box::new(read), Box::new(write));
+    }
+
+    ///Unmaps a device added by register_bus_handler(), returns false if the id is unknown
+    pub fn remove_bus_handler(&mut self, id: HandlerId) -> bool {
+        return self.bus.borrow_mut().remove_handler(id);
+    }
+
+    //Debug Port
+
+    ///Maps the homebrew debug port: text written to the print address is captured (and echoed), a write to the
+    ///exit address ends run_until_exit() with that exit code. Reads of both addresses return 0<br>
+    ///Returns false when an address is outside $4018 - $5FFF or already used by another device
+    pub fn enable_debug_port(&mut self, config: DebugPortConfig) -> bool {
+        self.disable_debug_port();
+
+        let port = DebugPort::new(config.echo);
+        let mut bus = self.bus.borrow_mut();
+
+        let print_port = port.clone();
+        let print = bus.register_handler(
+            config.print_address..=config.print_address,
+            Box::new(|_| 0),
+            Box::new(move |_, data| print_port.borrow_mut().print(data)),
+        );
+
+        let exit_port = port.clone();
+        let exit = bus.register_handler(
+            config.exit_address..=config.exit_address,
+            Box::new(|_| 0),
+            Box::new(move |_, data| exit_port.borrow_mut().exit(data)),
+        );
+
+        match (print, exit) {
+            (Some(print), Some(exit)) => {
+                drop(bus);
+                self.debug_port = Some((port, vec![print, exit]));
+
+                return true;
+            }
+            (print, exit) => {
+                for id in [print, exit].into_iter().flatten() {
+                    bus.remove_handler(id);
+                }
+
+                return false;
+            }
+        }
+    }
+
+    pub fn disable_debug_port(&mut self) {
+        if let Some((_, handlers)) = self.debug_port.take() {
+            let mut bus = self.bus.borrow_mut();
+
+            for id in handlers {
+                bus.remove_handler(id);
+            }
+        }
+    }
+
+    ///Takes the lines printed to the debug port since the last call
+    pub fn debug_output(&mut self) -> String {
+        match &self.debug_port {
+            Some((port, _)) => return port.borrow_mut().take_output(),
+            None => return String::new(),
+        }
+    }
+
+    ///The code written to the exit address since the last reset
+    pub fn exit_code(&self) -> Option<u8> {
+        return self.debug_port.as_ref().and_then(|(port, _)| port.borrow().get_exit_code());
+    }
+
+    ///Headless test runs: emulates until the game writes to the exit address or the frames run out<br>
+    ///Stops right after the instruction that wrote the exit code, returns None on timeout (or without a debug port)
+    pub fn run_until_exit(&mut self, frames: u32) -> Option<u8> {
+        self.debug_port.as_ref()?;
+
+        let last_frame = self.system.get_stats().frames + frames as u64;
+
+        while self.system.get_stats().frames < last_frame {
+            self.run_system(System::step_instruction);
+
+            if let Some(code) = self.exit_code() {
+                return Some(code);
+            }
+        }
+
+        return None;
+    }
+
+    //Statistics
+
+    ///Counters since the emulator was created (or reset_stats), reset() and load_state() keep them
+    pub fn stats(&self) -> Stats {
+        return self.system.get_stats();
+    }
+
+    pub fn reset_stats(&mut self) {
+        *self.system.get_stats_mut() = Stats::default();
+    }
+
+    ///Called by the frontend when the audio device ran out of samples
+    pub fn record_audio_underruns(&mut self, count: u64) {
+        self.system.get_stats_mut().audio_underruns += count;
+    }
+
+    ///Called by the frontend when frames couldn't be presented in time
+    pub fn record_dropped_frames(&mut self, count: u64) {
+        self.system.get_stats_mut().dropped_frames += count;
+    }
+
+    ///Colors the frame buffer and frame() use from the next pixel on, see Palette::from_file() for .pal files
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.ppu.borrow_mut().set_palette(palette);
+    }
+
+    pub fn palette(&self) -> Palette {
+        return self.ppu.borrow().get_palette().clone();
+    }
+
+    ///Hides the background or the sprites from the next pixel on, to look at a scene or capture clean sprites<br>
+    ///The layer is still emulated: sprite 0 hits and the game behave the same
+    pub fn set_layer_visible(&mut self, layer: Layer, visible: bool) {
+        self.ppu.borrow_mut().set_layer_visible(layer, visible);
+    }
+
+    pub fn is_layer_visible(&self, layer: Layer) -> bool {
+        return self.ppu.borrow().is_layer_visible(layer);
+    }
+
+    ///Draws more than 8 sprites per scanline, or rotates the 8 drawn every frame, from the next scanline on<br>
+    ///Only the screen changes: sprite 0 hits, the overflow flag and the game behave the same
+    pub fn set_sprite_limit(&mut self, limit: SpriteLimit) {
+        self.ppu.borrow_mut().set_sprite_limit(limit);
+    }
+
+    pub fn sprite_limit(&self) -> SpriteLimit {
+        return self.ppu.borrow().get_sprite_limit();
+    }
+
+    ///The last frame as 256x240 0x00RRGGBB pixels, row by row
+    pub fn frame_buffer(&self) -> Ref<'_, [u32]> {
+        return Ref::map(self.ppu.borrow(), |ppu| ppu.get_screen());
+    }
+
+    ///The last frame converted to a pixel format, 256x240 pixels row by row with no padding
+    pub fn frame(&self, format: PixelFormat) -> Vec<u8> {
+        let mut output = vec![0; format.frame_size(SCREEN_WIDTH * SCREEN_HEIGHT)];
+        self.copy_frame(format, &mut output);
+
+        return output;
+    }
+
+    ///Converts the last frame into a buffer the caller keeps between frames (a texture upload buffer, a
+    ///libretro frame, ...), returns false and leaves it untouched when it is smaller than a frame
+    pub fn copy_frame(&self, format: PixelFormat, output: &mut [u8]) -> bool {
+        if output.len() < format.frame_size(SCREEN_WIDTH * SCREEN_HEIGHT) {
+            return false;
+        }
+
+        let ppu = self.ppu.borrow();
+        frame::convert(ppu.get_screen_indices(), ppu.get_palette(), format, output);
+
+        return true;
+    }
+
+    ///The last frame for a screenshot or a recorded frame, see Capture::write_png()<br>
+    ///Display captures are the pixels frame_buffer() holds, so files look the same as the window
+    pub fn capture(&self, colors: CaptureColors) -> Capture {
+        let ppu = self.ppu.borrow();
+
+        match colors {
+            CaptureColors::Display => return Capture::display(ppu.get_screen()),
+            CaptureColors::Indexed => return Capture::indexed(ppu.get_screen_indices(), ppu.get_palette()),
+            CaptureColors::Ntsc => return Capture::ntsc(&self.ntsc_frame()),
+        }
+    }
+
+    ///The last frame decoded from an NTSC composite signal built out of its palette indexes and emphasis, 256x240
+    ///0x00RRGGBB pixels: colors blend across neighbouring pixels the way they do on a TV
+    pub fn ntsc_frame(&self) -> Vec<u32> {
+        let ppu = self.ppu.borrow();
+
+        return ntsc::decode_frame(ppu.get_screen_indices(), (ppu.frame_count & 0x01) != 0);
+    }
+
+    ///Takes the mono samples (about -1.0 - 1.0, filtered and resampled to the sample rate) produced since the last call
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        return self.system.take_audio_samples();
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        return self.system.get_sample_rate();
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.system.set_sample_rate(sample_rate);
+    }
+
+    ///Plugs a device (Zapper, paddle, ...) into controller port 0 ($4016) or 1 ($4017) in place of the controller
+    ///that was there, set_input() then goes to the device<br>
+    ///Returns false when the port doesn't exist
+    pub fn connect_input_device(&mut self, port: usize, device: impl InputDevice + 'static) -> bool {
+        return self.bus.borrow_mut().connect_input_device(port, Box::new(device));
+    }
+
+    ///Unplugs the device in port 0 or 1, reads of its register then only return open bus<br>
+    ///Returns false when the port was already empty or doesn't exist
+    pub fn disconnect_input_device(&mut self, port: usize) -> bool {
+        return self.bus.borrow_mut().disconnect_input_device(port);
+    }
+
+    ///Plugs a device into the Famicom expansion port (keyboard, multitap, ...), replacing the one that was there<br>
+    ///It receives every $4016 write and its data lines are ORed into the reads of both $4016 and $4017
+    pub fn connect_expansion_device(&mut self, device: impl InputDevice + 'static) {
+        self.bus.borrow_mut().connect_expansion_device(Box::new(device));
+    }
+
+    ///Returns false when the expansion port was already empty
+    pub fn disconnect_expansion_device(&mut self) -> bool {
+        return self.bus.borrow_mut().disconnect_expansion_device();
+    }
+
+    ///Presses or releases a button of the controller in port 0 or 1<br>
+    ///With a FourScore in both ports, 2 and 3 are players 3 and 4 (the input history only keeps ports 0 and 1)
+    pub fn set_input(&mut self, port: usize, button: Button, pressed: bool) {
+        self.bus.borrow_mut().set_button_state(port, button, pressed);
+        self.input_history.set_button(port, button, pressed);
+    }
+
+    ///Times the game strobed the controllers (wrote 1 to $4016 bit 0) since power on, the moment a standard
+    ///controller latches the buttons: the first strobe after a change is when the game sees it
+    pub fn input_strobes(&self) -> u64 {
+        return self.bus.borrow().input_strobes();
+    }
+
+    ///The buttons held during every frame step_frame()/run_frame() ran this session
+    pub fn input_history(&self) -> &InputHistory {
+        return &self.input_history;
+    }
+
+    pub fn clear_input_history(&mut self) {
+        self.input_history.clear();
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
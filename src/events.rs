@@ -0,0 +1,65 @@
+use std::fmt;
+
+use crate::{debugger::DebugHit, diagnostics::Diagnostic};
+
+///Why the emulation is paused
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PauseReason {
+    User,
+    FocusLost,                             //Auto-pause: the window went to the background
+    ControllerDisconnected { port: usize }, //Auto-pause: the device assigned to the port went away
+    Diagnostic(Diagnostic),                 //Pause on diagnostic: the game hit something that isn't emulated
+}
+
+impl fmt::Display for PauseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PauseReason::User => write!(f, "paused"),
+            PauseReason::FocusLost => write!(f, "paused: window lost focus"),
+            PauseReason::ControllerDisconnected { port } => {
+                write!(f, "paused: controller {} disconnected", port + 1)
+            }
+            PauseReason::Diagnostic(diagnostic) => write!(f, "paused: {}", diagnostic),
+        }
+    }
+}
+
+///How far a queued step runs, see Emulator::queue_step()
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepSize {
+    Instruction,
+    Scanline,
+    Frame,
+}
+
+///What the next Emulator::run_frame() call does
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RunState {
+    Running,
+    Paused(PauseReason),
+    Stepping(StepSize), //Paused with a step queued: it runs once and the emulation is paused again
+}
+
+///Why Emulator::run_cycles() returned
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CycleStop {
+    Budget,                           //Every cycle ran
+    FrameComplete,                    //The PPU finished a frame on the last cycle
+    Debug(DebugHit),                  //A breakpoint or watchpoint, the next instruction hasn't run yet
+    Jam { opcode: u8, address: u16 }, //The next instruction is a JAM, which locks up a real console
+}
+
+///What one Emulator::run_cycles() call did
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CyclesRun {
+    pub cycles: u64, //Master clock ticks that ran, at most the budget
+    pub stop: CycleStop,
+}
+
+///Notifications for embedders, collected until Emulator::take_events()
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EmulatorEvent {
+    Paused(PauseReason),
+    Resumed,
+    Diagnostic(Diagnostic), //Logged for the first time
+}
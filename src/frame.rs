@@ -0,0 +1,95 @@
+use crate::palette::Palette;
+
+///Layouts the frame can be copied out in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PixelFormat {
+    Rgba8,        //R, G, B, A bytes (WebGL/canvas ImageData)
+    Bgra8,        //B, G, R, A bytes (XRGB8888 on little endian: libretro, most windowing APIs)
+    Rgb565,       //16 bit little endian, red in the top 5 bits (small LCDs, libretro RGB565)
+    PaletteIndex, //One byte per pixel, the 2C02 color (0 - 63) without emphasis: for frontends with their own palette or shaders
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 => 4,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::PaletteIndex => 1,
+        }
+    }
+
+    ///Size of a whole frame in this format
+    pub fn frame_size(&self, pixels: usize) -> usize {
+        return pixels * self.bytes_per_pixel();
+    }
+}
+
+///Writes the frame, given as indexes into the palette's 512 colors, into output (frame_size() bytes, the rest is
+///left alone)<br>
+///Every format is a lookup in a table built once per call, so no color math runs per pixel
+pub(crate) fn convert(indices: &[u16], palette: &Palette, format: PixelFormat, output: &mut [u8]) {
+    match format {
+        PixelFormat::PaletteIndex => {
+            for (pixel, &index) in output.iter_mut().zip(indices) {
+                *pixel = (index & 0x3F) as u8;
+            }
+        }
+        PixelFormat::Rgba8 => expand(indices, palette, output, |color| {
+            let [_, red, green, blue] = color.to_be_bytes();
+            [red, green, blue, 0xFF]
+        }),
+        PixelFormat::Bgra8 => expand(indices, palette, output, |color| {
+            let [_, red, green, blue] = color.to_be_bytes();
+            [blue, green, red, 0xFF]
+        }),
+        PixelFormat::Rgb565 => expand(indices, palette, output, |color| {
+            let [_, red, green, blue] = color.to_be_bytes();
+            let packed = ((red as u16 >> 3) << 11) | ((green as u16 >> 2) << 5) | (blue as u16 >> 3);
+            packed.to_le_bytes()
+        }),
+    }
+}
+
+fn expand<const N: usize>(indices: &[u16], palette: &Palette, output: &mut [u8], encode: impl Fn(u32) -> [u8; N]) {
+    let table: Vec<[u8; N]> = palette.colors().iter().map(|&color| encode(color)).collect();
+
+    for (pixel, &index) in output.chunks_exact_mut(N).zip(indices) {
+        pixel.copy_from_slice(&table[(index & 0x1FF) as usize]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_encode_the_palette_color() {
+        //0x21: 0x4C9AEC
+        let indices = [0x21, 0x0F];
+        let palette = Palette::default();
+
+        let mut output = [0; 8];
+        convert(&indices, &palette, PixelFormat::Rgba8, &mut output);
+        assert_eq!(output, [0x4C, 0x9A, 0xEC, 0xFF, 0x00, 0x00, 0x00, 0xFF]);
+
+        convert(&indices, &palette, PixelFormat::Bgra8, &mut output);
+        assert_eq!(output, [0xEC, 0x9A, 0x4C, 0xFF, 0x00, 0x00, 0x00, 0xFF]);
+
+        let mut output = [0; 4];
+        convert(&indices, &palette, PixelFormat::Rgb565, &mut output);
+        assert_eq!(u16::from_le_bytes([output[0], output[1]]), (0x09 << 11) | (0x26 << 5) | 0x1D);
+        assert_eq!(&output[2..], &[0, 0]);
+
+        //Emphasized colors keep their index but not their RGB value
+        let indices = [0x21 | (0b111 << 6), 0x0F];
+
+        let mut output = [0xAA; 3];
+        convert(&indices, &palette, PixelFormat::PaletteIndex, &mut output);
+        assert_eq!(output, [0x21, 0x0F, 0xAA]);
+
+        let mut output = [0; 8];
+        convert(&indices, &palette, PixelFormat::Rgba8, &mut output);
+        let [_, red, green, blue] = palette.color(0x21, 0b111).to_be_bytes();
+        assert_eq!(&output[..4], &[red, green, blue, 0xFF]);
+    }
+}
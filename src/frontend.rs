@@ -0,0 +1,1061 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use rnes::{
+    audio::{AudioSink, NullSink, WavSink},
+    storage::GameStorage,
+    timing::{AUDIO_SPAN, PRESENT_SPAN},
+    video::{FrameBlend, VideoSink},
+    Button, CaptureColors, ConsoleType, Controller, Emulator, EmulatorEvent, FourScore, LatencyMeter, Layer,
+    PauseReason, Region, SpriteLimit, StepSize, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+use crate::FrontendOptions;
+
+///Every button, used to release a whole controller
+const BUTTONS: [Button; 8] = [
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+];
+
+///A named input layout: which key drives which button of which port and which gamepad plays in which port<br>
+///With a Four Score plugged in, ports 2 and 3 are players 3 and 4
+struct InputProfile {
+    name: &'static str,
+    bindings: &'static [(Key, usize, Button)],
+    gamepads: &'static [(usize, usize)], //Gamepad (0 for the first one connected) and port, needs the gamepad feature
+    four_score: bool,
+}
+
+impl InputProfile {
+    ///Profiles with gamepads are left out of the cycle without the gamepad feature, nothing could play in their ports
+    fn is_available(&self) -> bool {
+        return self.gamepads.is_empty() || cfg!(feature = "gamepad");
+    }
+}
+
+///Profiles cycled with PROFILE_KEY (the available ones), the first one is active at start
+const PROFILES: [InputProfile; 4] = [
+    InputProfile {
+        name: "keyboard P1",
+        bindings: &[
+            (Key::X, 0, Button::A),
+            (Key::Z, 0, Button::B),
+            (Key::RightShift, 0, Button::Select),
+            (Key::Enter, 0, Button::Start),
+            (Key::Up, 0, Button::Up),
+            (Key::Down, 0, Button::Down),
+            (Key::Left, 0, Button::Left),
+            (Key::Right, 0, Button::Right),
+        ],
+        gamepads: &[],
+        four_score: false,
+    },
+    InputProfile {
+        name: "keyboard P1 + P2",
+        bindings: &[
+            (Key::G, 0, Button::A),
+            (Key::F, 0, Button::B),
+            (Key::Q, 0, Button::Select),
+            (Key::E, 0, Button::Start),
+            (Key::W, 0, Button::Up),
+            (Key::S, 0, Button::Down),
+            (Key::A, 0, Button::Left),
+            (Key::D, 0, Button::Right),
+            (Key::L, 1, Button::A),
+            (Key::K, 1, Button::B),
+            (Key::RightShift, 1, Button::Select),
+            (Key::Enter, 1, Button::Start),
+            (Key::Up, 1, Button::Up),
+            (Key::Down, 1, Button::Down),
+            (Key::Left, 1, Button::Left),
+            (Key::Right, 1, Button::Right),
+        ],
+        gamepads: &[],
+        four_score: false,
+    },
+    InputProfile {
+        name: "gamepad P1 + keyboard P2",
+        bindings: &[
+            (Key::X, 1, Button::A),
+            (Key::Z, 1, Button::B),
+            (Key::RightShift, 1, Button::Select),
+            (Key::Enter, 1, Button::Start),
+            (Key::Up, 1, Button::Up),
+            (Key::Down, 1, Button::Down),
+            (Key::Left, 1, Button::Left),
+            (Key::Right, 1, Button::Right),
+        ],
+        gamepads: &[(0, 0)],
+        four_score: false,
+    },
+    InputProfile {
+        name: "Four Score x4",
+        bindings: &[],
+        gamepads: &[(0, 0), (1, 1), (2, 2), (3, 3)],
+        four_score: true,
+    },
+];
+
+///Switches to the next input profile
+const PROFILE_KEY: Key = Key::F1;
+
+///Shows the session statistics in the title bar
+const STATS_KEY: Key = Key::F2;
+
+///Starts or stops the instruction trace in the game directory (Game.trace.log, see GameStorage)
+const TRACE_KEY: Key = Key::F3;
+
+///Pauses, or resumes whatever paused the game (focus loss, a disconnected controller)
+const PAUSE_KEY: Key = Key::P;
+
+///Runs one frame while paused, held down it keeps advancing at the key repeat rate
+const FRAME_ADVANCE_KEY: Key = Key::N;
+
+///Held down the game runs backwards, REWIND_SPEED times as fast as it plays
+const REWIND_KEY: Key = Key::Backspace;
+const REWIND_SPEED: u32 = 4;
+
+///A snapshot every REWIND_INTERVAL frames, enough of them for about a minute of rewinding
+const REWIND_INTERVAL: u32 = 4;
+const REWIND_SNAPSHOTS: usize = 900;
+
+///Switches to the next region (NTSC, PAL, Dendy) and power cycles the game
+const REGION_KEY: Key = Key::F9;
+
+///Hide or show the background and sprite layers, the game keeps running the same
+const BACKGROUND_LAYER_KEY: Key = Key::F10;
+const SPRITE_LAYER_KEY: Key = Key::F11;
+
+///Cycles the sprites drawn past 8 per scanline: the hardware limit, rotated every frame (flicker), all of them
+const SPRITE_LIMIT_KEY: Key = Key::F6;
+
+///Saves the frame as it is shown to game-001.png, game-002.png, ... in the game directory<br>
+///With Alt held the PNG holds the 2C02 color indexes instead, for tools with their own palette or filter, with
+///Ctrl held the frame decoded from an NTSC composite signal
+const SCREENSHOT_KEY: Key = Key::F12;
+
+///Starts or stops writing every frame as a PNG (the same as a screenshot) into the game.frames folder
+const RECORD_KEY: Key = Key::F8;
+
+///Shows every frame mixed with the one before, so sprites that flicker on alternate frames look steady
+const BLEND_KEY: Key = Key::F4;
+
+///Save and load the state in the game directory (Game.state)
+const SAVE_STATE_KEY: Key = Key::F5;
+const LOAD_STATE_KEY: Key = Key::F7;
+
+///Root of the per-game directories when FrontendOptions::data_directory isn't set, next to the ROM
+const DATA_DIRECTORY: &str = "saves";
+
+///FrontendOptions::auto_save keeps its state apart from the F5 one: game.auto.state
+const AUTO_SAVE_EXTENSION: &str = "auto.state";
+
+///The title shows the game, region and emulation speed, refreshed once per interval
+const TITLE_INTERVAL: Duration = Duration::from_secs(1);
+
+///The WAV file when one was asked for, else the sound device, else nowhere (the game runs silent)
+fn open_audio(options: &FrontendOptions, emulator: &Emulator) -> Result<Box<dyn AudioSink>, String> {
+    if let Some(path) = &options.wav {
+        return match WavSink::create(path, emulator.get_sample_rate()) {
+            Ok(sink) => Ok(Box::new(sink)),
+            Err(error) => Err(format!("could not create {}: {}", path.display(), error)),
+        };
+    }
+
+    return Ok(audio::open().unwrap_or_else(|| Box::new(NullSink::new(emulator.get_sample_rate()))));
+}
+
+///Arcade images run as NES cartridges, without their extra hardware
+fn report_console_type(emulator: &Emulator) {
+    let Some(info) = emulator.cartridge_info() else {
+        return;
+    };
+
+    if info.console_type != ConsoleType::PlayChoice10 {
+        return;
+    }
+
+    match emulator.playchoice_roms() {
+        Some(roms) => eprintln!(
+            "PlayChoice-10 image: running the game alone, the instruction screen isn't emulated \
+             ({} byte INST-ROM, {} byte PROM kept)",
+            roms.inst_rom.len(),
+            roms.prom.len()
+        ),
+        None => eprintln!("PlayChoice-10 image without its INST-ROM: running the game alone"),
+    }
+}
+
+///The game's directory under FrontendOptions::data_directory, the files an older version kept next to the ROM are
+///moved into it
+fn open_storage(rom: &Path, options: &FrontendOptions, emulator: &Emulator, game: &str) -> Result<GameStorage, String> {
+    let root = match &options.data_directory {
+        Some(root) => root.clone(),
+        None => rom.with_file_name(DATA_DIRECTORY),
+    };
+
+    let storage = GameStorage::new(root, emulator.rom_crc32().unwrap_or_default(), game);
+
+    storage
+        .create()
+        .map_err(|error| format!("could not create {}: {}", storage.directory().display(), error))?;
+
+    match storage.migrate(rom) {
+        Ok(migrated) => {
+            for path in migrated {
+                eprintln!("moved to {}", path.display());
+            }
+        }
+        Err(error) => eprintln!("could not move the files next to the ROM: {}", error),
+    }
+
+    return Ok(storage);
+}
+
+///Opens a window and runs the ROM at the frame rate of its region until it is closed or Escape is pressed
+pub fn run(rom: &Path, options: &FrontendOptions) -> Result<(), String> {
+    let mut emulator = Emulator::new();
+    emulator.set_accuracy_profile(options.accuracy);
+
+    if let Some(palette) = &options.palette {
+        emulator.set_palette(palette.clone());
+    }
+    emulator.load_rom(rom).map_err(|error| error.to_string())?;
+    report_console_type(&emulator);
+
+    //Named after the file when the game table doesn't know the dump
+    let game = match emulator.game_title() {
+        Some(title) => title.to_string(),
+        None => rom
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "RNES".to_string()),
+    };
+
+    let storage = open_storage(rom, options, &emulator, &game)?;
+    load_battery_ram(&mut emulator, &storage);
+    load_cheats(&mut emulator, &storage);
+
+    if options.auto_save {
+        resume_auto_save(&mut emulator, &storage);
+    }
+
+    emulator.enable_rewind(REWIND_INTERVAL, REWIND_SNAPSHOTS);
+
+    //Taken from the game's NES 2.0 header or the resumed state
+    let mut region = emulator.region();
+    let mut frame_rate = region.frame_rate();
+
+    let mut screen = WindowSink::open()?;
+    screen.window.set_target_fps(frame_rate.round() as usize);
+
+    let mut audio = open_audio(options, &emulator)?;
+    emulator.set_sample_rate(audio.sample_rate());
+    let mut gamepads = gamepad::Gamepads::open();
+
+    emulator.set_auto_pause(true);
+    emulator.set_pause_on_diagnostic(options.pause_on_diagnostic);
+    let mut focused = true;
+
+    if options.timing_trace.is_some() {
+        emulator.start_timing_trace();
+    }
+
+    let mut title_time = Instant::now();
+    let mut title_frames = 0;
+    let mut speed = 100;
+
+    let mut profile = 0;
+    let mut show_stats = false;
+    update_title(&mut screen.window, &emulator, &game, region, speed, profile, show_stats);
+
+    let mut recording: Option<Recording> = None;
+    let mut blend: Option<FrameBlend> = None;
+    let mut latency = options.input_latency.then(|| InputLatency {
+        meter: LatencyMeter::new(),
+        held: [0; 4],
+        strobes: emulator.input_strobes(),
+    });
+
+    let mut frame_time = Instant::now();
+
+    while screen.window.is_open() && !screen.window.is_key_down(Key::Escape) {
+        if screen.window.is_active() != focused {
+            focused = !focused;
+            emulator.focus_changed(focused);
+        }
+
+        //Auto-pause when a gamepad of the profile goes away
+        if let Some(gamepads) = &mut gamepads {
+            for gamepad in gamepads.update() {
+                for &(_, port) in PROFILES[profile].gamepads.iter().filter(|&&(other, _)| other == gamepad) {
+                    emulator.controller_disconnected(port);
+                }
+            }
+        }
+
+        if screen.window.is_key_pressed(PAUSE_KEY, KeyRepeat::No) {
+            if emulator.is_paused() {
+                emulator.resume();
+            } else {
+                emulator.pause(PauseReason::User);
+            }
+        }
+
+        //The title prompts for the key while paused and goes back to the speed once resumed
+        for event in emulator.take_events() {
+            match event {
+                //Nothing is queued while paused: the sound fades out instead of stopping mid-wave
+                EmulatorEvent::Paused(_) => {
+                    update_title(&mut screen.window, &emulator, &game, region, speed, profile, show_stats);
+                    audio.discontinuity();
+                }
+                //Compatibility gaps go to the terminal, to be copied into bug reports
+                EmulatorEvent::Diagnostic(diagnostic) => {
+                    eprintln!("{}: {}", game, diagnostic);
+                }
+                EmulatorEvent::Resumed => {
+                    update_title(&mut screen.window, &emulator, &game, region, speed, profile, show_stats);
+
+                    title_time = Instant::now();
+                    title_frames = 0;
+                    frame_time = Instant::now();
+
+                    //The device played silence on purpose while paused
+                    audio.take_underruns();
+                }
+            }
+        }
+
+        if emulator.is_paused() {
+            if screen.window.is_key_pressed(FRAME_ADVANCE_KEY, KeyRepeat::Yes) {
+                apply_input(&mut emulator, &screen.window, gamepads.as_ref(), &PROFILES[profile]);
+
+                emulator.queue_step(StepSize::Frame);
+                emulator.run_frame();
+
+                //Single frames of sound would only click
+                emulator.audio_samples();
+            }
+
+            //The last frame stays up, dimmed
+            let frame: Vec<u32> = emulator.frame_buffer().iter().map(|pixel| (pixel >> 1) & 0x7F7F7F).collect();
+
+            screen.present(&frame).map_err(|error| error.to_string())?;
+
+            continue;
+        }
+        //Profiles only change between frames: the devices are swapped and every button is set from the new profile
+        //before the next frame runs
+        if screen.window.is_key_pressed(PROFILE_KEY, KeyRepeat::No) {
+            let next = (1..PROFILES.len())
+                .map(|step| (profile + step) % PROFILES.len())
+                .find(|&next| PROFILES[next].is_available())
+                .unwrap_or(profile);
+
+            if PROFILES[next].four_score != PROFILES[profile].four_score {
+                connect_controllers(&mut emulator, PROFILES[next].four_score);
+            }
+
+            profile = next;
+            update_title(&mut screen.window, &emulator, &game, region, speed, profile, show_stats);
+        }
+
+        if screen.window.is_key_pressed(STATS_KEY, KeyRepeat::No) {
+            show_stats = !show_stats;
+            update_title(&mut screen.window, &emulator, &game, region, speed, profile, show_stats);
+        }
+
+        if screen.window.is_key_pressed(TRACE_KEY, KeyRepeat::No) {
+            toggle_trace(&mut emulator, &storage);
+        }
+
+        if screen.window.is_key_pressed(REGION_KEY, KeyRepeat::No) {
+            let next = Region::ALL.iter().position(|&other| other == region).map_or(0, |index| index + 1);
+
+            region = Region::ALL[next % Region::ALL.len()];
+            emulator.switch_region(region);
+
+            frame_rate = region.frame_rate();
+            screen.window.set_target_fps(frame_rate.round() as usize);
+
+            update_title(&mut screen.window, &emulator, &game, region, speed, profile, show_stats);
+        }
+
+        for (key, layer) in [(BACKGROUND_LAYER_KEY, Layer::Background), (SPRITE_LAYER_KEY, Layer::Sprites)] {
+            if screen.window.is_key_pressed(key, KeyRepeat::No) {
+                emulator.set_layer_visible(layer, !emulator.is_layer_visible(layer));
+            }
+        }
+
+        if screen.window.is_key_pressed(BLEND_KEY, KeyRepeat::No) {
+            blend = if blend.is_some() { None } else { Some(FrameBlend::new()) };
+        }
+
+        if screen.window.is_key_pressed(SPRITE_LIMIT_KEY, KeyRepeat::No) {
+            let limit = match emulator.sprite_limit() {
+                SpriteLimit::Hardware => SpriteLimit::Flicker,
+                SpriteLimit::Flicker => SpriteLimit::Unlimited,
+                SpriteLimit::Unlimited => SpriteLimit::Hardware,
+            };
+
+            emulator.set_sprite_limit(limit);
+        }
+
+        let colors = if screen.window.is_key_down(Key::LeftAlt) || screen.window.is_key_down(Key::RightAlt) {
+            CaptureColors::Indexed
+        } else if screen.window.is_key_down(Key::LeftCtrl) || screen.window.is_key_down(Key::RightCtrl) {
+            CaptureColors::Ntsc
+        } else {
+            CaptureColors::Display
+        };
+
+        if screen.window.is_key_pressed(SCREENSHOT_KEY, KeyRepeat::No) {
+            save_screenshot(&emulator, &storage, colors);
+        }
+
+        if screen.window.is_key_pressed(RECORD_KEY, KeyRepeat::No) {
+            recording = match recording {
+                Some(recording) => {
+                    eprintln!("{} frames written to {}", recording.frames, recording.folder.display());
+                    None
+                }
+                None => Recording::start(&storage, colors),
+            };
+        }
+
+        if screen.window.is_key_pressed(SAVE_STATE_KEY, KeyRepeat::No) {
+            save_state(&emulator, &storage);
+        }
+
+        if screen.window.is_key_pressed(LOAD_STATE_KEY, KeyRepeat::No) {
+            audio.discontinuity();
+            load_state(&mut emulator, &storage);
+        }
+
+        let held = apply_input(&mut emulator, &screen.window, gamepads.as_ref(), &PROFILES[profile]);
+
+        //Polled once per frame, so a change is timed from the poll that saw it
+        if let Some(latency) = &mut latency {
+            if held != latency.held {
+                latency.meter.input_changed(Instant::now());
+                latency.held = held;
+            }
+        }
+
+        //Goes back REWIND_SPEED frames on top of the one run below, which redraws the screen
+        //Rewinding is silent: the jumps between snapshots would only click, the sound fades out and back in around it
+        let rewinding = screen.window.is_key_down(REWIND_KEY);
+        if rewinding {
+            if screen.window.is_key_pressed(REWIND_KEY, KeyRepeat::No) {
+                audio.discontinuity();
+            }
+
+            emulator.rewind(REWIND_SPEED + 1);
+        }
+
+        emulator.run_frame();
+
+        if let Some(latency) = &mut latency {
+            let strobes = emulator.input_strobes();
+            latency.meter.frame_ran(strobes != latency.strobes, Instant::now());
+            latency.strobes = strobes;
+        }
+
+        if let Some(active) = &mut recording {
+            if !active.write(&emulator) {
+                recording = None;
+            }
+        }
+
+        let audio_start = Instant::now();
+        let samples = emulator.audio_samples();
+        if !rewinding {
+            audio.queue(&samples);
+        }
+        emulator.record_audio_underruns(audio.take_underruns());
+        emulator.record_span(AUDIO_SPAN, audio_start, Instant::now());
+
+        let present_start = Instant::now();
+        let presented = match &mut blend {
+            Some(blend) => screen.present(&blend.apply(&emulator.frame_buffer())),
+            None => screen.present(&emulator.frame_buffer()),
+        };
+        presented.map_err(|error| error.to_string())?;
+        emulator.record_span(PRESENT_SPAN, present_start, Instant::now());
+
+        if let Some(latency) = &mut latency {
+            latency.meter.presented(Instant::now());
+        }
+
+        //A frame that took more than two frame periods skipped the ones in between
+        let missed = (frame_time.elapsed().as_secs_f64() * frame_rate).floor() as u64;
+        if missed >= 2 {
+            emulator.record_dropped_frames(missed - 1);
+        }
+        frame_time = Instant::now();
+
+        title_frames += 1;
+
+        let elapsed = title_time.elapsed();
+        if elapsed >= TITLE_INTERVAL {
+            speed = (title_frames as f64 / elapsed.as_secs_f64() / frame_rate * 100.0).round() as u32;
+
+            update_title(&mut screen.window, &emulator, &game, region, speed, profile, show_stats);
+
+            title_time = Instant::now();
+            title_frames = 0;
+        }
+    }
+
+    save_battery_ram(&emulator, &storage);
+
+    if emulator.is_tracing() {
+        toggle_trace(&mut emulator, &storage);
+    }
+
+    if let (Some(path), Some(trace)) = (&options.timing_trace, emulator.stop_timing_trace()) {
+        let result = fs::File::create(path).and_then(|file| trace.write_json(BufWriter::new(file)));
+
+        match result {
+            Ok(()) => eprintln!("timing trace written to {}", path.display()),
+            Err(error) => eprintln!("could not write {}: {}", path.display(), error),
+        }
+    }
+
+    if let Some(latency) = &latency {
+        eprintln!("input latency: {}", latency.meter);
+    }
+
+    if let Some(path) = &options.input_log {
+        let history = emulator.input_history();
+        let result = fs::File::create(path).and_then(|file| {
+            if path.extension().is_some_and(|extension| extension == "json") {
+                history.write_json(BufWriter::new(file))
+            } else {
+                history.write_csv(BufWriter::new(file))
+            }
+        });
+
+        match result {
+            Ok(()) => eprintln!("input log written to {}", path.display()),
+            Err(error) => eprintln!("could not write {}: {}", path.display(), error),
+        }
+    }
+
+    if options.auto_save {
+        write_state(&emulator, &storage.file(AUTO_SAVE_EXTENSION));
+    }
+
+    Ok(())
+}
+
+///Loads game.sav when the game has battery backed RAM, a missing file just means there is no save yet
+fn load_battery_ram(emulator: &mut Emulator, storage: &GameStorage) {
+    let path = storage.file("sav");
+
+    if let Ok(data) = fs::read(&path) {
+        if !emulator.load_battery_ram(&data) {
+            eprintln!("ignoring {}: the game has no battery RAM of that size", path.display());
+        }
+    }
+}
+
+///Applies the codes in game.cht (see cheats::CheatFormat for the formats)
+fn load_cheats(emulator: &mut Emulator, storage: &GameStorage) {
+    let path = storage.file("cht");
+
+    if let Ok(text) = fs::read_to_string(&path) {
+        match emulator.import_cheats(&text) {
+            Ok(()) => eprintln!("{} cheat codes loaded from {}", emulator.cheats().len(), path.display()),
+            Err(error) => eprintln!("ignoring {}: {}", path.display(), error),
+        }
+    }
+}
+
+fn save_battery_ram(emulator: &Emulator, storage: &GameStorage) {
+    let path = storage.file("sav");
+
+    if let Some(data) = emulator.battery_ram() {
+        if let Err(error) = fs::write(&path, data) {
+            eprintln!("could not save {}: {}", path.display(), error);
+        }
+    }
+}
+
+///A new trace replaces the last one, the file grows by a few MB per second of gameplay
+fn toggle_trace(emulator: &mut Emulator, storage: &GameStorage) {
+    let path = storage.file("trace.log");
+
+    if emulator.is_tracing() {
+        match emulator.stop_trace() {
+            Ok(()) => eprintln!("trace written to {}", path.display()),
+            Err(error) => eprintln!("could not write {}: {}", path.display(), error),
+        }
+
+        return;
+    }
+
+    match fs::File::create(&path) {
+        Ok(file) => {
+            //Nothing is being traced, so there is no previous trace to fail
+            let _ = emulator.start_trace(BufWriter::new(file));
+            eprintln!("tracing to {}", path.display());
+        }
+        Err(error) => eprintln!("could not create {}: {}", path.display(), error),
+    }
+}
+
+///Writes the frame to the first game-NNN.png that doesn't exist yet
+fn save_screenshot(emulator: &Emulator, storage: &GameStorage, colors: CaptureColors) {
+    let Some(path) = (1..1000).map(|number| storage.screenshot(number)).find(|path| !path.exists()) else {
+        eprintln!("could not save a screenshot: all 999 in {} are taken", storage.directory().display());
+        return;
+    };
+
+    match write_png(emulator, &path, colors) {
+        Ok(()) => eprintln!("screenshot saved to {}", path.display()),
+        Err(error) => eprintln!("could not save {}: {}", path.display(), error),
+    }
+}
+
+fn write_png(emulator: &Emulator, path: &Path, colors: CaptureColors) -> io::Result<()> {
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    emulator.capture(colors).write_png(&mut file)?;
+
+    return file.flush();
+}
+
+///Frames written as 000000.png, 000001.png, ... into game.frames, to be turned into a video by other tools
+struct Recording {
+    folder: PathBuf,
+    colors: CaptureColors,
+    frames: u32,
+}
+
+impl Recording {
+    ///Frames from an earlier recording are overwritten
+    fn start(storage: &GameStorage, colors: CaptureColors) -> Option<Self> {
+        let folder = storage.file("frames");
+
+        if let Err(error) = fs::create_dir_all(&folder) {
+            eprintln!("could not create {}: {}", folder.display(), error);
+            return None;
+        }
+
+        eprintln!("recording frames to {}", folder.display());
+
+        return Some(Self { folder, colors, frames: 0 });
+    }
+
+    ///Returns false when the frame couldn't be written, which ends the recording
+    fn write(&mut self, emulator: &Emulator) -> bool {
+        let path = self.folder.join(format!("{:06}.png", self.frames));
+
+        if let Err(error) = write_png(emulator, &path, self.colors) {
+            eprintln!("recording stopped, could not write {}: {}", path.display(), error);
+            return false;
+        }
+
+        self.frames += 1;
+
+        return true;
+    }
+}
+
+fn save_state(emulator: &Emulator, storage: &GameStorage) {
+    write_state(emulator, &storage.file("state"));
+}
+
+fn write_state(emulator: &Emulator, path: &Path) {
+    let result = emulator
+        .save_state()
+        .map_err(|error| error.to_string())
+        .and_then(|data| fs::write(path, data).map_err(|error| error.to_string()));
+
+    if let Err(error) = result {
+        eprintln!("could not save {}: {}", path.display(), error);
+    }
+}
+
+fn load_state(emulator: &mut Emulator, storage: &GameStorage) {
+    let path = storage.file("state");
+
+    let result = fs::read(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|data| emulator.load_state(&data).map_err(|error| error.to_string()));
+
+    if let Err(error) = result {
+        eprintln!("could not load {}: {}", path.display(), error);
+    }
+}
+
+///Asks on the terminal whether to continue from game.auto.state, the battery RAM was already loaded<br>
+///Nothing is asked when there is no auto-save or nobody can answer (stdin isn't a terminal)
+fn resume_auto_save(emulator: &mut Emulator, storage: &GameStorage) {
+    let path = storage.file(AUTO_SAVE_EXTENSION);
+
+    let Ok(data) = fs::read(&path) else {
+        return;
+    };
+
+    if !io::stdin().is_terminal() {
+        return;
+    }
+
+    print!("resume from {}? [Y/n] ", path.display());
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return;
+    }
+
+    if answer.trim().eq_ignore_ascii_case("n") {
+        return;
+    }
+
+    //A state from another version or game is left alone, it gets replaced on exit
+    if let Err(error) = emulator.load_state(&data) {
+        eprintln!("could not resume from {}: {}", path.display(), error);
+    }
+}
+
+///Sets every button of the four ports, a button is held when one of the profile's keys or gamepads holds it
+///Returns the buttons held in every port
+fn apply_input(
+    emulator: &mut Emulator,
+    window: &Window,
+    gamepads: Option<&gamepad::Gamepads>,
+    profile: &InputProfile,
+) -> [u8; 4] {
+    let mut held = [0u8; 4];
+
+    for &(key, port, button) in profile.bindings {
+        if window.is_key_down(key) {
+            held[port] |= button as u8;
+        }
+    }
+
+    if let Some(gamepads) = gamepads {
+        for &(gamepad, port) in profile.gamepads {
+            held[port] |= gamepads.buttons(gamepad);
+        }
+    }
+
+    for (port, buttons) in held.into_iter().enumerate() {
+        for button in BUTTONS {
+            emulator.set_input(port, button, (buttons & button as u8) != 0);
+        }
+    }
+
+    return held;
+}
+
+///Plugs a Four Score into both controller ports, or a standard controller
+fn connect_controllers(emulator: &mut Emulator, four_score: bool) {
+    for port in 0..2 {
+        if four_score {
+            emulator.connect_input_device(port, FourScore::new(port));
+        } else {
+            emulator.connect_input_device(port, Controller::new());
+        }
+    }
+}
+
+///FrontendOptions::input_latency: the meter and what it compares every frame with
+struct InputLatency {
+    meter: LatencyMeter,
+    held: [u8; 4], //Buttons of the last poll
+    strobes: u64,  //Emulator::input_strobes() after the last frame
+}
+
+///The game window, which also takes the keyboard input
+struct WindowSink {
+    window: Window,
+}
+
+impl WindowSink {
+    ///The title is set by update_title()
+    fn open() -> Result<Self, String> {
+        let options = WindowOptions {
+            scale: Scale::X2,
+            ..WindowOptions::default()
+        };
+
+        match Window::new("RNES", SCREEN_WIDTH, SCREEN_HEIGHT, options) {
+            Ok(mut window) => {
+                set_icon(&mut window);
+                return Ok(Self { window });
+            }
+            Err(error) => return Err(format!("could not open a window: {}", error)),
+        }
+    }
+}
+
+///16x16 controller, one character per pixel: # outline, l body, k d-pad, d select and start, r A and B
+const ICON: [&str; 16] = [
+    "................",
+    "................",
+    "................",
+    "................",
+    "################",
+    "#llllllllllllll#",
+    "#llklllllllllll#",
+    "#lkkklllllrrlrr#",
+    "#llkllllllrrlrr#",
+    "#lllllddlddllll#",
+    "#llllllllllllll#",
+    "################",
+    "................",
+    "................",
+    "................",
+    "................",
+];
+
+///0xAARRGGBB color of an ICON character, transparent for the rest
+fn icon_color(character: u8) -> u64 {
+    match character {
+        b'#' => return 0xFF000000,
+        b'l' => return 0xFFB0B0B0,
+        b'k' => return 0xFF202020,
+        b'd' => return 0xFF505050,
+        b'r' => return 0xFFD02020,
+        _ => return 0,
+    }
+}
+
+///minifb takes icon pixels only on X11 (the width, the height and then the ARGB pixels), Windows wants an .ico file
+///and macOS has no window icons, so the other platforms keep the default one
+#[cfg(target_os = "linux")]
+fn set_icon(window: &mut Window) {
+    let mut buffer = vec![ICON[0].len() as u64, ICON.len() as u64];
+    buffer.extend(ICON.iter().flat_map(|row| row.bytes().map(icon_color)));
+
+    if let Ok(icon) = minifb::Icon::try_from(&buffer[..]) {
+        window.set_icon(icon);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_icon(_window: &mut Window) {}
+
+impl VideoSink for WindowSink {
+    fn present(&mut self, frame: &[u32]) -> io::Result<()> {
+        return self
+            .window
+            .update_with_buffer(frame, SCREEN_WIDTH, SCREEN_HEIGHT)
+            .map_err(|error| io::Error::other(error.to_string()));
+    }
+}
+
+///"game - region - speed% - input profile", with the statistics appended when they are shown<br>
+///While paused the title prompts for the keys instead
+fn update_title(
+    window: &mut Window,
+    emulator: &Emulator,
+    game: &str,
+    region: Region,
+    speed: u32,
+    profile: usize,
+    show_stats: bool,
+) {
+    let title = match emulator.pause_reason() {
+        Some(reason) => format!("{} - {} - press P to resume, N for one frame - RNES", game, reason),
+        None => {
+            let title = format!("{} - {} - {}% - {} - RNES", game, region, speed, PROFILES[profile].name);
+
+            if show_stats {
+                format!("{} - {}", title, emulator.stats())
+            } else {
+                title
+            }
+        }
+    };
+
+    window.set_title(&title);
+}
+
+#[cfg(feature = "audio")]
+mod audio {
+    use cpal::{
+        traits::{DeviceTrait, HostTrait, StreamTrait},
+        Stream,
+    };
+    use rnes::{
+        audio::{sample_ring, AudioSink, RingProducer},
+        time_stretch::{TimeStretcher, MAX_TEMPO},
+    };
+
+    ///Plays through a sound device with cpal
+    pub struct CpalSink {
+        sample_rate: u32,
+        buffer: RingProducer,     //Holds at most 100ms, so the latency stays under it
+        stretcher: TimeStretcher, //Keeps the buffer around half full when the speed drifts
+        _stream: Stream,          //Playback stops when the stream is dropped
+    }
+
+    ///Plays through the default output device at its sample rate<br>
+    ///Returns None when there is no usable device
+    pub fn open() -> Option<Box<dyn AudioSink>> {
+        let device = cpal::default_host().default_output_device()?;
+        let config = device.default_output_config().ok()?;
+
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels() as usize;
+
+        let (buffer, mut output) = sample_ring(sample_rate as usize / 10);
+        let mut mono = Vec::new();
+
+        let stream = device
+            .build_output_stream(
+                &config.into(),
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    mono.resize(data.len() / channels, 0.0);
+                    output.fill(&mut mono);
+
+                    //The APU is mono, every channel gets the same sample
+                    for (frame, &sample) in data.chunks_mut(channels).zip(&mono) {
+                        frame.fill(sample);
+                    }
+                },
+                |error| eprintln!("audio error: {}", error),
+                None,
+            )
+            .ok()?;
+
+        stream.play().ok()?;
+
+        Some(Box::new(CpalSink {
+            sample_rate,
+            buffer,
+            stretcher: TimeStretcher::new(),
+            _stream: stream,
+        }))
+    }
+
+    impl AudioSink for CpalSink {
+        fn sample_rate(&self) -> u32 {
+            return self.sample_rate;
+        }
+
+        ///Speeds the samples up when the buffer fills past half and slows them down when it drains, by at most 3%<br>
+        ///Drops the newest samples when the emulation runs too far ahead of the device
+        fn queue(&mut self, samples: &[f32]) {
+            let target = self.buffer.capacity() as f64 / 2.0;
+            let tempo = 1.0 + (self.buffer.len() as f64 - target) / target * (MAX_TEMPO - 1.0);
+
+            let stretched = self.stretcher.process(samples, tempo);
+            self.buffer.push(&stretched);
+        }
+
+        fn take_underruns(&self) -> u64 {
+            return self.buffer.take_underruns();
+        }
+
+        ///The faded out end goes in after what is queued, so the device never cuts off in the middle of a wave
+        fn discontinuity(&mut self) {
+            let tail = self.stretcher.fade_out();
+            self.buffer.push(&tail);
+        }
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+mod audio {
+    use rnes::audio::AudioSink;
+
+    ///Built without the audio feature: there is no device to play through
+    pub fn open() -> Option<Box<dyn AudioSink>> {
+        None
+    }
+}
+
+#[cfg(feature = "gamepad")]
+mod gamepad {
+    use gilrs::{EventType, Gilrs};
+    use rnes::Button;
+
+    ///Gamepad buttons for the NES ones, A is the right face button like on the NES pad
+    const MAPPING: [(gilrs::Button, Button); 8] = [
+        (gilrs::Button::East, Button::A),
+        (gilrs::Button::South, Button::B),
+        (gilrs::Button::Select, Button::Select),
+        (gilrs::Button::Start, Button::Start),
+        (gilrs::Button::DPadUp, Button::Up),
+        (gilrs::Button::DPadDown, Button::Down),
+        (gilrs::Button::DPadLeft, Button::Left),
+        (gilrs::Button::DPadRight, Button::Right),
+    ];
+
+    pub struct Gamepads {
+        gilrs: Gilrs,
+    }
+
+    impl Gamepads {
+        ///Returns None (and the profiles only get their keys) when the platform's gamepad API can't be opened
+        pub fn open() -> Option<Self> {
+            Some(Self {
+                gilrs: Gilrs::new().ok()?,
+            })
+        }
+
+        ///Takes the events since the last call, which refreshes the button states, returns the gamepads that
+        ///disconnected
+        pub fn update(&mut self) -> Vec<usize> {
+            let mut disconnected = Vec::new();
+
+            while let Some(event) = self.gilrs.next_event() {
+                if matches!(event.event, EventType::Disconnected) {
+                    disconnected.push(usize::from(event.id));
+                }
+            }
+
+            return disconnected;
+        }
+
+        ///Buttons held on a gamepad (0 for the first one connected), one bit per Button
+        pub fn buttons(&self, gamepad: usize) -> u8 {
+            let Some((_, pad)) = self.gilrs.gamepads().find(|&(id, _)| usize::from(id) == gamepad) else {
+                return 0;
+            };
+
+            return MAPPING
+                .iter()
+                .filter(|&&(from, _)| pad.is_pressed(from))
+                .fold(0, |buttons, &(_, to)| buttons | to as u8);
+        }
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+mod gamepad {
+    ///Built without the gamepad feature: profiles only get their keys
+    pub struct Gamepads;
+
+    impl Gamepads {
+        pub fn open() -> Option<Self> {
+            None
+        }
+
+        pub fn update(&mut self) -> Vec<usize> {
+            Vec::new()
+        }
+
+        pub fn buttons(&self, _gamepad: usize) -> u8 {
+            0
+        }
+    }
+}
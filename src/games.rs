@@ -0,0 +1,54 @@
+use std::sync::OnceLock;
+
+///The bundled database, see games.txt for the format
+const DATABASE: &str = include_str!("games.txt");
+
+///Known dumps keyed by the CRC-32 of their PRG-ROM and CHR-ROM, read from the database the first time a title is
+///looked up, see Emulator::game_title()
+fn games() -> &'static [(u32, &'static str)] {
+    static GAMES: OnceLock<Vec<(u32, &'static str)>> = OnceLock::new();
+
+    return GAMES.get_or_init(|| parse(DATABASE));
+}
+
+///"CRC title" lines, blank lines and # comments are skipped and so are lines without a hex CRC and a title
+fn parse(text: &str) -> Vec<(u32, &str)> {
+    return text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (crc, title) = line.split_once(char::is_whitespace)?;
+            let crc = u32::from_str_radix(crc, 16).ok()?;
+            let title = title.trim();
+
+            return (!title.is_empty()).then_some((crc, title));
+        })
+        .collect();
+}
+
+///Title of the dump with the CRC-32, None for dumps the database doesn't know
+pub(crate) fn title(crc32: u32) -> Option<&'static str> {
+    return games().iter().find(|&&(crc, _)| crc == crc32).map(|&(_, title)| title);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_need_a_crc_and_a_title() {
+        let text = "# comment\n\n0000ABCD\tA Game\n  1234 Another Game  \nXYZ\tNot hex\n00000001\n";
+
+        assert_eq!(parse(text), [(0xABCD, "A Game"), (0x1234, "Another Game")]);
+    }
+
+    #[test]
+    fn every_entry_of_the_bundled_database_is_read() {
+        let entries = DATABASE.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#')).count();
+
+        assert_eq!(games().len(), entries);
+        assert_eq!(title(0x3337EC46), Some("Super Mario Bros."));
+        assert_eq!(title(0x12345678), None);
+    }
+}
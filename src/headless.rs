@@ -0,0 +1,142 @@
+use std::{io, path::Path};
+
+use crate::{
+    audio::{AudioSink, NullSink},
+    cartridge::CartridgeError,
+    checksum::crc32,
+    emulator::Emulator,
+    video::VideoSink,
+};
+
+///CRC-32 of a frame buffer, every 0x00RRGGBB pixel as 4 little endian bytes
+pub fn frame_crc32(frame: &[u32]) -> u32 {
+    let bytes: Vec<u8> = frame.iter().flat_map(|pixel| pixel.to_le_bytes()).collect();
+
+    return crc32(&bytes);
+}
+
+///Keeps the CRC-32 of every frame it is shown, the video output of HeadlessRunner
+#[derive(Default)]
+pub struct HashSink {
+    hashes: Vec<u32>, //Oldest first
+}
+
+impl HashSink {
+    //Constructor
+    pub fn new() -> Self {
+        Self { hashes: Vec::new() }
+    }
+
+    pub fn hashes(&self) -> &[u32] {
+        return &self.hashes;
+    }
+}
+
+impl VideoSink for HashSink {
+    fn present(&mut self, frame: &[u32]) -> io::Result<()> {
+        self.hashes.push(frame_crc32(frame));
+
+        Ok(())
+    }
+}
+
+///Runs a ROM without a window or audio device and keeps the CRC-32 of every frame<br>
+///For regression tests of the PPU output: run a test ROM for a fixed number of frames and compare the hash
+pub struct HeadlessRunner {
+    emulator: Emulator,
+    audio: Box<dyn AudioSink>, //Gets the samples of every frame, a NullSink unless set_audio_sink() was called
+    video: HashSink, //The hash of frame n (counted from 1) at index n - 1
+}
+
+impl HeadlessRunner {
+    //Constructor
+    pub fn new(rom: &[u8]) -> Result<Self, CartridgeError> {
+        let mut emulator = Emulator::new();
+        emulator.load_rom_bytes(rom)?;
+
+        Ok(Self::with_emulator(emulator))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, CartridgeError> {
+        let mut emulator = Emulator::new();
+        emulator.load_rom(path)?;
+
+        Ok(Self::with_emulator(emulator))
+    }
+
+    ///Takes an emulator set up by the caller (ROM loaded, region, accuracy, inputs, ...)
+    pub fn with_emulator(emulator: Emulator) -> Self {
+        let audio = Box::new(NullSink::new(emulator.get_sample_rate()));
+
+        Self {
+            emulator,
+            audio,
+            video: HashSink::new(),
+        }
+    }
+
+    ///Sends the sound to the sink (a WavSink to listen to a test run), the emulator is switched to its sample rate
+    pub fn set_audio_sink(&mut self, sink: impl AudioSink + 'static) {
+        self.emulator.set_sample_rate(sink.sample_rate());
+        self.audio = Box::new(sink);
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        return &self.emulator;
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Emulator {
+        return &mut self.emulator;
+    }
+
+    ///Runs the frames and returns the hash of the last one
+    pub fn run(&mut self, frames: u64) -> u32 {
+        for _ in 0..frames {
+            self.emulator.step_frame();
+            self.audio.queue(&self.emulator.audio_samples());
+            //Hashing can't fail
+            let _ = self.video.present(&self.emulator.frame_buffer());
+        }
+
+        return self.frame_hash();
+    }
+
+    ///Runs until `frames` frames have run in total, returns the hash of that frame
+    pub fn run_until(&mut self, frames: u64) -> u32 {
+        let remaining = frames.saturating_sub(self.frame_count());
+
+        return self.run(remaining);
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        return self.video.hashes().len() as u64;
+    }
+
+    ///Hash of the last frame run, the hash of the blank screen before the first one
+    pub fn frame_hash(&self) -> u32 {
+        return match self.video.hashes().last() {
+            Some(&hash) => hash,
+            None => frame_crc32(&self.emulator.frame_buffer()),
+        };
+    }
+
+    ///Hash of every frame run so far, the first frame first
+    pub fn frame_hashes(&self) -> &[u32] {
+        return self.video.hashes();
+    }
+
+    ///Test helper: runs until `frames` frames have run and panics unless that frame hashes to `expected`<br>
+    ///The message has the actual hash to paste in when the output changed on purpose
+    #[track_caller]
+    pub fn assert_frame_hash(&mut self, frames: u64, expected: u32) {
+        let hash = self.run_until(frames);
+
+        assert!(
+            hash == expected,
+            "frame {} hashes to {:08X}, expected {:08X}",
+            frames,
+            hash,
+            expected
+        );
+    }
+}
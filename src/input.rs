@@ -0,0 +1,127 @@
+use crate::controller::Button;
+
+///A device plugged into one of the controller ports or into the Famicom expansion port
+pub trait InputDevice {
+    ///Receives the $4016 output latch (OUT0 - OUT2 in bits 0-2) on every write<br>
+    ///OUT0 is the strobe of standard controllers, OUT1/OUT2 are used by expansion devices (keyboards, multitaps, ...)
+    fn write_output(&mut self, output: u8);
+
+    ///Returns the data lines D0-D4 for a read of $4016 (port 0) or $4017 (port 1)<br>
+    ///Controller port devices are only read through their own port, expansion devices through both
+    fn read(&mut self, port: usize) -> u8;
+
+    ///Presses or releases a joypad button, devices without buttons ignore it
+    fn set_button_state(&mut self, _button: Button, _pressed: bool) {}
+
+    ///Presses or releases a button of the second joypad behind a multitap (players 3 and 4 of a Four Score),
+    ///devices with a single joypad ignore it
+    fn set_second_button_state(&mut self, _button: Button, _pressed: bool) {}
+}
+
+///The $4016/$4017 input interface: the shared output latch, both controller ports and the expansion port
+pub struct InputPorts {
+    output_latch: u8,
+    strobes: u64, //Writes that set OUT0, when standard controllers latch the buttons
+    ports: [Option<Box<dyn InputDevice>>; 2],
+    expansion: Option<Box<dyn InputDevice>>,
+}
+
+impl InputPorts {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            output_latch: 0,
+            strobes: 0,
+            ports: [None, None],
+            expansion: None,
+        }
+    }
+
+    ///Plugs a device into controller port 0 ($4016) or 1 ($4017), replacing whatever was there<br>
+    ///Returns false (and drops the device) when the port doesn't exist
+    pub fn connect(&mut self, port: usize, device: Box<dyn InputDevice>) -> bool {
+        let Some(slot) = self.ports.get_mut(port) else {
+            return false;
+        };
+
+        let mut device = device;
+        device.write_output(self.output_latch);
+        *slot = Some(device);
+
+        return true;
+    }
+
+    ///Returns false when the port was already empty or doesn't exist
+    pub fn disconnect(&mut self, port: usize) -> bool {
+        return self.ports.get_mut(port).and_then(|slot| slot.take()).is_some();
+    }
+
+    pub fn connect_expansion(&mut self, device: Box<dyn InputDevice>) {
+        let mut device = device;
+        device.write_output(self.output_latch);
+
+        self.expansion = Some(device);
+    }
+
+    ///Returns false when the port was already empty
+    pub fn disconnect_expansion(&mut self) -> bool {
+        return self.expansion.take().is_some();
+    }
+
+    ///Forwards a button press or release to the device in the port, returns false if the port is empty<br>
+    ///Ports 2 and 3 are the second joypad of the multitap in port 0 and 1 (players 3 and 4)
+    pub fn set_button_state(&mut self, port: usize, button: Button, pressed: bool) -> bool {
+        if port >= 4 {
+            return false;
+        }
+
+        let Some(device) = &mut self.ports[port % 2] else {
+            return false;
+        };
+
+        if port < 2 {
+            device.set_button_state(button, pressed);
+        } else {
+            device.set_second_button_state(button, pressed);
+        }
+
+        return true;
+    }
+
+    ///CPU write to $4016, the latch is wired to every port at the same time
+    pub fn write(&mut self, data: u8) {
+        self.output_latch = data & 0x07;
+
+        if (data & 0x01) != 0 {
+            self.strobes += 1;
+        }
+
+        for device in self.ports.iter_mut().flatten() {
+            device.write_output(self.output_latch);
+        }
+
+        if let Some(device) = &mut self.expansion {
+            device.write_output(self.output_latch);
+        }
+    }
+
+    pub fn strobes(&self) -> u64 {
+        return self.strobes;
+    }
+
+    ///CPU read of $4016 (port 0) or $4017 (port 1)<br>
+    ///Only D0-D4 are returned, the BUS fills the upper bits from the open bus
+    pub fn read(&mut self, port: usize) -> u8 {
+        let mut data = 0x00;
+
+        if let Some(device) = &mut self.ports[port] {
+            data |= device.read(port);
+        }
+
+        if let Some(device) = &mut self.expansion {
+            data |= device.read(port);
+        }
+
+        return data & 0x1F;
+    }
+}
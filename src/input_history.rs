@@ -0,0 +1,175 @@
+use std::io::{self, Write};
+
+use crate::controller::Button;
+
+///Button letters in the CSV export, from bit 7 (Right) down to bit 0 (A)
+const BUTTON_LETTERS: [char; 8] = ['R', 'L', 'D', 'U', 'T', 'S', 'B', 'A'];
+
+///A stretch of frames with the same buttons held
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Run {
+    frames: u64,
+    buttons: [u8; 2], //Controller port 0 and 1, one bit per Button
+}
+
+///The buttons held on both controllers during every frame of the session, kept as runs of identical frames<br>
+///Recorded from Emulator::set_input() alone, so it doesn't depend on movies or save states: loading a state or
+///resetting just keeps appending frames
+#[derive(Clone, Default, Debug)]
+pub struct InputHistory {
+    runs: Vec<Run>,
+    buttons: [u8; 2], //Held right now, recorded at the end of the next frame
+}
+
+impl InputHistory {
+    //Constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn set_button(&mut self, port: usize, button: Button, pressed: bool) {
+        let Some(buttons) = self.buttons.get_mut(port) else {
+            return;
+        };
+
+        if pressed {
+            *buttons |= button as u8;
+        } else {
+            *buttons &= !(button as u8);
+        }
+    }
+
+    ///Adds a frame with the buttons held now
+    pub(crate) fn record_frame(&mut self) {
+        match self.runs.last_mut() {
+            Some(run) if run.buttons == self.buttons => run.frames += 1,
+            _ => self.runs.push(Run {
+                frames: 1,
+                buttons: self.buttons,
+            }),
+        }
+    }
+
+    ///Forgets the recorded frames, the buttons held stay held
+    pub fn clear(&mut self) {
+        self.runs.clear();
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        return self.runs.iter().map(|run| run.frames).sum();
+    }
+
+    ///Buttons of port 0 and 1 during a frame (counted from 0), None past the last recorded frame
+    pub fn buttons(&self, frame: u64) -> Option<[u8; 2]> {
+        let mut start = 0;
+
+        for run in &self.runs {
+            if frame < start + run.frames {
+                return Some(run.buttons);
+            }
+
+            start += run.frames;
+        }
+
+        return None;
+    }
+
+    ///One row per frame: frame,port_1,port_2 with the buttons as RLDUTSBA letters and . for released ones
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "frame,port_1,port_2")?;
+
+        let mut frame = 0;
+
+        for run in &self.runs {
+            let ports = run.buttons.map(button_letters);
+
+            for _ in 0..run.frames {
+                writeln!(writer, "{},{},{}", frame, ports[0], ports[1])?;
+                frame += 1;
+            }
+        }
+
+        return writer.flush();
+    }
+
+    ///The runs as they are stored: {"frames": total, "runs": [{"start", "frames", "ports": [port 0, port 1]}]},
+    ///the ports as bit masks (bit 0 A, B, Select, Start, Up, Down, Left, bit 7 Right)
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "{{\"frames\":{},\"runs\":[", self.frame_count())?;
+
+        let mut start = 0;
+
+        for (index, run) in self.runs.iter().enumerate() {
+            let separator = if index == 0 { "" } else { "," };
+
+            write!(
+                writer,
+                "{}\n{{\"start\":{},\"frames\":{},\"ports\":[{},{}]}}",
+                separator, start, run.frames, run.buttons[0], run.buttons[1]
+            )?;
+
+            start += run.frames;
+        }
+
+        writeln!(writer, "\n]}}")?;
+
+        return writer.flush();
+    }
+}
+
+fn button_letters(buttons: u8) -> String {
+    return BUTTON_LETTERS
+        .iter()
+        .enumerate()
+        .map(|(index, &letter)| if (buttons & (0x80 >> index)) != 0 { letter } else { '.' })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> InputHistory {
+        let mut history = InputHistory::new();
+
+        history.record_frame();
+        history.set_button(0, Button::A, true);
+        history.set_button(1, Button::Right, true);
+        history.record_frame();
+        history.record_frame();
+        history.set_button(0, Button::A, false);
+        history.record_frame();
+
+        return history;
+    }
+
+    #[test]
+    fn identical_frames_share_a_run() {
+        let history = history();
+
+        assert_eq!(history.runs.len(), 3);
+        assert_eq!(history.frame_count(), 4);
+        assert_eq!(history.buttons(2), Some([0x01, 0x80]));
+        assert_eq!(history.buttons(3), Some([0x00, 0x80]));
+        assert_eq!(history.buttons(4), None);
+    }
+
+    #[test]
+    fn exports_list_every_frame() {
+        let history = history();
+
+        let mut csv = Vec::new();
+        history.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "frame,port_1,port_2\n0,........,........\n1,.......A,R.......\n2,.......A,R.......\n3,........,R.......\n"
+        );
+
+        let mut json = Vec::new();
+        history.write_json(&mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"frames\":4,\"runs\":[\n{\"start\":0,\"frames\":1,\"ports\":[0,0]},\n{\"start\":1,\"frames\":2,\"ports\":[1,128]},\n{\"start\":3,\"frames\":1,\"ports\":[0,128]}\n]}\n"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+///How long one change of the held buttons took to be latched by the game and to reach the screen
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LatencySample {
+    pub frames: u32,          //Frames run from the change up to the one that latched it, that one included
+    pub to_latch: Duration,   //From the change to the end of the frame that latched it
+    pub to_present: Duration, //From the change to that frame being presented
+}
+
+///Measures the input latency of a frontend, to tune vsync and audio buffering<br>
+///The frontend reports when it saw the buttons change, every frame it ran and every frame it presented: a change
+///is latched by the first frame in which the game strobed the controllers (Emulator::input_strobes()) and shown when
+///that frame is presented<br>
+///One change is followed at a time, the ones made before it reached the screen aren't measured
+#[derive(Default)]
+pub struct LatencyMeter {
+    input: Option<(Instant, u32)>,             //Change not latched yet, frames run since
+    latched: Option<(Instant, u32, Duration)>, //Change waiting for its frame to be presented
+    samples: Vec<LatencySample>,
+}
+
+impl LatencyMeter {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            input: None,
+            latched: None,
+            samples: Vec::new(),
+        }
+    }
+
+    ///The held buttons changed
+    pub fn input_changed(&mut self, at: Instant) {
+        if self.input.is_none() && self.latched.is_none() {
+            self.input = Some((at, 0));
+        }
+    }
+
+    ///A frame finished running, strobed tells whether the game strobed the controllers during it
+    pub fn frame_ran(&mut self, strobed: bool, at: Instant) {
+        let Some((input, frames)) = self.input else {
+            return;
+        };
+
+        if strobed {
+            self.input = None;
+            self.latched = Some((input, frames + 1, at.saturating_duration_since(input)));
+        } else {
+            self.input = Some((input, frames + 1));
+        }
+    }
+
+    ///The last frame run went to the screen
+    pub fn presented(&mut self, at: Instant) {
+        if let Some((input, frames, to_latch)) = self.latched.take() {
+            self.samples.push(LatencySample { frames, to_latch, to_present: at.saturating_duration_since(input) });
+        }
+    }
+
+    pub fn samples(&self) -> &[LatencySample] {
+        return &self.samples;
+    }
+}
+
+///"n changes, latched after x frames / y ms on average, shown after z ms on average (min - max)"
+impl fmt::Display for LatencyMeter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.samples.len();
+
+        if count == 0 {
+            return write!(f, "no input changes reached the screen");
+        }
+
+        let milliseconds = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let average = |value: &dyn Fn(&LatencySample) -> f64| {
+            return self.samples.iter().map(value).sum::<f64>() / count as f64;
+        };
+
+        let presented = self.samples.iter().map(|sample| milliseconds(sample.to_present));
+        let min = presented.clone().fold(f64::MAX, f64::min);
+        let max = presented.fold(0.0, f64::max);
+
+        write!(
+            f,
+            "{} input changes: latched after {:.1} frames / {:.1}ms, shown after {:.1}ms on average ({:.1} - {:.1}ms)",
+            count,
+            average(&|sample| sample.frames as f64),
+            average(&|sample| milliseconds(sample.to_latch)),
+            average(&|sample| milliseconds(sample.to_present)),
+            min,
+            max
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_change_is_measured_up_to_the_frame_that_latched_it() {
+        let start = Instant::now();
+        let ms = |milliseconds: u64| start + Duration::from_millis(milliseconds);
+
+        let mut meter = LatencyMeter::new();
+        meter.input_changed(ms(0));
+
+        //Not strobed on the first frame, a second change while the first is in flight is ignored
+        meter.frame_ran(false, ms(4));
+        meter.presented(ms(10));
+        meter.input_changed(ms(12));
+        meter.frame_ran(true, ms(20));
+        meter.presented(ms(26));
+
+        assert_eq!(
+            meter.samples(),
+            [LatencySample { frames: 2, to_latch: Duration::from_millis(20), to_present: Duration::from_millis(26) }]
+        );
+
+        //Frames without a change aren't measured
+        meter.frame_ran(true, ms(36));
+        meter.presented(ms(42));
+        assert_eq!(meter.samples().len(), 1);
+
+        meter.input_changed(ms(50));
+        meter.frame_ran(true, ms(52));
+        meter.presented(ms(60));
+
+        assert_eq!(
+            meter.to_string(),
+            "2 input changes: latched after 1.5 frames / 11.0ms, shown after 18.0ms on average (10.0 - 26.0ms)"
+        );
+    }
+}
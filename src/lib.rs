@@ -0,0 +1,75 @@
+#![allow(clippy::needless_return, clippy::upper_case_acronyms)]
+
+//! RNES, a NES emulator<br>
+//! Embed it through [`Emulator`]: load a ROM, step frames, read the frame buffer and audio samples, feed input
+
+mod accuracy;
+mod apu;
+pub mod audio;
+mod bookmarks;
+mod bus;
+mod capture;
+mod cartridge;
+pub mod checksum;
+pub mod cheats;
+mod controller;
+mod coverage;
+mod cpu;
+pub mod debug_port;
+mod debugger;
+mod diagnostics;
+mod disassembler;
+mod emulator;
+mod events;
+mod frame;
+mod games;
+pub mod headless;
+mod input;
+mod input_history;
+mod latency;
+mod mapper;
+mod memory_map;
+mod ntsc;
+mod opcode;
+mod palette;
+mod ppu;
+mod region;
+mod rewind;
+pub mod rng;
+mod savestate;
+pub mod scan;
+pub mod storage;
+mod stats;
+mod system;
+pub mod time_stretch;
+pub mod timing;
+mod trace;
+pub mod video;
+mod watch;
+
+pub use accuracy::{AccuracyProfile, AccuracySettings, PpuBackend};
+pub use bookmarks::Bookmark;
+pub use bus::{HandlerId, InterceptorId, WriteAction, HANDLER_RANGE};
+pub use capture::{Capture, CaptureColors};
+pub use cartridge::{CartridgeError, CartridgeInfo, ConsoleType, PlayChoiceRoms};
+pub use controller::{Button, Controller, FourScore};
+pub use coverage::Coverage;
+pub use cpu::CpuRegisters;
+pub use debugger::{BreakpointId, DebugHit, WatchKind};
+pub use diagnostics::Diagnostic;
+pub use disassembler::DisasmLine;
+pub use opcode::AddressingMode;
+pub use palette::{Palette, PaletteError, PALETTE_2C02};
+pub use emulator::Emulator;
+pub use events::{CycleStop, CyclesRun, EmulatorEvent, PauseReason, RunState, StepSize};
+pub use frame::PixelFormat;
+pub use input::InputDevice;
+pub use input_history::InputHistory;
+pub use latency::{LatencyMeter, LatencySample};
+pub use memory_map::{MemoryKind, MemoryRegion};
+pub use ppu::{Layer, Mirroring, ScanlineScroll, SpriteLimit, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use region::Region;
+pub use savestate::{SaveStateError, SAVE_STATE_VERSION};
+pub use stats::Stats;
+pub use system::{CPU_CLOCK_RATE, DEFAULT_SAMPLE_RATE};
+pub use watch::{Register, WatchExpression, WatchFormat, WatchId, WatchSource, WatchValue};
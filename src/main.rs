@@ -1,14 +1,375 @@
-use bus::BUS;
-use cpu::CPU;
+#![allow(clippy::needless_return)]
 
-mod bus;
-mod cpu;
-mod opcode;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+};
+
+use rnes::{
+    debug_port::DebugPortConfig, headless::HeadlessRunner, rng::Rng, scan, AccuracyProfile, Emulator, Palette,
+};
+
+#[cfg(feature = "frontend")]
+mod frontend;
+
+const USAGE: &str = "usage: rnes [--auto-save] [--timing-trace <file>] [--accuracy <profile>] [--palette <file.pal>] [--input-log <file.csv|json>] [--pause-on-diagnostic] [--wav <file>] [--input-latency] [--data-dir <dir>] <rom>\n       rnes scan <dir> [frames]\n       rnes fuzz <rom> [runs] [frames] [seed]\n       rnes disasm <rom> [start] [end]\n       rnes test <rom> [frames]\n       rnes hash <rom> [frames]";
+
+///Startup fuzzing runs when no count is given
+const DEFAULT_FUZZ_RUNS: u32 = 8;
+
+///Frames a test ROM gets to write its exit code
+const DEFAULT_TEST_FRAMES: u32 = 3600;
+
+///Frames run before the screen is hashed when no count is given
+const DEFAULT_HASH_FRAMES: u32 = 600;
+
+///Exit status of a test ROM that never wrote an exit code (same as timeout(1))
+const TEST_TIMEOUT_EXIT: i32 = 124;
+
+///Frontend settings picked on the command line, only read by the frontend
+#[derive(Default)]
+#[cfg_attr(not(feature = "frontend"), allow(dead_code))]
+struct FrontendOptions {
+    ///Save the state to game.auto.state on exit and offer to resume from it at the next launch
+    auto_save: bool,
+    ///Record frame timing for the whole session and write it to this file (Chrome trace JSON) on exit
+    timing_trace: Option<PathBuf>,
+    ///Accuracy preset the game runs with
+    accuracy: AccuracyProfile,
+    ///Colors loaded from a .pal file instead of the built-in palette
+    palette: Option<Palette>,
+    ///Write the buttons held on every frame to this file on exit, as JSON for .json files and CSV otherwise
+    input_log: Option<PathBuf>,
+    ///Pause when the game first hits an opcode or board register that isn't emulated
+    pause_on_diagnostic: bool,
+    ///Write the sound to this WAV file instead of playing it
+    wav: Option<PathBuf>,
+    ///Measure how long button changes take to reach the screen and print the statistics on exit
+    input_latency: bool,
+    ///Root of the per-game directories (saves, states, cheats, screenshots), a saves folder next to the ROM if None
+    data_directory: Option<PathBuf>,
+}
 
 fn main() {
-    let mut bus = BUS::new();
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("scan") => {
+            let Some(directory) = args.get(2) else {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            };
+
+            let frames = parse_arg(&args, 3, "frames", scan::DEFAULT_SCAN_FRAMES);
+
+            let reports = match scan::scan_directory(Path::new(directory), frames) {
+                Ok(reports) => reports,
+                Err(error) => {
+                    eprintln!("could not read {}: {}", directory, error);
+                    process::exit(1);
+                }
+            };
+
+            for report in &reports {
+                println!("{}: {}", report.path.display(), report.status);
+
+                for diagnostic in &report.diagnostics {
+                    println!("    {}", diagnostic);
+                }
+            }
+
+            let working = reports
+                .iter()
+                .filter(|report| matches!(report.status, scan::ScanStatus::Ok { .. }))
+                .count();
+
+            println!("{}/{} ROMs ran {} frames", working, reports.len(), frames);
+        }
+        Some("fuzz") => {
+            let Some(rom) = args.get(2) else {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            };
+
+            let runs = parse_arg(&args, 3, "runs", DEFAULT_FUZZ_RUNS);
+            let frames = parse_arg(&args, 4, "frames", scan::DEFAULT_SCAN_FRAMES);
+            let seed = match args.get(5) {
+                Some(seed) => parse_seed(seed),
+                None => Rng::seed_from_time(),
+            };
+
+            let runs = scan::fuzz_rom(Path::new(rom), frames, runs, seed);
+
+            for run in &runs {
+                println!("seed {:016X}: {}", run.seed, run.status);
+            }
+
+            //Every run must end the same way (same frame hash, same crash, ...)
+            let mut outcomes: Vec<String> = runs.iter().map(|run| run.status.to_string()).collect();
+            outcomes.sort();
+            outcomes.dedup();
+
+            let consistent = outcomes.len() <= 1;
+
+            if consistent {
+                println!("all {} runs ended the same way", runs.len());
+            } else {
+                println!("runs diverged: the ROM depends on uninitialized power-on state");
+                process::exit(1);
+            }
+        }
+        Some("disasm") => {
+            let Some(rom) = args.get(2) else {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            };
+
+            let mut emulator = Emulator::new();
+
+            if let Err(error) = emulator.load_rom(rom) {
+                eprintln!("{}: {}", rom, error);
+                process::exit(1);
+            }
+
+            //Starts at the reset vector unless told otherwise
+            let start = match args.get(3) {
+                Some(start) => parse_address(start),
+                None => emulator.get_program_counter(),
+            };
+
+            let end = match args.get(4) {
+                Some(end) => parse_address(end),
+                None => start.saturating_add(0x3F),
+            };
+
+            //A comment line names the region (and bank) every time the listing enters a new one
+            let mut region = None;
+
+            for line in emulator.disassemble(start, end) {
+                let current = emulator.memory_region(line.address);
+
+                if current != region {
+                    if let Some(current) = &current {
+                        println!("; {}", current);
+                    }
+
+                    region = current;
+                }
+
+                println!("{}", line);
+            }
+        }
+        Some("test") => {
+            let Some(rom) = args.get(2) else {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            };
+
+            let frames = parse_arg(&args, 3, "frames", DEFAULT_TEST_FRAMES);
+
+            let mut emulator = Emulator::new();
+
+            if let Err(error) = emulator.load_rom(rom) {
+                eprintln!("{}: {}", rom, error);
+                process::exit(1);
+            }
+
+            //Printed lines are echoed to stderr as they come
+            emulator.enable_debug_port(DebugPortConfig::default());
+
+            match emulator.run_until_exit(frames) {
+                Some(code) => process::exit(code as i32),
+                None => {
+                    eprintln!("{}: no exit code after {} frames", rom, frames);
+                    process::exit(TEST_TIMEOUT_EXIT);
+                }
+            }
+        }
+        //Prints the value HeadlessRunner::assert_frame_hash() expects
+        Some("hash") => {
+            let Some(rom) = args.get(2) else {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            };
+
+            let frames = parse_arg(&args, 3, "frames", DEFAULT_HASH_FRAMES);
+
+            let mut runner = match HeadlessRunner::from_file(rom) {
+                Ok(runner) => runner,
+                Err(error) => {
+                    eprintln!("{}: {}", rom, error);
+                    process::exit(1);
+                }
+            };
+
+            println!("frame {}: {:08X}", frames, runner.run(frames as u64));
+        }
+        Some(_) => {
+            let mut auto_save = false;
+            let mut timing_trace = None;
+            let mut accuracy = AccuracyProfile::default();
+            let mut palette = None;
+            let mut input_log = None;
+            let mut pause_on_diagnostic = false;
+            let mut wav = None;
+            let mut input_latency = false;
+            let mut data_directory = None;
+            let mut index = 1;
+
+            //Options come before the ROM
+            loop {
+                match args.get(index).map(|arg| arg.as_str()) {
+                    Some("--auto-save") => auto_save = true,
+                    Some("--pause-on-diagnostic") => pause_on_diagnostic = true,
+                    Some("--input-latency") => input_latency = true,
+                    Some("--timing-trace") => {
+                        index += 1;
+                        timing_trace = args.get(index).map(PathBuf::from);
+
+                        if timing_trace.is_none() {
+                            eprintln!("{}", USAGE);
+                            process::exit(2);
+                        }
+                    }
+                    Some("--accuracy") => {
+                        index += 1;
+
+                        let Some(profile) = args.get(index) else {
+                            eprintln!("{}", USAGE);
+                            process::exit(2);
+                        };
+
+                        accuracy = match profile.parse() {
+                            Ok(profile) => profile,
+                            Err(error) => {
+                                eprintln!("{}", error);
+                                process::exit(2);
+                            }
+                        };
+                    }
+                    Some("--input-log") => {
+                        index += 1;
+                        input_log = args.get(index).map(PathBuf::from);
+
+                        if input_log.is_none() {
+                            eprintln!("{}", USAGE);
+                            process::exit(2);
+                        }
+                    }
+                    Some("--data-dir") => {
+                        index += 1;
+                        data_directory = args.get(index).map(PathBuf::from);
+
+                        if data_directory.is_none() {
+                            eprintln!("{}", USAGE);
+                            process::exit(2);
+                        }
+                    }
+                    Some("--wav") => {
+                        index += 1;
+                        wav = args.get(index).map(PathBuf::from);
+
+                        if wav.is_none() {
+                            eprintln!("{}", USAGE);
+                            process::exit(2);
+                        }
+                    }
+                    Some("--palette") => {
+                        index += 1;
+
+                        let Some(path) = args.get(index) else {
+                            eprintln!("{}", USAGE);
+                            process::exit(2);
+                        };
+
+                        palette = match Palette::from_file(path) {
+                            Ok(palette) => Some(palette),
+                            Err(error) => {
+                                eprintln!("{}: {}", path, error);
+                                process::exit(2);
+                            }
+                        };
+                    }
+                    _ => break,
+                }
+
+                index += 1;
+            }
+
+            let Some(rom) = args.get(index) else {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            };
+
+            let options = FrontendOptions {
+                auto_save,
+                timing_trace,
+                accuracy,
+                palette,
+                input_log,
+                pause_on_diagnostic,
+                wav,
+                input_latency,
+                data_directory,
+            };
+
+            run_frontend(Path::new(rom), options);
+        }
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(feature = "frontend")]
+fn run_frontend(rom: &Path, options: FrontendOptions) {
+    if let Err(error) = frontend::run(rom, &options) {
+        eprintln!("{}: {}", rom.display(), error);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "frontend"))]
+fn run_frontend(_rom: &Path, _options: FrontendOptions) {
+    eprintln!("rnes was built without the frontend feature");
+    process::exit(1);
+}
+
+fn parse_arg(args: &[String], index: usize, name: &str, default: u32) -> u32 {
+    match args.get(index).map(|arg| arg.parse::<u32>()) {
+        Some(Ok(value)) => value,
+        Some(Err(_)) => {
+            eprintln!("{} must be a positive number", name);
+            process::exit(2);
+        }
+        None => default,
+    }
+}
+
+///Seeds are printed in hex, so accept them back with or without 0x
+fn parse_seed(seed: &str) -> u64 {
+    let digits = seed.trim_start_matches("0x");
+
+    match u64::from_str_radix(digits, 16) {
+        Ok(seed) => seed,
+        Err(_) => {
+            eprintln!("seed must be a hexadecimal number");
+            process::exit(2);
+        }
+    }
+}
 
-    let address: u16 = 0x1234;
+///Addresses are written in hex, with or without $ or 0x
+fn parse_address(address: &str) -> u16 {
+    let digits = address.trim_start_matches('$').trim_start_matches("0x");
 
-    println!("Hello, world! {:?}", address >= 0x4232);
+    match u16::from_str_radix(digits, 16) {
+        Ok(address) => address,
+        Err(_) => {
+            eprintln!("{} is not a hexadecimal address", address);
+            process::exit(2);
+        }
+    }
 }
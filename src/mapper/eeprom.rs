@@ -0,0 +1,413 @@
+///Serial EEPROM chips found on Bandai boards, they keep the saves without a battery
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EepromChip {
+    X24C01, //128 bytes, no device address: the start is followed by the word address, bits sent LSB first
+    C24C02, //256 bytes, standard I2C: device address byte, word address byte, bits sent MSB first
+}
+
+impl EepromChip {
+    pub fn size(&self) -> usize {
+        match self {
+            EepromChip::X24C01 => 128,
+            EepromChip::C24C02 => 256,
+        }
+    }
+}
+
+///What the next byte on the bus is
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Phase {
+    Idle,    //Waiting for a start condition
+    Device,  //Device address and R/W bit (24C02), word address and R/W bit (X24C01)
+    Address, //Word address (24C02)
+    Write,   //Data written at the word address
+    Read,    //Data sent by the chip
+}
+
+///24C01/24C02 serial EEPROM driven through two lines, SCL (clock) and SDA (data)<br>
+///A start (SDA falling while SCL is high) begins a transfer and a stop (SDA rising while SCL is high) ends it,
+///in between a byte is 8 clocks followed by an acknowledge clock where the receiver pulls SDA low
+pub(crate) struct Eeprom {
+    chip: EepromChip,
+    data: Vec<u8>,
+
+    scl: bool,
+    sda: bool,
+
+    phase: Phase,
+    next_phase: Phase, //Phase after the acknowledge clock
+    bit: u8,           //Clocks of the current byte so far, the 9th is the acknowledge clock
+    shift: u8,         //Byte being received or sent
+    address: u8,
+    acknowledged: bool, //The CPU acknowledged the byte sent last, the chip sends the next one
+    output: bool,       //Level the chip leaves on SDA, high when it doesn't drive it
+}
+
+impl Eeprom {
+    //Constructor
+    pub fn new(chip: EepromChip) -> Self {
+        Self {
+            chip,
+            //Erased cells read as $FF
+            data: vec![0xFF; chip.size()],
+
+            scl: true,
+            sda: true,
+
+            phase: Phase::Idle,
+            next_phase: Phase::Idle,
+            bit: 0,
+            shift: 0,
+            address: 0,
+            acknowledged: false,
+            output: true,
+        }
+    }
+
+    ///SDA as the chip drives it, combined with the CPU's level by the open drain bus
+    pub fn output(&self) -> bool {
+        return self.output;
+    }
+
+    pub fn get_data(&self) -> &[u8] {
+        return &self.data;
+    }
+
+    ///Returns false (and changes nothing) when the size doesn't match the chip
+    pub fn load_data(&mut self, data: &[u8]) -> bool {
+        if data.len() != self.data.len() {
+            return false;
+        }
+
+        self.data.copy_from_slice(data);
+
+        return true;
+    }
+
+    ///New levels of the two lines, set by the CPU through a board register
+    pub fn write_lines(&mut self, scl: bool, sda: bool) {
+        if scl && self.scl && sda != self.sda {
+            if sda {
+                self.stop();
+            } else {
+                self.start();
+            }
+        } else if scl && !self.scl {
+            self.clock_rise(sda);
+        } else if !scl && self.scl {
+            self.clock_fall();
+        }
+
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn start(&mut self) {
+        self.phase = Phase::Device;
+        self.bit = 0;
+        self.shift = 0;
+        self.output = true;
+    }
+
+    fn stop(&mut self) {
+        self.phase = Phase::Idle;
+        self.output = true;
+    }
+
+    ///Position of the bit sent on a clock of the byte
+    fn bit_mask(&self, bit: u8) -> u8 {
+        match self.chip {
+            EepromChip::X24C01 => 1 << bit,
+            EepromChip::C24C02 => 0x80 >> bit,
+        }
+    }
+
+    ///Data is sampled while SCL is high, every rising edge is one clock of the byte
+    fn clock_rise(&mut self, sda: bool) {
+        match self.phase {
+            Phase::Idle => return,
+            Phase::Read => {
+                if self.bit == 8 {
+                    self.acknowledged = !sda;
+                }
+            }
+            _ => {
+                if self.bit < 8 && sda {
+                    self.shift |= self.bit_mask(self.bit);
+                }
+            }
+        }
+
+        self.bit += 1;
+    }
+
+    ///The chip changes SDA while SCL is low, for the clock after the one that just ended
+    fn clock_fall(&mut self) {
+        if self.phase == Phase::Idle {
+            return;
+        }
+
+        match self.bit {
+            //The byte is complete, the acknowledge clock follows
+            8 => {
+                if self.phase == Phase::Read {
+                    self.output = true;
+                } else {
+                    let byte = std::mem::take(&mut self.shift);
+                    self.receive(byte);
+                }
+            }
+            //The acknowledge clock is over, the next byte starts
+            9 => {
+                self.bit = 0;
+
+                if self.phase == Phase::Read {
+                    if self.acknowledged {
+                        self.address = self.address.wrapping_add(1);
+                        self.load_read_byte();
+                    } else {
+                        self.phase = Phase::Idle;
+                        self.output = true;
+                    }
+                } else {
+                    self.phase = self.next_phase;
+                    self.output = true;
+
+                    if self.phase == Phase::Read {
+                        self.load_read_byte();
+                    }
+                }
+            }
+            1..=7 if self.phase == Phase::Read => {
+                self.output = (self.shift & self.bit_mask(self.bit)) != 0;
+            }
+            _ => {}
+        }
+    }
+
+    ///Handles a complete byte sent by the CPU, the chip acknowledges it unless it isn't addressed
+    fn receive(&mut self, byte: u8) {
+        let read = match self.chip {
+            EepromChip::X24C01 => (byte & 0x80) != 0,
+            EepromChip::C24C02 => (byte & 0x01) != 0,
+        };
+
+        self.next_phase = match (self.phase, self.chip) {
+            (Phase::Device, EepromChip::X24C01) => {
+                self.address = byte & 0x7F;
+
+                if read { Phase::Read } else { Phase::Write }
+            }
+            (Phase::Device, EepromChip::C24C02) => {
+                //Device type 1010, the chip select pins are all tied low
+                if (byte & 0xFE) != 0xA0 {
+                    self.phase = Phase::Idle;
+                    return;
+                }
+
+                if read { Phase::Read } else { Phase::Address }
+            }
+            (Phase::Address, _) => {
+                self.address = byte;
+                Phase::Write
+            }
+            _ => {
+                let index = self.address as usize % self.data.len();
+                self.data[index] = byte;
+                self.address = self.address.wrapping_add(1);
+
+                Phase::Write
+            }
+        };
+
+        self.output = false;
+    }
+
+    ///Byte at the word address, its first bit goes out right away
+    fn load_read_byte(&mut self) {
+        self.shift = self.data[self.address as usize % self.data.len()];
+        self.output = (self.shift & self.bit_mask(0)) != 0;
+    }
+
+    //Save States
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let phase = |phase: Phase| phase as u8;
+
+        let mut data = vec![
+            self.scl as u8,
+            self.sda as u8,
+            phase(self.phase),
+            phase(self.next_phase),
+            self.bit,
+            self.shift,
+            self.address,
+            self.acknowledged as u8,
+            self.output as u8,
+        ];
+        data.extend_from_slice(&self.data);
+
+        return data;
+    }
+
+    ///Returns false (and changes nothing) when the data wasn't saved with the same chip
+    pub fn load_state(&mut self, data: &[u8]) -> bool {
+        if data.len() != 9 + self.data.len() {
+            return false;
+        }
+
+        let phase = |value: u8| match value {
+            1 => Phase::Device,
+            2 => Phase::Address,
+            3 => Phase::Write,
+            4 => Phase::Read,
+            _ => Phase::Idle,
+        };
+
+        self.scl = data[0] != 0;
+        self.sda = data[1] != 0;
+        self.phase = phase(data[2]);
+        self.next_phase = phase(data[3]);
+        self.bit = data[4];
+        self.shift = data[5];
+        self.address = data[6];
+        self.acknowledged = data[7] != 0;
+        self.output = data[8] != 0;
+        self.data.copy_from_slice(&data[9..]);
+
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Drives the bus like the games do: every bit is SCL low, SDA set, SCL high, SCL low
+    struct Master {
+        eeprom: Eeprom,
+    }
+
+    impl Master {
+        fn new(chip: EepromChip) -> Self {
+            return Self { eeprom: Eeprom::new(chip) };
+        }
+
+        fn start(&mut self) {
+            self.eeprom.write_lines(false, true);
+            self.eeprom.write_lines(true, true);
+            self.eeprom.write_lines(true, false);
+            self.eeprom.write_lines(false, false);
+        }
+
+        fn stop(&mut self) {
+            self.eeprom.write_lines(false, false);
+            self.eeprom.write_lines(true, false);
+            self.eeprom.write_lines(true, true);
+        }
+
+        ///Returns the level read on SDA while SCL is high
+        fn clock(&mut self, sda: bool) -> bool {
+            self.eeprom.write_lines(false, sda);
+            self.eeprom.write_lines(true, sda);
+            let level = sda && self.eeprom.output();
+            self.eeprom.write_lines(false, sda);
+
+            return level;
+        }
+
+        ///Sends a byte, returns true when the chip acknowledged it
+        fn send(&mut self, byte: u8, lsb_first: bool) -> bool {
+            for bit in 0..8 {
+                let mask = if lsb_first { 1 << bit } else { 0x80 >> bit };
+                self.clock((byte & mask) != 0);
+            }
+
+            return !self.clock(true);
+        }
+
+        fn receive(&mut self, lsb_first: bool, acknowledge: bool) -> u8 {
+            let mut byte = 0;
+
+            for bit in 0..8 {
+                let mask = if lsb_first { 1 << bit } else { 0x80 >> bit };
+                if self.clock(true) {
+                    byte |= mask;
+                }
+            }
+
+            self.clock(!acknowledge);
+
+            return byte;
+        }
+    }
+
+    #[test]
+    fn c24c02_writes_and_reads_back_sequentially() {
+        let mut master = Master::new(EepromChip::C24C02);
+
+        master.start();
+        assert!(master.send(0xA0, false));
+        assert!(master.send(0x10, false));
+        assert!(master.send(0x12, false));
+        assert!(master.send(0x34, false));
+        master.stop();
+
+        assert_eq!(&master.eeprom.get_data()[0x10..0x12], &[0x12, 0x34]);
+
+        //Random read: set the address with a write, then a repeated start reads from it
+        master.start();
+        assert!(master.send(0xA0, false));
+        assert!(master.send(0x10, false));
+        master.start();
+        assert!(master.send(0xA1, false));
+        assert_eq!(master.receive(false, true), 0x12);
+        assert_eq!(master.receive(false, false), 0x34);
+        master.stop();
+    }
+
+    #[test]
+    fn c24c02_ignores_other_devices() {
+        let mut master = Master::new(EepromChip::C24C02);
+
+        master.start();
+        assert!(!master.send(0xB0, false));
+        assert!(!master.send(0x00, false));
+        master.stop();
+
+        assert!(master.eeprom.get_data().iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn x24c01_takes_the_address_right_after_the_start() {
+        let mut master = Master::new(EepromChip::X24C01);
+
+        //Address $05, R/W low, LSB first
+        master.start();
+        assert!(master.send(0x05, true));
+        assert!(master.send(0xC3, true));
+        master.stop();
+
+        assert_eq!(master.eeprom.get_data()[0x05], 0xC3);
+
+        master.start();
+        assert!(master.send(0x85, true));
+        assert_eq!(master.receive(true, false), 0xC3);
+        master.stop();
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut master = Master::new(EepromChip::X24C01);
+        master.start();
+        master.send(0x01, true);
+        master.send(0x42, true);
+
+        let state = master.eeprom.save_state();
+        let mut eeprom = Eeprom::new(EepromChip::X24C01);
+        assert!(eeprom.load_state(&state));
+        assert_eq!(eeprom.save_state(), state);
+
+        assert!(!Eeprom::new(EepromChip::C24C02).load_state(&state));
+    }
+}
@@ -0,0 +1,326 @@
+use super::Mapper;
+
+///Wiring of a discrete logic board built around a single latch, which every write to its register range loads<br>
+///The PRG bank is 32KB at $8000 and the CHR bank 8KB at $0000, decoded from the latch value
+pub struct LatchBoard {
+    registers: (u16, u16), //First and last address of the latch
+    prg_bank: fn(u8) -> u8,
+    chr_bank: fn(u8) -> u8,
+    chr_enabled: fn(u8) -> bool, //Copy protected CNROM boards only connect the CHR-ROM for some latch values
+    bus_conflicts: bool,         //The ROM drives the data bus during writes to $8000 - $FFFF too
+}
+
+impl LatchBoard {
+    ///Boards with their latch in $6000 - $7FFF leave no room for PRG-RAM
+    fn has_prg_ram(&self) -> bool {
+        return self.registers.0 >= 0x8000;
+    }
+}
+
+///Color Dreams (mapper 11): PRG in bits 0-1, CHR in bits 4-7
+pub const COLOR_DREAMS: LatchBoard = LatchBoard {
+    registers: (0x8000, 0xFFFF),
+    prg_bank: |latch| latch & 0x03,
+    chr_bank: |latch| latch >> 4,
+    chr_enabled: |_| true,
+    bus_conflicts: true,
+};
+
+///BNROM (mapper 34, Deadly Towers): PRG in bits 0-1, 8KB of CHR-RAM
+pub const BNROM: LatchBoard = LatchBoard {
+    registers: (0x8000, 0xFFFF),
+    prg_bank: |latch| latch & 0x03,
+    chr_bank: |_| 0,
+    chr_enabled: |_| true,
+    bus_conflicts: true,
+};
+
+///Bit Corp UNL-PCI556 (mapper 38): latch at $7000 - $7FFF, PRG in bits 0-1, CHR in bits 2-3
+pub const PCI556: LatchBoard = LatchBoard {
+    registers: (0x7000, 0x7FFF),
+    prg_bank: |latch| latch & 0x03,
+    chr_bank: |latch| (latch >> 2) & 0x03,
+    chr_enabled: |_| true,
+    bus_conflicts: false,
+};
+
+///GxROM (mapper 66, Super Mario Bros. + Duck Hunt): PRG in bits 4-5, CHR in bits 0-1
+pub const GXROM: LatchBoard = LatchBoard {
+    registers: (0x8000, 0xFFFF),
+    prg_bank: |latch| (latch >> 4) & 0x03,
+    chr_bank: |latch| latch & 0x03,
+    chr_enabled: |_| true,
+    bus_conflicts: true,
+};
+
+///Jaleco JF-05 - JF-10 (mapper 87): latch at $6000 - $7FFF, fixed PRG, CHR in bits 0-1 wired in reverse
+pub const JALECO_87: LatchBoard = LatchBoard {
+    registers: (0x6000, 0x7FFF),
+    prg_bank: |_| 0,
+    chr_bank: |latch| ((latch & 0x01) << 1) | ((latch & 0x02) >> 1),
+    chr_enabled: |_| true,
+    bus_conflicts: false,
+};
+
+///Jaleco JF-11/JF-14 (mapper 140): latch at $6000 - $7FFF, PRG in bits 4-5, CHR in bits 0-3
+pub const JALECO_140: LatchBoard = LatchBoard {
+    registers: (0x6000, 0x7FFF),
+    prg_bank: |latch| (latch >> 4) & 0x03,
+    chr_bank: |latch| latch & 0x0F,
+    chr_enabled: |_| true,
+    bus_conflicts: false,
+};
+
+///CNROM with a CHR-ROM enable (mapper 185, submapper 0): the board isn't identified, so the CHR is connected for
+///the values the games use to enable it (any of bits 0-1 set, except $13)
+pub const CNROM_PROTECTED: LatchBoard = LatchBoard {
+    registers: (0x8000, 0xFFFF),
+    prg_bank: |_| 0,
+    chr_bank: |_| 0,
+    chr_enabled: |latch| (latch & 0x03) != 0 && latch != 0x13,
+    bus_conflicts: true,
+};
+
+///Mapper 185 submappers 4 - 7: the CHR is connected when bits 0-1 of the latch hold submapper - 4
+pub const CNROM_PROTECTED_BY_SUBMAPPER: [LatchBoard; 4] = [
+    LatchBoard { chr_enabled: |latch| (latch & 0x03) == 0, ..CNROM_PROTECTED },
+    LatchBoard { chr_enabled: |latch| (latch & 0x03) == 1, ..CNROM_PROTECTED },
+    LatchBoard { chr_enabled: |latch| (latch & 0x03) == 2, ..CNROM_PROTECTED },
+    LatchBoard { chr_enabled: |latch| (latch & 0x03) == 3, ..CNROM_PROTECTED },
+];
+
+///Discrete logic boards (mappers 11, 34, 38, 66, 87, 140 and 185), see LatchBoard
+pub struct LatchMapper {
+    prg_banks: u8, //16KB banks
+    chr_banks: u8, //8KB banks, 0 for CHR-RAM
+    board: &'static LatchBoard,
+
+    latch: u8,
+}
+
+impl LatchMapper {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8, board: &'static LatchBoard) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            board,
+
+            latch: 0,
+        }
+    }
+
+    ///Offset in CHR memory, bank numbers past the end of the CHR wrap around
+    fn ppu_map(&self, address: u16) -> usize {
+        let size = (self.chr_banks as usize * 0x2000).max(0x2000);
+
+        return ((self.board.chr_bank)(self.latch) as usize * 0x2000 + address as usize) % size;
+    }
+}
+
+impl Mapper for LatchMapper {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        //16KB images are mirrored into both halves of the 32KB bank
+        let size = (self.prg_banks as usize * 0x4000).max(0x4000);
+        let bank = (self.board.prg_bank)(self.latch) as usize;
+
+        return Some((bank * 0x8000 + (address & 0x7FFF) as usize) % size);
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if (self.board.registers.0..=self.board.registers.1).contains(&address) {
+            self.latch = data;
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        //A disconnected CHR-ROM leaves the reads to the open bus
+        if address > 0x1FFF || !(self.board.chr_enabled)(self.latch) {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    fn bus_conflicts(&self) -> bool {
+        return self.board.bus_conflicts;
+    }
+
+    fn has_prg_ram(&self) -> bool {
+        return self.board.has_prg_ram();
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return 0x8000;
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return 0x2000;
+    }
+
+    fn reset(&mut self) {
+        self.latch = 0;
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        return vec![self.latch];
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if let [latch] = *data {
+            self.latch = latch;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, CHR_BANK_SIZE, PRG_BANK_SIZE};
+
+    ///Image with 128KB PRG and 128KB CHR (no CHR for CHR-RAM), the first byte of every 32KB PRG bank holds the bank
+    ///number and the rest $FF, so writes to $8001 - $FFFF don't conflict; every CHR byte holds its 8KB bank number
+    fn cartridge(mapper: u8, submapper: u8, chr_rom: bool) -> Cartridge {
+        let mut rom = vec![0; 16];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 8;
+        rom[5] = if chr_rom { 16 } else { 0 };
+        rom[6] = mapper << 4;
+        rom[7] = (mapper & 0xF0) | 0x08;
+        rom[8] = submapper << 4;
+
+        for bank in 0..4 {
+            rom.push(bank);
+            rom.extend(std::iter::repeat_n(0xFF, 2 * PRG_BANK_SIZE - 1));
+        }
+        if chr_rom {
+            for bank in 0..16 {
+                rom.extend(std::iter::repeat_n(bank, CHR_BANK_SIZE));
+            }
+        }
+
+        return Cartridge::from_bytes(&rom).unwrap();
+    }
+
+    ///(PRG bank at $8000, CHR bank at $0000)
+    fn banks(cartridge: &Cartridge) -> (Option<u8>, Option<u8>) {
+        return (cartridge.cpu_read(0x8000), cartridge.ppu_read(0x0000));
+    }
+
+    #[test]
+    fn color_dreams_switches_prg_and_chr() {
+        let mut cartridge = cartridge(11, 0, true);
+
+        cartridge.cpu_write(0xC000, 0x52);
+        assert_eq!(banks(&cartridge), (Some(2), Some(5)));
+    }
+
+    #[test]
+    fn bnrom_switches_prg_with_chr_ram() {
+        let mut cartridge = cartridge(34, 0, false);
+
+        cartridge.cpu_write(0xC000, 0x03);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(3));
+
+        cartridge.ppu_write(0x1234, 0xAB);
+        assert_eq!(cartridge.ppu_read(0x1234), Some(0xAB));
+    }
+
+    #[test]
+    fn pci556_latch_is_at_7000() {
+        let mut cartridge = cartridge(38, 0, true);
+
+        cartridge.cpu_write(0x8001, 0x0F);
+        assert_eq!(banks(&cartridge), (Some(0), Some(0)));
+
+        cartridge.cpu_write(0x7000, 0x0E);
+        assert_eq!(banks(&cartridge), (Some(2), Some(3)));
+        assert_eq!(cartridge.cpu_read(0x6000), None);
+    }
+
+    #[test]
+    fn gxrom_switches_prg_and_chr() {
+        let mut cartridge = cartridge(66, 0, true);
+
+        cartridge.cpu_write(0xFFFF, 0x31);
+        assert_eq!(banks(&cartridge), (Some(3), Some(1)));
+    }
+
+    #[test]
+    fn jaleco_87_swaps_the_chr_bits() {
+        let mut cartridge = cartridge(87, 0, true);
+
+        cartridge.cpu_write(0x6000, 0x01);
+        assert_eq!(banks(&cartridge), (Some(0), Some(2)));
+
+        cartridge.cpu_write(0x7FFF, 0x02);
+        assert_eq!(banks(&cartridge), (Some(0), Some(1)));
+    }
+
+    #[test]
+    fn jaleco_140_switches_prg_and_chr() {
+        let mut cartridge = cartridge(140, 0, true);
+
+        cartridge.cpu_write(0x6000, 0x2C);
+        assert_eq!(banks(&cartridge), (Some(2), Some(12)));
+    }
+
+    #[test]
+    fn protected_cnrom_disconnects_the_chr() {
+        let mut submapper_0 = cartridge(185, 0, true);
+
+        submapper_0.cpu_write(0xC000, 0x00);
+        assert_eq!(submapper_0.ppu_read(0x0000), None);
+
+        submapper_0.cpu_write(0xC000, 0x01);
+        assert_eq!(submapper_0.ppu_read(0x0000), Some(0));
+
+        submapper_0.cpu_write(0xC000, 0x13);
+        assert_eq!(submapper_0.ppu_read(0x0000), None);
+
+        //Submapper 6: enabled by bits 0-1 = 2 only
+        let mut submapper_6 = cartridge(185, 6, true);
+
+        submapper_6.cpu_write(0xC000, 0x01);
+        assert_eq!(submapper_6.ppu_read(0x0000), None);
+
+        submapper_6.cpu_write(0xC000, 0x02);
+        assert_eq!(submapper_6.ppu_read(0x0000), Some(0));
+    }
+
+    #[test]
+    fn bus_conflicts_and_the_written_value_with_the_rom() {
+        let mut gxrom = cartridge(66, 0, true);
+
+        //$8000 holds 0 in bank 0, the latch gets 0
+        gxrom.cpu_write(0x8000, 0x31);
+        assert_eq!(banks(&gxrom), (Some(0), Some(0)));
+
+        gxrom.cpu_write(0x8001, 0x31);
+        assert_eq!(banks(&gxrom), (Some(3), Some(1)));
+
+        //$8000 holds 3 in bank 3: $21 & 3 = 1, PRG bank 0 and CHR bank 1
+        gxrom.cpu_write(0x8000, 0x21);
+        assert_eq!(banks(&gxrom), (Some(0), Some(1)));
+
+        //No conflicts when the latch isn't in the ROM's range
+        let mut jaleco = cartridge(140, 0, true);
+        jaleco.cpu_write(0x6000, 0x31);
+        assert_eq!(banks(&jaleco), (Some(3), Some(1)));
+    }
+}
@@ -0,0 +1,60 @@
+use super::Mapper;
+
+///NROM<br>
+///16KB (mirrored into $C000 - $FFFF) or 32KB of fixed PRG-ROM and 8KB of fixed CHR
+pub struct Mapper000 {
+    prg_banks: u8,
+    chr_banks: u8,
+}
+
+impl Mapper000 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+        }
+    }
+}
+
+impl Mapper for Mapper000 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let mask = if self.prg_banks > 1 { 0x7FFF } else { 0x3FFF };
+
+        return Some((address & mask) as usize);
+    }
+
+    fn cpu_map_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        return Some(address as usize);
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return Some(address as usize);
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return if self.prg_banks > 1 { 0x8000 } else { 0x4000 };
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return 0x2000;
+    }
+}
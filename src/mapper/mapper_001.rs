@@ -0,0 +1,210 @@
+use super::Mapper;
+use crate::ppu::Mirroring;
+
+///MMC1 (SxROM)<br>
+///The registers are loaded one bit at a time through a 5-bit shift register: every write to $8000 - $FFFF
+///shifts in bit 0, the fifth write copies the value into the register picked by address bits 13-14<br>
+///Writing a value with bit 7 set clears the shift register and locks the last PRG bank at $C000
+pub struct Mapper001 {
+    prg_banks: u8, //16KB banks
+    chr_banks: u8, //8KB banks, 0 for CHR-RAM
+
+    shift_register: u8,
+    shift_count: u8,
+
+    //Control ($8000 - $9FFF)
+    // Bits  Description
+    // 0-1   Mirroring: 0 one-screen (lower), 1 one-screen (upper), 2 vertical, 3 horizontal
+    // 2-3   PRG mode: 0/1 switch 32KB at $8000, 2 fix the first bank at $8000, 3 fix the last bank at $C000
+    // 4     CHR mode: 0 switch 8KB at a time, 1 switch two separate 4KB banks
+    control: u8,
+
+    chr_bank_0: u8, //$A000 - $BFFF
+    chr_bank_1: u8, //$C000 - $DFFF
+    prg_bank: u8,   //$E000 - $FFFF, bit 4 (PRG-RAM disable) isn't emulated
+}
+
+impl Mapper001 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+
+            shift_register: 0,
+            shift_count: 0,
+
+            control: 0x0C,
+
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    ///16KB PRG bank mapped at $8000 ($8000 - $BFFF) or $C000 ($C000 - $FFFF)
+    fn prg_bank_at(&self, address: u16) -> usize {
+        let bank = self.prg_bank & 0x0F;
+        let upper = address >= 0xC000;
+
+        let bank = match (self.control >> 2) & 0x03 {
+            0 | 1 => (bank & 0x0E) | upper as u8,
+            2 => if upper { bank } else { 0 },
+            _ => if upper { self.prg_banks.saturating_sub(1) } else { bank },
+        };
+
+        return bank as usize % self.prg_banks.max(1) as usize;
+    }
+
+    ///4KB CHR bank mapped at $0000 ($0000 - $0FFF) or $1000 ($1000 - $1FFF)
+    fn chr_bank_at(&self, address: u16) -> usize {
+        let upper = address >= 0x1000;
+
+        let bank = if (self.control & 0x10) == 0 {
+            (self.chr_bank_0 & 0x1E) | upper as u8
+        } else if upper {
+            self.chr_bank_1
+        } else {
+            self.chr_bank_0
+        };
+
+        //CHR-RAM boards have 8KB, two 4KB banks
+        let banks = (self.chr_banks as usize * 2).max(2);
+
+        return bank as usize % banks;
+    }
+
+    fn ppu_map(&self, address: u16) -> usize {
+        return self.chr_bank_at(address) * 0x1000 + (address & 0x0FFF) as usize;
+    }
+}
+
+impl Mapper for Mapper001 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        return Some(self.prg_bank_at(address) * 0x4000 + (address & 0x3FFF) as usize);
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        if (data & 0x80) != 0 {
+            self.shift_register = 0;
+            self.shift_count = 0;
+            self.control |= 0x0C;
+
+            return None;
+        }
+
+        //Bits come in LSB first, so they are shifted in from the top
+        self.shift_register = (self.shift_register >> 1) | ((data & 0x01) << 4);
+        self.shift_count += 1;
+
+        if self.shift_count == 5 {
+            let value = self.shift_register;
+
+            match address {
+                0x8000..=0x9FFF => self.control = value,
+                0xA000..=0xBFFF => self.chr_bank_0 = value,
+                0xC000..=0xDFFF => self.chr_bank_1 = value,
+                _ => self.prg_bank = value,
+            }
+
+            self.shift_register = 0;
+            self.shift_count = 0;
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    ///The fifth write to $E000 - $FFFF with bit 4 set disables the PRG-RAM
+    fn unsupported_write(&self, address: u16, data: u8) -> Option<&'static str> {
+        let completes_prg_bank = address >= 0xE000 && self.shift_count == 4 && (data & 0x80) == 0;
+
+        if completes_prg_bank && (data & 0x01) != 0 {
+            return Some("PRG-RAM disable");
+        }
+
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        let mirroring = match self.control & 0x03 {
+            0 => Mirroring::OneScreenLow,
+            1 => Mirroring::OneScreenHigh,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        };
+
+        return Some(mirroring);
+    }
+
+    ///PRG modes 0 and 1 switch 32KB at a time
+    fn prg_bank_size(&self) -> usize {
+        return if (self.control & 0x08) == 0 { 0x8000 } else { 0x4000 };
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return if (self.control & 0x10) == 0 { 0x2000 } else { 0x1000 };
+    }
+
+    ///SOROM (16KB) switches the PRG-RAM with bit 3 of the first CHR register, SXROM (32KB) with bits 2 - 3
+    fn prg_ram_bank(&self, banks: usize) -> usize {
+        match banks {
+            0 | 1 => return 0,
+            2 => return ((self.chr_bank_0 >> 3) & 0x01) as usize,
+            _ => return ((self.chr_bank_0 >> 2) & 0x03) as usize,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.shift_register = 0;
+        self.shift_count = 0;
+        self.control = 0x0C;
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        return vec![
+            self.shift_register,
+            self.shift_count,
+            self.control,
+            self.chr_bank_0,
+            self.chr_bank_1,
+            self.prg_bank,
+        ];
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if let [shift_register, shift_count, control, chr_bank_0, chr_bank_1, prg_bank] = *data {
+            self.shift_register = shift_register;
+            self.shift_count = shift_count;
+            self.control = control;
+            self.chr_bank_0 = chr_bank_0;
+            self.chr_bank_1 = chr_bank_1;
+            self.prg_bank = prg_bank;
+        }
+    }
+}
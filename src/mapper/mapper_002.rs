@@ -0,0 +1,136 @@
+use super::Mapper;
+
+///UxROM<br>
+///16KB switchable PRG bank at $8000 selected by any write to $8000 - $FFFF, the last bank is fixed at $C000<br>
+///8KB of CHR, RAM on most boards
+pub struct Mapper002 {
+    prg_banks: u8,
+    chr_banks: u8,
+
+    prg_bank: u8, //Bank at $8000 - $BFFF
+}
+
+impl Mapper002 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+
+            prg_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Mapper002 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let bank = if address >= 0xC000 {
+            self.prg_banks.saturating_sub(1)
+        } else {
+            self.prg_bank % self.prg_banks.max(1)
+        };
+
+        return Some(bank as usize * 0x4000 + (address & 0x3FFF) as usize);
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if address >= 0x8000 {
+            self.prg_bank = data;
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        return Some(address as usize);
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return Some(address as usize);
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return 0x4000;
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return 0x2000;
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        return vec![self.prg_bank];
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if let [prg_bank] = *data {
+            self.prg_bank = prg_bank;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, PRG_BANK_SIZE};
+
+    ///UxROM image with 8 PRG banks (128KB, Mega Man / Castlevania) and CHR-RAM, every byte holds its bank number
+    fn cartridge() -> Cartridge {
+        let mut rom = vec![0; 16];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 8;
+        rom[6] = 0x20;
+
+        for bank in 0..8 {
+            rom.extend(std::iter::repeat_n(bank, PRG_BANK_SIZE));
+        }
+
+        return Cartridge::from_bytes(&rom).unwrap();
+    }
+
+    #[test]
+    fn starts_with_the_first_and_last_banks() {
+        let cartridge = cartridge();
+
+        assert_eq!(cartridge.cpu_read(0x8000), Some(0));
+        assert_eq!(cartridge.cpu_read(0xFFFF), Some(7));
+    }
+
+    #[test]
+    fn writes_switch_the_lower_bank_only() {
+        let mut cartridge = cartridge();
+
+        cartridge.cpu_write(0xC123, 5);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(5));
+        assert_eq!(cartridge.cpu_read(0xBFFF), Some(5));
+        assert_eq!(cartridge.cpu_read(0xC000), Some(7));
+
+        //Boards only decode the bits they need, bank 9 is bank 1 on a 128KB board
+        cartridge.cpu_write(0x8000, 9);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+    }
+
+    #[test]
+    fn chr_ram_is_writable() {
+        let mut cartridge = cartridge();
+
+        cartridge.ppu_write(0x1234, 0xAB);
+        assert_eq!(cartridge.ppu_read(0x1234), Some(0xAB));
+    }
+}
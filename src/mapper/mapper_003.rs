@@ -0,0 +1,141 @@
+use super::Mapper;
+
+///CNROM<br>
+///16KB or 32KB of fixed PRG-ROM like NROM, 8KB CHR-ROM banks selected by any write to $8000 - $FFFF
+pub struct Mapper003 {
+    prg_banks: u8,
+    chr_banks: u8,
+
+    chr_bank: u8,
+}
+
+impl Mapper003 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+
+            chr_bank: 0,
+        }
+    }
+
+    fn ppu_map(&self, address: u16) -> usize {
+        let bank = self.chr_bank as usize % (self.chr_banks as usize).max(1);
+
+        return bank * 0x2000 + address as usize;
+    }
+}
+
+impl Mapper for Mapper003 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let mask = if self.prg_banks > 1 { 0x7FFF } else { 0x3FFF };
+
+        return Some((address & mask) as usize);
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if address >= 0x8000 {
+            self.chr_bank = data;
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return if self.prg_banks > 1 { 0x8000 } else { 0x4000 };
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return 0x2000;
+    }
+
+    fn reset(&mut self) {
+        self.chr_bank = 0;
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        return vec![self.chr_bank];
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if let [chr_bank] = *data {
+            self.chr_bank = chr_bank;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, CHR_BANK_SIZE, PRG_BANK_SIZE};
+
+    ///CNROM image with 32KB of PRG and 4 CHR banks (Arkanoid, Gradius), every CHR byte holds its bank number
+    fn cartridge() -> Cartridge {
+        let mut rom = vec![0; 16];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 2;
+        rom[5] = 4;
+        rom[6] = 0x30;
+
+        rom.extend(std::iter::repeat_n(0xEA, 2 * PRG_BANK_SIZE));
+
+        for bank in 0..4 {
+            rom.extend(std::iter::repeat_n(bank, CHR_BANK_SIZE));
+        }
+
+        return Cartridge::from_bytes(&rom).unwrap();
+    }
+
+    #[test]
+    fn writes_switch_the_chr_bank() {
+        let mut cartridge = cartridge();
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0));
+
+        cartridge.cpu_write(0x8000, 2);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(2));
+        assert_eq!(cartridge.ppu_read(0x1FFF), Some(2));
+
+        //Only the bits the board decodes matter, bank 7 is bank 3 with 4 banks
+        cartridge.cpu_write(0xFFFF, 7);
+        assert_eq!(cartridge.ppu_read(0x1000), Some(3));
+    }
+
+    #[test]
+    fn prg_stays_fixed() {
+        let mut cartridge = cartridge();
+
+        cartridge.cpu_write(0x8000, 1);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(0xEA));
+        assert_eq!(cartridge.cpu_read(0xFFFF), Some(0xEA));
+    }
+
+    #[test]
+    fn chr_rom_is_read_only() {
+        let mut cartridge = cartridge();
+
+        cartridge.ppu_write(0x0000, 0xAB);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0));
+    }
+}
@@ -0,0 +1,503 @@
+use super::Mapper;
+use crate::ppu::Mirroring;
+
+///Which board of the MMC3 family the game runs on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mmc3Board {
+    Mmc3,      //MMC3 (mapper 4)
+    Namco108,  //Namco 108 (mapper 206, DxROM): the MMC3's predecessor, no modes, mirroring control, IRQ or PRG-RAM
+    Namco3446, //NAMCOT-3446 (mapper 76): Namco 108 with R2 - R5 switching 2KB CHR banks, R0 and R1 unused
+    Namco3433, //NAMCOT-3433 (mapper 88): Namco 108 with CHR A16 wired to PPU A12, 128KB of CHR
+    Rambo1,    //Tengen RAMBO-1 (mapper 64): MMC3 with R8, R9 and RF, 1KB CHR mode and a CPU cycle IRQ mode, no PRG-RAM
+}
+
+///MMC3 (TxROM) and the Namco 108 family<br>
+///Eight bank registers (R0 - R7) loaded through $8000 (which register, banking modes) and $8001 (bank number)<br>
+///PRG is switched in 8KB banks and CHR in 1KB/2KB banks, the scanline counter is clocked by rising edges of PPU A12<br>
+///The Namco 108 boards only have the two bank registers, with both modes stuck at 0<br>
+///The RAMBO-1 adds a third switchable PRG bank (RF), R8 and R9 for 1KB banks in place of R0 and R1's second halves,
+///and can clock its IRQ counter every 4 CPU cycles instead of on A12
+pub struct Mapper004 {
+    prg_banks: u8, //16KB banks
+    chr_banks: u8, //8KB banks, 0 for CHR-RAM
+    board: Mmc3Board,
+
+    //Bank Select ($8000, even)
+    // Bits  Description
+    // 0-2   Register written by the next bank data write (0-3 on the RAMBO-1, R8, R9 and RF)
+    // 5     RAMBO-1 CHR mode: 0 R0 and R1 switch 2KB, 1 R0, R8, R1 and R9 switch 1KB
+    // 6     PRG mode: 0 R6 at $8000 and the second last bank at $C000, 1 swapped
+    // 7     CHR mode: 0 2KB banks at $0000, 1 2KB banks at $1000 (A12 inversion)
+    bank_select: u8,
+    registers: [u8; 16],
+
+    mirroring: Mirroring,
+
+    //Scanline IRQ
+    irq_latch: u8,    //$C000, even
+    irq_counter: u8,
+    irq_reload: bool, //$C001 clears the counter so the next A12 rise reloads it
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12: bool,        //Last PPU A12 level seen
+    irq_cycle_mode: bool, //RAMBO-1 $C001 bit 0: count CPU cycles instead of A12 rises
+    prescaler: u8,        //CPU cycles counted towards the next clock in cycle mode
+}
+
+impl Mapper004 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8, board: Mmc3Board) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+            board,
+
+            bank_select: 0,
+            registers: [0; 16],
+
+            mirroring: Mirroring::Vertical,
+
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12: false,
+            irq_cycle_mode: false,
+            prescaler: 0,
+        }
+    }
+
+    ///Offset of an address inside an 8KB PRG bank
+    fn prg_map(&self, address: u16) -> usize {
+        let banks = (self.prg_banks as usize * 2).max(1);
+        let second_last = banks.saturating_sub(2);
+
+        let swapped = (self.bank_select & 0x40) != 0;
+
+        //The RAMBO-1 switches the bank the MMC3 fixes to the second last with RF
+        let third = if self.board == Mmc3Board::Rambo1 { self.registers[15] as usize } else { second_last };
+
+        let bank = match ((address >> 13) & 0x03, self.board) {
+            (0, _) => if swapped { third } else { self.registers[6] as usize },
+            (1, Mmc3Board::Rambo1) if swapped => self.registers[6] as usize,
+            (1, _) => self.registers[7] as usize,
+            (2, Mmc3Board::Rambo1) if swapped => self.registers[7] as usize,
+            (2, _) => if swapped { self.registers[6] as usize } else { third },
+            _ => banks - 1,
+        };
+
+        return (bank % banks) * 0x2000 + (address & 0x1FFF) as usize;
+    }
+
+    ///Offset of an address inside a 1KB CHR bank, R0 and R1 select 2KB (even) banks
+    fn ppu_map(&self, address: u16) -> usize {
+        let banks = (self.chr_banks as usize * 8).max(8);
+
+        //CHR mode 1 swaps the two halves of the pattern tables
+        let address = if (self.bank_select & 0x80) != 0 { address ^ 0x1000 } else { address };
+
+        let slot = (address >> 10) as usize;
+        let one_kb = self.board == Mmc3Board::Rambo1 && (self.bank_select & 0x20) != 0;
+
+        let bank = match (self.board, slot) {
+            (Mmc3Board::Namco3446, _) => self.registers[2 + (slot >> 1)] as usize * 2 + (slot & 0x01),
+            (_, 0..=3) if one_kb => self.registers[[0, 8, 1, 9][slot]] as usize,
+            (_, 0) => (self.registers[0] & 0xFE) as usize,
+            (_, 1) => (self.registers[0] | 0x01) as usize,
+            (_, 2) => (self.registers[1] & 0xFE) as usize,
+            (_, 3) => (self.registers[1] | 0x01) as usize,
+            (_, slot) => self.registers[slot - 2] as usize,
+        };
+
+        //The NAMCOT-3433 takes the 64KB half from A12: the background and sprite tables have their own CHR
+        let bank = match self.board {
+            Mmc3Board::Namco3433 if (address & 0x1000) != 0 => bank | 0x40,
+            Mmc3Board::Namco3433 => bank & 0x3F,
+            _ => bank,
+        };
+
+        return (bank % banks) * 0x0400 + (address & 0x03FF) as usize;
+    }
+
+    ///The MMC3 and the RAMBO-1 decode $A000 - $FFFF (mirroring and IRQ), the Namco 108 boards only the bank registers
+    fn has_mmc3_registers(&self) -> bool {
+        return self.board == Mmc3Board::Mmc3 || self.board == Mmc3Board::Rambo1;
+    }
+
+    fn clock_scanline_counter(&mut self) {
+        //The RAMBO-1 reloads one clock late with latches above 1 (Hard Drivin', Skull & Crossbones)
+        if self.board == Mmc3Board::Rambo1 {
+            if self.irq_reload {
+                self.irq_counter = self.irq_latch.wrapping_add(if self.irq_latch <= 1 { 1 } else { 2 });
+                self.irq_reload = false;
+            } else if self.irq_counter == 0 {
+                self.irq_counter = self.irq_latch.wrapping_add(1);
+            }
+
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+
+            if self.irq_counter == 0 && self.irq_enabled {
+                self.irq_pending = true;
+            }
+
+            return;
+        }
+
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mapper004 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        return Some(self.prg_map(address));
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let even = (address & 0x0001) == 0;
+
+        //Namco 108: the bank select only picks the register, $A000 - $FFFF isn't decoded
+        if !self.has_mmc3_registers() {
+            match (address, even) {
+                (0x8000..=0x9FFF, true) => self.bank_select = data & 0x07,
+                (0x8000..=0x9FFF, false) => self.registers[self.bank_select as usize] = data,
+                _ => {}
+            }
+
+            return None;
+        }
+
+        match (address, even) {
+            (0x8000..=0x9FFF, true) => self.bank_select = data,
+            (0x8000..=0x9FFF, false) => {
+                let mask = if self.board == Mmc3Board::Rambo1 { 0x0F } else { 0x07 };
+                self.registers[(self.bank_select & mask) as usize] = data;
+            }
+            (0xA000..=0xBFFF, true) => {
+                self.mirroring = if (data & 0x01) != 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+            }
+            //PRG-RAM protect ($A001) isn't emulated (unsupported_write() reports it), the RAM is always enabled
+            (0xA000..=0xBFFF, false) => {}
+            (0xC000..=0xDFFF, true) => self.irq_latch = data,
+            (0xC000..=0xDFFF, false) if self.board == Mmc3Board::Rambo1 => {
+                self.irq_cycle_mode = (data & 0x01) != 0;
+                self.prescaler = 0;
+                self.irq_reload = true;
+            }
+            (0xC000..=0xDFFF, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            //Disabling also acknowledges a pending IRQ
+            (_, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (_, false) => self.irq_enabled = true,
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    ///PRG-RAM protect ($A001, odd): games that disable the RAM (bit 7 clear) or write protect it (bit 6)
+    fn unsupported_write(&self, address: u16, data: u8) -> Option<&'static str> {
+        if self.board == Mmc3Board::Mmc3 && (0xA000..=0xBFFF).contains(&address) && (address & 0x0001) != 0 && (data & 0xC0) != 0x80 {
+            return Some("PRG-RAM protection");
+        }
+
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    ///The Namco 108 boards are hard-wired
+    fn mirroring(&self) -> Option<Mirroring> {
+        if !self.has_mmc3_registers() {
+            return None;
+        }
+
+        return Some(self.mirroring);
+    }
+
+    fn has_prg_ram(&self) -> bool {
+        return self.board == Mmc3Board::Mmc3;
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return 0x2000;
+    }
+
+    ///R0 and R1 switch 2KB, listed as the two 1KB banks they are made of (the NAMCOT-3446 only switches 2KB)
+    fn chr_bank_size(&self) -> usize {
+        if self.board == Mmc3Board::Namco3446 {
+            return 0x0800;
+        }
+
+        return 0x0400;
+    }
+
+    ///RAMBO-1 cycle mode: the counter is clocked every 4 CPU cycles
+    fn cpu_clock(&mut self) {
+        if self.board != Mmc3Board::Rambo1 || !self.irq_cycle_mode {
+            return;
+        }
+
+        self.prescaler = (self.prescaler + 1) & 0x03;
+
+        if self.prescaler == 0 {
+            self.clock_scanline_counter();
+        }
+    }
+
+    ///The scanline counter is clocked when A12 goes from low to high, the Namco 108 boards have none
+    fn ppu_address(&mut self, address: u16) {
+        if !self.has_mmc3_registers() || self.irq_cycle_mode {
+            return;
+        }
+
+        let a12 = (address & 0x1000) != 0;
+
+        if a12 && !self.a12 {
+            self.clock_scanline_counter();
+        }
+
+        self.a12 = a12;
+    }
+
+    fn irq(&self) -> bool {
+        return self.irq_pending;
+    }
+
+    fn reset(&mut self) {
+        self.bank_select = 0;
+        self.irq_enabled = false;
+        self.irq_pending = false;
+        self.irq_cycle_mode = false;
+        self.prescaler = 0;
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut data = vec![self.bank_select];
+        data.extend_from_slice(&self.registers);
+        data.extend_from_slice(&[
+            (self.mirroring == Mirroring::Horizontal) as u8,
+            self.irq_latch,
+            self.irq_counter,
+            self.irq_reload as u8,
+            self.irq_enabled as u8,
+            self.irq_pending as u8,
+            self.a12 as u8,
+            self.irq_cycle_mode as u8,
+            self.prescaler,
+        ]);
+
+        return data;
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if data.len() != 26 {
+            return;
+        }
+
+        self.bank_select = data[0];
+        self.registers.copy_from_slice(&data[1..17]);
+        self.mirroring = if data[17] != 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+        self.irq_latch = data[18];
+        self.irq_counter = data[19];
+        self.irq_reload = data[20] != 0;
+        self.irq_enabled = data[21] != 0;
+        self.irq_pending = data[22] != 0;
+        self.a12 = data[23] != 0;
+        self.irq_cycle_mode = data[24] != 0;
+        self.prescaler = data[25];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cartridge::{Cartridge, CHR_BANK_SIZE, PRG_BANK_SIZE},
+        ppu::Mirroring,
+    };
+
+    ///Vertically mirrored image with 64KB PRG and 128KB CHR, every PRG byte holds its 8KB bank number and every
+    ///CHR byte its 1KB bank number
+    fn cartridge(mapper: u8) -> Cartridge {
+        let mut rom = vec![0; 16];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 4;
+        rom[5] = 16;
+        rom[6] = (mapper << 4) | 0x01;
+        rom[7] = mapper & 0xF0;
+
+        for bank in 0..(4 * PRG_BANK_SIZE / 0x2000) as u8 {
+            rom.extend(std::iter::repeat_n(bank, 0x2000));
+        }
+        for bank in 0..(16 * CHR_BANK_SIZE / 0x0400) as u8 {
+            rom.extend(std::iter::repeat_n(bank, 0x0400));
+        }
+
+        return Cartridge::from_bytes(&rom).unwrap();
+    }
+
+    ///Loads a bank register through $8000/$8001
+    fn select(cartridge: &mut Cartridge, register: u8, bank: u8) {
+        cartridge.cpu_write(0x8000, register);
+        cartridge.cpu_write(0x8001, bank);
+    }
+
+    #[test]
+    fn namco_108_has_only_the_bank_registers() {
+        let mut cartridge = cartridge(206);
+
+        select(&mut cartridge, 6, 3);
+        select(&mut cartridge, 2, 5);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xC000), Some(6));
+        assert_eq!(cartridge.cpu_read(0xE000), Some(7));
+        assert_eq!(cartridge.ppu_read(0x1000), Some(5));
+
+        //No PRG mode, mirroring, IRQ or PRG-RAM
+        select(&mut cartridge, 0x46, 1);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.cpu_read(0xC000), Some(6));
+
+        cartridge.cpu_write(0xA000, 0x01);
+        cartridge.cpu_write(0xC000, 0x00);
+        cartridge.cpu_write(0xE001, 0x00);
+        cartridge.ppu_address(0x0000);
+        cartridge.ppu_address(0x1000);
+        assert_eq!(cartridge.get_mirroring(), Mirroring::Vertical);
+        assert!(!cartridge.irq());
+        assert_eq!(cartridge.cpu_read(0x6000), None);
+    }
+
+    #[test]
+    fn rambo_1_switches_rf_and_1kb_chr_banks() {
+        let mut cartridge = cartridge(64);
+
+        select(&mut cartridge, 15, 5);
+        select(&mut cartridge, 6, 1);
+        select(&mut cartridge, 7, 2);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.cpu_read(0xA000), Some(2));
+        assert_eq!(cartridge.cpu_read(0xC000), Some(5));
+        assert_eq!(cartridge.cpu_read(0xE000), Some(7));
+
+        //PRG mode 1 rotates RF, R6 and R7
+        select(&mut cartridge, 0x40, 0);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(5));
+        assert_eq!(cartridge.cpu_read(0xA000), Some(1));
+        assert_eq!(cartridge.cpu_read(0xC000), Some(2));
+
+        select(&mut cartridge, 0x20, 3);
+        select(&mut cartridge, 0x28, 9);
+        select(&mut cartridge, 0x21, 4);
+        select(&mut cartridge, 0x29, 11);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(3));
+        assert_eq!(cartridge.ppu_read(0x0400), Some(9));
+        assert_eq!(cartridge.ppu_read(0x0800), Some(4));
+        assert_eq!(cartridge.ppu_read(0x0C00), Some(11));
+        assert_eq!(cartridge.cpu_read(0x6000), None);
+    }
+
+    #[test]
+    fn rambo_1_counts_a12_rises() {
+        let mut cartridge = cartridge(64);
+        cartridge.cpu_write(0xC000, 1);
+        cartridge.cpu_write(0xC001, 0);
+        cartridge.cpu_write(0xE001, 0);
+
+        cartridge.ppu_address(0x1000);
+        cartridge.ppu_address(0x0000);
+        assert!(!cartridge.irq());
+
+        cartridge.ppu_address(0x1000);
+        assert!(cartridge.irq());
+
+        //Cycle mode ignores A12
+        cartridge.cpu_write(0xE000, 0);
+        cartridge.cpu_write(0xE001, 0);
+        cartridge.cpu_write(0xC001, 1);
+        for _ in 0..4 {
+            cartridge.ppu_address(0x0000);
+            cartridge.ppu_address(0x1000);
+        }
+        assert!(!cartridge.irq());
+    }
+
+    #[test]
+    fn rambo_1_cycle_mode_clocks_every_4_cpu_cycles() {
+        let mut cartridge = cartridge(64);
+        cartridge.cpu_write(0xC000, 2);
+        cartridge.cpu_write(0xC001, 1);
+        cartridge.cpu_write(0xE001, 0);
+
+        //The reload takes latch + 2 with latches above 1, so the IRQ comes after 4 clocks
+        for _ in 0..15 {
+            cartridge.cpu_clock();
+        }
+        assert!(!cartridge.irq());
+
+        cartridge.cpu_clock();
+        assert!(cartridge.irq());
+
+        //$E000 acknowledges
+        cartridge.cpu_write(0xE000, 0);
+        assert!(!cartridge.irq());
+    }
+
+    #[test]
+    fn namcot_3446_switches_2kb_chr_banks() {
+        let mut cartridge = cartridge(76);
+
+        select(&mut cartridge, 2, 3);
+        select(&mut cartridge, 5, 1);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(6));
+        assert_eq!(cartridge.ppu_read(0x0400), Some(7));
+        assert_eq!(cartridge.ppu_read(0x1C00), Some(3));
+    }
+
+    #[test]
+    fn namcot_3433_splits_chr_on_a12() {
+        let mut cartridge = cartridge(88);
+
+        select(&mut cartridge, 0, 0x44);
+        select(&mut cartridge, 2, 0x05);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0x04));
+        assert_eq!(cartridge.ppu_read(0x1000), Some(0x45));
+    }
+}
@@ -0,0 +1,326 @@
+use super::Mapper;
+use crate::ppu::Mirroring;
+
+///CHR latches of the MMC2 and MMC4<br>
+///Each pattern table has two 4KB banks, one for each latch value ($FD or $FE), and the latch flips when the PPU
+///fetches the high plane of tile $FD or $FE from that table, after the fetch: the tile itself still uses the old bank
+pub(super) struct TileLatches {
+    chr_banks: u8, //8KB banks
+    exact_low_trigger: bool, //MMC2 only flips the $0000 latch on $0FD8/$0FE8, not on the other rows of the tiles
+
+    banks: [u8; 4], //$B000 - $E000: $0000 for $FD, $0000 for $FE, $1000 for $FD, $1000 for $FE
+    latches: [bool; 2], //true for $FE
+    mirroring: Mirroring,
+}
+
+impl TileLatches {
+    //Constructor
+    pub(super) fn new(chr_banks: u8, exact_low_trigger: bool) -> Self {
+        Self {
+            chr_banks,
+            exact_low_trigger,
+
+            banks: [0; 4],
+            latches: [true; 2],
+            mirroring: Mirroring::Vertical,
+        }
+    }
+
+    ///$B000 - $FFFF, the CHR bank and mirroring registers both boards share
+    pub(super) fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0xB000..=0xEFFF => self.banks[((address - 0xB000) >> 12) as usize] = data & 0x1F,
+            0xF000..=0xFFFF => {
+                self.mirroring = if (data & 0x01) != 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn ppu_map(&self, address: u16) -> usize {
+        let table = (address >> 12) as usize & 0x01;
+        let bank = self.banks[table * 2 + self.latches[table] as usize] as usize;
+        let banks = (self.chr_banks as usize * 2).max(2);
+
+        return (bank % banks) * 0x1000 + (address & 0x0FFF) as usize;
+    }
+
+    pub(super) fn ppu_address(&mut self, address: u16) {
+        let table = (address >> 12) as usize & 0x01;
+        let tile = address & 0x0FF8;
+
+        if table == 0 && self.exact_low_trigger && (address & 0x0007) != 0 {
+            return;
+        }
+
+        match tile {
+            0x0FD8 => self.latches[table] = false,
+            0x0FE8 => self.latches[table] = true,
+            _ => {}
+        }
+    }
+
+    pub(super) fn mirroring(&self) -> Mirroring {
+        return self.mirroring;
+    }
+
+    pub(super) fn reset(&mut self) {
+        self.latches = [true; 2];
+    }
+
+    pub(super) fn save_state(&self) -> Vec<u8> {
+        let mut data = self.banks.to_vec();
+        data.extend_from_slice(&[
+            self.latches[0] as u8,
+            self.latches[1] as u8,
+            (self.mirroring == Mirroring::Horizontal) as u8,
+        ]);
+
+        return data;
+    }
+
+    pub(super) fn load_state(&mut self, data: &[u8]) {
+        if let [bank_0, bank_1, bank_2, bank_3, latch_0, latch_1, horizontal] = *data {
+            self.banks = [bank_0, bank_1, bank_2, bank_3];
+            self.latches = [latch_0 != 0, latch_1 != 0];
+            self.mirroring = if horizontal != 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+        }
+    }
+}
+
+///MMC2 (PxROM, Punch-Out!!)<br>
+///8KB switchable PRG bank at $8000 selected by $A000, the last three 8KB banks are fixed at $A000 - $FFFF<br>
+///CHR is switched in 4KB banks by the tile latches
+pub struct Mapper009 {
+    prg_banks: u8, //16KB banks
+    chr_banks: u8,
+
+    prg_bank: u8,
+    latches: TileLatches,
+}
+
+impl Mapper009 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+
+            prg_bank: 0,
+            latches: TileLatches::new(chr_banks, true),
+        }
+    }
+}
+
+impl Mapper for Mapper009 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let banks = (self.prg_banks as usize * 2).max(4);
+
+        let bank = match address {
+            0x8000..=0x9FFF => self.prg_bank as usize % banks,
+            _ => banks - 4 + ((address - 0x8000) >> 13) as usize,
+        };
+
+        return Some(bank * 0x2000 + (address & 0x1FFF) as usize);
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        match address {
+            0xA000..=0xAFFF => self.prg_bank = data & 0x0F,
+            0xB000..=0xFFFF => self.latches.write(address, data),
+            _ => {}
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        return Some(self.latches.ppu_map(address));
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return Some(self.latches.ppu_map(address));
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        return Some(self.latches.mirroring());
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return 0x2000;
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return 0x1000;
+    }
+
+    fn ppu_address(&mut self, address: u16) {
+        self.latches.ppu_address(address);
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.latches.reset();
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut data = vec![self.prg_bank];
+        data.extend(self.latches.save_state());
+
+        return data;
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if let Some((&prg_bank, latches)) = data.split_first() {
+            self.prg_bank = prg_bank;
+            self.latches.load_state(latches);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        cartridge::{Cartridge, CHR_BANK_SIZE, PRG_BANK_SIZE},
+        ppu::PPU,
+    };
+
+    ///Image with 128KB PRG and 8 4KB CHR banks, every PRG byte holds its 8KB bank number and every tile of a CHR
+    ///bank draws pixel value bank & 3
+    pub(in crate::mapper) fn cartridge(mapper: u8) -> Cartridge {
+        let mut rom = vec![0; 16];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 8;
+        rom[5] = 4;
+        rom[6] = mapper << 4;
+
+        for bank in 0..(8 * PRG_BANK_SIZE / 0x2000) as u8 {
+            rom.extend(std::iter::repeat_n(bank, 0x2000));
+        }
+        for bank in 0..(4 * CHR_BANK_SIZE / 0x1000) as u8 {
+            let low = if (bank & 0x01) != 0 { 0xFF } else { 0x00 };
+            let high = if (bank & 0x02) != 0 { 0xFF } else { 0x00 };
+
+            for _ in 0..0x1000 / 16 {
+                rom.extend([low; 8]);
+                rom.extend([high; 8]);
+            }
+        }
+
+        return Cartridge::from_bytes(&rom).unwrap();
+    }
+
+    ///Renders two frames of a background whose first row has tile $FD in column 1 and tile $FE in column 31, with
+    ///bank 1 for $FD and bank 2 for $FE in the $0000 table, and returns the pixel values of the first two scanlines
+    pub(in crate::mapper) fn render(cartridge: Cartridge) -> [[u8; 256]; 2] {
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        cartridge.borrow_mut().cpu_write(0xB000, 1);
+        cartridge.borrow_mut().cpu_write(0xC000, 2);
+
+        let mut ppu = PPU::new();
+        ppu.connect_cartridge(cartridge);
+
+        ppu.ppu_write(0x2001, 0xFD);
+        ppu.ppu_write(0x201F, 0xFE);
+        for pixel in 0..4 {
+            ppu.ppu_write(0x3F00 + pixel, pixel as u8);
+        }
+
+        //Background only, from $0000
+        ppu.cpu_write(0x2001, 0x0A);
+
+        for _ in 0..2 * 262 * 341 {
+            ppu.clock();
+        }
+
+        let mut lines = [[0; 256]; 2];
+        for (y, line) in lines.iter_mut().enumerate() {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                *pixel = (ppu.get_screen_indices()[y * 256 + x] & 0x3F) as u8;
+            }
+        }
+
+        return lines;
+    }
+
+    #[test]
+    fn prg_has_one_switchable_bank() {
+        let mut cartridge = cartridge(9);
+
+        cartridge.cpu_write(0xA000, 5);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(5));
+        assert_eq!(cartridge.cpu_read(0xA000), Some(13));
+        assert_eq!(cartridge.cpu_read(0xC000), Some(14));
+        assert_eq!(cartridge.cpu_read(0xE000), Some(15));
+    }
+
+    #[test]
+    fn tile_fetches_flip_the_latches() {
+        let mut cartridge = cartridge(9);
+        cartridge.cpu_write(0xB000, 1);
+        cartridge.cpu_write(0xC000, 2);
+        cartridge.cpu_write(0xD000, 3);
+        cartridge.cpu_write(0xE000, 4);
+
+        //Both latches start at $FE
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0x00));
+        assert_eq!(cartridge.ppu_read(0x0008), Some(0xFF));
+        assert_eq!(cartridge.ppu_read(0x1000), Some(0x00));
+
+        //The MMC2 only watches the first row of the tiles in the $0000 table
+        cartridge.ppu_address(0x0FDA);
+        assert_eq!(cartridge.ppu_read(0x0008), Some(0xFF));
+        cartridge.ppu_address(0x0FD8);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0xFF));
+        assert_eq!(cartridge.ppu_read(0x0008), Some(0x00));
+
+        //Every row of the tiles in the $1000 table, independently of the other latch
+        cartridge.ppu_address(0x1FDF);
+        assert_eq!(cartridge.ppu_read(0x1000), Some(0xFF));
+        assert_eq!(cartridge.ppu_read(0x1008), Some(0xFF));
+        cartridge.ppu_address(0x1FE8);
+        assert_eq!(cartridge.ppu_read(0x1000), Some(0x00));
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0xFF));
+    }
+
+    #[test]
+    fn the_trigger_tile_still_uses_the_old_bank() {
+        let lines = render(cartridge(9));
+
+        //Scanline 0: tile $FD is drawn with the $FE bank, everything after it up to and including tile $FE with the
+        //$FD bank
+        assert_eq!(lines[0][0..16], [2; 16]);
+        assert_eq!(lines[0][16..256], [1; 240]);
+
+        //Scanline 1: the second row of tile $FD doesn't flip the MMC2's $0000 latch
+        assert_eq!(lines[1], [2; 256]);
+    }
+
+    #[test]
+    fn latches_survive_save_states() {
+        let mut cartridge = cartridge(9);
+        cartridge.cpu_write(0xB000, 1);
+        cartridge.ppu_address(0x0FD8);
+        let state = cartridge.save_state();
+
+        cartridge.ppu_address(0x0FE8);
+        cartridge.load_state(&state);
+        assert_eq!(cartridge.ppu_read(0x0000), Some(0xFF));
+    }
+}
@@ -0,0 +1,131 @@
+use super::{mapper_009::TileLatches, Mapper};
+use crate::ppu::Mirroring;
+
+///MMC4 (FxROM, Fire Emblem)<br>
+///16KB switchable PRG bank at $8000 selected by $A000, the last bank is fixed at $C000, 8KB of PRG-RAM<br>
+///CHR is switched in 4KB banks by the MMC2's tile latches, which flip on every row of tiles $FD and $FE
+pub struct Mapper010 {
+    prg_banks: u8,
+    chr_banks: u8,
+
+    prg_bank: u8,
+    latches: TileLatches,
+}
+
+impl Mapper010 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+
+            prg_bank: 0,
+            latches: TileLatches::new(chr_banks, false),
+        }
+    }
+}
+
+impl Mapper for Mapper010 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let bank = if address >= 0xC000 {
+            self.prg_banks.saturating_sub(1)
+        } else {
+            self.prg_bank % self.prg_banks.max(1)
+        };
+
+        return Some(bank as usize * 0x4000 + (address & 0x3FFF) as usize);
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        match address {
+            0xA000..=0xAFFF => self.prg_bank = data & 0x0F,
+            0xB000..=0xFFFF => self.latches.write(address, data),
+            _ => {}
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        return Some(self.latches.ppu_map(address));
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return Some(self.latches.ppu_map(address));
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        return Some(self.latches.mirroring());
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return 0x4000;
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return 0x1000;
+    }
+
+    fn ppu_address(&mut self, address: u16) {
+        self.latches.ppu_address(address);
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.latches.reset();
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut data = vec![self.prg_bank];
+        data.extend(self.latches.save_state());
+
+        return data;
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if let Some((&prg_bank, latches)) = data.split_first() {
+            self.prg_bank = prg_bank;
+            self.latches.load_state(latches);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::mapper_009::tests::{cartridge, render};
+
+    #[test]
+    fn prg_has_a_16kb_switchable_bank() {
+        let mut cartridge = cartridge(10);
+
+        cartridge.cpu_write(0xA000, 3);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(6));
+        assert_eq!(cartridge.cpu_read(0xA000), Some(7));
+        assert_eq!(cartridge.cpu_read(0xC000), Some(14));
+        assert_eq!(cartridge.cpu_read(0xFFFF), Some(15));
+    }
+
+    #[test]
+    fn every_row_of_the_trigger_tiles_flips_the_latches() {
+        let lines = render(cartridge(10));
+
+        for line in lines {
+            assert_eq!(line[0..16], [2; 16]);
+            assert_eq!(line[16..256], [1; 240]);
+        }
+    }
+}
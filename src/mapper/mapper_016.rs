@@ -0,0 +1,417 @@
+use super::{
+    eeprom::{Eeprom, EepromChip},
+    Mapper,
+};
+use crate::ppu::Mirroring;
+
+///Which Bandai board the game runs on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BandaiBoard {
+    Fcg,           //FCG-1/FCG-2 (mapper 16 submapper 4): registers at $6000 - $7FFF, no EEPROM
+    Lz93d50,       //LZ93D50 with a 24C02 (mapper 16 submapper 5): registers at $8000 - $FFFF
+    Lz93d50X24c01, //LZ93D50 with a 24C01 (mapper 159)
+    Unknown,       //Mapper 16 without a submapper: registers in both ranges and a 24C02
+}
+
+///Bandai FCG (mappers 16 and 159)<br>
+///Eight 1KB CHR banks, a 16KB PRG bank at $8000 (the last bank is fixed at $C000), mirroring and a 16 bit IRQ
+///counter clocked by the CPU<br>
+///LZ93D50 boards keep the saves in a serial EEPROM instead of battery backed RAM, driven through register $D and
+///read back on bit 4 of $6000 - $7FFF
+pub struct Mapper016 {
+    prg_banks: u8, //16KB banks
+    chr_banks: u8, //8KB banks, 0 for CHR-RAM
+    board: BandaiBoard,
+
+    //Registers (address & $0F)
+    // 0-7  1KB CHR bank at $0000, $0400, ... $1C00
+    // 8    16KB PRG bank at $8000
+    // 9    Mirroring: 0 vertical, 1 horizontal, 2 one screen low, 3 one screen high
+    // A    IRQ control: bit 0 enables the counter, writing acknowledges the IRQ (and reloads the LZ93D50 counter)
+    // B-C  IRQ latch (LZ93D50) or counter (FCG) low and high bytes
+    // D    EEPROM: bit 5 SCL, bit 6 SDA, bit 7 set when the CPU reads SDA
+    chr_registers: [u8; 8],
+    prg_bank: u8,
+    mirroring: Mirroring,
+
+    //IRQ
+    irq_enabled: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq_pending: bool,
+
+    eeprom: Option<Eeprom>,
+}
+
+impl Mapper016 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8, board: BandaiBoard) -> Self {
+        let eeprom = match board {
+            BandaiBoard::Fcg => None,
+            BandaiBoard::Lz93d50X24c01 => Some(Eeprom::new(EepromChip::X24C01)),
+            BandaiBoard::Lz93d50 | BandaiBoard::Unknown => Some(Eeprom::new(EepromChip::C24C02)),
+        };
+
+        Self {
+            prg_banks,
+            chr_banks,
+            board,
+
+            chr_registers: [0; 8],
+            prg_bank: 0,
+            mirroring: Mirroring::Vertical,
+
+            irq_enabled: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq_pending: false,
+
+            eeprom,
+        }
+    }
+
+    ///The board decodes its registers in this range
+    fn is_register(&self, address: u16) -> bool {
+        match self.board {
+            BandaiBoard::Fcg => (0x6000..=0x7FFF).contains(&address),
+            BandaiBoard::Lz93d50 | BandaiBoard::Lz93d50X24c01 => address >= 0x8000,
+            BandaiBoard::Unknown => address >= 0x6000,
+        }
+    }
+
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0x00..=0x07 => self.chr_registers[register as usize] = data,
+            0x08 => self.prg_bank = data & 0x0F,
+            0x09 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::OneScreenLow,
+                    _ => Mirroring::OneScreenHigh,
+                };
+            }
+            0x0A => {
+                self.irq_enabled = (data & 0x01) != 0;
+                self.irq_pending = false;
+
+                if self.board != BandaiBoard::Fcg {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            0x0B | 0x0C => {
+                let shift = if register == 0x0B { 0 } else { 8 };
+                let target = if self.board == BandaiBoard::Fcg { &mut self.irq_counter } else { &mut self.irq_latch };
+
+                *target = (*target & !(0xFF << shift)) | ((data as u16) << shift);
+            }
+            0x0D => {
+                if let Some(eeprom) = &mut self.eeprom {
+                    //The CPU lets go of SDA (the open drain line stays high) while it reads
+                    let scl = (data & 0x20) != 0;
+                    let sda = (data & 0x40) != 0 || (data & 0x80) != 0;
+
+                    eeprom.write_lines(scl, sda);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mapper016 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let banks = (self.prg_banks as usize).max(1);
+
+        let bank = if address < 0xC000 {
+            self.prg_bank as usize % banks
+        } else {
+            banks - 1
+        };
+
+        return Some(bank * 0x4000 + (address & 0x3FFF) as usize);
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if self.is_register(address) {
+            self.write_register(address & 0x000F, data);
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        let banks = (self.chr_banks as usize * 8).max(8);
+        let bank = self.chr_registers[(address >> 10) as usize] as usize % banks;
+
+        return Some(bank * 0x0400 + (address & 0x03FF) as usize);
+    }
+
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize> {
+        //CHR-RAM boards (no CHR-ROM banks) can be written
+        if address > 0x1FFF || self.chr_banks != 0 {
+            return None;
+        }
+
+        return self.ppu_map_read(address);
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        return Some(self.mirroring);
+    }
+
+    ///The EEPROM data line on bit 4, the other bits aren't driven (they read as 0 here)
+    fn read_register(&self, address: u16) -> Option<u8> {
+        if !(0x6000..=0x7FFF).contains(&address) {
+            return None;
+        }
+
+        let eeprom = self.eeprom.as_ref()?;
+
+        return Some((eeprom.output() as u8) << 4);
+    }
+
+    fn has_prg_ram(&self) -> bool {
+        return false;
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return 0x4000;
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return 0x0400;
+    }
+
+    ///The counter is checked before it counts down, so the IRQ fires on the cycle after it reached 0
+    fn cpu_clock(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+
+        if self.irq_counter == 0 {
+            self.irq_pending = true;
+        }
+
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+    }
+
+    fn irq(&self) -> bool {
+        return self.irq_pending;
+    }
+
+    fn reset(&mut self) {
+        self.irq_enabled = false;
+        self.irq_pending = false;
+    }
+
+    fn save_memory(&self) -> Option<&[u8]> {
+        return self.eeprom.as_ref().map(|eeprom| eeprom.get_data());
+    }
+
+    fn load_save_memory(&mut self, data: &[u8]) -> bool {
+        match &mut self.eeprom {
+            Some(eeprom) => eeprom.load_data(data),
+            None => false,
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mirroring = match self.mirroring {
+            Mirroring::Vertical => 0,
+            Mirroring::Horizontal => 1,
+            Mirroring::OneScreenLow => 2,
+            Mirroring::OneScreenHigh => 3,
+        };
+
+        let mut data = self.chr_registers.to_vec();
+        data.extend_from_slice(&[self.prg_bank, mirroring, self.irq_enabled as u8, self.irq_pending as u8]);
+        data.extend_from_slice(&self.irq_counter.to_le_bytes());
+        data.extend_from_slice(&self.irq_latch.to_le_bytes());
+
+        if let Some(eeprom) = &self.eeprom {
+            data.extend(eeprom.save_state());
+        }
+
+        return data;
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if data.len() < 16 {
+            return;
+        }
+
+        if let Some(eeprom) = &mut self.eeprom {
+            if !eeprom.load_state(&data[16..]) {
+                return;
+            }
+        }
+
+        self.chr_registers.copy_from_slice(&data[0..8]);
+        self.prg_bank = data[8];
+        self.write_register(0x09, data[9]);
+        self.irq_enabled = data[10] != 0;
+        self.irq_pending = data[11] != 0;
+        self.irq_counter = u16::from_le_bytes([data[12], data[13]]);
+        self.irq_latch = u16::from_le_bytes([data[14], data[15]]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cartridge::{Cartridge, CHR_BANK_SIZE, PRG_BANK_SIZE},
+        ppu::Mirroring,
+    };
+
+    ///Bandai image with 8 PRG banks and 2 CHR banks, every PRG byte holds its bank number and every CHR byte its
+    ///1KB bank number
+    fn cartridge(mapper: u8, submapper: Option<u8>) -> Cartridge {
+        let mut rom = vec![0; 16];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 8;
+        rom[5] = 2;
+        rom[6] = mapper << 4;
+        rom[7] = mapper & 0xF0;
+
+        if let Some(submapper) = submapper {
+            rom[7] |= 0x08;
+            rom[8] = submapper << 4;
+        }
+
+        for bank in 0..8 {
+            rom.extend(std::iter::repeat_n(bank, PRG_BANK_SIZE));
+        }
+        for bank in 0..(2 * CHR_BANK_SIZE / 0x0400) as u8 {
+            rom.extend(std::iter::repeat_n(bank, 0x0400));
+        }
+
+        return Cartridge::from_bytes(&rom).unwrap();
+    }
+
+    #[test]
+    fn switches_prg_chr_and_mirroring() {
+        let mut cartridge = cartridge(16, Some(5));
+
+        assert_eq!(cartridge.cpu_read(0xC000), Some(7));
+
+        cartridge.cpu_write(0x8008, 3);
+        cartridge.cpu_write(0x8005, 12);
+        cartridge.cpu_write(0xFFF9, 2);
+
+        assert_eq!(cartridge.cpu_read(0x8000), Some(3));
+        assert_eq!(cartridge.cpu_read(0xFFFF), Some(7));
+        assert_eq!(cartridge.ppu_read(0x1400), Some(12));
+        assert_eq!(cartridge.get_mirroring(), Mirroring::OneScreenLow);
+
+        //The FCG board only listens at $6000 - $7FFF
+        let mut fcg = self::cartridge(16, Some(4));
+        fcg.cpu_write(0x8008, 3);
+        assert_eq!(fcg.cpu_read(0x8000), Some(0));
+        fcg.cpu_write(0x6008, 3);
+        assert_eq!(fcg.cpu_read(0x8000), Some(3));
+    }
+
+    #[test]
+    fn irq_fires_after_the_latched_count() {
+        let mut cartridge = cartridge(16, Some(5));
+
+        cartridge.cpu_write(0x800B, 3);
+        cartridge.cpu_write(0x800C, 0);
+        cartridge.cpu_write(0x800A, 1);
+
+        for _ in 0..3 {
+            cartridge.cpu_clock();
+            assert!(!cartridge.irq());
+        }
+
+        cartridge.cpu_clock();
+        assert!(cartridge.irq());
+
+        cartridge.cpu_write(0x800A, 0);
+        assert!(!cartridge.irq());
+    }
+
+    ///Writes a byte to the 24C02 through register $D (SCL bit 5, SDA bit 6) the way the games do
+    fn eeprom_write(cartridge: &mut Cartridge, address: u8, data: u8) {
+        let mut lines = |scl: bool, sda: bool| {
+            cartridge.cpu_write(0x800D, ((scl as u8) << 5) | ((sda as u8) << 6));
+        };
+
+        //Start
+        lines(true, true);
+        lines(true, false);
+        lines(false, false);
+
+        for byte in [0xA0, address, data] {
+            for bit in 0..8 {
+                let sda = (byte & (0x80 >> bit)) != 0;
+                lines(false, sda);
+                lines(true, sda);
+                lines(false, sda);
+            }
+
+            //Acknowledge clock
+            lines(false, true);
+            lines(true, true);
+            lines(false, true);
+        }
+
+        //Stop
+        lines(false, false);
+        lines(true, false);
+        lines(true, true);
+    }
+
+    #[test]
+    fn eeprom_is_the_battery_ram() {
+        let mut cartridge = cartridge(16, Some(5));
+
+        eeprom_write(&mut cartridge, 0x20, 0x5A);
+
+        let save = cartridge.battery_ram().unwrap().to_vec();
+        assert_eq!(save.len(), 256);
+        assert_eq!(save[0x20], 0x5A);
+
+        let mut other = self::cartridge(16, Some(5));
+        assert!(other.load_battery_ram(&save));
+        assert_eq!(other.battery_ram().unwrap()[0x20], 0x5A);
+
+        //Mapper 159 has the smaller 24C01, the FCG board nothing at all
+        assert_eq!(self::cartridge(159, None).battery_ram().map(|ram| ram.len()), Some(128));
+        assert!(self::cartridge(16, Some(4)).battery_ram().is_none());
+    }
+
+    #[test]
+    fn eeprom_data_line_reads_on_bit_4() {
+        let mut cartridge = cartridge(16, Some(5));
+
+        //Idle chip: SDA released (high)
+        assert_eq!(cartridge.cpu_read(0x6000), Some(0x10));
+
+        //Start, then the chip acknowledges its device address by pulling SDA low
+        let mut lines = |scl: bool, sda: bool| cartridge.cpu_write(0x800D, ((scl as u8) << 5) | ((sda as u8) << 6));
+        lines(true, true);
+        lines(true, false);
+        lines(false, false);
+
+        for bit in 0..8 {
+            let sda = (0xA0 & (0x80 >> bit)) != 0;
+            lines(false, sda);
+            lines(true, sda);
+            lines(false, sda);
+        }
+
+        cartridge.cpu_write(0x800D, 0x80);
+        assert_eq!(cartridge.cpu_read(0x7FFF), Some(0x00));
+    }
+}
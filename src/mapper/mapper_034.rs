@@ -0,0 +1,137 @@
+use super::Mapper;
+
+///NINA-001 (mapper 34 with CHR-ROM, Impossible Mission II)<br>
+///Three registers at the end of the 8KB of PRG-RAM, which still stores the writes:
+///$7FFD the 32KB PRG bank, $7FFE and $7FFF the 4KB CHR banks at $0000 and $1000<br>
+///The BNROM boards sharing the mapper number are a LatchMapper
+pub struct Mapper034 {
+    prg_banks: u8, //16KB banks
+    chr_banks: u8, //8KB banks
+
+    prg_bank: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+}
+
+impl Mapper034 {
+    //Constructor
+    pub fn new(prg_banks: u8, chr_banks: u8) -> Self {
+        Self {
+            prg_banks,
+            chr_banks,
+
+            prg_bank: 0,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+        }
+    }
+
+    fn ppu_map(&self, address: u16) -> usize {
+        let bank = if address >= 0x1000 { self.chr_bank_1 } else { self.chr_bank_0 } as usize;
+        let banks = (self.chr_banks as usize * 2).max(2);
+
+        return (bank % banks) * 0x1000 + (address & 0x0FFF) as usize;
+    }
+}
+
+impl Mapper for Mapper034 {
+    fn cpu_map_read(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 {
+            return None;
+        }
+
+        let size = (self.prg_banks as usize * 0x4000).max(0x4000);
+
+        return Some((self.prg_bank as usize * 0x8000 + (address & 0x7FFF) as usize) % size);
+    }
+
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        match address {
+            0x7FFD => self.prg_bank = data & 0x01,
+            0x7FFE => self.chr_bank_0 = data & 0x0F,
+            0x7FFF => self.chr_bank_1 = data & 0x0F,
+            _ => {}
+        }
+
+        //PRG-ROM can't be written
+        return None;
+    }
+
+    fn ppu_map_read(&self, address: u16) -> Option<usize> {
+        if address > 0x1FFF {
+            return None;
+        }
+
+        return Some(self.ppu_map(address));
+    }
+
+    fn ppu_map_write(&mut self, _address: u16) -> Option<usize> {
+        //CHR-ROM only
+        return None;
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        return 0x8000;
+    }
+
+    fn chr_bank_size(&self) -> usize {
+        return 0x1000;
+    }
+
+    fn reset(&mut self) {
+        self.prg_bank = 0;
+        self.chr_bank_0 = 0;
+        self.chr_bank_1 = 0;
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        return vec![self.prg_bank, self.chr_bank_0, self.chr_bank_1];
+    }
+
+    fn load_state(&mut self, data: &[u8]) {
+        if let [prg_bank, chr_bank_0, chr_bank_1] = *data {
+            self.prg_bank = prg_bank;
+            self.chr_bank_0 = chr_bank_0;
+            self.chr_bank_1 = chr_bank_1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::{Cartridge, CHR_BANK_SIZE, PRG_BANK_SIZE};
+
+    ///NINA-001 image with 64KB PRG and 64KB CHR, every PRG byte holds its 32KB bank number and every CHR byte its
+    ///4KB bank number
+    fn cartridge() -> Cartridge {
+        let mut rom = vec![0; 16];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 4;
+        rom[5] = 8;
+        rom[6] = 0x22;
+        rom[7] = 0x20;
+
+        for bank in 0..2 {
+            rom.extend(std::iter::repeat_n(bank, 2 * PRG_BANK_SIZE));
+        }
+        for bank in 0..(8 * CHR_BANK_SIZE / 0x1000) as u8 {
+            rom.extend(std::iter::repeat_n(bank, 0x1000));
+        }
+
+        return Cartridge::from_bytes(&rom).unwrap();
+    }
+
+    #[test]
+    fn registers_switch_banks_and_keep_the_ram() {
+        let mut cartridge = cartridge();
+
+        cartridge.cpu_write(0x7FFD, 0x01);
+        cartridge.cpu_write(0x7FFE, 0x05);
+        cartridge.cpu_write(0x7FFF, 0x0E);
+        assert_eq!(cartridge.cpu_read(0x8000), Some(1));
+        assert_eq!(cartridge.ppu_read(0x0000), Some(5));
+        assert_eq!(cartridge.ppu_read(0x1000), Some(14));
+
+        assert_eq!(cartridge.cpu_read(0x7FFE), Some(0x05));
+    }
+}
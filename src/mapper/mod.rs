@@ -0,0 +1,156 @@
+mod eeprom;
+mod latch;
+mod mapper_000;
+mod mapper_001;
+mod mapper_002;
+mod mapper_003;
+mod mapper_004;
+mod mapper_009;
+mod mapper_010;
+mod mapper_016;
+mod mapper_034;
+
+pub use mapper_000::Mapper000;
+pub use mapper_001::Mapper001;
+pub use mapper_002::Mapper002;
+pub use mapper_003::Mapper003;
+pub use mapper_004::{Mapper004, Mmc3Board};
+pub use mapper_009::Mapper009;
+pub use mapper_010::Mapper010;
+pub use latch::LatchMapper;
+pub use mapper_016::{BandaiBoard, Mapper016};
+pub use mapper_034::Mapper034;
+
+use crate::ppu::Mirroring;
+
+///Cartridge boards translate CPU and PPU addresses into offsets in the PRG and CHR memories<br>
+///Every function returns None when the board doesn't respond to the address
+pub trait Mapper {
+    ///Offset in PRG memory for a CPU read ($4020 - $FFFF)
+    fn cpu_map_read(&self, address: u16) -> Option<usize>;
+
+    ///Offset in PRG memory for a CPU write, bank switching boards latch their registers here
+    fn cpu_map_write(&mut self, address: u16, data: u8) -> Option<usize>;
+
+    ///Offset in CHR memory for a PPU read ($0000 - $1FFF)
+    fn ppu_map_read(&self, address: u16) -> Option<usize>;
+
+    ///Offset in CHR memory for a PPU write, only boards with CHR-RAM accept them
+    fn ppu_map_write(&mut self, address: u16) -> Option<usize>;
+
+    ///Nametable mirroring selected by the board, None when it is hard-wired (the iNES header value)
+    fn mirroring(&self) -> Option<Mirroring> {
+        None
+    }
+
+    ///Name of the feature a CPU write asks for when the board doesn't emulate it (PRG-RAM protection, ...),
+    ///checked before cpu_map_write() so the emulator can report it
+    fn unsupported_write(&self, _address: u16, _data: u8) -> Option<&'static str> {
+        None
+    }
+
+    ///Value of a board register the CPU reads at the address (EEPROM data line, ...), checked after the PRG-RAM
+    fn read_register(&self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    ///Boards whose ROM drives the data bus while the CPU writes their registers, the register gets the written value
+    ///ANDed with the ROM byte at the address
+    fn bus_conflicts(&self) -> bool {
+        false
+    }
+
+    ///Boards that use $6000 - $7FFF for registers don't get the PRG-RAM iNES headers assume
+    fn has_prg_ram(&self) -> bool {
+        true
+    }
+
+    ///8KB PRG-RAM bank at $6000 - $7FFF out of `banks`, for boards with more PRG-RAM than the window shows
+    fn prg_ram_bank(&self, _banks: usize) -> usize {
+        0
+    }
+
+    ///Size of the PRG-ROM banks the board switches in $8000 - $FFFF in its current banking mode, for the memory map
+    fn prg_bank_size(&self) -> usize;
+
+    ///Size of the CHR banks the board switches in $0000 - $1FFF in its current banking mode
+    fn chr_bank_size(&self) -> usize;
+
+    ///Called on every CPU cycle, boards with a cycle based IRQ counter count here
+    fn cpu_clock(&mut self) {}
+
+    ///Called with the pattern table addresses the PPU puts on its bus, boards that watch A12 count scanlines with them
+    fn ppu_address(&mut self, _address: u16) {}
+
+    ///State of the board's IRQ line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    ///Restores the power-on bank configuration
+    fn reset(&mut self) {}
+
+    ///Non-volatile memory on the board itself (serial EEPROM), kept in the .sav file instead of the PRG-RAM
+    fn save_memory(&self) -> Option<&[u8]> {
+        None
+    }
+
+    ///Restores save_memory(), returns false when the board has none or the size doesn't match
+    fn load_save_memory(&mut self, _data: &[u8]) -> bool {
+        false
+    }
+
+    ///Bank registers for save states, boards without registers have nothing to save
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    ///Restores registers saved by save_state()
+    fn load_state(&mut self, _data: &[u8]) {}
+}
+
+///Creates the mapper for an iNES/NES 2.0 mapper number (and submapper), None if it isn't supported
+pub fn create_mapper(mapper_id: u16, submapper: u8, prg_banks: u8, chr_banks: u8) -> Option<Box<dyn Mapper>> {
+    match mapper_id {
+        0 => Some(Box::new(Mapper000::new(prg_banks, chr_banks))),
+        1 => Some(Box::new(Mapper001::new(prg_banks, chr_banks))),
+        2 => Some(Box::new(Mapper002::new(prg_banks, chr_banks))),
+        3 => Some(Box::new(Mapper003::new(prg_banks, chr_banks))),
+        4 => Some(Box::new(Mapper004::new(prg_banks, chr_banks, Mmc3Board::Mmc3))),
+        76 => Some(Box::new(Mapper004::new(prg_banks, chr_banks, Mmc3Board::Namco3446))),
+        88 => Some(Box::new(Mapper004::new(prg_banks, chr_banks, Mmc3Board::Namco3433))),
+        206 => Some(Box::new(Mapper004::new(prg_banks, chr_banks, Mmc3Board::Namco108))),
+        64 => Some(Box::new(Mapper004::new(prg_banks, chr_banks, Mmc3Board::Rambo1))),
+        9 => Some(Box::new(Mapper009::new(prg_banks, chr_banks))),
+        10 => Some(Box::new(Mapper010::new(prg_banks, chr_banks))),
+        16 => {
+            let board = match submapper {
+                4 => BandaiBoard::Fcg,
+                5 => BandaiBoard::Lz93d50,
+                _ => BandaiBoard::Unknown,
+            };
+
+            Some(Box::new(Mapper016::new(prg_banks, chr_banks, board)))
+        }
+        159 => Some(Box::new(Mapper016::new(prg_banks, chr_banks, BandaiBoard::Lz93d50X24c01))),
+        11 => Some(Box::new(LatchMapper::new(prg_banks, chr_banks, &latch::COLOR_DREAMS))),
+        //Submapper 1 or CHR-ROM: NINA-001, submapper 2 or CHR-RAM: BNROM
+        34 if submapper == 1 || (submapper == 0 && chr_banks != 0) => {
+            Some(Box::new(Mapper034::new(prg_banks, chr_banks)))
+        }
+        34 => Some(Box::new(LatchMapper::new(prg_banks, chr_banks, &latch::BNROM))),
+        38 => Some(Box::new(LatchMapper::new(prg_banks, chr_banks, &latch::PCI556))),
+        66 => Some(Box::new(LatchMapper::new(prg_banks, chr_banks, &latch::GXROM))),
+        87 => Some(Box::new(LatchMapper::new(prg_banks, chr_banks, &latch::JALECO_87))),
+        140 => Some(Box::new(LatchMapper::new(prg_banks, chr_banks, &latch::JALECO_140))),
+        185 => {
+            let board = match submapper {
+                4..=7 => &latch::CNROM_PROTECTED_BY_SUBMAPPER[submapper as usize - 4],
+                _ => &latch::CNROM_PROTECTED,
+            };
+
+            Some(Box::new(LatchMapper::new(prg_banks, chr_banks, board)))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+
+///What answers accesses to a region
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryKind {
+    Ram,
+    Rom,
+    Registers,
+    OpenBus, //Nothing drives the bus, reads return the last value on it
+}
+
+///A range of the CPU or PPU address space and what is mapped there<br>
+///Bank switching boards change the banks while the game runs, so the map is built again every time it's asked for
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MemoryRegion {
+    pub start: u16,
+    pub end: u16, //Inclusive
+    pub name: &'static str,
+    pub kind: MemoryKind,
+    pub bank: Option<usize>, //Bank switched in (counted in banks of the region's size), None for unbanked memory
+    pub battery: bool,       //Kept in the .sav file
+}
+
+impl MemoryRegion {
+    //Constructor
+    pub(crate) fn new(start: u16, end: u16, name: &'static str, kind: MemoryKind) -> Self {
+        Self {
+            start,
+            end,
+            name,
+            kind,
+            bank: None,
+            battery: false,
+        }
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        return (self.start..=self.end).contains(&address);
+    }
+
+    pub fn size(&self) -> usize {
+        return (self.end - self.start) as usize + 1;
+    }
+}
+
+impl fmt::Display for MemoryRegion {
+    ///"$8000-$BFFF PRG-ROM bank 3"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${:04X}-${:04X} {}", self.start, self.end, self.name)?;
+
+        if let Some(bank) = self.bank {
+            write!(f, " bank {}", bank)?;
+        }
+
+        if self.battery {
+            write!(f, " (battery)")?;
+        }
+
+        Ok(())
+    }
+}
+
+///Splits a window of the address space into regions of one bank each<br>
+///`map` gives the memory offset an address reads, the bank is that offset divided by the bank size
+pub(crate) fn bank_regions<F: Fn(u16) -> Option<usize>>(
+    start: u16,
+    end: u16,
+    bank_size: usize,
+    name: &'static str,
+    kind: MemoryKind,
+    map: F,
+) -> Vec<MemoryRegion> {
+    let bank_size = bank_size.max(1);
+
+    return (start as usize..=end as usize)
+        .step_by(bank_size)
+        .map(|address| {
+            let last = (address + bank_size - 1).min(end as usize) as u16;
+
+            match map(address as u16) {
+                Some(offset) => MemoryRegion {
+                    bank: Some(offset / bank_size),
+                    ..MemoryRegion::new(address as u16, last, name, kind)
+                },
+                None => MemoryRegion::new(address as u16, last, "Open bus", MemoryKind::OpenBus),
+            }
+        })
+        .collect();
+}
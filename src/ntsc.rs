@@ -0,0 +1,162 @@
+use std::f32::consts::PI;
+
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+///Composite voltages of the 2C02 for the 4 luma levels, the low and high halves of the color square wave
+const LOW_LEVELS: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const HIGH_LEVELS: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f32 = 0.518;
+const WHITE: f32 = 1.962;
+
+///Factor an emphasis bit scales the signal by while its color's wave is high
+const ATTENUATION: f32 = 0.746;
+
+///The PPU outputs 8 samples per pixel on a 12 phase subcarrier (the master clock is 6 times the color burst), so a
+///pixel covers 2/3 of a color cycle and the phase of a scanline moves on by 341 * 8 % 12 = 4 every line
+const SAMPLES_PER_PIXEL: usize = 8;
+const PHASES: usize = 12;
+const LINE_PHASE: usize = 4;
+
+///Middle of the color burst's high half: hue 8 is high on phases 4 - 9
+const BURST_PHASE: f32 = 6.5;
+
+///Normalized (0.0 black, 1.0 white) signal of a pixel at a subcarrier phase<br>
+///Index is the palette index with the emphasis bits above it, as PPU::get_screen_indices() holds it
+fn signal(index: u16, phase: usize) -> f32 {
+    let hue = (index & 0x0F) as usize;
+    let emphasis = index >> 6;
+
+    //$xE and $xF are black, at the $1x level
+    let level = if hue > 13 { 1 } else { ((index >> 4) & 0x03) as usize };
+
+    //Hue 0 is a flat high level (grays), hues 13 - 15 a flat low one
+    let low = if hue == 0 { HIGH_LEVELS[level] } else { LOW_LEVELS[level] };
+    let high = if hue > 12 { LOW_LEVELS[level] } else { HIGH_LEVELS[level] };
+
+    let in_phase = |color: usize| (color + phase) % PHASES < 6;
+
+    let mut voltage = if in_phase(hue) { high } else { low };
+
+    //Red, green and blue emphasis attenuate the phases of hues 0, 4 and 8
+    if ((emphasis & 0x01) != 0 && in_phase(0))
+        || ((emphasis & 0x02) != 0 && in_phase(4))
+        || ((emphasis & 0x04) != 0 && in_phase(8))
+    {
+        voltage *= ATTENUATION;
+    }
+
+    return (voltage - BLACK) / (WHITE - BLACK);
+}
+
+///Decodes one 0x00RRGGBB color from the 12 samples around the middle of a pixel, a full color cycle: luma is their
+///average, U and V the subcarrier demodulated against the color burst
+fn decode(samples: &[f32], first_phase: usize, subcarrier: &[(f32, f32); PHASES]) -> u32 {
+    let (mut y, mut u, mut v) = (0.0, 0.0, 0.0);
+
+    for (offset, &sample) in samples.iter().enumerate() {
+        let (cos, sin) = subcarrier[(first_phase + offset) % PHASES];
+
+        y += sample;
+        u -= sample * cos;
+        v += sample * sin;
+    }
+
+    let y = y / PHASES as f32;
+    let u = u * 2.0 / PHASES as f32;
+    let v = v * 2.0 / PHASES as f32;
+
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u32;
+
+    let red = channel(y + 1.140 * v);
+    let green = channel(y - 0.395 * u - 0.581 * v);
+    let blue = channel(y + 2.032 * u);
+
+    return (red << 16) | (green << 8) | blue;
+}
+
+///Builds the composite signal of the frame from its palette indexes and decodes it back into 256x240 0x00RRGGBB
+///pixels, with the color fringes, dot crawl and emphasis of a real TV instead of one palette color per pixel<br>
+///The frame's starting phase alternates every frame (the pre-render line is a dot short on odd frames)
+pub(crate) fn decode_frame(indices: &[u16], odd_frame: bool) -> Vec<u32> {
+    let mut subcarrier = [(0.0, 0.0); PHASES];
+    for (phase, wave) in subcarrier.iter_mut().enumerate() {
+        let angle = 2.0 * PI * (phase as f32 - BURST_PHASE) / PHASES as f32;
+        *wave = (angle.cos(), angle.sin());
+    }
+
+    let line_samples = SCREEN_WIDTH * SAMPLES_PER_PIXEL;
+    let margin = (PHASES - SAMPLES_PER_PIXEL) / 2;
+
+    let mut frame = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+    let mut samples = vec![0.0; line_samples + 2 * margin];
+
+    for (y, line) in indices.chunks(SCREEN_WIDTH).take(SCREEN_HEIGHT).enumerate() {
+        let line_phase = (y * LINE_PHASE + if odd_frame { LINE_PHASE } else { 0 }) % PHASES;
+
+        //The edge pixels are repeated into the margins, so the first and last pixels get a full window too
+        for (position, sample) in samples.iter_mut().enumerate() {
+            let x = (position.saturating_sub(margin) / SAMPLES_PER_PIXEL).min(line.len() - 1);
+            let phase = (line_phase + position + PHASES - margin) % PHASES;
+
+            *sample = signal(line[x], phase);
+        }
+
+        for x in 0..line.len() {
+            let start = x * SAMPLES_PER_PIXEL;
+            let first_phase = (line_phase + start + PHASES - margin) % PHASES;
+
+            frame.push(decode(&samples[start..start + PHASES], first_phase, &subcarrier));
+        }
+    }
+
+    return frame;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Decoded color of a whole frame of one palette index, from the middle of the screen
+    fn color(index: u16) -> (u32, u32, u32) {
+        let frame = decode_frame(&[index; SCREEN_WIDTH * SCREEN_HEIGHT], false);
+        let pixel = frame[120 * SCREEN_WIDTH + 128];
+
+        return (pixel >> 16, (pixel >> 8) & 0xFF, pixel & 0xFF);
+    }
+
+    #[test]
+    fn grays_have_no_color() {
+        let mut previous = 0;
+
+        for index in [0x00, 0x10, 0x20] {
+            let (red, green, blue) = color(index);
+            assert_eq!((red, green), (blue, blue));
+            assert!(blue > previous);
+
+            previous = blue;
+        }
+
+        assert_eq!(color(0x0F), (0, 0, 0));
+    }
+
+    #[test]
+    fn hues_decode_to_their_colors() {
+        let (red, green, blue) = color(0x16);
+        assert!(red > 2 * green && red > 2 * blue);
+
+        let (red, green, blue) = color(0x1A);
+        assert!(green > 2 * red && green > 2 * blue);
+
+        let (red, green, blue) = color(0x12);
+        assert!(blue > 2 * red && blue > 2 * green);
+    }
+
+    #[test]
+    fn emphasis_darkens_the_other_colors() {
+        let (_, green, blue) = color(0x10);
+        let (emphasized_red, emphasized_green, emphasized_blue) = color(0x10 | 0x40);
+
+        assert!(emphasized_green < green && emphasized_blue < blue);
+        assert!(emphasized_red > emphasized_green && emphasized_red > emphasized_blue);
+    }
+}
@@ -1,60 +1,254 @@
-use std::{borrow::Borrow, collections::HashMap};
-
 use crate::cpu::{StatusFlags, CPU};
 
-///Table Matrix of all opcodes and instructions
-pub const lookup_table: Vec<INSTRUCTION> = vec![];
-
-///Opcode Instruction Struct
+///Table Matrix of all opcodes and instructions<br>
+///Indexed by the opcode byte, the high nibble is the row and the low nibble the column of the 6502 instruction matrix<br>
+///The stable undocumented opcodes are implemented, the unstable ones and the JAMs are "XXX" placeholders
+#[rustfmt::skip]
+pub static LOOKUP_TABLE: [INSTRUCTION; 256] = [
+    //0x00 - 0x0F
+    op("BRK", brk, IMP, 7), op("ORA", ora, IZX, 6), op("XXX", xxx, IMP, 2), op("SLO", slo, IZX, 8),
+    op("NOP", nop, ZP0, 3), op("ORA", ora, ZP0, 3), op("ASL", asl, ZP0, 5), op("SLO", slo, ZP0, 5),
+    op("PHP", php, IMP, 3), op("ORA", ora, IMM, 2), op("ASL", asl, IMP, 2), op("XXX", xxx, IMP, 2),
+    op("NOP", nop, ABS, 4), op("ORA", ora, ABS, 4), op("ASL", asl, ABS, 6), op("SLO", slo, ABS, 6),
+    //0x10 - 0x1F
+    op("BPL", bpl, REL, 2), op_page("ORA", ora, IZY, 5), op("XXX", xxx, IMP, 2), op("SLO", slo, IZY, 8),
+    op("NOP", nop, ZPX, 4), op("ORA", ora, ZPX, 4), op("ASL", asl, ZPX, 6), op("SLO", slo, ZPX, 6),
+    op("CLC", clc, IMP, 2), op_page("ORA", ora, ABY, 4), op("NOP", nop, IMP, 2), op("SLO", slo, ABY, 7),
+    op_page("NOP", nop, ABX, 4), op_page("ORA", ora, ABX, 4), op("ASL", asl, ABX, 7), op("SLO", slo, ABX, 7),
+    //0x20 - 0x2F
+    op("JSR", jsr, ABS, 6), op("AND", and, IZX, 6), op("XXX", xxx, IMP, 2), op("RLA", rla, IZX, 8),
+    op("BIT", bit, ZP0, 3), op("AND", and, ZP0, 3), op("ROL", rol, ZP0, 5), op("RLA", rla, ZP0, 5),
+    op("PLP", plp, IMP, 4), op("AND", and, IMM, 2), op("ROL", rol, IMP, 2), op("XXX", xxx, IMP, 2),
+    op("BIT", bit, ABS, 4), op("AND", and, ABS, 4), op("ROL", rol, ABS, 6), op("RLA", rla, ABS, 6),
+    //0x30 - 0x3F
+    op("BMI", bmi, REL, 2), op_page("AND", and, IZY, 5), op("XXX", xxx, IMP, 2), op("RLA", rla, IZY, 8),
+    op("NOP", nop, ZPX, 4), op("AND", and, ZPX, 4), op("ROL", rol, ZPX, 6), op("RLA", rla, ZPX, 6),
+    op("SEC", sec, IMP, 2), op_page("AND", and, ABY, 4), op("NOP", nop, IMP, 2), op("RLA", rla, ABY, 7),
+    op_page("NOP", nop, ABX, 4), op_page("AND", and, ABX, 4), op("ROL", rol, ABX, 7), op("RLA", rla, ABX, 7),
+    //0x40 - 0x4F
+    op("RTI", rti, IMP, 6), op("EOR", eor, IZX, 6), op("XXX", xxx, IMP, 2), op("SRE", sre, IZX, 8),
+    op("NOP", nop, ZP0, 3), op("EOR", eor, ZP0, 3), op("LSR", lsr, ZP0, 5), op("SRE", sre, ZP0, 5),
+    op("PHA", pha, IMP, 3), op("EOR", eor, IMM, 2), op("LSR", lsr, IMP, 2), op("XXX", xxx, IMP, 2),
+    op("JMP", jmp, ABS, 3), op("EOR", eor, ABS, 4), op("LSR", lsr, ABS, 6), op("SRE", sre, ABS, 6),
+    //0x50 - 0x5F
+    op("BVC", bvc, REL, 2), op_page("EOR", eor, IZY, 5), op("XXX", xxx, IMP, 2), op("SRE", sre, IZY, 8),
+    op("NOP", nop, ZPX, 4), op("EOR", eor, ZPX, 4), op("LSR", lsr, ZPX, 6), op("SRE", sre, ZPX, 6),
+    op("CLI", cli, IMP, 2), op_page("EOR", eor, ABY, 4), op("NOP", nop, IMP, 2), op("SRE", sre, ABY, 7),
+    op_page("NOP", nop, ABX, 4), op_page("EOR", eor, ABX, 4), op("LSR", lsr, ABX, 7), op("SRE", sre, ABX, 7),
+    //0x60 - 0x6F
+    op("RTS", rts, IMP, 6), op("ADC", adc, IZX, 6), op("XXX", xxx, IMP, 2), op("RRA", rra, IZX, 8),
+    op("NOP", nop, ZP0, 3), op("ADC", adc, ZP0, 3), op("ROR", ror, ZP0, 5), op("RRA", rra, ZP0, 5),
+    op("PLA", pla, IMP, 4), op("ADC", adc, IMM, 2), op("ROR", ror, IMP, 2), op("XXX", xxx, IMP, 2),
+    op("JMP", jmp, IND, 5), op("ADC", adc, ABS, 4), op("ROR", ror, ABS, 6), op("RRA", rra, ABS, 6),
+    //0x70 - 0x7F
+    op("BVS", bvs, REL, 2), op_page("ADC", adc, IZY, 5), op("XXX", xxx, IMP, 2), op("RRA", rra, IZY, 8),
+    op("NOP", nop, ZPX, 4), op("ADC", adc, ZPX, 4), op("ROR", ror, ZPX, 6), op("RRA", rra, ZPX, 6),
+    op("SEI", sei, IMP, 2), op_page("ADC", adc, ABY, 4), op("NOP", nop, IMP, 2), op("RRA", rra, ABY, 7),
+    op_page("NOP", nop, ABX, 4), op_page("ADC", adc, ABX, 4), op("ROR", ror, ABX, 7), op("RRA", rra, ABX, 7),
+    //0x80 - 0x8F
+    op("NOP", nop, IMM, 2), op("STA", sta, IZX, 6), op("NOP", nop, IMM, 2), op("SAX", sax, IZX, 6),
+    op("STY", sty, ZP0, 3), op("STA", sta, ZP0, 3), op("STX", stx, ZP0, 3), op("SAX", sax, ZP0, 3),
+    op("DEY", dey, IMP, 2), op("NOP", nop, IMM, 2), op("TXA", txa, IMP, 2), op("XXX", xxx, IMP, 2),
+    op("STY", sty, ABS, 4), op("STA", sta, ABS, 4), op("STX", stx, ABS, 4), op("SAX", sax, ABS, 4),
+    //0x90 - 0x9F
+    op("BCC", bcc, REL, 2), op("STA", sta, IZY, 6), op("XXX", xxx, IMP, 2), op("XXX", xxx, IMP, 6),
+    op("STY", sty, ZPX, 4), op("STA", sta, ZPX, 4), op("STX", stx, ZPY, 4), op("SAX", sax, ZPY, 4),
+    op("TYA", tya, IMP, 2), op("STA", sta, ABY, 5), op("TXS", txs, IMP, 2), op("XXX", xxx, IMP, 5),
+    op("SHY", shy, ABX, 5), op("STA", sta, ABX, 5), op("XXX", xxx, IMP, 5), op("XXX", xxx, IMP, 5),
+    //0xA0 - 0xAF
+    op("LDY", ldy, IMM, 2), op("LDA", lda, IZX, 6), op("LDX", ldx, IMM, 2), op("LAX", lax, IZX, 6),
+    op("LDY", ldy, ZP0, 3), op("LDA", lda, ZP0, 3), op("LDX", ldx, ZP0, 3), op("LAX", lax, ZP0, 3),
+    op("TAY", tay, IMP, 2), op("LDA", lda, IMM, 2), op("TAX", tax, IMP, 2), op("XXX", xxx, IMP, 2),
+    op("LDY", ldy, ABS, 4), op("LDA", lda, ABS, 4), op("LDX", ldx, ABS, 4), op("LAX", lax, ABS, 4),
+    //0xB0 - 0xBF
+    op("BCS", bcs, REL, 2), op_page("LDA", lda, IZY, 5), op("XXX", xxx, IMP, 2), op_page("LAX", lax, IZY, 5),
+    op("LDY", ldy, ZPX, 4), op("LDA", lda, ZPX, 4), op("LDX", ldx, ZPY, 4), op("LAX", lax, ZPY, 4),
+    op("CLV", clv, IMP, 2), op_page("LDA", lda, ABY, 4), op("TSX", tsx, IMP, 2), op("XXX", xxx, IMP, 4),
+    op_page("LDY", ldy, ABX, 4), op_page("LDA", lda, ABX, 4), op_page("LDX", ldx, ABY, 4), op_page("LAX", lax, ABY, 4),
+    //0xC0 - 0xCF
+    op("CPY", cpy, IMM, 2), op("CMP", cmp, IZX, 6), op("NOP", nop, IMM, 2), op("DCP", dcp, IZX, 8),
+    op("CPY", cpy, ZP0, 3), op("CMP", cmp, ZP0, 3), op("DEC", dec, ZP0, 5), op("DCP", dcp, ZP0, 5),
+    op("INY", iny, IMP, 2), op("CMP", cmp, IMM, 2), op("DEX", dex, IMP, 2), op("XXX", xxx, IMP, 2),
+    op("CPY", cpy, ABS, 4), op("CMP", cmp, ABS, 4), op("DEC", dec, ABS, 6), op("DCP", dcp, ABS, 6),
+    //0xD0 - 0xDF
+    op("BNE", bne, REL, 2), op_page("CMP", cmp, IZY, 5), op("XXX", xxx, IMP, 2), op("DCP", dcp, IZY, 8),
+    op("NOP", nop, ZPX, 4), op("CMP", cmp, ZPX, 4), op("DEC", dec, ZPX, 6), op("DCP", dcp, ZPX, 6),
+    op("CLD", cld, IMP, 2), op_page("CMP", cmp, ABY, 4), op("NOP", nop, IMP, 2), op("DCP", dcp, ABY, 7),
+    op_page("NOP", nop, ABX, 4), op_page("CMP", cmp, ABX, 4), op("DEC", dec, ABX, 7), op("DCP", dcp, ABX, 7),
+    //0xE0 - 0xEF
+    op("CPX", cpx, IMM, 2), op("SBC", sbc, IZX, 6), op("NOP", nop, IMM, 2), op("ISB", isb, IZX, 8),
+    op("CPX", cpx, ZP0, 3), op("SBC", sbc, ZP0, 3), op("INC", inc, ZP0, 5), op("ISB", isb, ZP0, 5),
+    op("INX", inx, IMP, 2), op("SBC", sbc, IMM, 2), op("NOP", nop, IMP, 2), op("SBC", sbc, IMM, 2),
+    op("CPX", cpx, ABS, 4), op("SBC", sbc, ABS, 4), op("INC", inc, ABS, 6), op("ISB", isb, ABS, 6),
+    //0xF0 - 0xFF
+    op("BEQ", beq, REL, 2), op_page("SBC", sbc, IZY, 5), op("XXX", xxx, IMP, 2), op("ISB", isb, IZY, 8),
+    op("NOP", nop, ZPX, 4), op("SBC", sbc, ZPX, 4), op("INC", inc, ZPX, 6), op("ISB", isb, ZPX, 6),
+    op("SED", sed, IMP, 2), op_page("SBC", sbc, ABY, 4), op("NOP", nop, IMP, 2), op("ISB", isb, ABY, 7),
+    op_page("NOP", nop, ABX, 4), op_page("SBC", sbc, ABX, 4), op("INC", inc, ABX, 7), op("ISB", isb, ABX, 7),
+];
+
+///Opcode Instruction Struct<br>
+///The addressing mode is stored as an AddressingMode and dispatched with a match, so checking it is a plain comparison<br>
+///The addressing mode returns 1 when an indexed address crossed a page, the extra cycle is only
+///taken by the instructions with page_penalty (the reads: stores and read-modify-writes always take the long path)
 pub(crate) struct INSTRUCTION {
-    pub name: String,
-    pub addr_mode: Box<dyn Fn(&mut CPU) -> u8>,
-    pub operate: Box<dyn Fn(&mut CPU) -> u8>,
+    pub name: &'static str,
+    pub addr_mode: AddressingMode,
+    pub operate: fn(&mut CPU),
     pub cycles: u8,
+    pub page_penalty: bool,
+}
+
+///Builds a lookup table entry
+const fn op(
+    name: &'static str,
+    operate: fn(&mut CPU),
+    addr_mode: AddressingMode,
+    cycles: u8,
+) -> INSTRUCTION {
+    INSTRUCTION {
+        name,
+        addr_mode,
+        operate,
+        cycles,
+        page_penalty: false,
+    }
+}
+
+///Builds a lookup table entry that takes one more cycle when the indexed address crosses a page
+const fn op_page(
+    name: &'static str,
+    operate: fn(&mut CPU),
+    addr_mode: AddressingMode,
+    cycles: u8,
+) -> INSTRUCTION {
+    INSTRUCTION {
+        page_penalty: true,
+        ..op(name, operate, addr_mode, cycles)
+    }
+}
+
+///Returns true if the instruction being executed uses the Implied Addressing Mode (the operand is the accumulator or nothing)
+pub fn is_implied(cpu: &CPU) -> bool {
+    return LOOKUP_TABLE[cpu.get_opcode() as usize].addr_mode == AddressingMode::Implied;
+}
+
+///Addressing mode of a lookup table entry, for tools that decode instructions without executing them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddressingMode {
+    Implied,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Relative,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+}
+
+impl AddressingMode {
+    ///Instruction length in bytes, opcode included
+    pub fn length(&self) -> u16 {
+        match self {
+            AddressingMode::Implied => 1,
+            AddressingMode::Immediate
+            | AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageX
+            | AddressingMode::ZeroPageY
+            | AddressingMode::Relative
+            | AddressingMode::IndirectX
+            | AddressingMode::IndirectY => 2,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => 3,
+        }
+    }
+
+    ///Runs the addressing mode for the current instruction, returns 1 when an indexed address crossed a page
+    pub(crate) fn resolve(&self, cpu: &mut CPU) -> u8 {
+        match self {
+            AddressingMode::Implied => imp(cpu),
+            AddressingMode::Immediate => imm(cpu),
+            AddressingMode::ZeroPage => zp0(cpu),
+            AddressingMode::ZeroPageX => zpx(cpu),
+            AddressingMode::ZeroPageY => zpy(cpu),
+            AddressingMode::Relative => rel(cpu),
+            AddressingMode::Absolute => abs(cpu),
+            AddressingMode::AbsoluteX => abx(cpu),
+            AddressingMode::AbsoluteY => aby(cpu),
+            AddressingMode::Indirect => ind(cpu),
+            AddressingMode::IndirectX => indx(cpu),
+            AddressingMode::IndirectY => indy(cpu),
+        }
+    }
+}
+
+//Short names for the lookup table
+const IMP: AddressingMode = AddressingMode::Implied;
+const IMM: AddressingMode = AddressingMode::Immediate;
+const ZP0: AddressingMode = AddressingMode::ZeroPage;
+const ZPX: AddressingMode = AddressingMode::ZeroPageX;
+const ZPY: AddressingMode = AddressingMode::ZeroPageY;
+const REL: AddressingMode = AddressingMode::Relative;
+const ABS: AddressingMode = AddressingMode::Absolute;
+const ABX: AddressingMode = AddressingMode::AbsoluteX;
+const ABY: AddressingMode = AddressingMode::AbsoluteY;
+const IND: AddressingMode = AddressingMode::Indirect;
+const IZX: AddressingMode = AddressingMode::IndirectX;
+const IZY: AddressingMode = AddressingMode::IndirectY;
+
+///Mnemonic and addressing mode of an opcode
+pub(crate) fn decode(opcode: u8) -> (&'static str, AddressingMode) {
+    let instruction = &LOOKUP_TABLE[opcode as usize];
+
+    return (instruction.name, instruction.addr_mode);
+}
+
+///The twelve JAM opcodes, which lock up a real 6502 until reset (they run as "XXX" placeholders here)
+pub(crate) fn is_jam(opcode: u8) -> bool {
+    return opcode & 0x0F == 0x02 && !matches!(opcode, 0x82 | 0xA2 | 0xC2 | 0xE2);
 }
 
 //Addressing Modes
 
 ///Implied Addressing Mode
 pub fn imp(cpu: &mut CPU) -> u8 {
-    cpu.fetched = cpu.get_accumulator();
+    cpu.set_fetched(cpu.get_accumulator());
     return 0;
 }
 
 ///Immediate Addressing Mode
 pub fn imm(cpu: &mut CPU) -> u8 {
-    cpu.program_counter += 1;
-    cpu.abs_addr = cpu.program_counter;
+    cpu.set_abs_addr(cpu.get_program_counter());
+    cpu.set_program_counter(cpu.get_program_counter().wrapping_add(1));
 
     return 0;
 }
 
 ///Absolute Addressing Mode
 pub fn abs(cpu: &mut CPU) -> u8 {
-    let low_byte = cpu.read(cpu.program_counter) as u16;
-    cpu.program_counter += 1;
-
-    let high_byte = cpu.read(cpu.program_counter) as u16;
-    cpu.program_counter += 1;
+    let low_byte = cpu.read_program_byte() as u16;
+    let high_byte = cpu.read_program_byte() as u16;
 
-    cpu.abs_addr = (high_byte << 8) | low_byte;
+    cpu.set_abs_addr((high_byte << 8) | low_byte);
 
     return 0;
 }
 
 ///Absolute X Addressing Mode
 pub fn abx(cpu: &mut CPU) -> u8 {
-    let low_byte = cpu.read(cpu.program_counter) as u16;
-    cpu.program_counter += 1;
+    let low_byte = cpu.read_program_byte() as u16;
+    let high_byte = cpu.read_program_byte() as u16;
 
-    let high_byte = cpu.read(cpu.program_counter) as u16;
-    cpu.program_counter += 1;
+    let address = ((high_byte << 8) | low_byte).wrapping_add(cpu.get_register_x() as u16);
+    cpu.set_abs_addr(address);
 
-    cpu.abs_addr = (high_byte << 8) | low_byte;
-
-    cpu.abs_addr += cpu.regx as u16;
-
-    if (cpu.abs_addr & 0xFF00) != (high_byte << 8) {
+    if (address & 0xFF00) != (high_byte << 8) {
         return 1;
     } else {
         return 0;
@@ -63,89 +257,99 @@ pub fn abx(cpu: &mut CPU) -> u8 {
 
 ///Absolute Y Addressing Mode
 pub fn aby(cpu: &mut CPU) -> u8 {
-    let low_byte = cpu.read(cpu.program_counter) as u16;
-    cpu.program_counter += 1;
-
-    let high_byte = cpu.read(cpu.program_counter) as u16;
-    cpu.program_counter += 1;
+    let low_byte = cpu.read_program_byte() as u16;
+    let high_byte = cpu.read_program_byte() as u16;
 
-    cpu.abs_addr = ((high_byte << 8) | low_byte) as u16;
+    let address = ((high_byte << 8) | low_byte).wrapping_add(cpu.get_register_y() as u16);
+    cpu.set_abs_addr(address);
 
-    cpu.abs_addr += cpu.regx as u16;
-
-    if (cpu.abs_addr & 0xFF00) != (high_byte << 8) {
+    if (address & 0xFF00) != (high_byte << 8) {
         return 1;
     } else {
         return 0;
     }
 }
 
-///Relative Addressing Mode
+///Relative Addressing Mode<br>
+///The operand is a signed offset (-128 to +127), sign extended so adding it to the program counter with wrapping_add moves backwards too
 pub fn rel(cpu: &mut CPU) -> u8 {
-    cpu.rel_addr = cpu.read(cpu.program_counter) as u16;
-    cpu.program_counter += 1;
+    let offset = cpu.read_program_byte() as i8;
 
-    if (cpu.rel_addr & 0x80) != 0 {
-        cpu.rel_addr |= 0xFF00;
-    }
+    cpu.set_rel_addr(offset as u16);
 
     return 0;
 }
 
 ///Zero Page Addressing Mode
 pub fn zp0(cpu: &mut CPU) -> u8 {
-    cpu.abs_addr = cpu.read(cpu.program_counter) as u16;
-    cpu.program_counter += 1;
+    let address = cpu.read_program_byte() as u16;
 
-    cpu.abs_addr &= 0x00FF;
+    cpu.set_abs_addr(address & 0x00FF);
     return 0;
 }
 
 ///Zero Page X Addressing Mode
 pub fn zpx(cpu: &mut CPU) -> u8 {
-    cpu.abs_addr = (cpu.read(cpu.program_counter) + cpu.regx) as u16;
-    cpu.program_counter += 1;
+    let address = cpu.read_program_byte().wrapping_add(cpu.get_register_x()) as u16;
 
-    cpu.abs_addr &= 0x00FF;
+    cpu.set_abs_addr(address & 0x00FF);
 
     return 0;
 }
 
 ///Zero Page Y Addressing Mode
 pub fn zpy(cpu: &mut CPU) -> u8 {
-    cpu.abs_addr = (cpu.read(cpu.program_counter) + cpu.regy) as u16;
-    cpu.program_counter += 1;
+    let address = cpu.read_program_byte().wrapping_add(cpu.get_register_y()) as u16;
+
+    cpu.set_abs_addr(address & 0x00FF);
+
+    return 0;
+}
+
+///Indirect Addressing Mode<br>
+///Reads the 16-bit pointer and then the address it points to<br>
+///Emulates the hardware bug: if the pointer low byte is 0xFF the high byte is read from the start of the same page
+pub fn ind(cpu: &mut CPU) -> u8 {
+    let pointer_low = cpu.read_program_byte() as u16;
+    let pointer_high = cpu.read_program_byte() as u16;
+
+    let pointer = (pointer_high << 8) | pointer_low;
+
+    let low_byte = cpu.read(pointer) as u16;
+    let high_byte = if pointer_low == 0x00FF {
+        cpu.read(pointer & 0xFF00) as u16
+    } else {
+        cpu.read(pointer + 1) as u16
+    };
 
-    cpu.abs_addr &= 0x00FF;
+    cpu.set_abs_addr((high_byte << 8) | low_byte);
 
     return 0;
 }
 
 ///Indirect X Addressing Mode
 pub fn indx(cpu: &mut CPU) -> u8 {
-    let instruction = cpu.read(cpu.program_counter);
-    cpu.program_counter += 1;
+    let instruction = cpu.read_program_byte();
 
-    let low_byte = cpu.read(((instruction + cpu.regx) as u16) & 0x00FF) as u16;
-    let high_byte = cpu.read(((instruction + (cpu.regx + 1)) as u16) & 0x00FF) as u16;
+    let low_byte = cpu.read((instruction.wrapping_add(cpu.get_register_x()) as u16) & 0x00FF) as u16;
+    let high_byte = cpu.read((instruction.wrapping_add(cpu.get_register_x()).wrapping_add(1) as u16) & 0x00FF) as u16;
 
-    cpu.abs_addr = (high_byte << 8) | low_byte;
+    cpu.set_abs_addr((high_byte << 8) | low_byte);
 
     return 0;
 }
 
 ///Indirect Y Addressing Mode
 pub fn indy(cpu: &mut CPU) -> u8 {
-    let instruction = cpu.read(cpu.program_counter);
-    cpu.program_counter += 1;
+    let instruction = cpu.read_program_byte();
 
     let low_byte = cpu.read((instruction as u16) & 0x00FF) as u16;
-    let high_byte = cpu.read(((instruction + 1) as u16) & 0x00FF) as u16;
+    let high_byte = cpu.read((instruction.wrapping_add(1) as u16) & 0x00FF) as u16;
 
-    cpu.abs_addr = (high_byte << 8) | low_byte;
-    cpu.abs_addr += cpu.regy as u16;
+    let address = ((high_byte << 8) | low_byte).wrapping_add(cpu.get_register_y() as u16);
+    cpu.set_abs_addr(address);
 
-    if (cpu.abs_addr & 0xFF00) != (high_byte << 8) as u16 {
+    if (address & 0xFF00) != (high_byte << 8) {
         return 1;
     } else {
         return 0;
@@ -162,8 +366,13 @@ pub fn indy(cpu: &mut CPU) -> u8 {
 pub fn adc(cpu: &mut CPU) {
     cpu.fetch();
 
+    adc_fetched(cpu);
+}
+
+///ADC on the operand already fetched
+fn adc_fetched(cpu: &mut CPU) {
     let value = cpu.get_accumulator() as u16
-        + cpu.fetched as u16
+        + cpu.get_fetched() as u16
         + cpu.get_flag(crate::cpu::StatusFlags::C) as u16;
 
     cpu.clear_flags(
@@ -174,26 +383,31 @@ pub fn adc(cpu: &mut CPU) {
 
     cpu.set_flag(
         StatusFlags::V,
-        ((!(cpu.get_accumulator() as u16 ^ cpu.fetched as u16)
+        ((!(cpu.get_accumulator() as u16 ^ cpu.get_fetched() as u16)
             & (cpu.get_accumulator() as u16 ^ value))
             & 0x0080)
             != 0,
     );
 
     cpu.set_flag(StatusFlags::C, value > 0x00FF);
-    cpu.acu = (value & 0x00FF) as u8;
+    cpu.set_accumulator((value & 0x00FF) as u8);
 }
 
 /// Subtraction with Borrow In<br>
 /// Executes the equation A−M−(1−C)<br>
 /// Uses the check_if_zero_or_negative_u16() function to trigger the Flags N (Negative) and Z (Zero)<br>
-/// Uses the overflow equation to trigger the Flag V (Overflow)<br>
-/// !(A^M) & (A^R)
+/// Uses the overflow equation to trigger the Flag V (Overflow), with the inverted operand that is actually added<br>
+/// !(A^~M) & (A^R)
 pub fn sbc(cpu: &mut CPU) {
     cpu.fetch();
 
+    sbc_fetched(cpu);
+}
+
+///SBC on the operand already fetched
+fn sbc_fetched(cpu: &mut CPU) {
     let value = cpu.get_accumulator() as u16
-        + (cpu.fetched ^ 0x00FF) as u16
+        + (cpu.get_fetched() ^ 0x00FF) as u16
         + cpu.get_flag(crate::cpu::StatusFlags::C) as u16;
 
     cpu.clear_flags(
@@ -204,14 +418,14 @@ pub fn sbc(cpu: &mut CPU) {
 
     cpu.set_flag(
         StatusFlags::V,
-        ((!(cpu.get_accumulator() as u16 ^ cpu.fetched as u16)
+        ((!(cpu.get_accumulator() as u16 ^ (cpu.get_fetched() ^ 0x00FF) as u16)
             & (cpu.get_accumulator() as u16 ^ value))
             & 0x0080)
             != 0,
     );
 
     cpu.set_flag(StatusFlags::C, value > 0x00FF);
-    cpu.acu = (value & 0x00FF) as u8;
+    cpu.set_accumulator((value & 0x00FF) as u8);
 }
 
 /// "AND" Memory with Accumulator<br>
@@ -220,19 +434,29 @@ pub fn sbc(cpu: &mut CPU) {
 pub fn and(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = cpu.get_accumulator() & cpu.fetched;
+    and_fetched(cpu);
+}
+
+///AND on the operand already fetched
+fn and_fetched(cpu: &mut CPU) {
+    let value = cpu.get_accumulator() & cpu.get_fetched();
 
     cpu.clear_flags(StatusFlags::N as u8 | StatusFlags::Z as u8);
 
     check_if_zero_or_negative_u8(cpu, value);
 
-    cpu.acu = value as u8;
+    cpu.set_accumulator(value);
 }
 
 pub fn asl(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = (cpu.fetched as u16) << 1;
+    asl_fetched(cpu);
+}
+
+///ASL on the operand already fetched, returns the value written back
+fn asl_fetched(cpu: &mut CPU) -> u8 {
+    let value = (cpu.get_fetched() as u16) << 1;
 
     cpu.clear_flags(StatusFlags::C as u8 | StatusFlags::N as u8 | StatusFlags::Z as u8);
 
@@ -240,13 +464,13 @@ pub fn asl(cpu: &mut CPU) {
 
     check_if_zero_or_negative_u16(cpu, value);
 
-    let imp: Box<dyn Fn(&mut CPU) -> u8> = Box::new(imp);
-
-    if std::ptr::eq(&*lookup_table[cpu.cur_opcode as usize].addr_mode, &*imp) {
-        cpu.acu = (value & 0x00FF) as u8;
+    if is_implied(cpu) {
+        cpu.set_accumulator((value & 0x00FF) as u8);
     } else {
-        cpu.write(cpu.abs_addr, (value & 0x00FF) as u8)
+        cpu.write(cpu.get_abs_addr(), (value & 0x00FF) as u8)
     }
+
+    return (value & 0x00FF) as u8;
 }
 
 /// "AND" Memory with Accumulator<br>
@@ -260,233 +484,157 @@ pub fn asl(cpu: &mut CPU) {
 pub fn bit(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = cpu.get_accumulator() & cpu.fetched;
+    let value = cpu.get_accumulator() & cpu.get_fetched();
 
-    cpu.set_flag(StatusFlags::Z, (value & 0x00FF) != 0);
-    cpu.set_flag(StatusFlags::V, (cpu.fetched & 0x40) != 0);
-    cpu.set_flag(StatusFlags::N, (cpu.fetched & 0x80) != 0);
+    cpu.set_flag(StatusFlags::Z, value == 0);
+    cpu.set_flag(StatusFlags::V, (cpu.get_fetched() & 0x40) != 0);
+    cpu.set_flag(StatusFlags::N, (cpu.get_fetched() & 0x80) != 0);
 }
 
+/// Branch on Carry Clear
 pub fn bcc(cpu: &mut CPU) {
-    if cpu.get_flag(StatusFlags::C) == 0 {
-        cpu.cycles += 1;
-
-        cpu.abs_addr = cpu.program_counter + cpu.rel_addr;
-
-        if (cpu.abs_addr & 0x00FF) != (cpu.program_counter & 0xFF00) {
-            cpu.cycles += 1;
-        }
+    let condition = cpu.get_flag(StatusFlags::C) == 0;
 
-        cpu.program_counter = cpu.abs_addr;
-    }
+    branch(cpu, condition);
 }
 
+/// Branch on Carry Set
 pub fn bcs(cpu: &mut CPU) {
-    if cpu.get_flag(StatusFlags::C) == 1 {
-        cpu.cycles += 1;
-
-        cpu.abs_addr = cpu.program_counter + cpu.rel_addr;
+    let condition = cpu.get_flag(StatusFlags::C) == 1;
 
-        if (cpu.abs_addr & 0x00FF) != (cpu.program_counter & 0xFF00) {
-            cpu.cycles += 1;
-        }
-
-        cpu.program_counter = cpu.abs_addr;
-    }
+    branch(cpu, condition);
 }
 
+/// Branch on Result Zero
 pub fn beq(cpu: &mut CPU) {
-    if cpu.get_flag(StatusFlags::Z) == 1 {
-        cpu.cycles += 1;
-
-        cpu.abs_addr = cpu.program_counter + cpu.rel_addr;
-
-        if (cpu.abs_addr & 0x00FF) != (cpu.program_counter & 0xFF00) {
-            cpu.cycles += 1;
-        }
+    let condition = cpu.get_flag(StatusFlags::Z) == 1;
 
-        cpu.program_counter = cpu.abs_addr;
-    }
+    branch(cpu, condition);
 }
 
+/// Branch on Result Minus
 pub fn bmi(cpu: &mut CPU) {
-    if cpu.get_flag(StatusFlags::N) == 1 {
-        cpu.cycles += 1;
-
-        cpu.abs_addr = cpu.program_counter + cpu.rel_addr;
-
-        if (cpu.abs_addr & 0x00FF) != (cpu.program_counter & 0xFF00) {
-            cpu.cycles += 1;
-        }
+    let condition = cpu.get_flag(StatusFlags::N) == 1;
 
-        cpu.program_counter = cpu.abs_addr;
-    }
+    branch(cpu, condition);
 }
 
+/// Branch on Result not Zero
 pub fn bne(cpu: &mut CPU) {
-    if cpu.get_flag(StatusFlags::Z) == 0 {
-        cpu.cycles += 1;
-
-        cpu.abs_addr = cpu.program_counter + cpu.rel_addr;
+    let condition = cpu.get_flag(StatusFlags::Z) == 0;
 
-        if (cpu.abs_addr & 0x00FF) != (cpu.program_counter & 0xFF00) {
-            cpu.cycles += 1;
-        }
-
-        cpu.program_counter = cpu.abs_addr;
-    }
+    branch(cpu, condition);
 }
 
+/// Branch on Result Plus
 pub fn bpl(cpu: &mut CPU) {
-    if cpu.get_flag(StatusFlags::N) == 0 {
-        cpu.cycles += 1;
-
-        cpu.abs_addr = cpu.program_counter + cpu.rel_addr;
-
-        if (cpu.abs_addr & 0x00FF) != (cpu.program_counter & 0xFF00) {
-            cpu.cycles += 1;
-        }
+    let condition = cpu.get_flag(StatusFlags::N) == 0;
 
-        cpu.program_counter = cpu.abs_addr;
-    }
+    branch(cpu, condition);
 }
 
+/// Branch on Overflow Clear
 pub fn bvc(cpu: &mut CPU) {
-    if cpu.get_flag(StatusFlags::V) == 0 {
-        cpu.cycles += 1;
-
-        cpu.abs_addr = cpu.program_counter + cpu.rel_addr;
-
-        if (cpu.abs_addr & 0x00FF) != (cpu.program_counter & 0xFF00) {
-            cpu.cycles += 1;
-        }
+    let condition = cpu.get_flag(StatusFlags::V) == 0;
 
-        cpu.program_counter = cpu.abs_addr;
-    }
+    branch(cpu, condition);
 }
 
+/// Branch on Overflow Set
 pub fn bvs(cpu: &mut CPU) {
-    if cpu.get_flag(StatusFlags::V) == 1 {
-        cpu.cycles += 1;
-
-        cpu.abs_addr = cpu.program_counter + cpu.rel_addr;
+    let condition = cpu.get_flag(StatusFlags::V) == 1;
 
-        if (cpu.abs_addr & 0x00FF) != (cpu.program_counter & 0xFF00) {
-            cpu.cycles += 1;
-        }
-
-        cpu.program_counter = cpu.abs_addr;
-    }
+    branch(cpu, condition);
 }
 
+/// Force Break<br>
+/// BRK is two bytes long: the return address skips the padding byte after the opcode<br>
+/// The status is pushed with B set (so the handler can tell it from an IRQ), then interrupts are masked
 pub fn brk(cpu: &mut CPU) {
-    cpu.program_counter += 1;
-
-    cpu.set_flag(StatusFlags::I, true);
-
-    cpu.write(
-        cpu.get_stack_address(),
-        ((cpu.program_counter >> 8) & 0x00FF) as u8,
-    );
-    cpu.stack_pointer -= 1;
-
-    //Save the program counter low byte into the stack
-    cpu.write(
-        cpu.get_stack_address(),
-        (cpu.program_counter & 0x00FF) as u8,
-    );
-    cpu.stack_pointer -= 1;
+    cpu.set_program_counter(cpu.get_program_counter().wrapping_add(1));
 
-    cpu.set_flag(StatusFlags::B, true);
+    //Save the program counter into the stack, high byte first
+    cpu.push16(cpu.get_program_counter());
 
-    cpu.write(cpu.get_stack_address(), cpu.status);
-    cpu.stack_pointer -= 1;
+    cpu.push_status(true);
+    cpu.set_flag(StatusFlags::I, true);
 
     //The program counter is equal to the low_byte in the 0xFFFE RAM address and to the high_byte in the 0xFFFF RAM address
     let low_byte = cpu.read(0xFFFE) as u16;
     let high_byte = cpu.read(0xFFFF) as u16;
 
     //Execute the same thing to join two bytes into one opcocde/uint_16
-    cpu.program_counter = (high_byte << 8) | low_byte;
+    cpu.set_program_counter((high_byte << 8) | low_byte);
 }
 
 pub fn clc(cpu: &mut CPU) {
-    cpu.clear_flags(StatusFlags::C as u8)
+    cpu.clear_flags(StatusFlags::C as u8);
 }
 
 pub fn cld(cpu: &mut CPU) {
-    cpu.clear_flags(StatusFlags::D as u8)
+    cpu.clear_flags(StatusFlags::D as u8);
 }
 
 pub fn cli(cpu: &mut CPU) {
-    cpu.clear_flags(StatusFlags::I as u8)
+    cpu.clear_flags(StatusFlags::I as u8);
 }
 
 pub fn clv(cpu: &mut CPU) {
-    cpu.clear_flags(StatusFlags::V as u8)
+    cpu.clear_flags(StatusFlags::V as u8);
 }
 
 pub fn cmp(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = cpu.fetched as u16 - cpu.get_accumulator() as u16;
-
-    cpu.set_flag(
-        StatusFlags::C,
-        cpu.get_accumulator() as u16 >= cpu.fetched as u16,
-    );
+    cmp_fetched(cpu);
+}
 
-    check_if_zero_or_negative_u16(cpu, value);
+///CMP on the operand already fetched
+fn cmp_fetched(cpu: &mut CPU) {
+    compare(cpu, cpu.get_accumulator());
 }
 
 pub fn cpx(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = cpu.fetched as u16 - cpu.get_register_x() as u16;
-
-    cpu.set_flag(
-        StatusFlags::C,
-        cpu.get_accumulator() as u16 >= cpu.fetched as u16,
-    );
-
-    check_if_zero_or_negative_u16(cpu, value);
+    compare(cpu, cpu.get_register_x());
 }
 
 pub fn cpy(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = cpu.fetched as u16 - cpu.get_register_y() as u16;
-
-    cpu.set_flag(
-        StatusFlags::C,
-        cpu.get_accumulator() as u16 >= cpu.fetched as u16,
-    );
-
-    check_if_zero_or_negative_u16(cpu, value);
+    compare(cpu, cpu.get_register_y());
 }
 
 pub fn dec(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = cpu.fetched - 1;
+    dec_fetched(cpu);
+}
+
+///DEC on the operand already fetched, returns the value written back
+fn dec_fetched(cpu: &mut CPU) -> u8 {
+    let value = cpu.get_fetched().wrapping_sub(1);
 
-    cpu.write(cpu.abs_addr, value as u8);
+    cpu.write(cpu.get_abs_addr(), value);
 
-    check_if_zero_or_negative_u16(cpu, value as u16)
+    check_if_zero_or_negative_u16(cpu, value as u16);
+
+    return value;
 }
 
 pub fn dex(cpu: &mut CPU) {
-    let value = cpu.get_register_x() - 1;
+    let value = cpu.get_register_x().wrapping_sub(1);
 
-    cpu.regx = value;
+    cpu.set_register_x(value);
 
     check_if_zero_or_negative_u8(cpu, value);
 }
 
 pub fn dey(cpu: &mut CPU) {
-    let value = cpu.get_register_y() - 1;
+    let value = cpu.get_register_y().wrapping_sub(1);
 
-    cpu.regy = value;
+    cpu.set_register_y(value);
 
     check_if_zero_or_negative_u8(cpu, value);
 }
@@ -494,9 +642,14 @@ pub fn dey(cpu: &mut CPU) {
 pub fn eor(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = cpu.get_accumulator() ^ cpu.fetched;
+    eor_fetched(cpu);
+}
+
+///EOR on the operand already fetched
+fn eor_fetched(cpu: &mut CPU) {
+    let value = cpu.get_accumulator() ^ cpu.get_fetched();
 
-    cpu.acu = value as u8;
+    cpu.set_accumulator(value);
 
     check_if_zero_or_negative_u8(cpu, value);
 }
@@ -504,56 +657,53 @@ pub fn eor(cpu: &mut CPU) {
 pub fn inc(cpu: &mut CPU) {
     cpu.fetch();
 
-    let value = cpu.fetched as u16 + 1;
+    inc_fetched(cpu);
+}
+
+///INC on the operand already fetched, returns the value written back
+fn inc_fetched(cpu: &mut CPU) -> u8 {
+    let value = cpu.get_fetched() as u16 + 1;
 
-    cpu.write(cpu.abs_addr, value as u8);
+    cpu.write(cpu.get_abs_addr(), value as u8);
 
     check_if_zero_or_negative_u16(cpu, value);
+
+    return value as u8;
 }
 
 pub fn inx(cpu: &mut CPU) {
-    let value = cpu.get_register_x() + 1;
+    let value = cpu.get_register_x().wrapping_add(1);
 
-    cpu.regx = value;
+    cpu.set_register_x(value);
 
     check_if_zero_or_negative_u8(cpu, value);
 }
 
 pub fn iny(cpu: &mut CPU) {
-    let value = cpu.get_register_y() + 1;
+    let value = cpu.get_register_y().wrapping_add(1);
 
-    cpu.regy = value;
+    cpu.set_register_y(value);
 
     check_if_zero_or_negative_u8(cpu, value);
 }
 
 pub fn jmp(cpu: &mut CPU) {
-    cpu.program_counter = cpu.abs_addr;
+    cpu.set_program_counter(cpu.get_abs_addr());
 }
 
 pub fn jsr(cpu: &mut CPU) {
-    cpu.program_counter -= 1;
+    cpu.set_program_counter(cpu.get_program_counter() - 1);
 
-    cpu.write(
-        cpu.get_stack_address(),
-        ((cpu.program_counter >> 8) & 0x00FF) as u8,
-    );
-    cpu.stack_pointer -= 1;
-
-    //Save the program counter low byte into the stack
-    cpu.write(
-        cpu.get_stack_address(),
-        (cpu.program_counter & 0x00FF) as u8,
-    );
-    cpu.stack_pointer -= 1;
+    //Save the program counter into the stack, high byte first
+    cpu.push16(cpu.get_program_counter());
 
-    cpu.program_counter = cpu.abs_addr;
+    cpu.set_program_counter(cpu.get_abs_addr());
 }
 
 pub fn lda(cpu: &mut CPU) {
     cpu.fetch();
 
-    cpu.acu = cpu.fetched;
+    cpu.set_accumulator(cpu.get_fetched());
 
     check_if_zero_or_negative_u8(cpu, cpu.get_accumulator());
 }
@@ -561,7 +711,7 @@ pub fn lda(cpu: &mut CPU) {
 pub fn ldx(cpu: &mut CPU) {
     cpu.fetch();
 
-    cpu.regx = cpu.fetched;
+    cpu.set_register_x(cpu.get_fetched());
 
     check_if_zero_or_negative_u8(cpu, cpu.get_register_x());
 }
@@ -569,7 +719,7 @@ pub fn ldx(cpu: &mut CPU) {
 pub fn ldy(cpu: &mut CPU) {
     cpu.fetch();
 
-    cpu.regy = cpu.fetched;
+    cpu.set_register_y(cpu.get_fetched());
 
     check_if_zero_or_negative_u8(cpu, cpu.get_register_y());
 }
@@ -577,50 +727,758 @@ pub fn ldy(cpu: &mut CPU) {
 pub fn lsr(cpu: &mut CPU) {
     cpu.fetch();
 
-    cpu.set_flag(StatusFlags::C, (cpu.fetched & 0x0001) != 0);
+    lsr_fetched(cpu);
+}
+
+///LSR on the operand already fetched, returns the value written back
+fn lsr_fetched(cpu: &mut CPU) -> u8 {
+    cpu.set_flag(StatusFlags::C, (cpu.get_fetched() & 0x0001) != 0);
 
-    let value = cpu.fetched as u16 >> 1;
+    let value = cpu.get_fetched() as u16 >> 1;
 
     check_if_zero_or_negative_u16(cpu, value);
 
-    let imp: Box<dyn Fn(&mut CPU) -> u8> = Box::new(imp);
+    if is_implied(cpu) {
+        cpu.set_accumulator((value & 0x00FF) as u8);
+    } else {
+        cpu.write(cpu.get_abs_addr(), (value & 0x00FF) as u8)
+    }
+
+    return (value & 0x00FF) as u8;
+}
+
+/// No Operation<br>
+/// The undocumented NOPs with an operand only use the addressing mode to skip it (and pay for the page cross)
+pub fn nop(_cpu: &mut CPU) {}
+
+/// "OR" Memory with Accumulator<br>
+/// Executes the equation A | M<br>
+/// Uses the check_if_zero_or_negative_u8() function to trigger the Flags N (Negative) and Z (Zero)<br>
+pub fn ora(cpu: &mut CPU) {
+    cpu.fetch();
+
+    ora_fetched(cpu);
+}
+
+///ORA on the operand already fetched
+fn ora_fetched(cpu: &mut CPU) {
+    let value = cpu.get_accumulator() | cpu.get_fetched();
+
+    cpu.set_accumulator(value);
+
+    check_if_zero_or_negative_u8(cpu, value);
+}
+
+/// Push Accumulator on Stack
+pub fn pha(cpu: &mut CPU) {
+    cpu.push8(cpu.get_accumulator());
+}
+
+/// Push Processor Status on Stack<br>
+/// The pushed copy always has the B (Break) and G (Unused) flags set
+pub fn php(cpu: &mut CPU) {
+    cpu.push_status(true);
+}
+
+/// Pull Accumulator from Stack
+pub fn pla(cpu: &mut CPU) {
+    let value = cpu.pop8();
+    cpu.set_accumulator(value);
+
+    check_if_zero_or_negative_u8(cpu, cpu.get_accumulator());
+}
+
+/// Pull Processor Status from Stack<br>
+/// set_status() ignores the pulled B and forces G
+pub fn plp(cpu: &mut CPU) {
+    let value = cpu.pop8();
+    cpu.set_status(value);
+}
+
+/// Rotate One Bit Left (Memory or Accumulator)<br>
+/// The Carry goes into bit 0 and bit 7 goes into the Carry
+pub fn rol(cpu: &mut CPU) {
+    cpu.fetch();
+
+    rol_fetched(cpu);
+}
 
-    if std::ptr::eq(&*lookup_table[cpu.cur_opcode as usize].addr_mode, &*imp) {
-        cpu.acu = (value & 0x00FF) as u8;
+///ROL on the operand already fetched, returns the value written back
+fn rol_fetched(cpu: &mut CPU) -> u8 {
+    let value = ((cpu.get_fetched() as u16) << 1) | cpu.get_flag(StatusFlags::C) as u16;
+
+    cpu.set_flag(StatusFlags::C, (value & 0xFF00) != 0);
+
+    check_if_zero_or_negative_u16(cpu, value);
+
+    if is_implied(cpu) {
+        cpu.set_accumulator((value & 0x00FF) as u8);
     } else {
-        cpu.write(cpu.abs_addr, (value & 0x00FF) as u8)
+        cpu.write(cpu.get_abs_addr(), (value & 0x00FF) as u8)
     }
+
+    return (value & 0x00FF) as u8;
 }
 
-pub fn nop(cpu: &mut CPU) {
-    match cpu.cur_opcode {
-        0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => { //1
-        }
-        _ => { //0
-        }
+/// Rotate One Bit Right (Memory or Accumulator)<br>
+/// The Carry goes into bit 7 and bit 0 goes into the Carry
+pub fn ror(cpu: &mut CPU) {
+    cpu.fetch();
+
+    ror_fetched(cpu);
+}
+
+///ROR on the operand already fetched, returns the value written back
+fn ror_fetched(cpu: &mut CPU) -> u8 {
+    let value = ((cpu.get_flag(StatusFlags::C) as u16) << 7) | (cpu.get_fetched() as u16 >> 1);
+
+    cpu.set_flag(StatusFlags::C, (cpu.get_fetched() & 0x01) != 0);
+
+    check_if_zero_or_negative_u16(cpu, value);
+
+    if is_implied(cpu) {
+        cpu.set_accumulator((value & 0x00FF) as u8);
+    } else {
+        cpu.write(cpu.get_abs_addr(), (value & 0x00FF) as u8)
+    }
+
+    return (value & 0x00FF) as u8;
+}
+
+/// Return from Interrupt<br>
+/// Pulls the status register (B ignored, G forced like PLP) and then the program counter
+pub fn rti(cpu: &mut CPU) {
+    let value = cpu.pop8();
+    cpu.set_status(value);
+
+    let address = cpu.pop16();
+    cpu.set_program_counter(address);
+}
+
+/// Return from Subroutine<br>
+/// JSR pushed the address of its last byte, so the pulled address is incremented by one
+pub fn rts(cpu: &mut CPU) {
+    let address = cpu.pop16();
+    cpu.set_program_counter(address.wrapping_add(1));
+}
+
+pub fn sec(cpu: &mut CPU) {
+    cpu.set_flag(StatusFlags::C, true);
+}
+
+pub fn sed(cpu: &mut CPU) {
+    cpu.set_flag(StatusFlags::D, true);
+}
+
+pub fn sei(cpu: &mut CPU) {
+    cpu.set_flag(StatusFlags::I, true);
+}
+
+pub fn sta(cpu: &mut CPU) {
+    cpu.write(cpu.get_abs_addr(), cpu.get_accumulator());
+}
+
+pub fn stx(cpu: &mut CPU) {
+    cpu.write(cpu.get_abs_addr(), cpu.get_register_x());
+}
+
+pub fn sty(cpu: &mut CPU) {
+    cpu.write(cpu.get_abs_addr(), cpu.get_register_y());
+}
+
+pub fn tax(cpu: &mut CPU) {
+    cpu.set_register_x(cpu.get_accumulator());
+
+    check_if_zero_or_negative_u8(cpu, cpu.get_register_x());
+}
+
+pub fn tay(cpu: &mut CPU) {
+    cpu.set_register_y(cpu.get_accumulator());
+
+    check_if_zero_or_negative_u8(cpu, cpu.get_register_y());
+}
+
+pub fn tsx(cpu: &mut CPU) {
+    cpu.set_register_x(cpu.get_stack_pointer());
+
+    check_if_zero_or_negative_u8(cpu, cpu.get_register_x());
+}
+
+pub fn txa(cpu: &mut CPU) {
+    cpu.set_accumulator(cpu.get_register_x());
+
+    check_if_zero_or_negative_u8(cpu, cpu.get_accumulator());
+}
+
+/// Transfer Index X to Stack Pointer, the only transfer that doesn't change the flags
+pub fn txs(cpu: &mut CPU) {
+    cpu.set_stack_pointer(cpu.get_register_x());
+}
+
+pub fn tya(cpu: &mut CPU) {
+    cpu.set_accumulator(cpu.get_register_y());
+
+    check_if_zero_or_negative_u8(cpu, cpu.get_accumulator());
+}
+
+//Undocumented Opcodes
+//The read-modify-write combinations read the operand once, write the result back and run the second instruction on
+//that result, so read sensitive registers ($2007, $4015) only see one read
+
+/// Load Accumulator and Index X
+pub fn lax(cpu: &mut CPU) {
+    cpu.fetch();
+
+    cpu.set_accumulator(cpu.get_fetched());
+    cpu.set_register_x(cpu.get_fetched());
+
+    check_if_zero_or_negative_u8(cpu, cpu.get_fetched());
+}
+
+/// Store Accumulator AND Index X, no flags change
+pub fn sax(cpu: &mut CPU) {
+    cpu.write(cpu.get_abs_addr(), cpu.get_accumulator() & cpu.get_register_x());
+}
+
+/// Decrement Memory then Compare with Accumulator (DEC + CMP)
+pub fn dcp(cpu: &mut CPU) {
+    cpu.fetch();
+
+    let result = dec_fetched(cpu);
+    cpu.set_fetched(result);
+
+    cmp_fetched(cpu);
+}
+
+/// Increment Memory then Subtract with Borrow (INC + SBC)
+pub fn isb(cpu: &mut CPU) {
+    cpu.fetch();
+
+    let result = inc_fetched(cpu);
+    cpu.set_fetched(result);
+
+    sbc_fetched(cpu);
+}
+
+/// Shift Left then "OR" with Accumulator (ASL + ORA)
+pub fn slo(cpu: &mut CPU) {
+    cpu.fetch();
+
+    let result = asl_fetched(cpu);
+    cpu.set_fetched(result);
+
+    ora_fetched(cpu);
+}
+
+/// Rotate Left then "AND" with Accumulator (ROL + AND)
+pub fn rla(cpu: &mut CPU) {
+    cpu.fetch();
+
+    let result = rol_fetched(cpu);
+    cpu.set_fetched(result);
+
+    and_fetched(cpu);
+}
+
+/// Shift Right then "Exclusive OR" with Accumulator (LSR + EOR)
+pub fn sre(cpu: &mut CPU) {
+    cpu.fetch();
+
+    let result = lsr_fetched(cpu);
+    cpu.set_fetched(result);
+
+    eor_fetched(cpu);
+}
+
+/// Rotate Right then Add with Carry (ROR + ADC)
+pub fn rra(cpu: &mut CPU) {
+    cpu.fetch();
+
+    let result = ror_fetched(cpu);
+    cpu.set_fetched(result);
+
+    adc_fetched(cpu);
+}
+
+/// Store Index Y AND (high byte of the base address + 1), no flags change<br>
+/// When indexing crosses a page the stored value also replaces the high byte of the target address
+pub fn shy(cpu: &mut CPU) {
+    let address = cpu.get_abs_addr();
+    let base = address.wrapping_sub(cpu.get_register_x() as u16);
+    let value = cpu.get_register_y() & ((base >> 8) as u8).wrapping_add(1);
+
+    if (base & 0xFF00) != (address & 0xFF00) {
+        cpu.write(((value as u16) << 8) | (address & 0x00FF), value);
+    } else {
+        cpu.write(address, value);
     }
 }
 
+///Placeholder for the unstable undocumented opcodes and the JAMs
+pub fn xxx(_cpu: &mut CPU) {}
 
 
 //Extra Functions
+
+///Shared by the eight branch instructions<br>
+///A taken branch costs one extra cycle, and one more when the target is on a different page than the next instruction
+fn branch(cpu: &mut CPU, condition: bool) {
+    if condition {
+        cpu.add_cycles(1);
+
+        let target = cpu.get_program_counter().wrapping_add(cpu.get_rel_addr());
+
+        if (target & 0xFF00) != (cpu.get_program_counter() & 0xFF00) {
+            cpu.add_cycles(1);
+        }
+
+        cpu.set_abs_addr(target);
+        cpu.set_program_counter(target);
+    }
+}
+
 ///Checks if the value equals to zero or if the value (AND) the most significant bit on an 8-bit value (0x80)
 // How to calculate the most significant bits:
 //     7 bit
 // 7 6 5 4 3 2 1 0 (binary indexes)
 // 1 0 0 0 0 0 0 0 (binary) = 0x80 (hexadecimal)
+///Both flags are always written, so a previous result never leaves a stale Z or N behind
 pub fn check_if_zero_or_negative_u16(cpu: &mut CPU, value: u16) {
-    if (value & 0x00FF) == 0 {
-        cpu.set_flag(StatusFlags::Z, true)
-    } else if (value & 0x00FF) & 0x0080 != 0 {
-        cpu.set_flag(StatusFlags::N, true)
-    }
+    cpu.set_flag(StatusFlags::Z, (value & 0x00FF) == 0);
+    cpu.set_flag(StatusFlags::N, (value & 0x0080) != 0);
+}
+
+///CMP, CPX and CPY: C is set when the register is greater or equal (no borrow), Z and N come from register - M
+pub fn compare(cpu: &mut CPU, register: u8) {
+    let value = register.wrapping_sub(cpu.get_fetched());
+
+    cpu.set_flag(StatusFlags::C, register >= cpu.get_fetched());
+
+    check_if_zero_or_negative_u8(cpu, value);
 }
 
 pub fn check_if_zero_or_negative_u8(cpu: &mut CPU, value: u8) {
-    if value == 0 {
-        cpu.set_flag(StatusFlags::Z, true)
-    } else if (value & 0x80) != 0 {
-        cpu.set_flag(StatusFlags::N, true)
+    cpu.set_flag(StatusFlags::Z, value == 0);
+    cpu.set_flag(StatusFlags::N, (value & 0x80) != 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{accuracy::AccuracySettings, cartridge::Cartridge, system::System};
+
+    ///Runs the reset sequence of an NROM-256 console with the program at origin (also the reset vector)
+    fn boot(origin: u16, program: &[u8]) -> System {
+        let mut rom = vec![0; 16 + 2 * 16 * 1024 + 8 * 1024];
+        rom[0..4].copy_from_slice(b"NES\x1A");
+        rom[4] = 2;
+        rom[5] = 1;
+
+        let prg = &mut rom[16..16 + 2 * 16 * 1024];
+        let offset = (origin - 0x8000) as usize;
+        prg[offset..offset + program.len()].copy_from_slice(program);
+        prg[0x7FFC] = (origin & 0x00FF) as u8;
+        prg[0x7FFD] = (origin >> 8) as u8;
+
+        let cartridge = Cartridge::from_bytes(&rom).unwrap();
+
+        let mut system = System::new();
+        system.get_bus().borrow_mut().insert_cartridge(Rc::new(RefCell::new(cartridge)));
+        system.reset();
+
+        //The reset sequence
+        system.step_instruction();
+
+        return system;
+    }
+
+    ///Runs one instruction, returns the new program counter and the CPU cycles it took
+    fn step(system: &mut System) -> (u16, u64) {
+        let start = system.get_cpu_cycle_count();
+        system.step_instruction();
+
+        let program_counter = system.get_cpu().borrow().get_program_counter();
+
+        return (program_counter, system.get_cpu_cycle_count() - start);
+    }
+
+    //Z is clear after reset, so BNE is taken and BEQ isn't
+
+    #[test]
+    fn branch_not_taken() {
+        let mut system = boot(0x8100, &[0xF0, 0xF0]); //BEQ -16
+
+        assert_eq!(step(&mut system), (0x8102, 2));
+    }
+
+    #[test]
+    fn backward_branch_same_page() {
+        let mut system = boot(0x8150, &[0xD0, 0xFE]); //BNE -2
+
+        assert_eq!(step(&mut system), (0x8150, 3));
+    }
+
+    #[test]
+    fn backward_branch_across_pages() {
+        let mut system = boot(0x8100, &[0xD0, 0xF0]); //BNE -16
+
+        assert_eq!(step(&mut system), (0x80F2, 4));
+    }
+
+    #[test]
+    fn forward_branch_across_pages() {
+        let mut system = boot(0x80F0, &[0xD0, 0x20]); //BNE +32
+
+        assert_eq!(step(&mut system), (0x8112, 4));
+    }
+
+    #[test]
+    fn branch_to_the_end_of_the_page() {
+        let mut system = boot(0x80F0, &[0xD0, 0x0D]); //BNE +13
+
+        assert_eq!(step(&mut system), (0x80FF, 3));
+    }
+
+    //Page-cross penalties, X is set to 1 by the first instruction
+
+    #[test]
+    fn read_takes_the_page_cross_penalty() {
+        let mut system = boot(0x8000, &[0xA2, 0x01, 0xBD, 0xFF, 0x80, 0xBD, 0x00, 0x80]); //LDX #1, LDA $80FF,X, LDA $8000,X
+        step(&mut system);
+
+        assert_eq!(step(&mut system).1, 5);
+        assert_eq!(step(&mut system).1, 4);
+    }
+
+    #[test]
+    fn store_always_takes_the_long_path() {
+        let mut system = boot(0x8000, &[0xA2, 0x01, 0x9D, 0xFF, 0x02, 0x9D, 0x00, 0x02]); //LDX #1, STA $02FF,X, STA $0200,X
+        step(&mut system);
+
+        assert_eq!(step(&mut system).1, 5);
+        assert_eq!(step(&mut system).1, 5);
+    }
+
+    #[test]
+    fn read_modify_write_ignores_the_page_cross() {
+        let mut system = boot(0x8000, &[0xA2, 0x01, 0x1E, 0xFF, 0x02, 0x1E, 0x00, 0x02]); //LDX #1, ASL $02FF,X, ASL $0200,X
+        step(&mut system);
+
+        assert_eq!(step(&mut system).1, 7);
+        assert_eq!(step(&mut system).1, 7);
+    }
+
+    #[test]
+    fn indexed_store_dummy_reads_the_target() {
+        //LDA #$20, STA $2006, LDA #$00, STA $2006, LDX #7, STA $2000,X
+        let program = [0xA9, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA2, 0x07, 0x9D, 0x00, 0x20];
+
+        for dummy_reads in [false, true] {
+            let mut system = boot(0x8000, &program);
+            system.set_accuracy(AccuracySettings { dummy_reads, ..AccuracySettings::default() });
+
+            for _ in 0..6 {
+                step(&mut system);
+            }
+
+            //The read of $2007 before the write moves the VRAM address as well
+            let expected = if dummy_reads { 0x2002 } else { 0x2001 };
+            assert_eq!(system.get_ppu().borrow().vram_address, expected);
+        }
+    }
+
+    ///The opcodes with a page-cross penalty, from the 6502 instruction timing tables
+    #[test]
+    fn page_penalty_matches_the_timing_tables() {
+        let expected = [
+            0x11, 0x19, 0x1C, 0x1D, //ORA, NOP
+            0x31, 0x39, 0x3C, 0x3D, //AND, NOP
+            0x51, 0x59, 0x5C, 0x5D, //EOR, NOP
+            0x71, 0x79, 0x7C, 0x7D, //ADC, NOP
+            0xB1, 0xB3, 0xB9, 0xBC, 0xBD, 0xBE, 0xBF, //LDA, LAX, LDY, LDX
+            0xD1, 0xD9, 0xDC, 0xDD, //CMP, NOP
+            0xF1, 0xF9, 0xFC, 0xFD, //SBC, NOP
+        ];
+
+        let penalties: Vec<u8> = (0..=255u8)
+            .filter(|&opcode| super::LOOKUP_TABLE[opcode as usize].page_penalty)
+            .collect();
+
+        assert_eq!(penalties, expected);
+    }
+
+    //B flag: pushed set by BRK and PHP, clear by IRQ and NMI, never kept in P
+
+    ///Writes the IRQ/BRK vector ($FFFE) to point at $9000
+    fn set_irq_vector(system: &System) {
+        let bus = system.get_bus();
+        bus.borrow_mut().poke(0xFFFE, 0x00);
+        bus.borrow_mut().poke(0xFFFF, 0x90);
+    }
+
+    fn status(system: &System) -> u8 {
+        return system.get_cpu().borrow().get_registers().status;
+    }
+
+    ///The byte pushed last (the status for PHP, BRK and the interrupts)
+    fn stack_top(system: &System) -> u8 {
+        let stack_pointer = system.get_cpu().borrow().get_stack_pointer();
+
+        return system.get_bus().borrow().peek(0x0100 + stack_pointer as u16 + 1);
+    }
+
+    #[test]
+    fn php_pushes_b_set() {
+        let mut system = boot(0x8000, &[0x08]); //PHP
+        step(&mut system);
+
+        //Reset leaves I and G set
+        assert_eq!(stack_top(&system), 0x34);
+        assert_eq!(status(&system), 0x24);
+    }
+
+    #[test]
+    fn brk_pushes_b_set_and_skips_the_padding_byte() {
+        let mut system = boot(0x8000, &[0x58, 0x00, 0xFF]); //CLI, BRK
+        set_irq_vector(&system);
+        step(&mut system);
+
+        assert_eq!(step(&mut system), (0x9000, 7));
+
+        //The pushed copy still has I clear, the return address skips the padding byte
+        assert_eq!(stack_top(&system), 0x30);
+        let bus = system.get_bus();
+        assert_eq!(bus.borrow().peek(0x01FD), 0x80);
+        assert_eq!(bus.borrow().peek(0x01FC), 0x03);
+
+        assert_eq!(status(&system), 0x24);
+    }
+
+    #[test]
+    fn irq_pushes_b_clear() {
+        let mut system = boot(0x8000, &[0x58, 0xEA]); //CLI, NOP
+        set_irq_vector(&system);
+        step(&mut system);
+
+        system.get_cpu().borrow_mut().interrupt_request();
+        assert_eq!(step(&mut system), (0x9000, 7));
+
+        assert_eq!(stack_top(&system), 0x20);
+        assert_eq!(status(&system), 0x24);
+    }
+
+    #[test]
+    fn nmi_pushes_b_clear() {
+        let mut system = boot(0x8000, &[0xEA]); //NOP
+        let bus = system.get_bus();
+        bus.borrow_mut().poke(0xFFFA, 0x00);
+        bus.borrow_mut().poke(0xFFFB, 0xA0);
+
+        system.get_cpu().borrow_mut().non_maskable_input();
+        assert_eq!(step(&mut system), (0xA000, 8));
+
+        assert_eq!(stack_top(&system), 0x24);
+    }
+
+    #[test]
+    fn plp_ignores_b_and_forces_unused() {
+        let mut system = boot(0x8000, &[0xA9, 0xD3, 0x48, 0x28]); //LDA #$D3, PHA, PLP
+        for _ in 0..3 {
+            step(&mut system);
+        }
+
+        assert_eq!(status(&system), 0xE3);
+    }
+
+    #[test]
+    fn rti_ignores_b_and_forces_unused() {
+        //Pushes $9000 and then the status $D3 by hand
+        let mut system = boot(
+            0x8000,
+            &[0xA9, 0x90, 0x48, 0xA9, 0x00, 0x48, 0xA9, 0xD3, 0x48, 0x40], //LDA #, PHA (x3), RTI
+        );
+        for _ in 0..6 {
+            step(&mut system);
+        }
+
+        assert_eq!(step(&mut system), (0x9000, 6));
+        assert_eq!(status(&system), 0xE3);
+    }
+
+    #[test]
+    fn rti_returns_from_brk() {
+        let mut system = boot(0x8000, &[0x58, 0x00, 0xFF, 0xEA]); //CLI, BRK, NOP
+        set_irq_vector(&system);
+        system.get_bus().borrow_mut().poke(0x9000, 0x40); //RTI
+
+        for _ in 0..3 {
+            step(&mut system);
+        }
+
+        assert_eq!(system.get_cpu().borrow().get_program_counter(), 0x8003);
+        assert_eq!(status(&system), 0x20);
+    }
+
+    //Undocumented opcodes
+
+    #[test]
+    fn lax_loads_a_and_x() {
+        let mut system = boot(0x8000, &[0xAF, 0x00, 0x80]); //LAX $8000
+        step(&mut system);
+
+        let cpu = system.get_cpu();
+        assert_eq!(cpu.borrow().get_accumulator(), 0xAF);
+        assert_eq!(cpu.borrow().get_register_x(), 0xAF);
+        assert_eq!(cpu.borrow().get_registers().status & 0x80, 0x80);
+    }
+
+    #[test]
+    fn aby_indexes_with_y() {
+        //LDX #1, LDY #2, LDA $8000,Y
+        let mut system = boot(0x8000, &[0xA2, 0x01, 0xA0, 0x02, 0xB9, 0x00, 0x80]);
+        for _ in 0..3 {
+            step(&mut system);
+        }
+
+        assert_eq!(system.get_cpu().borrow().get_accumulator(), 0xA0);
+    }
+
+    #[test]
+    fn compares_set_carry_zero_and_negative_from_the_register() {
+        //(opcode, register load, register value, operand, expected C Z N)
+        let cases = [
+            (0xC9, 0xA9, 0x40, 0x10, (true, false, false)), //CMP
+            (0xC9, 0xA9, 0x10, 0x10, (true, true, false)),
+            (0xC9, 0xA9, 0x10, 0x40, (false, false, true)),
+            (0xE0, 0xA2, 0x40, 0x10, (true, false, false)), //CPX
+            (0xE0, 0xA2, 0x10, 0x40, (false, false, true)),
+            (0xC0, 0xA0, 0x10, 0x10, (true, true, false)),  //CPY
+            (0xC0, 0xA0, 0x10, 0x40, (false, false, true)),
+        ];
+
+        for (opcode, load, register, operand, (carry, zero, negative)) in cases {
+            //LDA #0 first so CPX/CPY can't get their result from the accumulator
+            let mut system = boot(0x8000, &[0xA9, 0x00, load, register, opcode, operand]);
+            for _ in 0..3 {
+                step(&mut system);
+            }
+
+            let status = system.get_cpu().borrow().get_registers().status;
+            assert_eq!(status & 0x01 != 0, carry, "{:02X} {:02X} {:02X}", opcode, register, operand);
+            assert_eq!(status & 0x02 != 0, zero, "{:02X} {:02X} {:02X}", opcode, register, operand);
+            assert_eq!(status & 0x80 != 0, negative, "{:02X} {:02X} {:02X}", opcode, register, operand);
+        }
+    }
+
+    #[test]
+    fn bit_sets_zero_when_no_bits_match() {
+        //LDA #$0F, BIT $8000 (holds $A9, $A9 & $0F = $09)
+        let mut system = boot(0x8000, &[0xA9, 0x0F, 0x2C, 0x00, 0x80]);
+        step(&mut system);
+        step(&mut system);
+        assert_eq!(system.get_cpu().borrow().get_registers().status & 0x02, 0x00);
+
+        //LDA #$10, BIT $8002 (holds $2C, $2C & $10 = $00)
+        let mut system = boot(0x8000, &[0xA9, 0x10, 0x2C, 0x02, 0x80]);
+        step(&mut system);
+        step(&mut system);
+        assert_eq!(system.get_cpu().borrow().get_registers().status & 0x02, 0x02);
+    }
+
+    #[test]
+    fn sbc_sets_overflow_on_signed_overflow() {
+        //(A, M, expected V), carry set so there is no borrow
+        let cases = [(0x50, 0xB0, true), (0xD0, 0x70, true), (0x50, 0x10, false), (0xD0, 0xF0, false)];
+
+        for (a, m, overflow) in cases {
+            let mut system = boot(0x8000, &[0x38, 0xA9, a, 0xE9, m]); //SEC, LDA #a, SBC #m
+            for _ in 0..3 {
+                step(&mut system);
+            }
+
+            let status = system.get_cpu().borrow().get_registers().status;
+            assert_eq!(status & 0x40 != 0, overflow, "{:02X} - {:02X}", a, m);
+        }
+    }
+
+    #[test]
+    fn sax_stores_a_and_x() {
+        let mut system = boot(0x8000, &[0xA9, 0xF0, 0xA2, 0x3C, 0x87, 0x10]); //LDA #$F0, LDX #$3C, SAX $10
+        for _ in 0..3 {
+            step(&mut system);
+        }
+
+        assert_eq!(system.get_bus().borrow().peek(0x0010), 0x30);
+    }
+
+    #[test]
+    fn slo_shifts_memory_and_ors_the_result() {
+        let mut system = boot(0x8000, &[0xA9, 0xC1, 0x85, 0x10, 0xA9, 0x01, 0x07, 0x10]); //LDA #$C1, STA $10, LDA #1, SLO $10
+        for _ in 0..3 {
+            step(&mut system);
+        }
+
+        assert_eq!(step(&mut system).1, 5);
+        assert_eq!(system.get_bus().borrow().peek(0x0010), 0x82);
+        assert_eq!(system.get_cpu().borrow().get_accumulator(), 0x83);
+        assert_eq!(system.get_cpu().borrow().get_registers().status & 0x01, 0x01);
+    }
+
+    #[test]
+    fn shy_stores_y_and_the_high_byte_plus_one() {
+        let mut system = boot(0x8000, &[0xA0, 0xFF, 0xA2, 0x01, 0x9C, 0x00, 0x02]); //LDY #$FF, LDX #1, SHY $0200,X
+        for _ in 0..2 {
+            step(&mut system);
+        }
+
+        assert_eq!(step(&mut system).1, 5);
+        assert_eq!(system.get_bus().borrow().peek(0x0201), 0x03);
+    }
+
+    #[test]
+    fn shy_crossing_a_page_replaces_the_high_byte() {
+        let mut system = boot(0x8000, &[0xA0, 0x05, 0xA2, 0x02, 0x9C, 0xFF, 0x02]); //LDY #5, LDX #2, SHY $02FF,X
+        for _ in 0..3 {
+            step(&mut system);
+        }
+
+        //Y & $03 = $01, so the write lands on $0101 instead of $0301
+        assert_eq!(system.get_bus().borrow().peek(0x0101), 0x01);
+        assert_eq!(system.get_bus().borrow().peek(0x0301), 0x00);
+    }
+
+    #[test]
+    fn dcp_reads_its_operand_once() {
+        //LDA #$20, STA $2006, LDA #0, STA $2006, DCP $2007, LDA #$55, STA $2007
+        #[rustfmt::skip]
+        let mut system = boot(0x8000, &[
+            0xA9, 0x20, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20,
+            0xCF, 0x07, 0x20, 0xA9, 0x55, 0x8D, 0x07, 0x20,
+        ]);
+        for _ in 0..7 {
+            step(&mut system);
+        }
+
+        //One read and one write advance v twice, so the next store lands on $2002
+        let ppu = system.get_ppu();
+        assert_eq!(ppu.borrow().ppu_read(0x2002), 0x55);
+        assert_eq!(ppu.borrow().ppu_read(0x2003), 0x00);
+    }
+
+    #[test]
+    fn multi_byte_nops_skip_their_operands() {
+        //NOP #$00, NOP $00, NOP $00,X, NOP $0000, NOP $80FF,X (X = 1 crosses the page)
+        let mut system = boot(
+            0x8000,
+            &[0xA2, 0x01, 0x80, 0x00, 0x04, 0x00, 0x14, 0x00, 0x0C, 0x00, 0x00, 0x1C, 0xFF, 0x80],
+        );
+        step(&mut system);
+
+        assert_eq!(step(&mut system), (0x8004, 2));
+        assert_eq!(step(&mut system), (0x8006, 3));
+        assert_eq!(step(&mut system), (0x8008, 4));
+        assert_eq!(step(&mut system), (0x800B, 4));
+        assert_eq!(step(&mut system), (0x800E, 5));
     }
 }
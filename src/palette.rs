@@ -0,0 +1,157 @@
+use std::{fmt, fs, io, path::Path};
+
+///The 64 colors the 2C02 can output, as 0x00RRGGBB
+pub const PALETTE_2C02: [u32; 64] = [
+    0x545454, 0x001E74, 0x081090, 0x300088, 0x440064, 0x5C0030, 0x540400, 0x3C1800,
+    0x202A00, 0x083A00, 0x004000, 0x003C00, 0x00323C, 0x000000, 0x000000, 0x000000,
+    0x989698, 0x084CC4, 0x3032EC, 0x5C1EE4, 0x8814B0, 0xA01464, 0x982220, 0x783C00,
+    0x545A00, 0x287200, 0x087C00, 0x007628, 0x006678, 0x000000, 0x000000, 0x000000,
+    0xECEEEC, 0x4C9AEC, 0x787CEC, 0xB062EC, 0xE454EC, 0xEC58B4, 0xEC6A64, 0xD48820,
+    0xA0AA00, 0x74C400, 0x4CD020, 0x38CC6C, 0x38B4CC, 0x3C3C3C, 0x000000, 0x000000,
+    0xECEEEC, 0xA8CCEC, 0xBCBCEC, 0xD4B2EC, 0xECAEEC, 0xECAED4, 0xECB4B0, 0xE4C490,
+    0xCCD278, 0xB4DE78, 0xA8E290, 0x98E2B4, 0xA0D6E4, 0xA0A2A0, 0x000000, 0x000000,
+];
+
+///Colors per emphasis combination, .pal files hold either one set or all eight
+const COLOR_COUNT: usize = 64;
+const EMPHASIS_COUNT: usize = 8;
+
+///How much the emphasis bits darken the channels that aren't emphasized
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error),
+    InvalidSize(usize), //Neither 192 (64 colors) nor 1536 bytes (64 colors for each emphasis combination)
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(error) => write!(f, "could not read the palette file: {}", error),
+            PaletteError::InvalidSize(size) => {
+                write!(f, "a .pal file has 192 or 1536 bytes, this one has {}", size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl From<io::Error> for PaletteError {
+    fn from(error: io::Error) -> Self {
+        PaletteError::Io(error)
+    }
+}
+
+///The RGB color of every 2C02 color index under every combination of the emphasis bits (PPUMASK bits 5 - 7)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Palette {
+    colors: Vec<u32>, //0x00RRGGBB, emphasis * 64 + index
+}
+
+impl Palette {
+    ///64 colors, the emphasis variants are derived from them
+    pub fn from_colors(colors: &[u32; COLOR_COUNT]) -> Self {
+        let mut all = Vec::with_capacity(COLOR_COUNT * EMPHASIS_COUNT);
+
+        for emphasis in 0..EMPHASIS_COUNT as u8 {
+            all.extend(colors.iter().map(|&color| Self::emphasize(color, emphasis)));
+        }
+
+        Self { colors: all }
+    }
+
+    ///A .pal file: RGB triplets for the 64 colors, or for all 512 (the emphasis combinations in order)
+    pub fn from_pal(data: &[u8]) -> Result<Self, PaletteError> {
+        let colors: Vec<u32> = data
+            .chunks_exact(3)
+            .map(|rgb| u32::from_be_bytes([0, rgb[0], rgb[1], rgb[2]]))
+            .collect();
+
+        if data.len() == COLOR_COUNT * 3 {
+            return Ok(Self::from_colors(&colors.try_into().unwrap()));
+        }
+
+        if data.len() == COLOR_COUNT * EMPHASIS_COUNT * 3 {
+            return Ok(Self { colors });
+        }
+
+        Err(PaletteError::InvalidSize(data.len()))
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, PaletteError> {
+        return Self::from_pal(&fs::read(path)?);
+    }
+
+    ///Color of a palette index (0 - 63) with the emphasis bits (0 - 7: red, green, blue)
+    pub fn color(&self, index: u8, emphasis: u8) -> u32 {
+        return self.colors[(emphasis as usize & 0x07) * COLOR_COUNT + (index as usize & 0x3F)];
+    }
+
+    ///All 512 colors, emphasis * 64 + index
+    pub fn colors(&self) -> &[u32] {
+        return &self.colors;
+    }
+
+    ///A channel keeps its level only when no other channel is emphasized, so all three bits darken everything
+    fn emphasize(color: u32, emphasis: u8) -> u32 {
+        if emphasis == 0 {
+            return color;
+        }
+
+        let [_, red, green, blue] = color.to_be_bytes();
+        let channel = |value: u8, bit: u8| {
+            if (emphasis & !bit) == 0 {
+                value
+            } else {
+                (value as f32 * EMPHASIS_ATTENUATION).round() as u8
+            }
+        };
+
+        return u32::from_be_bytes([0, channel(red, 0x01), channel(green, 0x02), channel(blue, 0x04)]);
+    }
+}
+
+impl Default for Palette {
+    ///The built-in NTSC 2C02 palette
+    fn default() -> Self {
+        Self::from_colors(&PALETTE_2C02)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_pal_files_get_emphasis_variants() {
+        let mut data = vec![0; 192];
+        data[3..6].copy_from_slice(&[0xFF, 0x80, 0x40]);
+
+        let palette = Palette::from_pal(&data).unwrap();
+        assert_eq!(palette.color(0x01, 0), 0xFF8040);
+        assert_eq!(palette.color(0x41, 0), 0xFF8040);
+
+        //Red emphasis darkens green and blue, all three darken every channel
+        assert_eq!(palette.color(0x01, 0b001), 0xFF6834);
+        assert_eq!(palette.color(0x01, 0b100), 0xD06840);
+        assert_eq!(palette.color(0x01, 0b111), 0xD06834);
+    }
+
+    #[test]
+    fn full_pal_files_are_used_as_is() {
+        let mut data = vec![0; 1536];
+        data[7 * 192 + 3..7 * 192 + 6].copy_from_slice(&[0x12, 0x34, 0x56]);
+
+        let palette = Palette::from_pal(&data).unwrap();
+        assert_eq!(palette.color(0x01, 7), 0x123456);
+        assert_eq!(palette.color(0x01, 0), 0x000000);
+    }
+
+    #[test]
+    fn other_sizes_are_rejected() {
+        assert!(matches!(Palette::from_pal(&[0; 191]), Err(PaletteError::InvalidSize(191))));
+        assert_eq!(Palette::default().color(0x21, 0), PALETTE_2C02[0x21]);
+    }
+}
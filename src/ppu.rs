@@ -0,0 +1,1640 @@
+use std::{cell::RefCell, rc::Rc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    accuracy::{AccuracySettings, PpuBackend},
+    cartridge::Cartridge,
+    palette::Palette,
+    region::Region,
+    rng::Rng,
+};
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+///Nametable arrangement, wired by the cartridge
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    OneScreenLow,  //Every nametable shows the first physical one
+    OneScreenHigh, //Every nametable shows the second physical one
+}
+
+///The two layers the PPU composites, see PPU::set_layer_visible()
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layer {
+    Background,
+    Sprites,
+}
+
+///Which sprites are drawn when more than 8 share a scanline, see PPU::set_sprite_limit()
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SpriteLimit {
+    ///The first 8 in OAM order, like the hardware
+    #[default]
+    Hardware,
+    ///8 of them, starting one sprite later every frame so each one disappears in turn: the flicker games rotate
+    ///their OAM for, even in games that don't
+    Flicker,
+    ///All of them
+    Unlimited,
+}
+
+//PPUCTRL ($2000) Flags
+pub enum ControlFlags {
+    NametableX = 1 << 0,        //Base nametable address (bit 0)
+    NametableY = 1 << 1,        //Base nametable address (bit 1)
+    IncrementMode = 1 << 2,     //VRAM address increment per $2007 access (0: 1, 1: 32)
+    SpritePattern = 1 << 3,     //Sprite pattern table address for 8x8 sprites
+    BackgroundPattern = 1 << 4, //Background pattern table address
+    SpriteSize = 1 << 5,        //Sprite size (0: 8x8, 1: 8x16)
+    //Bit 6: PPU master/slave select, the EXT pins aren't connected on the NES
+    EnableNmi = 1 << 7,         //Generate an NMI at the start of vblank
+}
+
+//PPUMASK ($2001) Flags
+pub enum MaskFlags {
+    Grayscale = 1 << 0,
+    ShowBackgroundLeft = 1 << 1, //Show the background in the leftmost 8 pixels
+    ShowSpritesLeft = 1 << 2,    //Show sprites in the leftmost 8 pixels
+    ShowBackground = 1 << 3,
+    ShowSprites = 1 << 4,
+    EmphasizeRed = 1 << 5,
+    EmphasizeGreen = 1 << 6,
+    EmphasizeBlue = 1 << 7,
+}
+
+//PPUSTATUS ($2002) Flags
+pub enum StatusFlags {
+    SpriteOverflow = 1 << 5,
+    SpriteZeroHit = 1 << 6,
+    VerticalBlank = 1 << 7,
+}
+
+//OAM Attribute Flags (byte 2 of every sprite)
+pub enum SpriteFlags {
+    Palette = 0x03,            //Sprite palette (4 - 7)
+    Priority = 1 << 5,         //Behind the background
+    FlipHorizontal = 1 << 6,
+    FlipVertical = 1 << 7,
+}
+
+///Fields of the loopy VRAM address (v and t): 0yyy NNYY YYYX XXXX<br>
+///fine Y scroll, nametable select, coarse Y and coarse X (tiles)
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+
+///Scroll a scanline was drawn with: v when its tile fetches started and fine X, for inspecting raster splits
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ScanlineScroll {
+    pub v: u16,
+    pub fine_x: u8,
+}
+
+impl ScanlineScroll {
+    ///Horizontal scroll in pixels over the two nametables side by side (0 - 511)
+    pub fn x(&self) -> u16 {
+        return ((self.v & NAMETABLE_X) >> 10) * 256 + (self.v & COARSE_X) * 8 + self.fine_x as u16;
+    }
+
+    ///Vertical scroll in pixels over the two nametables stacked (0 - 479, rows 30 and 31 go past a nametable)
+    pub fn y(&self) -> u16 {
+        return ((self.v & NAMETABLE_Y) >> 11) * 240 + ((self.v & COARSE_Y) >> 5) * 8 + ((self.v & FINE_Y) >> 12);
+    }
+}
+
+///Maximum number of sprites drawn on a single scanline
+pub const SPRITES_PER_SCANLINE: usize = 8;
+
+///Frames the I/O latch holds its value without being driven (about 600ms), with open bus decay on
+const IO_LATCH_DECAY_FRAMES: u64 = 36;
+
+///A sprite selected for the current scanline with its row of pattern data already fetched (and flipped)
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct SpriteEntry {
+    x: u8,
+    attribute: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    sprite_zero: bool,
+}
+
+impl SpriteEntry {
+    const EMPTY: SpriteEntry = SpriteEntry {
+        x: 0xFF,
+        attribute: 0,
+        pattern_low: 0,
+        pattern_high: 0,
+        sprite_zero: false,
+    };
+}
+
+///Memory, registers and timing of the PPU, as stored in save states
+#[derive(Serialize, Deserialize)]
+pub struct PpuState {
+    name_tables: Vec<u8>,
+    pattern_tables: Vec<u8>,
+    palette: Vec<u8>,
+    oam: Vec<u8>,
+
+    control: u8,
+    mask: u8,
+    status: u8,
+    oam_address: u8,
+    vram_address: u16,
+    temp_address: u16,
+    fine_x: u8,
+
+    address_latch: bool,
+    data_buffer: u8,
+    io_latch: u8,
+    io_latch_frame: u64,
+    corrupt_oam_rows: u32,
+
+    next_tile: (u8, u8, u8),
+    pattern_shift: (u16, u16),
+    attribute_shift: (u16, u16),
+    line_address: u16,
+
+    sprite_scanline: Vec<SpriteEntry>,
+    secondary_oam: [u8; 32],
+
+    scanline: i16,
+    cycle: i16,
+    frame_count: u64,
+    nmi: bool,
+}
+
+pub struct PPU {
+    //Memory
+    pub name_tables: [[u8; 1024]; 2], //2KB of VRAM for two nametables
+    pub pattern_tables: [[u8; 4096]; 2], //Pattern memory used while no cartridge provides CHR
+    pub palette: [u8; 32],
+    pub oam: [u8; 256],
+    pub mirroring: Mirroring, //Used while no cartridge is connected
+
+    //Registers
+    pub control: u8, //PPUCTRL
+    pub mask: u8,    //PPUMASK
+    pub status: u8,  //PPUSTATUS
+    pub oam_address: u8,
+    pub vram_address: u16, //v: the $2007 address, while rendering the tile being fetched (15 bits, see COARSE_X)
+    pub temp_address: u16, //t: scroll and address set up by $2000/$2005/$2006, copied into v while rendering
+    pub fine_x: u8,        //x: fine X scroll (0 - 7)
+
+    //Assist Variables
+    address_latch: bool,    //w: first or second write of $2005/$2006, the toggle is shared
+    data_buffer: u8,
+    io_latch: u8,           //Last value on the CPU side data bus, read back from the write only registers
+    io_latch_frame: u64,    //Frame the latch was last driven on
+    corrupt_oam_rows: u32,  //8 byte OAM rows overwritten with row 0 once rendering starts again
+
+    //Background pipeline: the tile fetched for the next 8 dots and the 16 pixel shift registers drawn from,
+    //bit 15 is the current pixel (before fine X)
+    next_tile: (u8, u8, u8), //Palette, pattern low plane, pattern high plane
+    pattern_shift: (u16, u16),
+    attribute_shift: (u16, u16),
+    line_address: u16, //v at the start of the scanline's fetches, drawn from by the scanline backend
+
+    //Accuracy settings
+    backend: PpuBackend,
+    open_bus_decay: bool,
+    oam_corruption: bool,
+    ppudata_glitch: bool,
+
+    //Debug toggles: hidden layers are left out of the composite only
+    hide_background: bool,
+    hide_sprites: bool,
+    sprite_limit: SpriteLimit,
+    shown_sprites: Vec<(usize, SpriteEntry)>, //OAM index and sprite drawn past the limit, for Flicker and Unlimited
+
+    //Sprites on the scanline being drawn (secondary OAM)
+    sprite_scanline: [SpriteEntry; SPRITES_PER_SCANLINE],
+    sprite_count: usize,
+    secondary_oam: [u8; 32], //OAM bytes of those sprites, $FF past the last one, read back through $2004
+
+    //Timing
+    pub scanline: i16,
+    pub cycle: i16,
+    pub frame_count: u64,
+    pub frame_complete: bool,
+    pub nmi: bool,
+    region: Region, //Scanlines per frame and start of the vertical blank
+
+    screen: Vec<u32>,
+    screen_indices: Vec<u16>, //The same frame as palette indexes (0 - 63) with the emphasis bits above them
+    scanline_scroll: Vec<ScanlineScroll>, //One per visible scanline, recorded as the scanline starts
+    output_palette: Palette, //RGB colors of the 64 indexes under each emphasis
+
+    cartridge: Option<Rc<RefCell<Cartridge>>>,
+}
+
+impl PPU {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            name_tables: [[0; 1024]; 2],
+            pattern_tables: [[0; 4096]; 2],
+            palette: [0; 32],
+            oam: [0; 256],
+            mirroring: Mirroring::Horizontal,
+
+            control: 0,
+            mask: 0,
+            status: 0,
+            oam_address: 0,
+            vram_address: 0,
+            temp_address: 0,
+            fine_x: 0,
+
+            address_latch: false,
+            data_buffer: 0,
+            io_latch: 0,
+            io_latch_frame: 0,
+            corrupt_oam_rows: 0,
+
+            next_tile: (0, 0, 0),
+            pattern_shift: (0, 0),
+            attribute_shift: (0, 0),
+            line_address: 0,
+
+            backend: PpuBackend::Dot,
+            open_bus_decay: false,
+            oam_corruption: false,
+            ppudata_glitch: false,
+
+            hide_background: false,
+            hide_sprites: false,
+            sprite_limit: SpriteLimit::Hardware,
+            shown_sprites: Vec::with_capacity(64),
+
+            sprite_scanline: [SpriteEntry::EMPTY; SPRITES_PER_SCANLINE],
+            sprite_count: 0,
+            secondary_oam: [0xFF; 32],
+
+            scanline: 0,
+            cycle: 0,
+            frame_count: 0,
+            frame_complete: false,
+            nmi: false,
+            region: Region::Ntsc,
+
+            screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            screen_indices: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            scanline_scroll: vec![ScanlineScroll::default(); SCREEN_HEIGHT],
+            output_palette: Palette::default(),
+
+            cartridge: None,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+
+        //Switching to a shorter frame past its end starts the next one
+        if self.scanline >= region.scanline_count() - 1 {
+            self.scanline = -1;
+        }
+    }
+
+    ///Colors used from the next pixel on
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.output_palette = palette;
+    }
+
+    pub fn get_palette(&self) -> &Palette {
+        return &self.output_palette;
+    }
+
+    pub(crate) fn set_accuracy(&mut self, settings: &AccuracySettings) {
+        self.backend = settings.ppu_backend;
+        self.open_bus_decay = settings.open_bus_decay;
+        self.oam_corruption = settings.oam_corruption;
+        self.ppudata_glitch = settings.ppudata_glitch;
+    }
+
+    ///Leaves a layer out of the screen while it is still fetched and evaluated, so sprite 0 hits and the sprite
+    ///overflow flag (and the game) don't change
+    pub fn set_layer_visible(&mut self, layer: Layer, visible: bool) {
+        match layer {
+            Layer::Background => self.hide_background = !visible,
+            Layer::Sprites => self.hide_sprites = !visible,
+        }
+    }
+
+    pub fn is_layer_visible(&self, layer: Layer) -> bool {
+        match layer {
+            Layer::Background => return !self.hide_background,
+            Layer::Sprites => return !self.hide_sprites,
+        }
+    }
+
+    ///Drawing more than 8 sprites on a scanline (or rotating the 8 drawn) only changes the screen: sprite 0 hits,
+    ///the overflow flag and $2004 reads still follow the 8 sprites the hardware evaluates
+    pub fn set_sprite_limit(&mut self, limit: SpriteLimit) {
+        self.sprite_limit = limit;
+        self.show_evaluated_sprites();
+    }
+
+    pub fn get_sprite_limit(&self) -> SpriteLimit {
+        return self.sprite_limit;
+    }
+
+    ///Clears the registers and timing, the memory contents are kept like on hardware
+    pub fn reset(&mut self) {
+        self.control = 0;
+        self.mask = 0;
+        self.status = 0;
+        self.oam_address = 0;
+        self.vram_address = 0;
+        self.temp_address = 0;
+        self.fine_x = 0;
+
+        self.address_latch = false;
+        self.data_buffer = 0;
+        self.corrupt_oam_rows = 0;
+
+        self.next_tile = (0, 0, 0);
+        self.pattern_shift = (0, 0);
+        self.attribute_shift = (0, 0);
+
+        self.sprite_count = 0;
+        self.secondary_oam = [0xFF; 32];
+        self.shown_sprites.clear();
+
+        self.scanline = 0;
+        self.cycle = 0;
+        self.frame_complete = false;
+        self.nmi = false;
+    }
+
+    ///Power cycle: palette, OAM and nametable RAM start from zeros (see randomize_power_on_state())
+    pub fn clear_memory(&mut self) {
+        for name_table in &mut self.name_tables {
+            name_table.fill(0);
+        }
+
+        self.oam.fill(0);
+        self.palette.fill(0);
+    }
+
+    ///Startup fuzzing: palette, OAM and nametable RAM contents are undefined at power on
+    pub fn randomize_power_on_state(&mut self, rng: &mut Rng) {
+        for name_table in &mut self.name_tables {
+            rng.fill(name_table);
+        }
+
+        rng.fill(&mut self.oam);
+        rng.fill(&mut self.palette);
+
+        //Palette RAM is only 6 bits wide
+        for entry in &mut self.palette {
+            *entry &= 0x3F;
+        }
+    }
+
+    //Save States
+
+    pub fn save_state(&self) -> PpuState {
+        PpuState {
+            name_tables: self.name_tables.concat(),
+            pattern_tables: self.pattern_tables.concat(),
+            palette: self.palette.to_vec(),
+            oam: self.oam.to_vec(),
+
+            control: self.control,
+            mask: self.mask,
+            status: self.status,
+            oam_address: self.oam_address,
+            vram_address: self.vram_address,
+            temp_address: self.temp_address,
+            fine_x: self.fine_x,
+
+            address_latch: self.address_latch,
+            data_buffer: self.data_buffer,
+            io_latch: self.io_latch,
+            io_latch_frame: self.io_latch_frame,
+            corrupt_oam_rows: self.corrupt_oam_rows,
+
+            next_tile: self.next_tile,
+            pattern_shift: self.pattern_shift,
+            attribute_shift: self.attribute_shift,
+            line_address: self.line_address,
+
+            sprite_scanline: self.sprite_scanline[..self.sprite_count].to_vec(),
+            secondary_oam: self.secondary_oam,
+
+            scanline: self.scanline,
+            cycle: self.cycle,
+            frame_count: self.frame_count,
+            nmi: self.nmi,
+        }
+    }
+
+    ///Restores a state saved by save_state(), memories of the wrong size are left untouched
+    pub fn load_state(&mut self, state: &PpuState) {
+        for (table, data) in self.name_tables.iter_mut().zip(state.name_tables.chunks_exact(1024)) {
+            table.copy_from_slice(data);
+        }
+
+        for (table, data) in self.pattern_tables.iter_mut().zip(state.pattern_tables.chunks_exact(4096)) {
+            table.copy_from_slice(data);
+        }
+
+        if state.palette.len() == self.palette.len() {
+            self.palette.copy_from_slice(&state.palette);
+        }
+
+        if state.oam.len() == self.oam.len() {
+            self.oam.copy_from_slice(&state.oam);
+        }
+
+        self.control = state.control;
+        self.mask = state.mask;
+        self.status = state.status;
+        self.oam_address = state.oam_address;
+        self.vram_address = state.vram_address;
+        self.temp_address = state.temp_address;
+        self.fine_x = state.fine_x;
+
+        self.address_latch = state.address_latch;
+        self.data_buffer = state.data_buffer;
+        self.io_latch = state.io_latch;
+        self.io_latch_frame = state.io_latch_frame;
+        self.corrupt_oam_rows = state.corrupt_oam_rows;
+
+        self.next_tile = state.next_tile;
+        self.pattern_shift = state.pattern_shift;
+        self.attribute_shift = state.attribute_shift;
+        self.line_address = state.line_address;
+
+        self.sprite_count = state.sprite_scanline.len().min(SPRITES_PER_SCANLINE);
+        self.sprite_scanline[..self.sprite_count].copy_from_slice(&state.sprite_scanline[..self.sprite_count]);
+        self.secondary_oam = state.secondary_oam;
+        self.show_evaluated_sprites();
+
+        self.scanline = state.scanline;
+        self.cycle = state.cycle;
+        self.frame_count = state.frame_count;
+        self.frame_complete = false;
+        self.nmi = state.nmi;
+    }
+
+    ///Routes pattern table accesses to the cartridge CHR memory and uses its nametable mirroring
+    pub fn connect_cartridge(&mut self, cartridge: Rc<RefCell<Cartridge>>) {
+        self.cartridge = Some(cartridge);
+    }
+
+    ///Lets the cartridge see an address driven on the PPU bus (MMC3 watches A12, MMC2/MMC4 the tile fetches)
+    fn notify_address(&self, address: u16) {
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().ppu_address(address);
+        }
+    }
+
+    pub fn get_mirroring(&self) -> Mirroring {
+        if let Some(cartridge) = &self.cartridge {
+            return cartridge.borrow().get_mirroring();
+        }
+
+        return self.mirroring;
+    }
+
+    ///The last rendered frame as 0x00RRGGBB pixels, row by row
+    pub fn get_screen(&self) -> &[u32] {
+        return &self.screen;
+    }
+
+    ///The last rendered frame as indexes into the 512 color palette (emphasis * 64 + color), row by row
+    pub fn get_screen_indices(&self) -> &[u16] {
+        return &self.screen_indices;
+    }
+
+    ///Scroll of every scanline of the frame, so raster splits (status bars, parallax) can be inspected<br>
+    ///Lines past the one being drawn still hold the previous frame's values
+    pub fn get_scanline_scroll(&self) -> &[ScanlineScroll] {
+        return &self.scanline_scroll;
+    }
+
+    //CPU Interface ($2000 - $2007)
+
+    ///Reads one of the eight PPU registers, only the low 3 bits of the address are used
+    pub fn cpu_read(&mut self, address: u16) -> u8 {
+        self.decay_io_latch();
+
+        let data = match address & 0x0007 {
+            //PPUSTATUS: the low 5 bits are whatever was last left on the I/O latch
+            0x0002 => {
+                let data = (self.status & 0xE0) | (self.io_latch & 0x1F);
+
+                self.status &= !(StatusFlags::VerticalBlank as u8);
+                self.address_latch = false;
+
+                data
+            }
+            0x0004 => self.oam_data(),
+            //PPUDATA: reads below the palettes are delayed by one read through the data buffer
+            0x0007 => {
+                let mut data = self.data_buffer;
+                self.notify_address(self.vram_address);
+                self.data_buffer = self.ppu_read(self.vram_address);
+
+                if self.vram_address >= 0x3F00 {
+                    data = self.data_buffer;
+                }
+
+                self.increment_vram_address();
+
+                data
+            }
+            //PPUCTRL, PPUMASK, OAMADDR, PPUSCROLL and PPUADDR are write only and read back the latch
+            _ => self.io_latch,
+        };
+
+        self.drive_io_latch(data);
+
+        return data;
+    }
+
+    ///What cpu_read() would return, without clearing VBlank, toggling the latch or moving the VRAM address
+    pub fn cpu_peek(&self, address: u16) -> u8 {
+        match address & 0x0007 {
+            0x0002 => {
+                return (self.status & 0xE0) | (self.io_latch & 0x1F);
+            }
+            0x0004 => {
+                return self.oam_data();
+            }
+            0x0007 => {
+                if self.vram_address >= 0x3F00 {
+                    return self.ppu_read(self.vram_address);
+                }
+
+                return self.data_buffer;
+            }
+            _ => {
+                return self.io_latch;
+            }
+        }
+    }
+
+    ///OAMDATA: while the dot renderer draws, reads see what sprite evaluation is working on instead of
+    ///OAM[OAMADDR]: $FF while secondary OAM is cleared (dots 1 - 64), the Y byte of the sprite being checked
+    ///(65 - 256), the secondary OAM bytes being fetched (257 - 320), then the first of them
+    fn oam_data(&self) -> u8 {
+        if self.backend != PpuBackend::Dot || !self.rendering_enabled() || !(-1..240).contains(&self.scanline) {
+            return self.oam[self.oam_address as usize];
+        }
+
+        match self.cycle {
+            1..=64 => return 0xFF,
+            65..=256 => return self.oam[(((self.cycle - 65) >> 1) as usize % 64) * 4],
+            257..=320 => {
+                let offset = (self.cycle - 257) as usize;
+
+                //Y, tile, attribute and X, then X again for the rest of the 8 dots of the fetch
+                return self.secondary_oam[(offset / 8) * 4 + (offset % 8).min(3)];
+            }
+            _ => return self.secondary_oam[0],
+        }
+    }
+
+    ///Writes one of the eight PPU registers, only the low 3 bits of the address are used
+    pub fn cpu_write(&mut self, address: u16, data: u8) {
+        self.drive_io_latch(data);
+
+        match address & 0x0007 {
+            //PPUCTRL: the nametable select bits go to t
+            0x0000 => {
+                self.control = data;
+                let nametable = data & (ControlFlags::NametableX as u8 | ControlFlags::NametableY as u8);
+                self.temp_address = (self.temp_address & !(NAMETABLE_X | NAMETABLE_Y)) | ((nametable as u16) << 10);
+            }
+            0x0001 => {
+                let was_rendering = self.rendering_enabled();
+                self.mask = data;
+
+                if self.oam_corruption && was_rendering && !self.rendering_enabled() {
+                    self.mark_oam_corruption();
+                }
+            }
+            0x0003 => {
+                self.oam_address = data;
+            }
+            0x0004 => {
+                self.oam[self.oam_address as usize] = data;
+                self.oam_address = self.oam_address.wrapping_add(1);
+            }
+            //PPUSCROLL: first write is X (coarse X in t, fine X), second write is Y (coarse and fine Y in t)
+            0x0005 => {
+                let data = data as u16;
+
+                if !self.address_latch {
+                    self.temp_address = (self.temp_address & !COARSE_X) | (data >> 3);
+                    self.fine_x = (data & 0x07) as u8;
+                } else {
+                    self.temp_address = (self.temp_address & !(FINE_Y | COARSE_Y)) | ((data & 0x07) << 12) | ((data & 0xF8) << 2);
+                }
+
+                self.address_latch = !self.address_latch;
+            }
+            //PPUADDR: first write is the high byte (bit 14 is cleared), the second one the low byte and t is copied to v
+            0x0006 => {
+                if !self.address_latch {
+                    self.temp_address = ((data as u16 & 0x3F) << 8) | (self.temp_address & 0x00FF);
+                } else {
+                    self.temp_address = (self.temp_address & 0xFF00) | data as u16;
+                    self.vram_address = self.temp_address;
+                }
+
+                self.address_latch = !self.address_latch;
+            }
+            0x0007 => {
+                self.notify_address(self.vram_address);
+                self.ppu_write(self.vram_address, data);
+                self.increment_vram_address();
+            }
+            _ => {}
+        }
+    }
+
+    fn drive_io_latch(&mut self, data: u8) {
+        self.io_latch = data;
+        self.io_latch_frame = self.frame_count;
+    }
+
+    ///The latch is a capacitor: without open bus decay it keeps its value forever
+    fn decay_io_latch(&mut self) {
+        if self.open_bus_decay && self.frame_count.saturating_sub(self.io_latch_frame) >= IO_LATCH_DECAY_FRAMES {
+            self.io_latch = 0;
+        }
+    }
+
+    ///While rendering, v is the scroll position and a $2007 access clocks both of its counters instead
+    fn increment_vram_address(&mut self) {
+        if self.ppudata_glitch
+            && self.backend == PpuBackend::Dot
+            && self.rendering_enabled()
+            && (-1..240).contains(&self.scanline)
+        {
+            self.vram_address = Self::increment_y(Self::increment_coarse_x(self.vram_address));
+            return;
+        }
+
+        let increment = if (self.control & ControlFlags::IncrementMode as u8) != 0 { 32 } else { 1 };
+
+        self.vram_address = self.vram_address.wrapping_add(increment) & 0x7FFF;
+    }
+
+    //PPU Bus ($0000 - $3FFF)
+
+    pub fn ppu_read(&self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+
+        if let Some(cartridge) = &self.cartridge {
+            if let Some(data) = cartridge.borrow().ppu_read(address) {
+                return data;
+            }
+        }
+
+        match address {
+            //Pattern tables
+            0x0000..=0x1FFF => {
+                return self.pattern_tables[((address & 0x1000) >> 12) as usize][(address & 0x0FFF) as usize];
+            }
+            //Nametables
+            0x2000..=0x3EFF => {
+                let (table, offset) = self.name_table_index(address);
+                return self.name_tables[table][offset];
+            }
+            //Palettes
+            _ => {
+                let mut data = self.palette[Self::palette_index(address)];
+
+                if (self.mask & MaskFlags::Grayscale as u8) != 0 {
+                    data &= 0x30;
+                }
+
+                return data;
+            }
+        }
+    }
+
+    pub fn ppu_write(&mut self, address: u16, data: u8) {
+        let address = address & 0x3FFF;
+
+        if let Some(cartridge) = &self.cartridge {
+            if cartridge.borrow_mut().ppu_write(address, data) {
+                return;
+            }
+        }
+
+        match address {
+            0x0000..=0x1FFF => {
+                self.pattern_tables[((address & 0x1000) >> 12) as usize][(address & 0x0FFF) as usize] = data;
+            }
+            0x2000..=0x3EFF => {
+                let (table, offset) = self.name_table_index(address);
+                self.name_tables[table][offset] = data;
+            }
+            _ => {
+                self.palette[Self::palette_index(address)] = data;
+            }
+        }
+    }
+
+    ///Maps a $2000 - $3EFF address to one of the two physical nametables following the mirroring
+    fn name_table_index(&self, address: u16) -> (usize, usize) {
+        let address = address & 0x0FFF;
+        let offset = (address & 0x03FF) as usize;
+
+        //0x000 -> A, 0x400 -> B, 0x800 -> C, 0xC00 -> D
+        let table = match self.get_mirroring() {
+            Mirroring::Vertical => (address >> 10) & 0x01,
+            Mirroring::Horizontal => (address >> 11) & 0x01,
+            Mirroring::OneScreenLow => 0,
+            Mirroring::OneScreenHigh => 1,
+        };
+
+        return (table as usize, offset);
+    }
+
+    ///$3F10/$3F14/$3F18/$3F1C mirror the background entries $3F00/$3F04/$3F08/$3F0C
+    fn palette_index(address: u16) -> usize {
+        let mut index = address & 0x001F;
+
+        if index & 0x0013 == 0x0010 {
+            index &= !0x0010;
+        }
+
+        return index as usize;
+    }
+
+    //Rendering
+
+    ///Current background pixel out of the shift registers as a (palette, pixel) pair, pixel 0 is transparent
+    fn background_pixel(&self) -> (u8, u8) {
+        let bit = 15 - self.fine_x;
+
+        let pixel = (((self.pattern_shift.1 >> bit) & 0x01) << 1) | ((self.pattern_shift.0 >> bit) & 0x01);
+        let palette = (((self.attribute_shift.1 >> bit) & 0x01) << 1) | ((self.attribute_shift.0 >> bit) & 0x01);
+
+        return (palette as u8, pixel as u8);
+    }
+
+    ///Palette and pattern row (low plane, high plane) of the background tile a VRAM address points at
+    fn background_tile(&self, address: u16) -> (u8, u8, u8) {
+        let tile_id = self.ppu_read(0x2000 | (address & 0x0FFF)) as u16;
+
+        //Every attribute byte covers a 4x4 tile area split into four 2x2 quadrants
+        let attribute = self.ppu_read(
+            0x23C0 | (address & (NAMETABLE_X | NAMETABLE_Y)) | ((address >> 4) & 0x38) | ((address >> 2) & 0x07),
+        );
+        let shift = ((address >> 4) & 0x04) | (address & 0x02);
+        let palette = (attribute >> shift) & 0x03;
+
+        let pattern_base: u16 = if (self.control & ControlFlags::BackgroundPattern as u8) != 0 {
+            0x1000
+        } else {
+            0x0000
+        };
+
+        let fine_y = (address & FINE_Y) >> 12;
+        let low_plane = self.ppu_read(pattern_base + tile_id * 16 + fine_y);
+        let high_plane = self.ppu_read(pattern_base + tile_id * 16 + fine_y + 8);
+
+        //MMC2/MMC4 switch CHR banks after fetching tiles $FD and $FE
+        self.notify_address(pattern_base + tile_id * 16 + fine_y + 8);
+
+        return (palette, low_plane, high_plane);
+    }
+
+    ///Puts the fetched tile into the low 8 bits of the shift registers, which the last 8 shifts emptied
+    fn load_background_shifters(&mut self) {
+        let (palette, low_plane, high_plane) = self.next_tile;
+        let fill = |bit: u8| if (palette & bit) != 0 { 0x00FF } else { 0x0000 };
+
+        self.pattern_shift = (
+            (self.pattern_shift.0 & 0xFF00) | low_plane as u16,
+            (self.pattern_shift.1 & 0xFF00) | high_plane as u16,
+        );
+        self.attribute_shift = (
+            (self.attribute_shift.0 & 0xFF00) | fill(0x01),
+            (self.attribute_shift.1 & 0xFF00) | fill(0x02),
+        );
+    }
+
+    fn shift_background(&mut self) {
+        self.pattern_shift = (self.pattern_shift.0 << 1, self.pattern_shift.1 << 1);
+        self.attribute_shift = (self.attribute_shift.0 << 1, self.attribute_shift.1 << 1);
+    }
+
+    ///Next tile to the right, wrapping into the horizontally adjacent nametable
+    fn increment_coarse_x(address: u16) -> u16 {
+        if (address & COARSE_X) == 31 {
+            return (address & !COARSE_X) ^ NAMETABLE_X;
+        }
+
+        return address + 1;
+    }
+
+    ///Next pixel row: fine Y, then coarse Y which wraps into the vertically adjacent nametable after row 29
+    ///(rows 30 and 31 are the attribute table, reached by writing them, wrap to 0 without switching)
+    fn increment_y(address: u16) -> u16 {
+        if (address & FINE_Y) != FINE_Y {
+            return address + 0x1000;
+        }
+
+        let address = address & !FINE_Y;
+
+        match (address & COARSE_Y) >> 5 {
+            29 => (address & !COARSE_Y) ^ NAMETABLE_Y,
+            31 => address & !COARSE_Y,
+            _ => address + 0x0020,
+        }
+    }
+
+    ///Moves v along while rendering: coarse X after every tile fetch, Y at the end of the visible dots,
+    ///then the horizontal bits (dot 257) and, on the pre-render line, the vertical bits (dots 280 - 304) of t
+    fn update_scroll(&mut self) {
+        let cycle = self.cycle;
+
+        if ((1..=256).contains(&cycle) || (321..=336).contains(&cycle)) && cycle % 8 == 0 {
+            self.vram_address = Self::increment_coarse_x(self.vram_address);
+        }
+
+        if cycle == 256 {
+            self.vram_address = Self::increment_y(self.vram_address);
+        }
+
+        if cycle == 257 {
+            let horizontal = COARSE_X | NAMETABLE_X;
+            self.vram_address = (self.vram_address & !horizontal) | (self.temp_address & horizontal);
+        }
+
+        if self.scanline == -1 && (280..=304).contains(&cycle) {
+            let vertical = FINE_Y | NAMETABLE_Y | COARSE_Y;
+            self.vram_address = (self.vram_address & !vertical) | (self.temp_address & vertical);
+        }
+
+        if cycle == 321 {
+            self.line_address = self.vram_address;
+        }
+    }
+
+    ///Background fetches for the dot backend: one tile every 8 dots, the first two of a scanline at the end of
+    ///the previous one (dots 321 - 336), loaded into the shift registers when the next tile fetch starts
+    fn fetch_background(&mut self) {
+        let cycle = self.cycle;
+
+        if (2..=257).contains(&cycle) || (322..=337).contains(&cycle) {
+            self.shift_background();
+
+            if (cycle - 1) % 8 == 0 {
+                self.load_background_shifters();
+            }
+        }
+
+        if ((1..=256).contains(&cycle) || (321..=336).contains(&cycle)) && (cycle - 1) % 8 == 0 {
+            self.next_tile = self.background_tile(self.vram_address);
+        }
+    }
+
+    ///Draws a whole scanline for the scanline backend, with one fetch per tile from where v stood when the
+    ///line's fetches started
+    fn render_scanline(&mut self, y: usize) {
+        let mut address = self.line_address;
+        let fine_x = self.fine_x as usize;
+
+        //Fine X shifts the line left by up to 7 pixels, so 33 tiles can be partly visible
+        for tile_index in 0..33usize {
+            let tile = self.background_tile(address);
+            address = Self::increment_coarse_x(address);
+
+            for column in 0..8 {
+                let x = (tile_index * 8 + column).wrapping_sub(fine_x);
+
+                if x < SCREEN_WIDTH {
+                    self.render_pixel(x, y, |_| Self::tile_pixel(tile, column));
+                }
+            }
+        }
+    }
+
+    ///(palette, pixel) of a column (0 - 7) of a tile fetched by background_tile()
+    fn tile_pixel(tile: (u8, u8, u8), fine_x: usize) -> (u8, u8) {
+        let (palette, low_plane, high_plane) = tile;
+
+        let bit = 7 - fine_x;
+        let pixel = (((high_plane >> bit) & 0x01) << 1) | ((low_plane >> bit) & 0x01);
+
+        return (palette, pixel);
+    }
+
+    ///Height of every sprite, 8x8 or 8x16 following PPUCTRL
+    fn sprite_height(&self) -> i16 {
+        if (self.control & ControlFlags::SpriteSize as u8) != 0 {
+            return 16;
+        } else {
+            return 8;
+        }
+    }
+
+    ///Secondary OAM evaluation: picks the first 8 sprites (in OAM order) covering the next scanline and
+    ///fetches their pattern row, a 9th sprite sets the overflow flag<br>
+    ///Past the limit the Flicker and Unlimited sprite limits keep going and fetch the rest of the sprites for the
+    ///screen only
+    fn evaluate_sprites(&mut self) {
+        self.sprite_count = 0;
+        self.secondary_oam = [0xFF; 32];
+        self.shown_sprites.clear();
+
+        let height = self.sprite_height();
+
+        for index in 0..64 {
+            let sprite = &self.oam[index * 4..index * 4 + 4];
+
+            //OAM Y is one less than the first scanline the sprite appears on
+            let row = self.scanline - sprite[0] as i16;
+
+            if !(0..height).contains(&row) {
+                continue;
+            }
+
+            if self.sprite_count == SPRITES_PER_SCANLINE {
+                self.status |= StatusFlags::SpriteOverflow as u8;
+
+                if self.sprite_limit == SpriteLimit::Hardware {
+                    break;
+                }
+
+                let entry = self.sprite_entry(index, row, height, false);
+                self.shown_sprites.push((index, entry));
+                continue;
+            }
+
+            self.secondary_oam[self.sprite_count * 4..self.sprite_count * 4 + 4].copy_from_slice(sprite);
+
+            let entry = self.sprite_entry(index, row, height, true);
+            self.sprite_scanline[self.sprite_count] = entry;
+            self.sprite_count += 1;
+
+            if self.sprite_limit != SpriteLimit::Hardware {
+                self.shown_sprites.push((index, entry));
+            }
+        }
+
+        //Every frame the 8 drawn sprites start one further into the line's sprites, wrapping around, and are then
+        //drawn in OAM order so the priorities stay the same
+        if self.sprite_limit == SpriteLimit::Flicker && self.shown_sprites.len() > SPRITES_PER_SCANLINE {
+            let start = (self.frame_count % self.shown_sprites.len() as u64) as usize;
+
+            self.shown_sprites.rotate_left(start);
+            self.shown_sprites.truncate(SPRITES_PER_SCANLINE);
+            self.shown_sprites.sort_by_key(|&(index, _)| index);
+        }
+    }
+
+    ///Pattern row `row` of the sprite at the OAM index, flipped the way it is drawn<br>
+    ///`fetched` is false for the sprites past the hardware limit, which the cartridge never sees
+    fn sprite_entry(&self, index: usize, row: i16, height: i16, fetched: bool) -> SpriteEntry {
+        let (tile_id, attribute, x) = (self.oam[index * 4 + 1], self.oam[index * 4 + 2], self.oam[index * 4 + 3]);
+
+        let row = if (attribute & SpriteFlags::FlipVertical as u8) != 0 {
+            height - 1 - row
+        } else {
+            row
+        } as u16;
+
+        let pattern_address = if height == 16 {
+            //8x16 sprites take the table from bit 0 of the tile id, the bottom half is the next tile
+            let table = (tile_id as u16 & 0x01) << 12;
+            let tile = (tile_id as u16 & 0xFE) + (row >> 3);
+
+            table + tile * 16 + (row & 0x07)
+        } else {
+            let table: u16 = if (self.control & ControlFlags::SpritePattern as u8) != 0 {
+                0x1000
+            } else {
+                0x0000
+            };
+
+            table + tile_id as u16 * 16 + row
+        };
+
+        let mut pattern_low = self.ppu_read(pattern_address);
+        let mut pattern_high = self.ppu_read(pattern_address + 8);
+
+        if fetched {
+            self.notify_address(pattern_address + 8);
+        }
+
+        if (attribute & SpriteFlags::FlipHorizontal as u8) != 0 {
+            pattern_low = pattern_low.reverse_bits();
+            pattern_high = pattern_high.reverse_bits();
+        }
+
+        return SpriteEntry {
+            x,
+            attribute,
+            pattern_low,
+            pattern_high,
+            sprite_zero: index == 0,
+        };
+    }
+
+    ///Draws the evaluated sprites until the next evaluation, after a load or a sprite limit change
+    fn show_evaluated_sprites(&mut self) {
+        self.shown_sprites.clear();
+
+        if self.sprite_limit != SpriteLimit::Hardware {
+            //The OAM index only orders the Flicker rotation, which is redone on the next scanline
+            self.shown_sprites.extend(self.sprite_scanline[..self.sprite_count].iter().copied().enumerate());
+        }
+    }
+
+    ///First opaque sprite pixel at the x position as (palette, pixel, behind background, sprite zero)<br>
+    ///Lower OAM indexes win over higher ones before the background is looked at, pixel 0 means no sprite is
+    ///visible there
+    fn sprite_pixel<'a>(sprites: impl IntoIterator<Item = &'a SpriteEntry>, x: usize) -> (u8, u8, bool, bool) {
+        for sprite in sprites {
+            let offset = x as i16 - sprite.x as i16;
+
+            if !(0..8).contains(&offset) {
+                continue;
+            }
+
+            let bit = 7 - offset;
+            let pixel = (((sprite.pattern_high >> bit) & 0x01) << 1) | ((sprite.pattern_low >> bit) & 0x01);
+
+            if pixel != 0 {
+                let palette = 4 + (sprite.attribute & SpriteFlags::Palette as u8);
+                let behind = (sprite.attribute & SpriteFlags::Priority as u8) != 0;
+
+                return (palette, pixel, behind, sprite.sprite_zero);
+            }
+        }
+
+        return (0, 0, false, false);
+    }
+
+    ///Priority multiplexer: picks the (palette, pixel) drawn from the background pixel and the sprite pixel<br>
+    ///The sprite is the first opaque one in OAM order and only its priority bit counts, so a behind-background
+    ///sprite hides the front sprites after it wherever the background is opaque (the mask trick used by
+    ///Super Mario Bros. 3 for items coming out of blocks)<br>
+    ///Transparent pixels show the universal background color
+    fn multiplex(background: (u8, u8), sprite: (u8, u8, bool)) -> (u8, u8) {
+        let (background_palette, background_pixel) = background;
+        let (sprite_palette, sprite_pixel, behind) = sprite;
+
+        match (background_pixel, sprite_pixel) {
+            (0, 0) => (0, 0),
+            (0, _) => (sprite_palette, sprite_pixel),
+            (_, 0) => (background_palette, background_pixel),
+            _ if behind => (background_palette, background_pixel),
+            _ => (sprite_palette, sprite_pixel),
+        }
+    }
+
+    ///Draws one pixel of the screen, the background (palette, pixel) is only looked up when it is shown
+    fn render_pixel(&mut self, x: usize, y: usize, background: impl FnOnce(&Self) -> (u8, u8)) {
+        let show_background = (self.mask & MaskFlags::ShowBackground as u8) != 0
+            && (x >= 8 || (self.mask & MaskFlags::ShowBackgroundLeft as u8) != 0);
+
+        let show_sprites = (self.mask & MaskFlags::ShowSprites as u8) != 0
+            && (x >= 8 || (self.mask & MaskFlags::ShowSpritesLeft as u8) != 0);
+
+        let (background_palette, background_pixel) = if show_background { background(self) } else { (0, 0) };
+
+        let (sprite_palette, sprite_pixel, behind, sprite_zero) = if show_sprites {
+            Self::sprite_pixel(&self.sprite_scanline[..self.sprite_count], x)
+        } else {
+            (0, 0, false, false)
+        };
+
+        //Sprite 0 hit: an opaque sprite 0 pixel over an opaque background pixel (never at x = 255)
+        if sprite_zero && background_pixel != 0 && sprite_pixel != 0 && x != 255 {
+            self.status |= StatusFlags::SpriteZeroHit as u8;
+        }
+
+        //Past the limit the sprites drawn aren't the ones the hardware evaluated
+        let (sprite_palette, sprite_pixel, behind) = if show_sprites && self.sprite_limit != SpriteLimit::Hardware {
+            let (palette, pixel, behind, _) = Self::sprite_pixel(self.shown_sprites.iter().map(|(_, sprite)| sprite), x);
+            (palette, pixel, behind)
+        } else {
+            (sprite_palette, sprite_pixel, behind)
+        };
+
+        let background_pixel = if self.hide_background { 0 } else { background_pixel };
+        let sprite_pixel = if self.hide_sprites { 0 } else { sprite_pixel };
+
+        let (palette, pixel) = Self::multiplex(
+            (background_palette, background_pixel),
+            (sprite_palette, sprite_pixel, behind),
+        );
+
+        let index = self.get_color_index(palette, pixel);
+        let emphasis = self.region.emphasis(self.mask);
+        self.screen_indices[y * SCREEN_WIDTH + x] = ((emphasis as u16) << 6) | index as u16;
+        self.screen[y * SCREEN_WIDTH + x] = self.output_palette.color(index, emphasis);
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        return (self.mask & (MaskFlags::ShowBackground as u8 | MaskFlags::ShowSprites as u8)) != 0;
+    }
+
+    ///Turning rendering off while the PPU walks OAM leaves the row (8 bytes) it was at to be overwritten with
+    ///row 0 when rendering starts again, which row depends on the dot: secondary OAM clear (1 - 64),
+    ///sprite evaluation (65 - 256, 2 dots per sprite checked) or sprite fetches (257 - 320)
+    fn mark_oam_corruption(&mut self) {
+        if !(-1..240).contains(&self.scanline) {
+            return;
+        }
+
+        let row = match self.cycle {
+            1..=64 => (self.cycle - 1) >> 1,
+            65..=256 => ((self.cycle - 65) >> 2).min(31),
+            257..=320 => (self.cycle - 257) >> 3,
+            _ => return,
+        };
+
+        self.corrupt_oam_rows |= 1 << row;
+    }
+
+    fn apply_oam_corruption(&mut self) {
+        for row in 1..32 {
+            if (self.corrupt_oam_rows & (1 << row)) != 0 {
+                self.oam.copy_within(0..8, row * 8);
+            }
+        }
+
+        self.corrupt_oam_rows = 0;
+    }
+
+    ///Color (0 - 63) a palette entry holds
+    fn get_color_index(&self, palette: u8, pixel: u8) -> u8 {
+        return self.ppu_read(0x3F00 + ((palette as u16) << 2) + pixel as u16) & 0x3F;
+    }
+
+    ///Advances the PPU by one dot (341 dots per scanline, 262 scanlines per frame on NTSC, 312 on PAL and Dendy)<br>
+    ///Scanline -1 is the pre-render line, 0-239 are visible and 241 (291 on Dendy) starts the vertical blank
+    pub fn clock(&mut self) {
+        if self.scanline == -1 && self.cycle == 1 {
+            self.status &= !(StatusFlags::VerticalBlank as u8
+                | StatusFlags::SpriteZeroHit as u8
+                | StatusFlags::SpriteOverflow as u8);
+        }
+
+        if self.scanline == self.region.vblank_scanline() && self.cycle == 1 {
+            self.status |= StatusFlags::VerticalBlank as u8;
+
+            if (self.control & ControlFlags::EnableNmi as u8) != 0 {
+                self.nmi = true;
+            }
+        }
+
+        if (-1..240).contains(&self.scanline) && self.cycle == 1 && self.corrupt_oam_rows != 0 && self.rendering_enabled() {
+            self.apply_oam_corruption();
+        }
+
+        let rendering_line = (-1..240).contains(&self.scanline) && self.rendering_enabled();
+
+        //While rendering the line is drawn from where v stood at dot 321 of the previous one
+        if (0..240).contains(&self.scanline) && self.cycle == 0 {
+            let v = if self.rendering_enabled() { self.line_address } else { self.vram_address };
+
+            self.scanline_scroll[self.scanline as usize] = ScanlineScroll { v, fine_x: self.fine_x };
+        }
+
+        if rendering_line && self.backend == PpuBackend::Dot {
+            self.fetch_background();
+        }
+
+        if (0..240).contains(&self.scanline) {
+            let y = self.scanline as usize;
+
+            match self.backend {
+                PpuBackend::Dot if (1..=256).contains(&self.cycle) => {
+                    self.render_pixel((self.cycle - 1) as usize, y, |ppu| ppu.background_pixel());
+                }
+                PpuBackend::Scanline if self.cycle == 256 => {
+                    self.render_scanline(y);
+                }
+                _ => {}
+            }
+        }
+
+        //After the pixel: the last tile fetch of the line still used the old v
+        if rendering_line {
+            self.update_scroll();
+        }
+
+        //Sprites for the next scanline are picked once the current one is drawn
+        if (-1..239).contains(&self.scanline) && self.cycle == 257 {
+            if self.rendering_enabled() {
+                self.evaluate_sprites();
+            } else {
+                self.sprite_count = 0;
+            }
+        }
+
+        //The pattern table the fetches switch to: sprites from 257, the next scanline's background from 321
+        //8x16 sprites fetch from $1000 for the unused slots (tile $FF)
+        if (-1..240).contains(&self.scanline) && (self.cycle == 257 || self.cycle == 321) && self.rendering_enabled() {
+            let high_table = if self.cycle == 321 {
+                (self.control & ControlFlags::BackgroundPattern as u8) != 0
+            } else {
+                self.sprite_height() == 16 || (self.control & ControlFlags::SpritePattern as u8) != 0
+            };
+
+            self.notify_address(if high_table { 0x1000 } else { 0x0000 });
+        }
+
+        self.cycle += 1;
+
+        if self.cycle >= 341 {
+            self.cycle = 0;
+            self.scanline += 1;
+
+            if self.scanline >= self.region.scanline_count() - 1 {
+                self.scanline = -1;
+                self.frame_count += 1;
+                self.frame_complete = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::PALETTE_2C02;
+
+    const BACKDROP: u8 = 0x0F;
+    const BACKGROUND: u8 = 0x01;
+    const BEHIND_SPRITE: u8 = 0x16;
+    const FRONT_SPRITE: u8 = 0x2A;
+
+    ///Scanline 16: opaque background tiles at x 16 - 31 and 48 - 55, sprites at x 16, 32 and 48
+    ///- OAM 0 (behind) and OAM 1 (front) both at x 16 and x 32, OAM 0 first
+    ///- OAM 4 (front) alone at x 48
+    fn render(oam: &[(u8, u8)]) -> PPU {
+        return render_with(PpuBackend::Dot, (0, 0), oam);
+    }
+
+    fn render_with(backend: PpuBackend, scroll: (u8, u8), oam: &[(u8, u8)]) -> PPU {
+        let mut ppu = PPU::new();
+        ppu.backend = backend;
+
+        //Tile 1: solid pixel 1, tile 2: solid pixel 2
+        for row in 0..8 {
+            ppu.ppu_write(16 + row, 0xFF);
+            ppu.ppu_write(32 + 8 + row, 0xFF);
+        }
+
+        for column in [2, 3, 6] {
+            ppu.ppu_write(0x2000 + 2 * 32 + column, 0x01);
+        }
+
+        ppu.ppu_write(0x3F00, BACKDROP);
+        ppu.ppu_write(0x3F01, BACKGROUND);
+        ppu.ppu_write(0x3F12, BEHIND_SPRITE);
+        ppu.ppu_write(0x3F16, FRONT_SPRITE);
+
+        for (index, &(x, attribute)) in oam.iter().enumerate() {
+            ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[15, 0x02, attribute, x]);
+        }
+
+        ppu.mask = MaskFlags::ShowBackground as u8
+            | MaskFlags::ShowSprites as u8
+            | MaskFlags::ShowBackgroundLeft as u8
+            | MaskFlags::ShowSpritesLeft as u8;
+
+        ppu.cpu_write(0x2005, scroll.0);
+        ppu.cpu_write(0x2005, scroll.1);
+
+        //The first frame starts at scanline 0 without the pre-render line's fetches and scroll copy
+        for _ in 0..2 {
+            ppu.frame_complete = false;
+
+            while !ppu.frame_complete {
+                ppu.clock();
+            }
+        }
+
+        return ppu;
+    }
+
+    fn pixel(ppu: &PPU, x: usize) -> u32 {
+        return pixel_at(ppu, x, 16);
+    }
+
+    fn pixel_at(ppu: &PPU, x: usize, y: usize) -> u32 {
+        return ppu.get_screen()[y * SCREEN_WIDTH + x];
+    }
+
+    const BEHIND: u8 = SpriteFlags::Priority as u8;
+    const FRONT: u8 = 0x01;
+
+    #[test]
+    fn behind_sprite_masks_later_front_sprites() {
+        let ppu = render(&[(16, BEHIND), (16, FRONT), (32, BEHIND), (32, FRONT), (48, FRONT)]);
+
+        //Over the background OAM 0 wins the sprite side and then loses to the background
+        assert_eq!(pixel(&ppu, 16), PALETTE_2C02[BACKGROUND as usize]);
+        assert_eq!(pixel(&ppu, 23), PALETTE_2C02[BACKGROUND as usize]);
+
+        //Over a transparent background the behind sprite is still drawn
+        assert_eq!(pixel(&ppu, 32), PALETTE_2C02[BEHIND_SPRITE as usize]);
+
+        //Without a sprite in front of it the front sprite covers the background
+        assert_eq!(pixel(&ppu, 48), PALETTE_2C02[FRONT_SPRITE as usize]);
+        assert_eq!(pixel(&ppu, 56), PALETTE_2C02[BACKDROP as usize]);
+    }
+
+    #[test]
+    fn front_sprite_first_in_oam_covers_the_background() {
+        let ppu = render(&[(16, FRONT), (16, BEHIND)]);
+
+        assert_eq!(pixel(&ppu, 16), PALETTE_2C02[FRONT_SPRITE as usize]);
+        assert_eq!(pixel(&ppu, 24), PALETTE_2C02[BACKGROUND as usize]);
+    }
+
+    #[test]
+    fn mid_frame_split_shows_in_the_scanline_scroll() {
+        let mut ppu = PPU::new();
+        ppu.mask = MaskFlags::ShowBackground as u8;
+
+        //Scroll (0, 0), then the $2006/$2005/$2005/$2006 split in the horizontal blank of scanline 100
+        while !ppu.frame_complete {
+            ppu.clock();
+        }
+        while ppu.scanline != 100 || ppu.cycle != 260 {
+            ppu.clock();
+        }
+
+        //The writes share the toggle: nametable 1, Y = 35, X = 43, then coarse Y and X again to load v
+        ppu.cpu_write(0x2006, 0x04);
+        ppu.cpu_write(0x2005, 35);
+        ppu.cpu_write(0x2005, 43);
+        ppu.cpu_write(0x2006, ((35 & 0xF8) << 2) | (43 >> 3));
+
+        while ppu.scanline != 240 {
+            ppu.clock();
+        }
+
+        let scroll = ppu.get_scanline_scroll();
+        for y in [0, 50, 100] {
+            assert_eq!((scroll[y].x(), scroll[y].y()), (0, y as u16));
+        }
+
+        assert_eq!(scroll[101], ScanlineScroll { v: 0x3485, fine_x: 3 });
+        assert_eq!((scroll[101].x(), scroll[101].y()), (256 + 43, 35));
+        assert_eq!((scroll[102].x(), scroll[102].y()), (256 + 43, 36));
+    }
+
+    #[test]
+    fn hidden_layers_are_still_emulated() {
+        let mut ppu = render(&[(16, FRONT), (32, FRONT)]);
+
+        ppu.set_layer_visible(Layer::Sprites, false);
+        ppu.frame_complete = false;
+        while !ppu.frame_complete {
+            ppu.clock();
+        }
+
+        //Sprite 0 still hits the background it is no longer drawn over
+        assert_eq!(pixel(&ppu, 16), PALETTE_2C02[BACKGROUND as usize]);
+        assert_eq!(pixel(&ppu, 32), PALETTE_2C02[BACKDROP as usize]);
+        assert_ne!(ppu.status & StatusFlags::SpriteZeroHit as u8, 0);
+
+        ppu.set_layer_visible(Layer::Sprites, true);
+        ppu.set_layer_visible(Layer::Background, false);
+        ppu.frame_complete = false;
+        while !ppu.frame_complete {
+            ppu.clock();
+        }
+
+        assert_eq!(pixel(&ppu, 24), PALETTE_2C02[BACKDROP as usize]);
+        assert_eq!(pixel(&ppu, 32), PALETTE_2C02[FRONT_SPRITE as usize]);
+        assert!(!ppu.is_layer_visible(Layer::Background));
+    }
+
+    #[test]
+    fn sprite_limits_only_change_the_screen() {
+        //10 front sprites on one line, sprite 0 over the background
+        let oam: Vec<(u8, u8)> = (0..10).map(|sprite| (16 * (sprite + 1), FRONT)).collect();
+        let mut ppu = render(&oam);
+
+        let drawn = |ppu: &PPU| -> Vec<bool> {
+            return (0..10).map(|sprite| pixel(ppu, 16 * (sprite + 1)) == PALETTE_2C02[FRONT_SPRITE as usize]).collect();
+        };
+
+        let next_frame = |ppu: &mut PPU| {
+            ppu.status = 0;
+            ppu.frame_complete = false;
+            while !ppu.frame_complete {
+                ppu.clock();
+            }
+        };
+
+        assert_eq!(drawn(&ppu), [true, true, true, true, true, true, true, true, false, false]);
+
+        ppu.set_sprite_limit(SpriteLimit::Unlimited);
+        next_frame(&mut ppu);
+        assert_eq!(drawn(&ppu), [true; 10]);
+
+        //Each sprite is left out in turn, the hardware's 8 sprites still make the hit and the overflow
+        ppu.set_sprite_limit(SpriteLimit::Flicker);
+        let mut frames = Vec::new();
+
+        for _ in 0..10 {
+            next_frame(&mut ppu);
+            assert_ne!(ppu.status & StatusFlags::SpriteZeroHit as u8, 0);
+            assert_ne!(ppu.status & StatusFlags::SpriteOverflow as u8, 0);
+
+            frames.push(drawn(&ppu));
+        }
+
+        assert!(frames.iter().all(|frame| frame.iter().filter(|&&shown| shown).count() == SPRITES_PER_SCANLINE));
+        assert!((0..10).all(|sprite| frames.iter().any(|frame| !frame[sprite])));
+        assert_ne!(frames[0], frames[1]);
+    }
+
+    #[test]
+    fn scanline_backend_draws_the_same_frame() {
+        let oam = [(16, BEHIND), (16, FRONT), (32, BEHIND), (32, FRONT), (48, FRONT)];
+
+        for scroll in [(0, 0), (13, 9)] {
+            let dot = render_with(PpuBackend::Dot, scroll, &oam);
+            let scanline = render_with(PpuBackend::Scanline, scroll, &oam);
+
+            assert!(dot.get_screen() == scanline.get_screen(), "scroll {:?}", scroll);
+        }
+    }
+
+    #[test]
+    fn scroll_moves_the_background() {
+        //The tiles at x 16 - 31, y 16 - 23 move to x 3 - 18, y 7 - 14
+        let ppu = render_with(PpuBackend::Dot, (13, 9), &[]);
+        let background = PALETTE_2C02[BACKGROUND as usize];
+        let backdrop = PALETTE_2C02[BACKDROP as usize];
+
+        assert_eq!(pixel_at(&ppu, 3, 7), background);
+        assert_eq!(pixel_at(&ppu, 18, 14), background);
+        assert_eq!(pixel_at(&ppu, 2, 7), backdrop);
+        assert_eq!(pixel_at(&ppu, 19, 14), backdrop);
+        assert_eq!(pixel_at(&ppu, 3, 6), backdrop);
+        assert_eq!(pixel_at(&ppu, 3, 15), backdrop);
+    }
+
+    #[test]
+    fn scroll_and_address_writes_share_t() {
+        let mut ppu = PPU::new();
+
+        ppu.cpu_write(0x2000, 0x03);
+        ppu.cpu_write(0x2005, 0x7D);
+        ppu.cpu_write(0x2005, 0x5E);
+        assert_eq!(ppu.temp_address, 0x6D6F);
+        assert_eq!(ppu.fine_x, 5);
+
+        //The first $2006 write replaces the high byte (and clears bit 14), the second one also sets v
+        ppu.cpu_write(0x2006, 0x3D);
+        assert_eq!(ppu.temp_address, 0x3D6F);
+        assert_eq!(ppu.vram_address, 0x0000);
+        ppu.cpu_write(0x2006, 0xF0);
+        assert_eq!(ppu.vram_address, 0x3DF0);
+
+        //Reading $2002 resets the toggle, so the next $2005 write is X again
+        ppu.cpu_write(0x2005, 0x00);
+        ppu.cpu_read(0x2002);
+        ppu.cpu_write(0x2005, 0x0A);
+        assert_eq!(ppu.temp_address & COARSE_X, 0x01);
+        assert_eq!(ppu.fine_x, 2);
+    }
+
+    #[test]
+    fn scroll_increments_wrap_into_the_next_nametable() {
+        assert_eq!(PPU::increment_coarse_x(0x001F), NAMETABLE_X);
+        assert_eq!(PPU::increment_coarse_x(0x041F), 0x0000);
+
+        //Coarse Y 29 wraps into the next nametable, 31 (inside the attribute table) only wraps
+        assert_eq!(PPU::increment_y(0x73A0), NAMETABLE_Y);
+        assert_eq!(PPU::increment_y(0x73E0), 0x0000);
+        assert_eq!(PPU::increment_y(0x0000), 0x1000);
+    }
+
+    #[test]
+    fn io_latch_only_fades_with_open_bus_decay() {
+        for decay in [false, true] {
+            let mut ppu = PPU::new();
+            ppu.open_bus_decay = decay;
+
+            ppu.cpu_write(0x2003, 0x5A);
+            assert_eq!(ppu.cpu_read(0x2005), 0x5A);
+            assert_eq!(ppu.cpu_read(0x2002) & 0x1F, 0x1A);
+
+            ppu.frame_count += IO_LATCH_DECAY_FRAMES;
+            let expected = if decay { 0x00 } else { 0x1A };
+            assert_eq!(ppu.cpu_read(0x2000), expected, "decay {}", decay);
+        }
+    }
+
+    #[test]
+    fn oamdata_reads_follow_sprite_evaluation() {
+        let mut ppu = PPU::new();
+
+        //Only sprite 2 covers scanline 10
+        for (index, byte) in ppu.oam.iter_mut().enumerate() {
+            *byte = if index % 4 == 0 { 0xF0 } else { index as u8 };
+        }
+        ppu.oam[8] = 5;
+        ppu.oam_address = 0x21;
+
+        //Not rendering: OAM[OAMADDR]
+        ppu.scanline = 10;
+        ppu.cycle = 30;
+        assert_eq!(ppu.cpu_read(0x2004), 0x21);
+
+        ppu.mask = MaskFlags::ShowSprites as u8;
+        assert_eq!(ppu.cpu_read(0x2004), 0xFF);
+
+        ppu.cycle = 70;
+        assert_eq!(ppu.cpu_read(0x2004), 5);
+
+        ppu.cycle = 257;
+        ppu.evaluate_sprites();
+        assert_eq!(ppu.cpu_read(0x2004), 5);
+
+        ppu.cycle = 259;
+        assert_eq!(ppu.cpu_peek(0x2004), 10);
+
+        ppu.cycle = 264;
+        assert_eq!(ppu.cpu_read(0x2004), 11);
+
+        ppu.cycle = 265;
+        assert_eq!(ppu.cpu_read(0x2004), 0xFF);
+
+        ppu.cycle = 330;
+        assert_eq!(ppu.cpu_read(0x2004), 5);
+    }
+
+    #[test]
+    fn ppudata_during_rendering_bumps_coarse_x_and_y() {
+        for glitch in [false, true] {
+            let mut ppu = PPU::new();
+            ppu.ppudata_glitch = glitch;
+            ppu.mask = MaskFlags::ShowBackground as u8;
+            ppu.scanline = 100;
+            ppu.cycle = 50;
+
+            //Coarse X 31 wraps into the next nametable, fine Y 7 carries into coarse Y
+            ppu.vram_address = 0x7000 | (5 << 5) | 31;
+            ppu.cpu_write(0x2007, 0x00);
+
+            let expected = if glitch { NAMETABLE_X | (6 << 5) } else { 0x7000 | (6 << 5) };
+            assert_eq!(ppu.vram_address, expected, "glitch {}", glitch);
+        }
+
+        //Outside of rendering the increment is the usual one
+        let mut ppu = PPU::new();
+        ppu.ppudata_glitch = true;
+        ppu.mask = MaskFlags::ShowBackground as u8;
+        ppu.scanline = 241;
+        ppu.vram_address = 0x2000;
+        ppu.cpu_read(0x2007);
+        assert_eq!(ppu.vram_address, 0x2001);
+    }
+
+    #[test]
+    fn rendering_off_during_sprite_evaluation_corrupts_an_oam_row() {
+        for corruption in [false, true] {
+            let mut ppu = PPU::new();
+            ppu.oam_corruption = corruption;
+
+            for (index, byte) in ppu.oam.iter_mut().enumerate() {
+                *byte = index as u8;
+            }
+
+            //Dot 100 is evaluating sprite 17, in row 8
+            ppu.mask = MaskFlags::ShowBackground as u8 | MaskFlags::ShowSprites as u8;
+            ppu.scanline = 10;
+            ppu.cycle = 100;
+            ppu.cpu_write(0x2001, 0x00);
+            ppu.cpu_write(0x2001, 0x18);
+
+            while ppu.scanline != 11 || ppu.cycle != 2 {
+                ppu.clock();
+            }
+
+            let row: Vec<u8> = if corruption { (0..8).collect() } else { (64..72).collect() };
+            assert_eq!(&ppu.oam[64..72], row.as_slice(), "corruption {}", corruption);
+            assert_eq!(ppu.oam[72], 72);
+        }
+    }
+}
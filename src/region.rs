@@ -0,0 +1,170 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ppu::MaskFlags;
+
+///Console model the timing follows<br>
+///The master clock is divided by 12 (NTSC) or 16 (PAL) or 15 (Dendy) for the CPU and by 4 or 5 for the PPU,
+///PAL and Dendy consoles draw 312 scanlines per frame instead of 262
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy, //Famiclone: PAL frame rate with NTSC like CPU timing and a late vertical blank
+}
+
+impl Region {
+    pub const ALL: [Region; 3] = [Region::Ntsc, Region::Pal, Region::Dendy];
+
+    ///CPU clock in Hz
+    pub fn cpu_clock_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+            Region::Dendy => 1_773_448.0,
+        }
+    }
+
+    ///Frames per second, the rate a frontend should present frames at
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal | Region::Dendy => 50.0070,
+        }
+    }
+
+    ///Master clock divisors (CPU, PPU): the CPU runs once every cpu / ppu PPU dots (3 or 3.2)
+    pub(crate) fn clock_divisors(&self) -> (u64, u64) {
+        match self {
+            Region::Ntsc => (12, 4),
+            Region::Pal => (16, 5),
+            Region::Dendy => (15, 5),
+        }
+    }
+
+    ///Scanlines per frame, pre-render line included
+    pub(crate) fn scanline_count(&self) -> i16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    ///Scanline the vertical blank (and NMI) starts on<br>
+    ///PAL has a 70 line vertical blank, Dendy keeps NTSC's 20 lines and starts it 50 lines later
+    pub(crate) fn vblank_scanline(&self) -> i16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    ///Frame counter steps in CPU cycles (4-step sequence, then the fifth step of the 5-step one)<br>
+    ///Dendy uses the NTSC sequence
+    pub(crate) fn frame_counter_steps(&self) -> [u32; 5] {
+        match self {
+            Region::Ntsc | Region::Dendy => [7457, 14913, 22371, 29829, 37281],
+            Region::Pal => [8313, 16627, 24939, 33253, 41565],
+        }
+    }
+
+    ///Color emphasis bits (PPUMASK bits 5 - 7) as red, green, blue<br>
+    ///The PAL PPU (and the Dendy one derived from it) has the red and green bits swapped
+    pub(crate) fn emphasis(&self, mask: u8) -> u8 {
+        let emphasis = MaskFlags::EmphasizeRed as u8 | MaskFlags::EmphasizeGreen as u8 | MaskFlags::EmphasizeBlue as u8;
+        let bits = (mask & emphasis) >> 5;
+
+        match self {
+            Region::Ntsc => bits,
+            Region::Pal | Region::Dendy => (bits & 0x04) | ((bits & 0x01) << 1) | ((bits & 0x02) >> 1),
+        }
+    }
+
+    ///Noise channel timer periods in CPU cycles, selected by the low 4 bits of $400E
+    pub(crate) fn noise_periods(&self) -> [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068],
+            Region::Pal => [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778],
+        }
+    }
+
+    ///DMC output rates in CPU cycles per bit, selected by the low 4 bits of $4010
+    pub(crate) fn dmc_rates(&self) -> [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54],
+            Region::Pal => [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50],
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Ntsc => write!(f, "NTSC"),
+            Region::Pal => write!(f, "PAL"),
+            Region::Dendy => write!(f, "Dendy"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::System;
+
+    ///(PPU dots, CPU cycles) of one whole frame, measured from the start of the second one
+    fn frame_length(region: Region) -> (u64, u64) {
+        let mut system = System::new();
+        system.set_region(region);
+
+        system.step_frame();
+        let (dots, cycles) = (system.get_clock_count(), system.get_cpu_cycle_count());
+
+        system.step_frame();
+
+        return (system.get_clock_count() - dots, system.get_cpu_cycle_count() - cycles);
+    }
+
+    #[test]
+    fn frames_have_the_region_scanline_count() {
+        assert_eq!(frame_length(Region::Ntsc).0, 262 * 341);
+        assert_eq!(frame_length(Region::Pal).0, 312 * 341);
+        assert_eq!(frame_length(Region::Dendy).0, 312 * 341);
+    }
+
+    #[test]
+    fn cpu_runs_at_the_region_divisor() {
+        //341 * 262 / 3, 341 * 312 / 3.2 and 341 * 312 / 3, rounded either way
+        let (_, ntsc) = frame_length(Region::Ntsc);
+        let (_, pal) = frame_length(Region::Pal);
+        let (_, dendy) = frame_length(Region::Dendy);
+
+        assert!((29780..=29781).contains(&ntsc), "{}", ntsc);
+        assert!((33247..=33248).contains(&pal), "{}", pal);
+        assert_eq!(dendy, 35464);
+    }
+
+    #[test]
+    fn pal_swaps_red_and_green_emphasis() {
+        assert_eq!(Region::Ntsc.emphasis(0b0010_0000), 0b001);
+        assert_eq!(Region::Pal.emphasis(0b0010_0000), 0b010);
+        assert_eq!(Region::Dendy.emphasis(0b1100_0000), 0b101);
+    }
+
+    #[test]
+    fn vblank_starts_on_the_region_scanline() {
+        for region in [Region::Ntsc, Region::Pal, Region::Dendy] {
+            let mut system = System::new();
+            system.set_region(region);
+
+            let ppu = system.get_ppu();
+            while (ppu.borrow().status & 0x80) == 0 {
+                system.clock();
+            }
+
+            assert_eq!(ppu.borrow().scanline, region.vblank_scanline(), "{}", region);
+        }
+    }
+}
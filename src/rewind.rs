@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+
+///An older snapshot, stored as the difference to the next newer one
+struct Delta {
+    frame: u64,
+    length: usize, //Of the decoded snapshot, states can change size (sprite lists, ...)
+    data: Vec<u8>,
+}
+
+///Save states taken every few frames, the newest one whole and every older one as a delta to its successor<br>
+///Consecutive states mostly differ in a few RAM bytes, so a delta is a short list of changed runs
+pub(crate) struct RewindBuffer {
+    interval: u32,
+    capacity: usize, //Snapshots kept, counting the newest one
+    countdown: u32,  //Frames until the next snapshot
+    frame: u64,      //Frames run since the buffer was created, moved back by rewinds
+
+    latest: Option<(u64, Vec<u8>)>,
+    older: VecDeque<Delta>, //Oldest first
+}
+
+impl RewindBuffer {
+    //Constructor
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            countdown: interval.max(1),
+            frame: 0,
+
+            latest: None,
+            older: VecDeque::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.older.clear();
+        self.countdown = self.interval;
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        return self.older.len() + self.latest.is_some() as usize;
+    }
+
+    ///Counts a finished frame, returns true when a snapshot is due and has to be given to push()
+    pub fn frame_completed(&mut self) -> bool {
+        self.frame += 1;
+        self.countdown -= 1;
+
+        if self.countdown == 0 {
+            self.countdown = self.interval;
+            return true;
+        }
+
+        return false;
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some((frame, previous)) = self.latest.take() {
+            self.older.push_back(Delta {
+                frame,
+                length: previous.len(),
+                data: encode(&previous, &state),
+            });
+
+            //The oldest delta only leads back from the one after it, so it can go on its own
+            if self.older.len() >= self.capacity {
+                self.older.pop_front();
+            }
+        }
+
+        self.latest = Some((self.frame, state));
+    }
+
+    ///Takes out the newest snapshot at least `frames` frames old, with how many frames back it is<br>
+    ///Every newer snapshot is dropped, None when no snapshot is old enough
+    pub fn pop(&mut self, frames: u32) -> Option<(u64, Vec<u8>)> {
+        let target = self.frame.checked_sub(frames as u64)?;
+
+        loop {
+            let (frame, state) = self.latest.take()?;
+
+            if let Some(delta) = self.older.pop_back() {
+                self.latest = Some((delta.frame, decode(&state, &delta.data, delta.length)));
+            }
+
+            if frame <= target {
+                let rewound = self.frame - frame;
+
+                self.frame = frame;
+                self.countdown = self.interval;
+
+                return Some((rewound, state));
+            }
+        }
+    }
+}
+
+///Run length encoding of old XOR new: (unchanged bytes, changed bytes, the XORed changed bytes) repeated,
+///the counts as LEB128
+fn encode(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let difference: Vec<u8> = (0..old.len())
+        .map(|index| old[index] ^ new.get(index).copied().unwrap_or(0))
+        .collect();
+
+    let mut data = Vec::new();
+    let mut index = 0;
+
+    while index < difference.len() {
+        let same = difference[index..].iter().take_while(|&&byte| byte == 0).count();
+        let changed = difference[index + same..].iter().take_while(|&&byte| byte != 0).count();
+
+        write_length(&mut data, same);
+        write_length(&mut data, changed);
+        data.extend(&difference[index + same..index + same + changed]);
+
+        index += same + changed;
+    }
+
+    return data;
+}
+
+///Rebuilds the old snapshot from the newer one and the delta
+fn decode(new: &[u8], data: &[u8], length: usize) -> Vec<u8> {
+    let mut old: Vec<u8> = (0..length).map(|index| new.get(index).copied().unwrap_or(0)).collect();
+
+    let mut position = 0;
+    let mut index = 0;
+
+    while position < data.len() {
+        index += read_length(data, &mut position);
+        let changed = read_length(data, &mut position);
+
+        for (byte, difference) in old[index..index + changed].iter_mut().zip(&data[position..position + changed]) {
+            *byte ^= difference;
+        }
+
+        index += changed;
+        position += changed;
+    }
+
+    return old;
+}
+
+fn write_length(data: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        data.push((length as u8 & 0x7F) | 0x80);
+        length >>= 7;
+    }
+
+    data.push(length as u8);
+}
+
+fn read_length(data: &[u8], position: &mut usize) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*position];
+        *position += 1;
+
+        length |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if (byte & 0x80) == 0 {
+            return length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas_restore_the_old_state() {
+        let old: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        for new in [old.clone(), vec![0; 1000], old[..600].to_vec(), [&old[..], &[1, 2, 3]].concat()] {
+            let mut changed = new.clone();
+            changed[500] ^= 0xFF;
+
+            let delta = encode(&old, &changed);
+            assert_eq!(decode(&changed, &delta, old.len()), old);
+        }
+
+        //Identical states cost the two run lengths and nothing else
+        assert_eq!(encode(&old, &old).len(), 3);
+    }
+
+    #[test]
+    fn pop_goes_back_to_a_snapshot_old_enough() {
+        let mut buffer = RewindBuffer::new(2, 3);
+
+        for frame in 1..=8u8 {
+            if buffer.frame_completed() {
+                buffer.push(vec![frame; 4]);
+            }
+        }
+
+        //Frames 4, 6 and 8 are kept, frame 2 fell out
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop(3), Some((4, vec![4; 4])));
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.pop(0), None);
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+///Small deterministic generator (SplitMix64), the same seed always gives the same sequence<br>
+///The console's generator lives in System and goes into save states, so anything random replays the same way
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    //Constructor
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    ///Seed taken from the system clock, for runs that don't ask for a specific one
+    pub fn seed_from_time() -> u64 {
+        return std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0);
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+
+        let mut value = self.state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D049BB133111EB);
+
+        return value ^ (value >> 31);
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        return (self.next_u64() >> 56) as u8;
+    }
+
+    pub fn fill(&mut self, buffer: &mut [u8]) {
+        for byte in buffer {
+            *byte = self.next_u8();
+        }
+    }
+}
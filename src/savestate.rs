@@ -0,0 +1,107 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apu::ApuState, bus::BusState, cartridge::CartridgeState, cpu::CpuState, ppu::PpuState,
+    region::Region,
+    rng::Rng,
+};
+
+///Bumped whenever the layout of SaveState changes, older states are rejected
+pub const SAVE_STATE_VERSION: u32 = 14;
+
+const MAGIC: [u8; 4] = *b"RNST";
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Encoding(bincode::Error),
+    InvalidFormat,           //Not a save state (missing RNST header)
+    UnsupportedVersion(u32), //Saved by a version of RNES with a different layout
+    NoCartridge,
+    CartridgeMismatch,       //Saved with a different game
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Encoding(error) => write!(f, "corrupted save state: {}", error),
+            SaveStateError::InvalidFormat => write!(f, "not an RNES save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported (expected {})",
+                version, SAVE_STATE_VERSION
+            ),
+            SaveStateError::NoCartridge => write!(f, "no cartridge is inserted"),
+            SaveStateError::CartridgeMismatch => write!(f, "the save state belongs to another game"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+impl From<bincode::Error> for SaveStateError {
+    fn from(error: bincode::Error) -> Self {
+        SaveStateError::Encoding(error)
+    }
+}
+
+///Written before the state so the version can be checked before decoding the rest
+#[derive(Serialize, Deserialize)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+}
+
+///Snapshot of the whole console<br>
+///Only the console is saved: cheat codes, write interceptors (frozen addresses), breakpoints, watchpoints and the
+///debug port belong to the host, a load keeps the ones that are active and writes the frozen addresses again
+#[derive(Serialize, Deserialize)]
+pub struct SaveState {
+    pub cpu: CpuState,
+    pub bus: BusState,
+    pub ppu: PpuState,
+    pub apu: ApuState,
+    pub cartridge: CartridgeState,
+
+    //Master clock
+    pub clock_counter: u64,
+    pub region: Region,
+    pub rng: Rng,
+
+    ///Cheat codes or write interceptors were active when the state was taken, so RAM may hold values the game
+    ///never wrote<br>
+    ///Movies and netplay should refuse such states, they would desync on a console without the same cheats
+    pub cheats_active: bool,
+}
+
+impl SaveState {
+    ///Binary format: the RNST header with the version, then the bincode encoded state
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SaveStateError> {
+        let header = Header {
+            magic: MAGIC,
+            version: SAVE_STATE_VERSION,
+        };
+
+        let mut data = bincode::serialize(&header)?;
+        data.extend(bincode::serialize(self)?);
+
+        Ok(data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, SaveStateError> {
+        let header: Header = bincode::deserialize(data).map_err(|_| SaveStateError::InvalidFormat)?;
+
+        if header.magic != MAGIC {
+            return Err(SaveStateError::InvalidFormat);
+        }
+
+        if header.version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(header.version));
+        }
+
+        let offset = bincode::serialized_size(&header)? as usize;
+
+        Ok(bincode::deserialize(&data[offset..])?)
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    cell::RefCell,
+    fmt, fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    cartridge::{Cartridge, CartridgeError},
+    diagnostics::Diagnostic,
+    system::System,
+};
+
+///Frames emulated per ROM when no count is given
+pub const DEFAULT_SCAN_FRAMES: u32 = 300;
+
+///Opcodes that lock up the 6502 until a reset (KIL/JAM)
+const JAM_OPCODES: [u8; 12] = [
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+];
+
+pub enum ScanStatus {
+    ///Ran every frame, with the FNV-1a hash of the last frame
+    Ok { frame_hash: u64 },
+    UnsupportedMapper(u16),
+    LoadError(String),
+    ///The emulator panicked, with the panic message and the frame it happened in
+    Crash { frame: u32, message: String },
+    ///The CPU reached a JAM opcode
+    Jam { frame: u32, address: u16 },
+}
+
+impl fmt::Display for ScanStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanStatus::Ok { frame_hash } => write!(f, "ok (frame hash {:016X})", frame_hash),
+            ScanStatus::UnsupportedMapper(id) => write!(f, "unsupported mapper {}", id),
+            ScanStatus::LoadError(error) => write!(f, "load error: {}", error),
+            ScanStatus::Crash { frame, message } => write!(f, "crash at frame {}: {}", frame, message),
+            ScanStatus::Jam { frame, address } => write!(f, "CPU jam at ${:04X} (frame {})", address, frame),
+        }
+    }
+}
+
+pub struct ScanReport {
+    pub path: PathBuf,
+    pub status: ScanStatus,
+    pub diagnostics: Vec<Diagnostic>, //Opcodes and board registers the ROM used that aren't emulated
+}
+
+///Loads every .nes file in the directory headlessly, runs it for the given number of frames and reports how it went
+pub fn scan_directory(directory: &Path, frames: u32) -> io::Result<Vec<ScanReport>> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .map(|extension| extension.eq_ignore_ascii_case("nes"))
+                .unwrap_or(false)
+        })
+        .collect();
+
+    roms.sort();
+
+    let reports = with_silent_panics(|| {
+        roms.into_iter()
+            .map(|path| {
+                let (status, diagnostics) = run_rom(&path, frames, None);
+
+                ScanReport {
+                    path,
+                    status,
+                    diagnostics,
+                }
+            })
+            .collect()
+    });
+
+    Ok(reports)
+}
+
+pub struct FuzzRun {
+    pub seed: u64,
+    pub status: ScanStatus,
+}
+
+///Startup state fuzzing: runs the ROM once per seed (seed, seed + 1, ...) with randomized power-on state<br>
+///A ROM that initializes everything it reads ends every run on the same frame hash
+pub fn fuzz_rom(path: &Path, frames: u32, runs: u32, seed: u64) -> Vec<FuzzRun> {
+    return with_silent_panics(|| {
+        (0..runs as u64)
+            .map(|run| {
+                let seed = seed.wrapping_add(run);
+
+                FuzzRun {
+                    seed,
+                    status: run_rom(path, frames, Some(seed)).0,
+                }
+            })
+            .collect()
+    });
+}
+
+///Runs a single ROM headlessly for the given number of frames
+pub fn scan_rom(path: &Path, frames: u32) -> ScanStatus {
+    return run_rom(path, frames, None).0;
+}
+
+///Panics are reported per run, so keep the default hook from printing them
+fn with_silent_panics<T>(f: impl FnOnce() -> T) -> T {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let result = f();
+
+    panic::set_hook(default_hook);
+
+    return result;
+}
+
+///Runs the ROM, randomizing the power-on state from the seed if there is one<br>
+///Returns how it went and the diagnostics logged on the way
+fn run_rom(path: &Path, frames: u32, fuzz_seed: Option<u64>) -> (ScanStatus, Vec<Diagnostic>) {
+    let cartridge = match Cartridge::from_file(path) {
+        Ok(cartridge) => cartridge,
+        Err(CartridgeError::UnsupportedMapper(id)) => return (ScanStatus::UnsupportedMapper(id), Vec::new()),
+        Err(error) => return (ScanStatus::LoadError(error.to_string()), Vec::new()),
+    };
+
+    let mut system = System::new();
+    let bus = system.get_bus();
+    bus.borrow_mut().insert_cartridge(Rc::new(RefCell::new(cartridge)));
+
+    let mut frame = 0;
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        system.reset();
+
+        if let Some(seed) = fuzz_seed {
+            system.set_rng_seed(seed);
+            system.randomize_power_on_state();
+        }
+
+        while frame < frames {
+            if let Some(address) = run_frame(&mut system) {
+                return Some(address);
+            }
+
+            frame += 1;
+        }
+
+        None
+    }));
+
+    let status = match result {
+        Ok(Some(address)) => ScanStatus::Jam { frame, address },
+        Ok(None) => ScanStatus::Ok {
+            frame_hash: hash_frame(&bus.borrow().get_screen()),
+        },
+        Err(payload) => {
+            let message = if let Some(message) = payload.downcast_ref::<&str>() {
+                message.to_string()
+            } else if let Some(message) = payload.downcast_ref::<String>() {
+                message.clone()
+            } else {
+                "unknown panic".to_string()
+            };
+
+            ScanStatus::Crash { frame, message }
+        }
+    };
+
+    let diagnostics = bus.borrow().get_diagnostics().borrow().log().to_vec();
+
+    return (status, diagnostics);
+}
+
+///Runs the system one instruction at a time until the PPU completes a frame<br>
+///Returns the address of the instruction if the CPU reached a JAM opcode
+fn run_frame(system: &mut System) -> Option<u16> {
+    let bus = system.get_bus();
+    let cpu = system.get_cpu();
+    let ppu = system.get_ppu();
+
+    ppu.borrow_mut().frame_complete = false;
+
+    while !ppu.borrow().frame_complete {
+        let address = cpu.borrow().get_program_counter();
+
+        if JAM_OPCODES.contains(&bus.borrow().peek(address)) {
+            return Some(address);
+        }
+
+        system.step_instruction();
+    }
+
+    None
+}
+
+///FNV-1a hash of the frame, stable across runs and Rust versions
+fn hash_frame(screen: &[u32]) -> u64 {
+    let mut hash: u64 = 0xCBF29CE484222325;
+
+    for pixel in screen {
+        for byte in pixel.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001B3);
+        }
+    }
+
+    return hash;
+}
@@ -0,0 +1,24 @@
+use std::fmt;
+
+///Session counters, for performance tuning and bug reports<br>
+///The console fills in the emulation counters, the frontend reports the audio underruns and dropped frames
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub frames: u64,
+    pub instructions: u64,
+    pub irqs: u64, //Taken IRQs (APU frame counter, mapper), masked requests aren't counted
+    pub nmis: u64,
+
+    pub audio_underruns: u64, //The audio device asked for samples the emulation hadn't produced yet
+    pub dropped_frames: u64,  //Frames the frontend couldn't present in time
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames, {} instructions, {} IRQs, {} NMIs, {} audio underruns, {} dropped frames",
+            self.frames, self.instructions, self.irqs, self.nmis, self.audio_underruns, self.dropped_frames
+        )
+    }
+}
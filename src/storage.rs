@@ -0,0 +1,155 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+///Files a frontend keeps next to the ROM (game.sav, game.state, ...), moved into the game's directory by migrate()
+pub const MIGRATED_EXTENSIONS: [&str; 6] = ["sav", "state", "auto.state", "cht", "trace.log", "frames"];
+
+///Where the files of one game go: a directory under the root named after the CRC-32 of the ROM
+///(Emulator::rom_crc32()) and the game, so renamed or moved ROMs keep their saves and two dumps with the same name
+///don't share them<br>
+///Inside, every file is named after the game: "Game.sav", "Game.state", "Game-001.png", ...
+pub struct GameStorage {
+    directory: PathBuf,
+    name: String, //Sanitized, safe as a file name
+}
+
+impl GameStorage {
+    //Constructor
+    pub fn new<P: AsRef<Path>>(root: P, rom_crc32: u32, name: &str) -> Self {
+        let name = sanitize(name);
+
+        Self {
+            directory: root.as_ref().join(format!("{:08X} {}", rom_crc32, name)),
+            name,
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        return &self.directory;
+    }
+
+    ///Creates the directory (and the root) when it doesn't exist yet
+    pub fn create(&self) -> io::Result<()> {
+        return fs::create_dir_all(&self.directory);
+    }
+
+    ///The game's file with the extension ("sav", "auto.state", ...)
+    pub fn file(&self, extension: &str) -> PathBuf {
+        return self.directory.join(format!("{}.{}", self.name, extension));
+    }
+
+    ///Numbered screenshot, from 1
+    pub fn screenshot(&self, number: u32) -> PathBuf {
+        return self.directory.join(format!("{}-{:03}.png", self.name, number));
+    }
+
+    ///Moves the files a frontend used to keep next to the ROM (game.sav, game.state, game-001.png, ... with
+    ///MIGRATED_EXTENSIONS) into the directory, returns where they went<br>
+    ///A file already in the directory is never replaced: the loose one stays where it is
+    pub fn migrate<P: AsRef<Path>>(&self, rom: P) -> io::Result<Vec<PathBuf>> {
+        let rom = rom.as_ref();
+        let mut moves = Vec::new();
+
+        for extension in MIGRATED_EXTENSIONS {
+            moves.push((rom.with_extension(extension), self.file(extension)));
+        }
+
+        let stem = rom.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        for number in 1..1000 {
+            moves.push((rom.with_file_name(format!("{}-{:03}.png", stem, number)), self.screenshot(number)));
+        }
+
+        let mut migrated = Vec::new();
+
+        for (from, to) in moves {
+            if !from.exists() || to.exists() {
+                continue;
+            }
+
+            self.create()?;
+            fs::rename(&from, &to)?;
+            migrated.push(to);
+        }
+
+        return Ok(migrated);
+    }
+}
+
+///Keeps letters, digits, spaces and - _ . ( ) ! , ' the rest (path separators, : * ? ...) becomes _
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|character| {
+            if character.is_alphanumeric() || " -_.()!,'".contains(character) {
+                character
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    //No hidden directories and no trailing dots or spaces, which Windows drops
+    let name = name.trim_matches(|character| character == '.' || character == ' ');
+
+    if name.is_empty() {
+        return "game".to_string();
+    }
+
+    return name.to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///Empty directory of its own in the temporary directory
+    fn temporary(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("rnes-storage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+
+        return directory;
+    }
+
+    #[test]
+    fn files_are_named_after_the_crc_and_the_game() {
+        let storage = GameStorage::new("saves", 0x0123ABCD, "Zelda II: The Adventure of Link");
+
+        assert_eq!(storage.directory(), Path::new("saves/0123ABCD Zelda II_ The Adventure of Link"));
+        assert_eq!(storage.file("sav"), storage.directory().join("Zelda II_ The Adventure of Link.sav"));
+        assert_eq!(storage.screenshot(7), storage.directory().join("Zelda II_ The Adventure of Link-007.png"));
+
+        assert_eq!(sanitize("../.."), "_");
+        assert_eq!(sanitize(""), "game");
+    }
+
+    #[test]
+    fn loose_files_move_into_the_directory() {
+        let root = temporary("migrate");
+        let rom = root.join("mario.nes");
+        fs::write(&rom, b"NES").unwrap();
+        fs::write(root.join("mario.sav"), b"save").unwrap();
+        fs::write(root.join("mario.auto.state"), b"auto").unwrap();
+        fs::write(root.join("mario-002.png"), b"png").unwrap();
+        fs::write(root.join("mario.cht"), b"loose").unwrap();
+
+        let storage = GameStorage::new(root.join("saves"), 1, "Super Mario Bros.");
+        storage.create().unwrap();
+        fs::write(storage.file("cht"), b"kept").unwrap();
+
+        let migrated = storage.migrate(&rom).unwrap();
+        assert_eq!(migrated, [storage.file("sav"), storage.file("auto.state"), storage.screenshot(2)]);
+
+        assert_eq!(fs::read(storage.file("sav")).unwrap(), b"save");
+        assert!(!root.join("mario.sav").exists());
+        assert!(rom.exists());
+
+        //The file already in the directory wins
+        assert_eq!(fs::read(storage.file("cht")).unwrap(), b"kept");
+        assert_eq!(fs::read(root.join("mario.cht")).unwrap(), b"loose");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
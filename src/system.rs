@@ -0,0 +1,475 @@
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+    time::Instant,
+};
+
+use crate::{
+    accuracy::AccuracySettings,
+    apu::APU,
+    audio::AudioPipeline,
+    bus::BUS,
+    cpu::CPU,
+    disassembler::disassemble_instruction,
+    ppu::PPU,
+    region::Region,
+    rng::Rng,
+    savestate::{SaveState, SaveStateError},
+    stats::Stats,
+    timing::{Subsystem, SubsystemTimes},
+    trace::Tracer,
+};
+
+///NTSC CPU clock in Hz, see Region::cpu_clock_rate() for the others
+pub const CPU_CLOCK_RATE: f64 = 1_789_773.0;
+
+///Default rate of the audio samples collected from the APU
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+///Master clock of the console: owns the BUS and keeps the CPU, PPU and APU in step<br>
+///The PPU runs on every tick, the CPU and the APU on every third one (every 3.2 on PAL)
+pub struct System {
+    bus: Rc<RefCell<BUS>>,
+    cpu: Rc<RefCell<CPU>>,
+    ppu: Rc<RefCell<PPU>>,
+    apu: Rc<RefCell<APU>>,
+
+    clock_counter: u64, //PPU clocks since the last reset
+    region: Region,
+    accuracy: AccuracySettings,
+    rng: Rng, //Every random behavior draws from it, saved in save states
+
+    //Audio
+    audio: AudioPipeline, //Fed the APU levels on every CPU cycle
+
+    stats: Stats,
+    tracer: Option<Tracer>,
+    subsystem_times: Option<SubsystemTimes>, //Only collected while a timing trace runs
+
+    //Scanline Stops
+    scanline_dot: Option<i16>,     //The step functions return early when the PPU reaches this dot
+    scanline_reached: Option<i16>, //Scanline the dot was reached on, until taken
+}
+
+impl System {
+    //Constructor
+    pub fn new() -> Self {
+        let bus = BUS::new();
+        let cpu = bus.borrow().get_cpu();
+        let ppu = bus.borrow().get_ppu();
+        let apu = bus.borrow().get_apu();
+
+        let mut system = Self {
+            bus,
+            cpu,
+            ppu,
+            apu,
+
+            clock_counter: 0,
+            region: Region::Ntsc,
+            accuracy: AccuracySettings::default(),
+            rng: Rng::new(0),
+
+            audio: AudioPipeline::new(Region::Ntsc.cpu_clock_rate(), DEFAULT_SAMPLE_RATE),
+
+            stats: Stats::default(),
+            tracer: None,
+            subsystem_times: None,
+
+            scanline_dot: None,
+            scanline_reached: None,
+        };
+
+        system.set_accuracy(AccuracySettings::default());
+
+        return system;
+    }
+
+    pub fn get_bus(&self) -> Rc<RefCell<BUS>> {
+        return self.bus.clone();
+    }
+
+    pub fn get_cpu(&self) -> Rc<RefCell<CPU>> {
+        return self.cpu.clone();
+    }
+
+    pub fn get_ppu(&self) -> Rc<RefCell<PPU>> {
+        return self.ppu.clone();
+    }
+
+    ///PPU clocks since the last reset
+    #[cfg(test)]
+    pub fn get_clock_count(&self) -> u64 {
+        return self.clock_counter;
+    }
+
+    ///CPU cycles since the last reset
+    pub fn get_cpu_cycle_count(&self) -> u64 {
+        let (cpu_divisor, ppu_divisor) = self.region.clock_divisors();
+
+        return (self.clock_counter * ppu_divisor).div_ceil(cpu_divisor);
+    }
+
+    pub fn get_region(&self) -> Region {
+        return self.region;
+    }
+
+    ///Switches the timing of every component, the PPU starts a new frame if it was past the end of a shorter one
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.borrow_mut().set_region(region);
+        self.apu.borrow_mut().set_region(region);
+        self.audio.set_input_rate(region.cpu_clock_rate());
+    }
+
+    pub fn get_accuracy(&self) -> AccuracySettings {
+        return self.accuracy;
+    }
+
+    ///Hands every setting to the component it changes, they can be switched at any time
+    pub fn set_accuracy(&mut self, settings: AccuracySettings) {
+        self.accuracy = settings;
+        self.cpu.borrow_mut().set_dummy_reads(settings.dummy_reads);
+        self.ppu.borrow_mut().set_accuracy(&settings);
+        self.apu.borrow_mut().set_accuracy(&settings);
+    }
+
+    ///Counters since the System was created, they survive resets and save state loads
+    pub fn get_stats(&self) -> Stats {
+        return self.stats;
+    }
+
+    pub fn get_stats_mut(&mut self) -> &mut Stats {
+        return &mut self.stats;
+    }
+
+    ///Restarts the generator, what it gives from then on only depends on the seed
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    ///Startup fuzzing with the console's generator, see BUS::randomize_power_on_state()
+    pub fn randomize_power_on_state(&mut self) {
+        self.bus.borrow_mut().randomize_power_on_state(&mut self.rng);
+    }
+
+    ///Turns the console off and on: the memories are cleared, then it resets
+    pub fn power_cycle(&mut self) {
+        self.bus.borrow_mut().clear_memory();
+        self.reset();
+    }
+
+    ///Resets every component and the master clock
+    pub fn reset(&mut self) {
+        self.bus.borrow().reset();
+
+        self.clock_counter = 0;
+        self.audio.clear();
+    }
+
+    //Save States
+
+    ///Snapshots every component, fails when no cartridge is inserted
+    pub fn save_state(&self) -> Result<SaveState, SaveStateError> {
+        let bus = self.bus.borrow();
+        let cartridge = bus.get_cartridge().ok_or(SaveStateError::NoCartridge)?;
+
+        let state = SaveState {
+            cpu: self.cpu.borrow().save_state(),
+            bus: bus.save_state(),
+            ppu: self.ppu.borrow().save_state(),
+            apu: self.apu.borrow().save_state(),
+            cartridge: cartridge.borrow().save_state(),
+
+            clock_counter: self.clock_counter,
+            region: self.region,
+            rng: self.rng.clone(),
+
+            cheats_active: bus.has_write_interceptors() || bus.has_cheats(),
+        };
+
+        Ok(state)
+    }
+
+    ///Restores a snapshot taken with the same game, nothing changes if it fails
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), SaveStateError> {
+        let cartridge = self.bus.borrow().get_cartridge().ok_or(SaveStateError::NoCartridge)?;
+
+        if !cartridge.borrow_mut().load_state(&state.cartridge) {
+            return Err(SaveStateError::CartridgeMismatch);
+        }
+
+        self.cpu.borrow_mut().load_state(&state.cpu);
+        self.bus.borrow_mut().load_state(&state.bus);
+        self.ppu.borrow_mut().load_state(&state.ppu);
+        self.apu.borrow_mut().load_state(&state.apu);
+
+        self.clock_counter = state.clock_counter;
+        self.rng = state.rng.clone();
+        self.audio.clear();
+        self.set_region(state.region);
+
+        Ok(())
+    }
+
+    //Timing
+
+    ///One master tick (one PPU dot), returns true when the CPU ran a cycle on it
+    pub fn clock(&mut self) -> bool {
+        let mark = self.timing_mark();
+
+        {
+            let mut ppu = self.ppu.borrow_mut();
+            ppu.clock();
+
+            //The PPU just wrapped around to the pre-render scanline
+            if ppu.scanline == -1 && ppu.cycle == 0 {
+                self.stats.frames += 1;
+            }
+
+            if self.scanline_dot == Some(ppu.cycle) {
+                self.scanline_reached = Some(ppu.scanline);
+            }
+        }
+
+        let mark = self.timing_lap(Subsystem::Ppu, mark);
+
+        //The CPU runs on the dots where the master clock passes a multiple of its divisor
+        let (cpu_divisor, ppu_divisor) = self.region.clock_divisors();
+        let cpu_cycle = (self.clock_counter * ppu_divisor) % cpu_divisor < ppu_divisor;
+
+        if cpu_cycle {
+            self.clock_cpu();
+            let mark = self.timing_lap(Subsystem::Cpu, mark);
+
+            let levels = {
+                let mut apu = self.apu.borrow_mut();
+                apu.clock();
+                apu.levels()
+            };
+
+            self.audio.push(levels);
+
+            self.timing_lap(Subsystem::Apu, mark);
+        }
+
+        self.clock_counter += 1;
+
+        return cpu_cycle;
+    }
+
+    ///One CPU cycle: an OAM DMA cycle while one is running, otherwise the CPU itself<br>
+    ///Interrupts are only taken between instructions, NMI first, the IRQ line is shared by the APU and the cartridge
+    fn clock_cpu(&mut self) {
+        //The cartridge sees M2 even while a DMA halts the CPU
+        self.bus.borrow().clock_cartridge();
+
+        //A DMC sample fetch halts the CPU (and delays an OAM DMA) for a few cycles
+        if self.apu.borrow_mut().cpu_stalled() {
+            return;
+        }
+
+        let dmc_request = self.apu.borrow().dmc_dma_request();
+        if let Some(address) = dmc_request {
+            //The halted CPU repeats the read it was doing, registers see it twice (controller bits get lost)
+            //The CPU runs whole instructions at once here, so its last read stands in for the halted one
+            if self.accuracy.dmc_conflicts {
+                let bus = self.bus.borrow();
+                let conflict = bus.last_read_address();
+
+                if (0x2000..=0x401F).contains(&conflict) {
+                    bus.read(conflict);
+                }
+            }
+
+            let data = self.bus.borrow().read(address);
+            self.apu.borrow_mut().dmc_dma_complete(data);
+
+            //This cycle was the first one of the fetch
+            self.apu.borrow_mut().cpu_stalled();
+            return;
+        }
+
+        if self.bus.borrow().dma_active() {
+            let odd_cycle = self.get_cpu_cycle_count() % 2 == 1;
+            self.bus.borrow_mut().clock_dma(odd_cycle);
+            return;
+        }
+
+        if self.cpu.borrow().complete() {
+            let nmi = self.ppu.borrow().nmi;
+
+            if nmi {
+                self.ppu.borrow_mut().nmi = false;
+                self.cpu.borrow_mut().non_maskable_input();
+                self.stats.nmis += 1;
+            } else if (self.apu.borrow().irq() || self.bus.borrow().cartridge_irq())
+                && self.cpu.borrow_mut().interrupt_request()
+            {
+                self.stats.irqs += 1;
+            }
+        }
+
+        //Still complete after the interrupts: this cycle fetches a new instruction
+        if self.cpu.borrow().complete() {
+            self.stats.instructions += 1;
+
+            if self.tracer.is_some() {
+                self.trace_instruction();
+            }
+        }
+
+        self.cpu.borrow_mut().clock();
+    }
+
+    fn trace_instruction(&mut self) {
+        let registers = self.cpu.borrow().get_registers();
+        let line = {
+            let bus = self.bus.borrow();
+            disassemble_instruction(|address| bus.peek(address), registers.program_counter)
+        };
+        let (scanline, cycle) = {
+            let ppu = self.ppu.borrow();
+            (ppu.scanline, ppu.cycle)
+        };
+        let cpu_cycles = self.get_cpu_cycle_count();
+
+        if let Some(tracer) = &mut self.tracer {
+            tracer.log(&line, registers, scanline, cycle, cpu_cycles);
+        }
+    }
+
+    ///The CPU completed an instruction and no DMA is running, so its next cycle starts a new one
+    pub fn instruction_complete(&self) -> bool {
+        return self.cpu.borrow().complete() && !self.bus.borrow().dma_active();
+    }
+
+    ///Runs until the CPU has completed its current instruction (or interrupt sequence)<br>
+    ///Returns false when it stopped early at the scanline dot, calling it again carries on
+    pub fn step_instruction(&mut self) -> bool {
+        loop {
+            if self.clock() && self.instruction_complete() {
+                return true;
+            }
+
+            if self.scanline_reached.is_some() {
+                return false;
+            }
+        }
+    }
+
+    ///Runs until the PPU starts the next scanline, false when it stopped early at the scanline dot
+    pub fn step_scanline(&mut self) -> bool {
+        let scanline = self.ppu.borrow().scanline;
+
+        while self.ppu.borrow().scanline == scanline {
+            self.clock();
+
+            if self.scanline_reached.is_some() && self.ppu.borrow().scanline == scanline {
+                return false;
+            }
+        }
+
+        return true;
+    }
+
+    ///Runs until the PPU completes the next frame, false when it stopped early at the scanline dot
+    pub fn step_frame(&mut self) -> bool {
+        self.ppu.borrow_mut().frame_complete = false;
+
+        while !self.ppu.borrow().frame_complete {
+            self.clock();
+
+            if self.scanline_reached.is_some() && !self.ppu.borrow().frame_complete {
+                return false;
+            }
+        }
+
+        return true;
+    }
+
+    //Scanline Stops
+
+    ///Makes the step functions return early whenever the PPU reaches the dot (0 - 340) of any scanline
+    pub fn set_scanline_dot(&mut self, dot: Option<u16>) {
+        self.scanline_dot = dot.map(|dot| dot as i16);
+        self.scanline_reached = None;
+    }
+
+    pub fn get_scanline_dot(&self) -> Option<u16> {
+        return self.scanline_dot.map(|dot| dot as u16);
+    }
+
+    ///The scanline (-1 for the pre-render one) the dot was last reached on
+    pub fn take_scanline_reached(&mut self) -> Option<i16> {
+        return self.scanline_reached.take();
+    }
+
+    //Subsystem Timing
+
+    ///Starts (or stops) summing the time spent clocking the CPU, PPU and APU
+    pub fn set_subsystem_timing(&mut self, enabled: bool) {
+        self.subsystem_times = enabled.then(SubsystemTimes::default);
+    }
+
+    ///Time summed since the last call, zero when the timing is off
+    pub fn take_subsystem_times(&mut self) -> SubsystemTimes {
+        match &mut self.subsystem_times {
+            Some(times) => std::mem::take(times),
+            None => SubsystemTimes::default(),
+        }
+    }
+
+    ///Start of the next measured section, None when the timing is off
+    fn timing_mark(&self) -> Option<Instant> {
+        return self.subsystem_times.as_ref().map(|_| Instant::now());
+    }
+
+    ///Adds the time since the mark to the subsystem and starts the next section
+    fn timing_lap(&mut self, subsystem: Subsystem, mark: Option<Instant>) -> Option<Instant> {
+        let times = self.subsystem_times.as_mut()?;
+        let now = Instant::now();
+
+        times.add(subsystem, now.saturating_duration_since(mark?));
+
+        return Some(now);
+    }
+
+    //Trace Logging
+
+    ///Starts logging every instruction to the writer, replacing the current trace (which is finished first)
+    pub fn start_trace(&mut self, writer: Box<dyn Write>) -> io::Result<()> {
+        let result = self.stop_trace();
+        self.tracer = Some(Tracer::new(writer));
+
+        return result;
+    }
+
+    ///Stops logging and flushes the writer, returns the first write error of the trace
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        match self.tracer.take() {
+            Some(tracer) => tracer.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        return self.tracer.is_some();
+    }
+
+    //Audio
+
+    ///Takes the mono samples (about -1.0 - 1.0) produced since the last call
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        return self.audio.take_samples();
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        return self.audio.get_output_rate();
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.audio.set_output_rate(sample_rate);
+    }
+}
@@ -0,0 +1,200 @@
+///Grain length in samples (about 11ms at 44.1kHz), consecutive grains overlap by half
+const GRAIN: usize = 512;
+const HOP: usize = GRAIN / 2;
+
+///How far (in samples, both ways) a grain can move from its nominal position to line up with the previous one
+const SEEK: usize = 64;
+
+///Tempo range the stretcher follows, anything outside is clamped
+pub const MIN_TEMPO: f64 = 0.97;
+pub const MAX_TEMPO: f64 = 1.03;
+
+///WSOLA (waveform similarity overlap-add) time-stretcher for the mono APU stream<br>
+///Plays the samples slightly faster or slower without changing their pitch, so the audio output can
+///follow small emulation speed changes instead of dropping samples or running dry<br>
+///Adds GRAIN + SEEK samples of latency
+pub struct TimeStretcher {
+    input: Vec<f32>,     //Samples that can still be part of a grain
+    position: f64,       //Nominal start of the next grain in input
+    continuation: usize, //Where the last grain would have continued, the next one is matched against it
+    tail: Vec<f32>,      //Second half of the last windowed grain, added to the next one
+    window: Vec<f32>,    //Periodic Hann window, two halves overlapped add up to 1
+}
+
+impl TimeStretcher {
+    //Constructor
+    pub fn new() -> Self {
+        let window = (0..GRAIN)
+            .map(|index| {
+                let phase = index as f64 / GRAIN as f64 * std::f64::consts::TAU;
+                (0.5 - 0.5 * phase.cos()) as f32
+            })
+            .collect();
+
+        Self {
+            input: Vec::new(),
+            position: SEEK as f64,
+            continuation: SEEK,
+            tail: vec![0.0; HOP],
+            window,
+        }
+    }
+
+    ///Drops the buffered samples, used after the stream was interrupted (reset, save state load, ...)<br>
+    ///The next samples fade in from silence through the window of the first grain
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    ///Ends the stream before an interruption (pause, save state load, ...): returns the second half of the last
+    ///grain, which fades out to silence over HOP samples, and clears the stretcher so the next samples fade back in
+    pub fn fade_out(&mut self) -> Vec<f32> {
+        let tail = std::mem::take(&mut self.tail);
+        self.clear();
+
+        return tail;
+    }
+
+    ///Stretches the samples, a tempo above 1.0 gives fewer samples (plays faster), below 1.0 more<br>
+    ///Samples are kept until there are enough for a whole grain, so the output comes in HOP sized chunks
+    pub fn process(&mut self, samples: &[f32], tempo: f64) -> Vec<f32> {
+        let tempo = tempo.clamp(MIN_TEMPO, MAX_TEMPO);
+
+        self.input.extend_from_slice(samples);
+
+        let mut output = Vec::new();
+
+        loop {
+            let nominal = self.position.round() as usize;
+
+            //The grain has to fit even when it moves forward by SEEK
+            if nominal + SEEK + GRAIN > self.input.len() || self.continuation + HOP > self.input.len() {
+                break;
+            }
+
+            let start = self.best_start(nominal);
+
+            for index in 0..HOP {
+                output.push(self.tail[index] + self.input[start + index] * self.window[index]);
+                self.tail[index] = self.input[start + HOP + index] * self.window[HOP + index];
+            }
+
+            self.continuation = start + HOP;
+            self.position += HOP as f64 * tempo;
+        }
+
+        //Nothing before the earliest sample the next grain could use is needed anymore
+        let consumed = (self.position as usize).saturating_sub(SEEK).min(self.continuation);
+        self.input.drain(..consumed);
+        self.position -= consumed as f64;
+        self.continuation -= consumed;
+
+        return output;
+    }
+
+    ///Start near the nominal position whose first half looks most like the continuation of the last grain
+    fn best_start(&self, nominal: usize) -> usize {
+        let target = &self.input[self.continuation..self.continuation + HOP];
+
+        let mut best = nominal;
+        let mut best_score = f32::MIN;
+
+        for start in nominal.saturating_sub(SEEK)..=nominal + SEEK {
+            let score: f32 = self.input[start..start + HOP]
+                .iter()
+                .zip(target)
+                .map(|(sample, expected)| sample * expected)
+                .sum();
+
+            if score > best_score {
+                best = start;
+                best_score = score;
+            }
+        }
+
+        return best;
+    }
+}
+
+impl Default for TimeStretcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(length: usize, period: f32) -> Vec<f32> {
+        return (0..length)
+            .map(|index| (index as f32 / period * std::f32::consts::TAU).sin() * 0.5)
+            .collect();
+    }
+
+    #[test]
+    fn output_length_follows_the_tempo() {
+        let input = sine(48000, 100.0);
+
+        for tempo in [MIN_TEMPO, 1.0, MAX_TEMPO] {
+            let mut stretcher = TimeStretcher::new();
+            let output = stretcher.process(&input, tempo);
+
+            let expected = input.len() as f64 / tempo;
+            let error = (output.len() as f64 - expected).abs() / expected;
+            assert!(error < 0.02, "tempo {}: {} samples for {}", tempo, output.len(), expected);
+        }
+    }
+
+    #[test]
+    fn tempo_one_passes_the_signal_through() {
+        let input = sine(8192, 100.0);
+
+        let mut stretcher = TimeStretcher::new();
+        let output = stretcher.process(&input, 1.0);
+
+        //After the first grain faded in, the output is the input delayed by SEEK samples
+        for (index, sample) in output.iter().enumerate().skip(HOP) {
+            assert!((sample - input[index + SEEK]).abs() < 1e-4, "sample {}", index);
+        }
+    }
+
+    #[test]
+    fn fading_out_and_back_in_never_jumps() {
+        let input = vec![0.5; 4096];
+        let smooth = |samples: &[f32]| samples.windows(2).all(|pair| (pair[1] - pair[0]).abs() < 0.01);
+
+        let mut stretcher = TimeStretcher::new();
+        let output = stretcher.process(&input, 1.0);
+        assert_eq!(output[0], 0.0);
+        assert!(smooth(&output));
+        assert!((output[HOP] - 0.5).abs() < 1e-4);
+
+        let tail = stretcher.fade_out();
+        assert_eq!(tail.len(), HOP);
+        assert!((tail[0] - 0.5).abs() < 1e-4 && tail[HOP - 1].abs() < 1e-3);
+        assert!(smooth(&tail));
+
+        //Nothing of the old stream is left, the new one starts from silence again
+        let output = stretcher.process(&input, 1.0);
+        assert_eq!(output[0], 0.0);
+        assert!(smooth(&output));
+    }
+
+    #[test]
+    fn stretching_keeps_the_pitch() {
+        let input = sine(48000, 100.0);
+
+        let mut stretcher = TimeStretcher::new();
+        let output = stretcher.process(&input, MIN_TEMPO);
+
+        //Same period: rising zero crossings every ~100 samples in both
+        let crossings = output
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        let period = output.len() as f64 / crossings as f64;
+
+        assert!((period - 100.0).abs() < 2.0, "period {}", period);
+    }
+}
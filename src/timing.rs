@@ -0,0 +1,183 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+///Span names the console records for every frame
+pub const FRAME_SPAN: &str = "frame";
+pub const CPU_SPAN: &str = "cpu";
+pub const PPU_SPAN: &str = "ppu";
+pub const APU_SPAN: &str = "apu";
+
+///Spans a frontend is expected to add with Emulator::record_span()
+pub const AUDIO_SPAN: &str = "audio mix";
+pub const PRESENT_SPAN: &str = "present";
+
+///One complete ("X") event
+struct Span {
+    name: String,
+    category: &'static str,
+    start: Duration, //Since the trace started
+    duration: Duration,
+}
+
+///Wall clock timing spans, exported as Chrome trace JSON (chrome://tracing, ui.perfetto.dev)<br>
+///Frames are measured as a whole, the CPU/PPU/APU time is summed over the frame (timing every clock on its own
+///would be unreadable) and drawn as back to back spans from the start of the frame
+pub struct TimingTrace {
+    start: Instant,
+    spans: Vec<Span>,
+}
+
+impl TimingTrace {
+    //Constructor
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            spans: Vec::new(),
+        }
+    }
+
+    ///Adds a span measured by the host program (presenting the frame, mixing audio, ...)
+    pub fn record(&mut self, name: &str, start: Instant, end: Instant) {
+        self.push(name, "host", start, end.saturating_duration_since(start));
+    }
+
+    ///Adds a frame span followed by the time the subsystems spent in it
+    pub(crate) fn record_frame(&mut self, start: Instant, end: Instant, times: SubsystemTimes) {
+        self.push(FRAME_SPAN, "console", start, end.saturating_duration_since(start));
+
+        let mut cursor = start;
+
+        for (name, duration) in [(CPU_SPAN, times.cpu), (PPU_SPAN, times.ppu), (APU_SPAN, times.apu)] {
+            self.push(name, "console", cursor, duration);
+            cursor += duration;
+        }
+    }
+
+    fn push(&mut self, name: &str, category: &'static str, start: Instant, duration: Duration) {
+        self.spans.push(Span {
+            name: name.to_string(),
+            category,
+            start: start.saturating_duration_since(self.start),
+            duration,
+        });
+    }
+
+    pub fn span_count(&self) -> usize {
+        return self.spans.len();
+    }
+
+    ///Writes the JSON object format: {"traceEvents": [...]}, timestamps in microseconds
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[")?;
+        write!(writer, "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":1,\"args\":{{\"name\":\"RNES\"}}}}")?;
+
+        for span in &self.spans {
+            write!(
+                writer,
+                ",\n{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":1}}",
+                escape(&span.name),
+                span.category,
+                span.start.as_secs_f64() * 1e6,
+                span.duration.as_secs_f64() * 1e6
+            )?;
+        }
+
+        writeln!(writer, "\n]}}")?;
+
+        return writer.flush();
+    }
+}
+
+impl Default for TimingTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///JSON string escaping for span names
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            character if character.is_control() => escaped.push_str(&format!("\\u{:04X}", character as u32)),
+            character => escaped.push(character),
+        }
+    }
+
+    return escaped;
+}
+
+///Which part of the console a clock went to
+#[derive(Clone, Copy)]
+pub(crate) enum Subsystem {
+    Cpu,
+    Ppu,
+    Apu,
+}
+
+///Time the System spent clocking each subsystem since it was last taken
+#[derive(Clone, Copy, Default)]
+pub(crate) struct SubsystemTimes {
+    pub cpu: Duration,
+    pub ppu: Duration,
+    pub apu: Duration,
+}
+
+impl SubsystemTimes {
+    pub fn add(&mut self, subsystem: Subsystem, duration: Duration) {
+        match subsystem {
+            Subsystem::Cpu => self.cpu += duration,
+            Subsystem::Ppu => self.ppu += duration,
+            Subsystem::Apu => self.apu += duration,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(trace: &TimingTrace) -> String {
+        let mut output = Vec::new();
+        trace.write_json(&mut output).unwrap();
+
+        return String::from_utf8(output).unwrap();
+    }
+
+    #[test]
+    fn subsystem_spans_follow_the_frame_start() {
+        let mut trace = TimingTrace::new();
+        let start = trace.start + Duration::from_millis(1);
+        let times = SubsystemTimes {
+            cpu: Duration::from_micros(300),
+            ppu: Duration::from_micros(200),
+            apu: Duration::from_micros(100),
+        };
+
+        trace.record_frame(start, start + Duration::from_millis(16), times);
+
+        let json = json(&trace);
+        assert!(json.contains("\"name\":\"frame\",\"cat\":\"console\",\"ph\":\"X\",\"ts\":1000.000,\"dur\":16000.000"));
+        assert!(json.contains("\"name\":\"cpu\",\"cat\":\"console\",\"ph\":\"X\",\"ts\":1000.000,\"dur\":300.000"));
+        assert!(json.contains("\"name\":\"ppu\",\"cat\":\"console\",\"ph\":\"X\",\"ts\":1300.000,\"dur\":200.000"));
+        assert!(json.contains("\"name\":\"apu\",\"cat\":\"console\",\"ph\":\"X\",\"ts\":1500.000,\"dur\":100.000"));
+    }
+
+    #[test]
+    fn host_span_names_are_escaped() {
+        let mut trace = TimingTrace::new();
+        let now = Instant::now();
+
+        trace.record("say \"hi\"\n", now, now);
+
+        let json = json(&trace);
+        assert!(json.contains("\"name\":\"say \\\"hi\\\"\\u000A\",\"cat\":\"host\""), "{}", json);
+        assert!(json.starts_with("{\"displayTimeUnit\":\"ms\",\"traceEvents\":["));
+        assert!(json.trim_end().ends_with("]}"));
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::{self, Write};
+
+use crate::{cpu::CpuRegisters, disassembler::DisasmLine};
+
+///Writes one line per executed instruction in the nestest.log layout:<br>
+///"C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"<br>
+///Registers, PPU position and CPU cycle count are taken before the instruction runs
+pub(crate) struct Tracer {
+    writer: Box<dyn Write>,
+    error: Option<io::Error>, //First failed write, nothing else is written after it
+}
+
+impl Tracer {
+    //Constructor
+    pub fn new(writer: Box<dyn Write>) -> Self {
+        Self { writer, error: None }
+    }
+
+    pub fn log(&mut self, line: &DisasmLine, registers: CpuRegisters, scanline: i16, cycle: i16, cpu_cycles: u64) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = writeln!(self.writer, "{}", format_line(line, registers, scanline, cycle, cpu_cycles));
+
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+
+    ///Flushes the writer, returns the first error of the whole trace
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        return self.writer.flush();
+    }
+}
+
+pub(crate) fn format_line(
+    line: &DisasmLine,
+    registers: CpuRegisters,
+    scanline: i16,
+    cycle: i16,
+    cpu_cycles: u64,
+) -> String {
+    let bytes: Vec<String> = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+
+    let instruction = if line.operand.is_empty() {
+        line.mnemonic.to_string()
+    } else {
+        format!("{} {}", line.mnemonic, line.operand)
+    };
+
+    return format!(
+        "{:04X}  {:<8}  {:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        line.address,
+        bytes.join(" "),
+        instruction,
+        registers.a,
+        registers.x,
+        registers.y,
+        registers.status,
+        registers.stack_pointer,
+        scanline,
+        cycle,
+        cpu_cycles
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassemble_instruction;
+
+    #[test]
+    fn lines_follow_the_nestest_layout() {
+        let memory = [0x4C, 0xF5, 0xC5];
+        let line = disassemble_instruction(|address| memory[(address - 0xC000) as usize], 0xC000);
+
+        let registers = CpuRegisters {
+            a: 0x00,
+            x: 0x00,
+            y: 0x00,
+            status: 0x24,
+            stack_pointer: 0xFD,
+            program_counter: 0xC000,
+        };
+
+        assert_eq!(
+            format_line(&line, registers, 0, 21, 7),
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use std::io;
+
+///Where a frontend shows the frames: a window, a texture, a hash for regression tests (headless::HashSink)
+pub trait VideoSink {
+    ///One 256x240 frame of 0x00RRGGBB pixels, row by row, as Emulator::frame_buffer() holds it
+    fn present(&mut self, frame: &[u32]) -> io::Result<()>;
+}
+
+///Mixes every frame 50/50 with the one before it, like the slow fade of a CRT's phosphors: sprites a game flickers
+///on alternate frames (to show more than 8 on a line) look steady and half transparent instead of blinking
+#[derive(Default)]
+pub struct FrameBlend {
+    previous: Vec<u32>, //The frame before, as the emulator drew it
+}
+
+impl FrameBlend {
+    //Constructor
+    pub fn new() -> Self {
+        Self { previous: Vec::new() }
+    }
+
+    ///The frame to show, the first one is shown as it is
+    pub fn apply(&mut self, frame: &[u32]) -> Vec<u32> {
+        let output = if self.previous.len() == frame.len() {
+            frame.iter().zip(&self.previous).map(|(&current, &previous)| blend(current, previous)).collect()
+        } else {
+            frame.to_vec()
+        };
+
+        self.previous.clear();
+        self.previous.extend_from_slice(frame);
+
+        return output;
+    }
+}
+
+///Average of two 0x00RRGGBB pixels, channel by channel (rounded down)
+pub fn blend(a: u32, b: u32) -> u32 {
+    //The low bits are dropped before the halving so no channel carries into the next one
+    return (a & b) + (((a ^ b) & 0xFEFEFE) >> 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blend_averages_every_channel() {
+        assert_eq!(blend(0xFF0000, 0x0000FF), 0x7F007F);
+        assert_eq!(blend(0x102030, 0x102030), 0x102030);
+        assert_eq!(blend(0xFFFFFF, 0x000000), 0x7F7F7F);
+        assert_eq!(blend(0x010101, 0x020202), 0x010101);
+    }
+
+    #[test]
+    fn frames_are_mixed_with_the_one_before() {
+        let mut frames = FrameBlend::new();
+
+        assert_eq!(frames.apply(&[0xFF0000, 0x000000]), [0xFF0000, 0x000000]);
+        assert_eq!(frames.apply(&[0x000000, 0x00FF00]), [0x7F0000, 0x007F00]);
+
+        //Mixed with the frame the emulator drew, not with the blended one
+        assert_eq!(frames.apply(&[0x000000, 0x00FF00]), [0x000000, 0x00FF00]);
+    }
+}
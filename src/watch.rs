@@ -0,0 +1,208 @@
+use std::{fmt, str::FromStr};
+
+use crate::cpu::CpuRegisters;
+
+///Identifier returned when a watch expression is added, used to remove it later
+pub type WatchId = usize;
+
+///CPU register read by a watch expression
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    P,  //Status
+    S,  //Stack pointer
+    PC, //Program counter, the only 16 bit register
+}
+
+impl Register {
+    const ALL: [Register; 6] = [Register::A, Register::X, Register::Y, Register::P, Register::S, Register::PC];
+
+    fn read(&self, registers: &CpuRegisters) -> u16 {
+        match self {
+            Register::A => return registers.a as u16,
+            Register::X => return registers.x as u16,
+            Register::Y => return registers.y as u16,
+            Register::P => return registers.status as u16,
+            Register::S => return registers.stack_pointer as u16,
+            Register::PC => return registers.program_counter,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Register::A => "A",
+            Register::X => "X",
+            Register::Y => "Y",
+            Register::P => "P",
+            Register::S => "S",
+            Register::PC => "PC",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+///What a watch expression reads
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchSource {
+    Byte(u16),                        //One byte of CPU memory
+    Word(u16),                        //16 bit little endian pair: the byte at the address is the low byte
+    Register(Register),               //8 bits, 16 for PC
+    RegisterPair(Register, Register), //High byte and low byte, X:A is X * 256 + A
+}
+
+///How the value of a watch expression is shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum WatchFormat {
+    #[default]
+    Hex,
+    Unsigned,
+    Signed, //Two's complement at the width of the value
+}
+
+///A value the debugger shows after every frame or step, see Emulator::add_watch()<br>
+///Written as the source followed by an optional format:
+///- `$0010` byte, `$0010.w` 16 bit little endian pair
+///- `A`, `X`, `Y`, `P`, `S`, `PC` registers, `X:A` two 8 bit registers as the high and low byte
+///- `,x` hex (default), `,u` unsigned, `,s` signed: `$0010,s`, `X:A,u`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WatchExpression {
+    pub source: WatchSource,
+    pub format: WatchFormat,
+}
+
+impl WatchExpression {
+    pub fn new(source: WatchSource, format: WatchFormat) -> Self {
+        Self { source, format }
+    }
+
+    ///8 or 16
+    pub fn bits(&self) -> u32 {
+        match self.source {
+            WatchSource::Byte(_) => return 8,
+            WatchSource::Register(register) if register != Register::PC => return 8,
+            _ => return 16,
+        }
+    }
+
+    ///Reads the value through `peek` (side effect free memory reads) and the registers
+    pub fn evaluate(&self, peek: impl Fn(u16) -> u8, registers: &CpuRegisters) -> u16 {
+        match self.source {
+            WatchSource::Byte(address) => return peek(address) as u16,
+            WatchSource::Word(address) => {
+                return u16::from_le_bytes([peek(address), peek(address.wrapping_add(1))]);
+            }
+            WatchSource::Register(register) => return register.read(registers),
+            WatchSource::RegisterPair(high, low) => {
+                return (high.read(registers) << 8) | (low.read(registers) & 0x00FF);
+            }
+        }
+    }
+
+    ///Shows a value read by evaluate() in the expression's format: "$FF", "255" or "-1"
+    pub fn format_value(&self, value: u16) -> String {
+        match (self.format, self.bits()) {
+            (WatchFormat::Hex, 8) => return format!("${:02X}", value),
+            (WatchFormat::Hex, _) => return format!("${:04X}", value),
+            (WatchFormat::Unsigned, _) => return value.to_string(),
+            (WatchFormat::Signed, 8) => return (value as u8 as i8).to_string(),
+            (WatchFormat::Signed, _) => return (value as i16).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for WatchExpression {
+    ///The syntax parsed by from_str(), the default format is left out
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.source {
+            WatchSource::Byte(address) => write!(f, "${:04X}", address)?,
+            WatchSource::Word(address) => write!(f, "${:04X}.w", address)?,
+            WatchSource::Register(register) => write!(f, "{}", register)?,
+            WatchSource::RegisterPair(high, low) => write!(f, "{}:{}", high, low)?,
+        }
+
+        match self.format {
+            WatchFormat::Hex => Ok(()),
+            WatchFormat::Unsigned => write!(f, ",u"),
+            WatchFormat::Signed => write!(f, ",s"),
+        }
+    }
+}
+
+impl FromStr for WatchExpression {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid watch expression {} (try $0010, $0010.w, A or X:A, then ,s or ,u)", text);
+
+        let (source, format) = match text.trim().rsplit_once(',') {
+            Some((source, format)) => {
+                let format = match format.trim().to_ascii_lowercase().as_str() {
+                    "x" => WatchFormat::Hex,
+                    "u" => WatchFormat::Unsigned,
+                    "s" => WatchFormat::Signed,
+                    _ => return Err(invalid()),
+                };
+
+                (source.trim(), format)
+            }
+            None => (text.trim(), WatchFormat::Hex),
+        };
+
+        let register = |name: &str| Register::ALL.into_iter().find(|register| register.to_string().eq_ignore_ascii_case(name));
+        let address = |digits: &str| u16::from_str_radix(digits, 16).ok();
+
+        let source = if let Some(digits) = source.strip_prefix('$') {
+            match digits.strip_suffix(".w").or_else(|| digits.strip_suffix(".W")) {
+                Some(digits) => WatchSource::Word(address(digits).ok_or_else(invalid)?),
+                None => WatchSource::Byte(address(digits).ok_or_else(invalid)?),
+            }
+        } else if let Some((high, low)) = source.split_once(':') {
+            match (register(high), register(low)) {
+                //PC doesn't fit in a byte
+                (Some(high), Some(low)) if high != Register::PC && low != Register::PC => {
+                    WatchSource::RegisterPair(high, low)
+                }
+                _ => return Err(invalid()),
+            }
+        } else {
+            WatchSource::Register(register(source).ok_or_else(invalid)?)
+        };
+
+        Ok(Self { source, format })
+    }
+}
+
+///A watch expression evaluated, see Emulator::watches()
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WatchValue {
+    pub id: WatchId,
+    pub expression: WatchExpression,
+    pub value: u16,
+    pub text: String, //The value in the expression's format
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expressions_round_trip() {
+        for text in ["$0010", "$07FE.w,s", "A,u", "PC", "X:A"] {
+            assert_eq!(text.parse::<WatchExpression>().unwrap().to_string(), text);
+        }
+
+        assert_eq!(
+            " x:a , S ".parse::<WatchExpression>(),
+            Ok(WatchExpression::new(WatchSource::RegisterPair(Register::X, Register::A), WatchFormat::Signed))
+        );
+
+        for text in ["", "$", "$10000", "Q", "PC:A", "A,z"] {
+            assert!(text.parse::<WatchExpression>().is_err(), "{}", text);
+        }
+    }
+}
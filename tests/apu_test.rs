@@ -0,0 +1,73 @@
+#![allow(clippy::needless_return)]
+
+//! blargg's apu_test harness<br>
+//! Runs every ROM of apu_test/rom_singles and reads the result the ROMs leave in PRG-RAM: $6000 is the status
+//! ($80 while running, $81 asks for a reset, 0 passed), $6001 - $6003 hold DE B0 61 once it is valid and the
+//! text from $6004 on explains a failure<br>
+//! The ROMs aren't part of the repository: put them in tests/roms/apu_test/ and run `cargo test -- --ignored`<br>
+//! The length counter, envelope, sweep and DMC details they check are covered by the hand-written tests in src/apu.rs
+
+use rnes::Emulator;
+
+const ROM_FOLDER: &str = "tests/roms/apu_test";
+
+const ROMS: [&str; 8] = [
+    "1-len_ctr",
+    "2-len_table",
+    "3-irq_flag",
+    "4-jitter",
+    "5-len_timing",
+    "6-irq_flag_timing",
+    "7-dmc_basics",
+    "8-dmc_rates",
+];
+
+//Every ROM finishes in a few seconds
+const FRAME_LIMIT: u32 = 60 * 20;
+
+///Runs one ROM to the end, Err holds the status and the text it printed
+fn run(name: &str) -> Result<(), String> {
+    let path = format!("{}/{}.nes", ROM_FOLDER, name);
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom(&path).map_err(|error| format!("{} can't be loaded: {}", path, error))?;
+
+    for _ in 0..FRAME_LIMIT {
+        emulator.step_frame();
+
+        if [emulator.peek(0x6001), emulator.peek(0x6002), emulator.peek(0x6003)] != [0xDE, 0xB0, 0x61] {
+            continue;
+        }
+
+        match emulator.peek(0x6000) {
+            0x80 => {}
+            //The reset has to come at least 100ms later
+            0x81 => {
+                for _ in 0..6 {
+                    emulator.step_frame();
+                }
+
+                emulator.reset();
+            }
+            0x00 => return Ok(()),
+            status => {
+                let text: Vec<u8> = (0x6004..0x7000)
+                    .map(|address| emulator.peek(address))
+                    .take_while(|&byte| byte != 0)
+                    .collect();
+
+                return Err(format!("{}: failed with {}\n{}", name, status, String::from_utf8_lossy(&text).trim()));
+            }
+        }
+    }
+
+    return Err(format!("{}: still running after {} frames", name, FRAME_LIMIT));
+}
+
+#[test]
+#[ignore = "needs blargg's apu_test ROMs in tests/roms/apu_test/"]
+fn apu_test() {
+    let failures: Vec<String> = ROMS.iter().filter_map(|name| run(name).err()).collect();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
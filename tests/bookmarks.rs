@@ -0,0 +1,80 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::Emulator;
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::COUNTER)).unwrap();
+
+    return emulator;
+}
+
+#[test]
+fn jumping_to_a_bookmark_restores_its_state_and_frame() {
+    let mut emulator = emulator();
+
+    emulator.step_frame();
+    let frame = emulator.add_bookmark("start").unwrap();
+    let state = emulator.save_state().unwrap();
+
+    for _ in 0..5 {
+        emulator.step_frame();
+    }
+    assert_eq!(emulator.frame_number(), frame + 5);
+
+    assert!(emulator.jump_to_bookmark("start").unwrap());
+    assert_eq!(emulator.frame_number(), frame);
+    assert!(emulator.save_state().unwrap() == state);
+
+    assert!(!emulator.jump_to_bookmark("missing").unwrap());
+}
+
+#[test]
+fn bookmarks_are_ordered_by_frame_and_replaced_by_name() {
+    let mut emulator = emulator();
+
+    emulator.add_bookmark("b").unwrap();
+    for _ in 0..3 {
+        emulator.step_frame();
+    }
+    emulator.add_bookmark("c").unwrap();
+    emulator.jump_to_bookmark("b").unwrap();
+    emulator.step_frame();
+    emulator.add_bookmark("a").unwrap();
+
+    let names: Vec<&str> = emulator.bookmarks().iter().map(|bookmark| bookmark.name.as_str()).collect();
+    assert_eq!(names, ["b", "a", "c"]);
+
+    //Taking "b" again later moves it
+    emulator.jump_to_bookmark("c").unwrap();
+    emulator.step_frame();
+    let frame = emulator.add_bookmark("b").unwrap();
+    assert_eq!(emulator.bookmarks().len(), 3);
+    assert_eq!(emulator.bookmarks()[2].frame, frame);
+
+    assert!(emulator.remove_bookmark("a"));
+    assert!(!emulator.remove_bookmark("a"));
+}
+
+#[test]
+fn next_and_previous_jump_between_bookmarks() {
+    let mut emulator = emulator();
+
+    for name in ["one", "two", "three"] {
+        emulator.add_bookmark(name).unwrap();
+        emulator.step_frame();
+        emulator.step_frame();
+    }
+
+    //Past "three"
+    assert_eq!(emulator.next_bookmark().unwrap(), None);
+    assert_eq!(emulator.previous_bookmark().unwrap().as_deref(), Some("three"));
+    assert_eq!(emulator.previous_bookmark().unwrap().as_deref(), Some("two"));
+    assert_eq!(emulator.next_bookmark().unwrap().as_deref(), Some("three"));
+
+    //Loading another game drops them
+    emulator.load_rom_bytes(&common::rom(common::COUNTER)).unwrap();
+    assert!(emulator.bookmarks().is_empty());
+}
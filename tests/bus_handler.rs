@@ -0,0 +1,48 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use rnes::{Emulator, HANDLER_RANGE};
+
+#[test]
+fn the_game_reads_and_writes_a_host_device() {
+    let program = [
+        0xA9, 0x42, 0x8D, 0x00, 0x41, //LDA #$42, STA $4100
+        0xAD, 0x01, 0x41, 0x85, 0x10, //LDA $4101, STA $10
+        0x4C, 0x0A, 0xC0, //JMP $C00A
+    ];
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(&program)).unwrap();
+
+    let written = Rc::new(RefCell::new(Vec::new()));
+    let log = written.clone();
+    let id = emulator
+        .register_bus_handler(0x4100..=0x41FF, |address| address as u8, move |address, data| {
+            log.borrow_mut().push((address, data))
+        })
+        .unwrap();
+
+    emulator.step_frame();
+
+    assert_eq!(*written.borrow(), vec![(0x4100, 0x42)]);
+    assert_eq!(emulator.peek(0x0010), 0x01);
+
+    assert!(emulator.remove_bus_handler(id));
+    assert!(!emulator.remove_bus_handler(id));
+}
+
+#[test]
+fn handlers_stay_inside_the_unused_range_and_apart() {
+    let mut emulator = Emulator::new();
+    let register = |emulator: &mut Emulator, range| emulator.register_bus_handler(range, |_| 0, |_, _| {});
+
+    //Over the APU and I/O registers, over the cartridge space
+    assert!(register(&mut emulator, 0x4017..=0x4020).is_none());
+    assert!(register(&mut emulator, 0x5F00..=0x6000).is_none());
+
+    assert!(register(&mut emulator, HANDLER_RANGE).is_some());
+    assert!(register(&mut emulator, 0x4100..=0x4100).is_none());
+}
@@ -0,0 +1,30 @@
+//Every test file compiles its own copy of this module and uses only some of it
+#![allow(dead_code)]
+
+///Sets the universal background color to $21 and loops
+pub const BLUE_BACKGROUND: &[u8] = &[
+    0xA9, 0x3F, 0x8D, 0x06, 0x20, //LDA #$3F, STA $2006
+    0xA9, 0x00, 0x8D, 0x06, 0x20, //LDA #$00, STA $2006
+    0xA9, 0x21, 0x8D, 0x07, 0x20, //LDA #$21, STA $2007
+    0x4C, 0x0F, 0xC0, //JMP $C00F
+];
+
+///Counts $0010 up every instruction
+pub const COUNTER: &[u8] = &[
+    0xE6, 0x10, //INC $10
+    0x4C, 0x00, 0xC0, //JMP $C000
+];
+
+///NROM-128 image with CHR-RAM running the program at $C000 (reset vector)
+pub fn rom(program: &[u8]) -> Vec<u8> {
+    let mut prg = vec![0xEA; 0x4000];
+    prg[..program.len()].copy_from_slice(program);
+    prg[0x3FFC] = 0x00;
+    prg[0x3FFD] = 0xC0;
+
+    let mut data = b"NES\x1A\x01\x00".to_vec();
+    data.resize(16, 0);
+    data.extend(prg);
+
+    return data;
+}
@@ -0,0 +1,42 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::Emulator;
+
+#[test]
+fn coverage_marks_the_instructions_that_ran() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::COUNTER)).unwrap();
+    assert!(emulator.coverage().is_none());
+
+    emulator.enable_coverage();
+    emulator.step_frame();
+
+    let coverage = emulator.coverage().unwrap();
+
+    //INC $10 and JMP $C000, nothing after them
+    assert!((0xC000..=0xC004).all(|address| coverage.is_executed(address)));
+    assert!(!coverage.is_executed(0xC005));
+    assert_eq!(coverage.executed_address_count(), 5);
+
+    let opcodes: Vec<u8> = coverage.executed_opcodes().iter().map(|(opcode, _)| *opcode).collect();
+    assert_eq!(opcodes, vec![0x4C, 0xE6]);
+    assert_eq!(coverage.opcode_count(0x4C) + coverage.opcode_count(0xE6), coverage.instruction_count());
+
+    let mut csv = Vec::new();
+    coverage.export(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert!(csv.starts_with("opcode,count\n$4C,"));
+    assert!(csv.ends_with("start,end\n$C000,$C004\n"));
+
+    //Clearing starts over, disabling hands the coverage back
+    emulator.clear_coverage();
+    assert_eq!(emulator.coverage().unwrap().instruction_count(), 0);
+
+    //The first step finishes the instruction the frame ended in, which was already counted
+    emulator.step_instruction();
+    emulator.step_instruction();
+    assert_eq!(emulator.disable_coverage().unwrap().instruction_count(), 1);
+    assert!(emulator.coverage().is_none());
+}
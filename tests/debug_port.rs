@@ -0,0 +1,81 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::rom;
+use rnes::{debug_port::DebugPortConfig, Emulator};
+
+///LDA #byte, STA address for every byte, then loops forever
+fn writes(bytes: &[(u16, u8)]) -> Vec<u8> {
+    let mut program = Vec::new();
+
+    for &(address, byte) in bytes {
+        program.extend([0xA9, byte, 0x8D, address as u8, (address >> 8) as u8]);
+    }
+
+    let loop_address = 0xC000 + program.len() as u16;
+    program.extend([0x4C, loop_address as u8, (loop_address >> 8) as u8]);
+
+    return program;
+}
+
+fn quiet() -> DebugPortConfig {
+    DebugPortConfig {
+        echo: false,
+        ..DebugPortConfig::default()
+    }
+}
+
+#[test]
+fn captures_printed_lines_and_the_exit_code() {
+    let program = writes(&[(0x4018, b'o'), (0x4018, b'k'), (0x4018, b'\n'), (0x4018, b'!'), (0x401A, 3)]);
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&rom(&program)).unwrap();
+    assert!(emulator.enable_debug_port(quiet()));
+
+    assert_eq!(emulator.run_until_exit(10), Some(3));
+    //The unfinished line isn't returned
+    assert_eq!(emulator.debug_output(), "ok\n");
+    assert_eq!(emulator.debug_output(), "");
+}
+
+#[test]
+fn times_out_without_an_exit_write() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&rom(&writes(&[]))).unwrap();
+    assert!(emulator.enable_debug_port(quiet()));
+
+    assert_eq!(emulator.run_until_exit(2), None);
+    assert_eq!(emulator.exit_code(), None);
+}
+
+#[test]
+fn rejects_addresses_outside_the_unused_range() {
+    let mut emulator = Emulator::new();
+
+    let config = DebugPortConfig {
+        print_address: 0x2000,
+        ..quiet()
+    };
+    assert!(!emulator.enable_debug_port(config));
+
+    //The exit handler registered before the failure was removed again
+    let config = DebugPortConfig {
+        print_address: 0x5000,
+        ..quiet()
+    };
+    assert!(emulator.enable_debug_port(config));
+}
+
+#[test]
+fn reset_clears_the_exit_code() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&rom(&writes(&[(0x401A, 1)]))).unwrap();
+    assert!(emulator.enable_debug_port(quiet()));
+
+    assert_eq!(emulator.run_until_exit(10), Some(1));
+
+    emulator.reset();
+    assert_eq!(emulator.exit_code(), None);
+}
@@ -0,0 +1,108 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::{cell::Cell, rc::Rc};
+
+use common::rom;
+use rnes::{DebugHit, Emulator, WatchKind};
+
+//C000 LDA #$05
+//C002 STA $0010
+//C005 LDA $0010
+//C008 JMP $C008
+const PROGRAM: [u8; 11] = [0xA9, 0x05, 0x8D, 0x10, 0x00, 0xAD, 0x10, 0x00, 0x4C, 0x08, 0xC0];
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&rom(&PROGRAM)).unwrap();
+
+    //Finish the reset sequence
+    emulator.step_instruction();
+    assert_eq!(emulator.get_program_counter(), 0xC000);
+
+    return emulator;
+}
+
+#[test]
+fn stops_before_a_breakpoint_and_resumes_from_it() {
+    let mut emulator = emulator();
+    let id = emulator.add_breakpoint(0xC008);
+
+    assert_eq!(emulator.run_until_break(1), Some(DebugHit::Breakpoint { id, address: 0xC008 }));
+    assert_eq!(emulator.get_program_counter(), 0xC008);
+    assert_eq!(emulator.peek(0x0010), 0x05);
+
+    //The JMP at the breakpoint runs and lands on it again
+    assert_eq!(emulator.run_until_break(1), Some(DebugHit::Breakpoint { id, address: 0xC008 }));
+
+    assert!(emulator.remove_breakpoint(id));
+    assert_eq!(emulator.run_until_break(1), None);
+}
+
+#[test]
+fn watchpoints_stop_after_the_access() {
+    let mut emulator = emulator();
+    let write = emulator.add_watchpoint(0x0010..=0x0010, WatchKind::Write);
+    let read = emulator.add_watchpoint(0x0000..=0x00FF, WatchKind::Read);
+
+    assert_eq!(
+        emulator.run_until_break(1),
+        Some(DebugHit::Watchpoint { id: write, address: 0x0010, value: 0x05, write: true })
+    );
+    assert_eq!(emulator.get_program_counter(), 0xC005);
+
+    assert_eq!(
+        emulator.run_until_break(1),
+        Some(DebugHit::Watchpoint { id: read, address: 0x0010, value: 0x05, write: false })
+    );
+    assert_eq!(emulator.get_program_counter(), 0xC008);
+}
+
+#[test]
+fn run_to_and_the_callback() {
+    let mut emulator = emulator();
+
+    let hits = Rc::new(Cell::new(0));
+    let counter = hits.clone();
+    emulator.set_debug_callback(move |_| counter.set(counter.get() + 1));
+
+    assert_eq!(emulator.run_to(0xC005, 1), Some(DebugHit::RunTo { address: 0xC005 }));
+    assert_eq!(hits.get(), 1);
+
+    //The one-shot breakpoint is gone
+    assert_eq!(emulator.run_until_break(1), None);
+    assert_eq!(hits.get(), 1);
+}
+
+#[test]
+fn watch_expressions_are_read_after_every_step() {
+    let mut emulator = emulator();
+
+    let byte = emulator.add_watch("$0010".parse().unwrap());
+    let signed = emulator.add_watch("$0010,s".parse().unwrap());
+    let word = emulator.add_watch("$000F.w".parse().unwrap());
+    let pair = emulator.add_watch("A:X,u".parse().unwrap());
+
+    let texts = |emulator: &Emulator| -> Vec<String> { emulator.watches().into_iter().map(|watch| watch.text).collect() };
+
+    assert_eq!(texts(&emulator), ["$00", "0", "$0000", "0"]);
+
+    //LDA #$05, STA $0010
+    emulator.step_instruction();
+    emulator.step_instruction();
+
+    assert_eq!(texts(&emulator), ["$05", "5", "$0500", "1280"]);
+    assert_eq!(emulator.watches()[2].value, 0x0500);
+
+    emulator.poke(0x0010, 0xFE);
+    assert_eq!(emulator.watches()[1].text, "-2");
+
+    assert!(emulator.remove_watch(signed));
+    assert!(!emulator.remove_watch(signed));
+    let ids: Vec<_> = emulator.watches().iter().map(|watch| watch.id).collect();
+    assert_eq!(ids, [byte, word, pair]);
+
+    emulator.clear_watches();
+    assert!(emulator.watches().is_empty());
+}
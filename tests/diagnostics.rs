@@ -0,0 +1,75 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::{Diagnostic, Emulator, EmulatorEvent, PauseReason};
+
+///MMC3 image with 32KB PRG running the program at $E000 (the fixed last bank)
+fn mmc3_rom(program: &[u8]) -> Vec<u8> {
+    let mut prg = vec![0xEA; 0x8000];
+    prg[0x6000..0x6000 + program.len()].copy_from_slice(program);
+    prg[0x7FFC] = 0x00;
+    prg[0x7FFD] = 0xE0;
+
+    let mut data = b"NES\x1A\x02\x00\x40".to_vec();
+    data.resize(16, 0);
+    data.extend(prg);
+
+    return data;
+}
+
+#[test]
+fn unimplemented_opcodes_are_logged_once() {
+    let mut emulator = Emulator::new();
+
+    //Two JAMs (run as NOPs here) in a loop
+    emulator.load_rom_bytes(&common::rom(&[0x02, 0x02, 0x4C, 0x00, 0xC0])).unwrap();
+    emulator.step_frame();
+    emulator.step_frame();
+
+    let expected = Diagnostic::UnimplementedOpcode {
+        opcode: 0x02,
+        address: 0xC000,
+    };
+
+    assert_eq!(emulator.diagnostics(), vec![expected]);
+    assert_eq!(emulator.take_events(), vec![EmulatorEvent::Diagnostic(expected)]);
+    assert!(!emulator.is_paused());
+
+    //Loading a ROM starts a new log
+    emulator.load_rom_bytes(&common::rom(&[0x4C, 0x00, 0xC0])).unwrap();
+    assert!(emulator.diagnostics().is_empty());
+}
+
+#[test]
+fn unsupported_board_registers_can_pause() {
+    let mut emulator = Emulator::new();
+    emulator.set_pause_on_diagnostic(true);
+
+    //LDA #$40, STA $A001 (write protect the PRG-RAM), JMP $E005
+    emulator.load_rom_bytes(&mmc3_rom(&[0xA9, 0x40, 0x8D, 0x01, 0xA0, 0x4C, 0x05, 0xE0])).unwrap();
+
+    assert!(emulator.run_frame());
+    assert!(!emulator.run_frame());
+
+    let expected = Diagnostic::UnsupportedRegister {
+        mapper: 4,
+        register: "PRG-RAM protection",
+        address: 0xA001,
+        data: 0x40,
+        program_counter: 0xE002,
+    };
+
+    assert_eq!(emulator.pause_reason(), Some(PauseReason::Diagnostic(expected)));
+    assert_eq!(
+        expected.to_string(),
+        "mapper 4: PRG-RAM protection is not emulated ($40 written to $A001 by the instruction at $E002)"
+    );
+
+    //Clearing the log reports the gap again once the game repeats it
+    emulator.resume();
+    emulator.clear_diagnostics();
+    emulator.reset();
+    emulator.step_frame();
+    assert_eq!(emulator.diagnostics(), vec![expected]);
+}
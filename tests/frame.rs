@@ -0,0 +1,78 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::{CaptureColors, Emulator, Palette, PixelFormat, PALETTE_2C02, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+#[test]
+fn every_format_holds_the_same_frame() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::BLUE_BACKGROUND)).unwrap();
+    emulator.step_frame();
+    emulator.step_frame();
+
+    let pixels = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+    let indices = emulator.frame(PixelFormat::PaletteIndex);
+    assert_eq!(indices.len(), pixels);
+    assert!(indices.iter().all(|&index| index == 0x21));
+
+    let rgba = emulator.frame(PixelFormat::Rgba8);
+    let bgra = emulator.frame(PixelFormat::Bgra8);
+    let rgb565 = emulator.frame(PixelFormat::Rgb565);
+    assert_eq!((rgba.len(), bgra.len(), rgb565.len()), (pixels * 4, pixels * 4, pixels * 2));
+
+    let argb = emulator.frame_buffer()[0];
+    assert_eq!(&rgba[..4], &[(argb >> 16) as u8, (argb >> 8) as u8, argb as u8, 0xFF]);
+    assert_eq!(&bgra[..4], &[argb as u8, (argb >> 8) as u8, (argb >> 16) as u8, 0xFF]);
+
+    //A reused buffer must fit a whole frame
+    let mut small = vec![0; pixels];
+    assert!(!emulator.copy_frame(PixelFormat::Rgb565, &mut small));
+    assert!(emulator.copy_frame(PixelFormat::PaletteIndex, &mut small));
+}
+
+#[test]
+fn loaded_palettes_color_the_frame() {
+    let mut pal = vec![0; 192];
+    pal[0x21 * 3..0x21 * 3 + 3].copy_from_slice(&[0x12, 0x34, 0x56]);
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::BLUE_BACKGROUND)).unwrap();
+    emulator.set_palette(Palette::from_pal(&pal).unwrap());
+    emulator.step_frame();
+    emulator.step_frame();
+
+    assert_eq!(emulator.frame_buffer()[0], 0x123456);
+    assert_eq!(&emulator.frame(PixelFormat::Rgba8)[..4], &[0x12, 0x34, 0x56, 0xFF]);
+
+    emulator.set_palette(Palette::default());
+    emulator.step_frame();
+    assert_eq!(emulator.frame_buffer()[0], PALETTE_2C02[0x21]);
+}
+
+#[test]
+fn captures_match_the_displayed_frame() {
+    let mut pal = vec![0; 192];
+    pal[0x21 * 3..0x21 * 3 + 3].copy_from_slice(&[0x12, 0x34, 0x56]);
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::BLUE_BACKGROUND)).unwrap();
+    emulator.set_palette(Palette::from_pal(&pal).unwrap());
+    emulator.step_frame();
+    emulator.step_frame();
+
+    //Screenshots use the loaded palette, the same as the window
+    let display = emulator.capture(CaptureColors::Display);
+    assert_eq!(display.pixels.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+    assert!(display.pixels.chunks(3).all(|pixel| pixel == [0x12, 0x34, 0x56]));
+
+    let indexed = emulator.capture(CaptureColors::Indexed);
+    assert!(indexed.pixels.iter().all(|&index| index == 0x21));
+    assert_eq!(&indexed.palette[0x21 * 3..0x21 * 3 + 3], &[0x12, 0x34, 0x56]);
+
+    let mut png = Vec::new();
+    display.write_png(&mut png).unwrap();
+    assert!(png.starts_with(b"\x89PNG\r\n\x1A\n"));
+    assert!(png.ends_with(b"IEND\xAE\x42\x60\x82"));
+}
@@ -0,0 +1,67 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::{checksum::crc32, Emulator};
+
+///Overwrites the 4 bytes at offset (in the PRG-ROM) so the image's PRG-ROM and CHR-ROM hash to the CRC<br>
+///A CRC-32 is affine in the bits of the message, so the bytes are the solution of a 32x32 system over GF(2)
+fn forge(rom: &mut [u8], offset: usize, target: u32) {
+    let hash = |rom: &[u8]| crc32(&rom[16..]);
+
+    rom[offset..offset + 4].fill(0);
+    let base = hash(rom);
+
+    //The change of the CRC for every bit, kept reduced by highest set bit along with the bits that produce it
+    let mut basis: [Option<(u32, u32)>; 32] = [None; 32];
+
+    for bit in 0..32 {
+        rom[offset + bit / 8] ^= 1 << (bit % 8);
+        let (mut change, mut bits) = (hash(rom) ^ base, 1u32 << bit);
+        rom[offset + bit / 8] ^= 1 << (bit % 8);
+
+        for top in (0..32).rev() {
+            if (change >> top) & 0x01 == 0 {
+                continue;
+            }
+
+            match basis[top] {
+                Some((other_change, other_bits)) => {
+                    change ^= other_change;
+                    bits ^= other_bits;
+                }
+                None => {
+                    basis[top] = Some((change, bits));
+                    break;
+                }
+            }
+        }
+    }
+
+    let (mut remaining, mut bits) = (target ^ base, 0u32);
+    for top in (0..32).rev() {
+        if (remaining >> top) & 0x01 != 0 {
+            let (change, other_bits) = basis[top].expect("every CRC can be reached");
+            remaining ^= change;
+            bits ^= other_bits;
+        }
+    }
+
+    rom[offset..offset + 4].copy_from_slice(&bits.to_le_bytes());
+}
+
+#[test]
+fn the_title_comes_from_the_database() {
+    let mut emulator = Emulator::new();
+    assert_eq!(emulator.game_title(), None);
+
+    let mut rom = common::rom(common::COUNTER);
+    emulator.load_rom_bytes(&rom).unwrap();
+    assert_eq!(emulator.game_title(), None);
+
+    //The bytes past the program are never run
+    forge(&mut rom, 16 + 0x100, 0x3FE272FB);
+    emulator.load_rom_bytes(&rom).unwrap();
+    assert_eq!(emulator.rom_crc32(), Some(0x3FE272FB));
+    assert_eq!(emulator.game_title(), Some("The Legend of Zelda"));
+}
@@ -0,0 +1,77 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::{cell::Cell, rc::Rc};
+
+use rnes::{
+    audio::AudioSink,
+    headless::{frame_crc32, HashSink, HeadlessRunner},
+    video::VideoSink,
+    PALETTE_2C02, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+///Counts the samples it gets
+struct CountingSink {
+    samples: Rc<Cell<usize>>,
+}
+
+impl AudioSink for CountingSink {
+    fn sample_rate(&self) -> u32 {
+        return 48_000;
+    }
+
+    fn queue(&mut self, samples: &[f32]) {
+        self.samples.set(self.samples.get() + samples.len());
+    }
+}
+
+#[test]
+fn frames_are_hashed_as_they_run() {
+    let mut runner = HeadlessRunner::new(&common::rom(common::BLUE_BACKGROUND)).unwrap();
+
+    let blue = frame_crc32(&vec![PALETTE_2C02[0x21]; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+    runner.assert_frame_hash(2, blue);
+    assert_eq!(runner.frame_count(), 2);
+
+    //Already past frame 2, nothing more runs
+    assert_eq!(runner.run_until(1), blue);
+    assert_eq!(runner.frame_count(), 2);
+
+    runner.run(3);
+    assert_eq!(runner.frame_hashes().len(), 5);
+    assert!(runner.frame_hashes()[1..].iter().all(|&hash| hash == blue));
+}
+
+#[test]
+#[should_panic(expected = "frame 3 hashes to")]
+fn a_different_frame_fails_the_assertion() {
+    let mut runner = HeadlessRunner::new(&common::rom(common::BLUE_BACKGROUND)).unwrap();
+
+    runner.assert_frame_hash(3, 0x12345678);
+}
+
+#[test]
+fn the_audio_sink_gets_every_frame_of_samples() {
+    let mut runner = HeadlessRunner::new(&common::rom(common::BLUE_BACKGROUND)).unwrap();
+
+    let samples = Rc::new(Cell::new(0));
+    runner.set_audio_sink(CountingSink { samples: samples.clone() });
+    assert_eq!(runner.emulator().get_sample_rate(), 48_000);
+
+    //60 NTSC frames are about a second
+    runner.run(60);
+    assert!((47_000..=49_000).contains(&samples.get()), "{}", samples.get());
+}
+
+#[test]
+fn the_hash_sink_hashes_what_it_is_shown() {
+    let mut sink = HashSink::new();
+    let black = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+    let blue = vec![PALETTE_2C02[0x21]; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+    sink.present(&black).unwrap();
+    sink.present(&blue).unwrap();
+    assert_eq!(sink.hashes(), [frame_crc32(&black), frame_crc32(&blue)]);
+}
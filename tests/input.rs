@@ -0,0 +1,119 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use rnes::{Button, Emulator, FourScore, InputDevice};
+
+///Expansion port device that records the output latch and answers D1 on $4016 and OUT0 - OUT2 on $4017 (D2 - D4)
+struct Probe {
+    outputs: Rc<RefCell<Vec<u8>>>,
+    output: u8,
+}
+
+impl InputDevice for Probe {
+    fn write_output(&mut self, output: u8) {
+        self.output = output;
+        self.outputs.borrow_mut().push(output);
+    }
+
+    fn read(&mut self, port: usize) -> u8 {
+        match port {
+            0 => return 0x02,
+            _ => return self.output << 2,
+        }
+    }
+}
+
+#[test]
+fn the_expansion_port_sees_the_latch_and_drives_both_ports() {
+    let program = [
+        0xA9, 0x05, 0x8D, 0x16, 0x40, //LDA #$05, STA $4016 (OUT0 and OUT2)
+        0xAD, 0x16, 0x40, 0x85, 0x10, //LDA $4016, STA $10
+        0xAD, 0x17, 0x40, 0x85, 0x11, //LDA $4017, STA $11
+        0x4C, 0x0F, 0xC0, //JMP $C00F
+    ];
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(&program)).unwrap();
+
+    let outputs = Rc::new(RefCell::new(Vec::new()));
+    emulator.connect_expansion_device(Probe {
+        outputs: outputs.clone(),
+        output: 0,
+    });
+
+    emulator.step_frame();
+
+    //The latch at connection time, then the write. The controllers return A (released) and the upper bits are open bus
+    assert_eq!(*outputs.borrow(), vec![0x00, 0x05]);
+    assert_eq!(emulator.peek(0x0010), 0x42);
+    assert_eq!(emulator.peek(0x0011), 0x54);
+
+    assert!(emulator.disconnect_expansion_device());
+    assert!(!emulator.disconnect_expansion_device());
+}
+
+#[test]
+fn only_two_controller_ports_exist() {
+    let mut emulator = Emulator::new();
+    let probe = || Probe {
+        outputs: Rc::new(RefCell::new(Vec::new())),
+        output: 0,
+    };
+
+    assert!(emulator.connect_input_device(1, probe()));
+    assert!(!emulator.connect_input_device(2, probe()));
+
+    assert!(emulator.disconnect_input_device(1));
+    assert!(!emulator.disconnect_input_device(1));
+    assert!(!emulator.disconnect_input_device(2));
+}
+
+#[test]
+fn four_score_reads_two_players_and_a_signature_per_port() {
+    //Strobes, then stores bit 0 of 24 reads of $4016 at $10 and of $4017 at $30
+    let program = [
+        0xA9, 0x01, 0x8D, 0x16, 0x40, //LDA #$01, STA $4016
+        0xA9, 0x00, 0x8D, 0x16, 0x40, //LDA #$00, STA $4016
+        0xA2, 0x00, //LDX #$00
+        0xAD, 0x16, 0x40, 0x29, 0x01, 0x95, 0x10, //LDA $4016, AND #$01, STA $10,X
+        0xAD, 0x17, 0x40, 0x29, 0x01, 0x95, 0x30, //LDA $4017, AND #$01, STA $30,X
+        0xE8, 0xE0, 0x18, 0xD0, 0xED, //INX, CPX #$18, BNE $C00C
+        0x4C, 0x1F, 0xC0, //JMP $C01F
+    ];
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(&program)).unwrap();
+
+    assert!(emulator.connect_input_device(0, FourScore::new(0)));
+    assert!(emulator.connect_input_device(1, FourScore::new(1)));
+
+    emulator.set_input(0, Button::A, true);
+    emulator.set_input(1, Button::B, true);
+    emulator.set_input(2, Button::Start, true);
+    emulator.set_input(3, Button::Right, true);
+
+    assert_eq!(emulator.input_strobes(), 0);
+    emulator.step_frame();
+
+    //Only the write of 1 raises the strobe
+    assert_eq!(emulator.input_strobes(), 1);
+
+    let bits = |start: u16| -> Vec<u8> { (start..start + 24).map(|address| emulator.peek(address)).collect() };
+
+    #[rustfmt::skip]
+    assert_eq!(bits(0x10), [
+        1, 0, 0, 0, 0, 0, 0, 0, //Player 1: A
+        0, 0, 0, 1, 0, 0, 0, 0, //Player 3: Start
+        0, 0, 0, 1, 0, 0, 0, 0, //Signature
+    ]);
+
+    #[rustfmt::skip]
+    assert_eq!(bits(0x30), [
+        0, 1, 0, 0, 0, 0, 0, 0, //Player 2: B
+        0, 0, 0, 0, 0, 0, 0, 1, //Player 4: Right
+        0, 0, 1, 0, 0, 0, 0, 0, //Signature
+    ]);
+}
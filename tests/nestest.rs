@@ -0,0 +1,97 @@
+#![allow(clippy::needless_return)]
+
+//! nestest CPU harness<br>
+//! Runs nestest.nes in automation mode (starting at $C000, no PPU needed) and compares every executed
+//! instruction against nestest.log, stopping at the first divergence<br>
+//! The ROM and log aren't part of the repository: put them in tests/roms/ and run `cargo test -- --ignored`<br>
+//! The opcode fixes it found are covered by the hand-written tests in src/opcode.rs
+
+use std::fs;
+
+use rnes::{CpuRegisters, Emulator};
+
+const ROM_PATH: &str = "tests/roms/nestest.nes";
+const LOG_PATH: &str = "tests/roms/nestest.log";
+
+//Automation mode entry point, the reset vector points at the PPU driven menu
+const AUTOMATION_START: u16 = 0xC000;
+
+///One line of nestest.log: the registers before the instruction runs and the CPU cycle it starts at
+struct LogLine {
+    registers: CpuRegisters,
+    cycles: u64,
+}
+
+///"C000  4C F5 C5  JMP $C5F5      A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+fn parse_line(line: &str) -> Option<LogLine> {
+    let program_counter = u16::from_str_radix(line.get(0..4)?, 16).ok()?;
+
+    let field = |name: &str| -> Option<&str> {
+        let start = line.find(name)? + name.len();
+
+        return line[start..].split_whitespace().next();
+    };
+
+    let register = |name: &str| -> Option<u8> { u8::from_str_radix(field(name)?, 16).ok() };
+
+    return Some(LogLine {
+        registers: CpuRegisters {
+            a: register(" A:")?,
+            x: register(" X:")?,
+            y: register(" Y:")?,
+            status: register(" P:")?,
+            stack_pointer: register(" SP:")?,
+            program_counter,
+        },
+        cycles: field("CYC:")?.parse().ok()?,
+    });
+}
+
+#[test]
+#[ignore = "needs tests/roms/nestest.nes and tests/roms/nestest.log"]
+fn nestest() {
+    let log = fs::read_to_string(LOG_PATH).unwrap_or_else(|error| panic!("{} can't be read: {}", LOG_PATH, error));
+    let expected: Vec<LogLine> = log
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            parse_line(line).unwrap_or_else(|| panic!("nestest.log:{}: can't parse {:?}", index + 1, line))
+        })
+        .collect();
+
+    let mut emulator = Emulator::new();
+    emulator.load_rom(ROM_PATH).unwrap_or_else(|error| panic!("{} can't be loaded: {}", ROM_PATH, error));
+
+    //Finish the reset sequence, then jump to the automation entry point
+    emulator.step_instruction();
+    emulator.set_program_counter(AUTOMATION_START);
+
+    //The log starts counting at 7 (the reset sequence), only the difference matters
+    let start_cycles = emulator.cpu_cycles();
+    let log_start_cycles = expected[0].cycles;
+
+    for (index, line) in expected.iter().enumerate() {
+        let registers = emulator.cpu_registers();
+        let cycles = emulator.cpu_cycles() - start_cycles + log_start_cycles;
+
+        if registers != line.registers || cycles != line.cycles {
+            panic!(
+                "nestest.log:{}: diverged\n expected {:04X} {} CYC:{}\n      got {:04X} {} CYC:{}",
+                index + 1,
+                line.registers.program_counter,
+                line.registers,
+                line.cycles,
+                registers.program_counter,
+                registers,
+                cycles
+            );
+        }
+
+        emulator.step_instruction();
+    }
+
+    //Automation mode leaves the result codes in $0002 (official opcodes) and $0003 (unofficial opcodes)
+    assert_eq!(emulator.peek(0x0002), 0x00, "official opcode tests failed");
+    assert_eq!(emulator.peek(0x0003), 0x00, "unofficial opcode tests failed");
+}
@@ -0,0 +1,87 @@
+use rnes::{Emulator, EmulatorEvent, PauseReason, RunState, StepSize};
+
+#[test]
+fn run_frame_stops_while_paused() {
+    let mut emulator = Emulator::new();
+
+    assert!(emulator.run_frame());
+    let frames = emulator.stats().frames;
+
+    emulator.pause(PauseReason::User);
+    assert!(!emulator.run_frame());
+    assert_eq!(emulator.stats().frames, frames);
+
+    //Stepping by hand still works
+    emulator.step_frame();
+    assert_eq!(emulator.stats().frames, frames + 1);
+
+    emulator.resume();
+    assert!(emulator.run_frame());
+}
+
+#[test]
+fn focus_loss_only_pauses_with_auto_pause() {
+    let mut emulator = Emulator::new();
+
+    emulator.focus_changed(false);
+    assert!(!emulator.is_paused());
+
+    emulator.set_auto_pause(true);
+    emulator.focus_changed(false);
+    assert_eq!(emulator.pause_reason(), Some(PauseReason::FocusLost));
+
+    emulator.focus_changed(true);
+    assert!(!emulator.is_paused());
+
+    assert_eq!(
+        emulator.take_events(),
+        vec![EmulatorEvent::Paused(PauseReason::FocusLost), EmulatorEvent::Resumed]
+    );
+    assert!(emulator.take_events().is_empty());
+}
+
+#[test]
+fn focus_doesnt_resume_other_pauses() {
+    let mut emulator = Emulator::new();
+    emulator.set_auto_pause(true);
+
+    emulator.controller_disconnected(1);
+    emulator.focus_changed(false);
+    emulator.focus_changed(true);
+
+    //The first reason is kept and only the player resumes it
+    assert_eq!(emulator.pause_reason(), Some(PauseReason::ControllerDisconnected { port: 1 }));
+    assert_eq!(emulator.take_events().len(), 1);
+}
+
+#[test]
+fn queued_step_runs_once_while_paused() {
+    let mut emulator = Emulator::new();
+    assert_eq!(emulator.run_state(), RunState::Running);
+
+    //Nothing to advance while running
+    emulator.queue_step(StepSize::Frame);
+    assert_eq!(emulator.run_state(), RunState::Running);
+
+    emulator.pause(PauseReason::User);
+    emulator.queue_step(StepSize::Frame);
+    assert_eq!(emulator.run_state(), RunState::Stepping(StepSize::Frame));
+
+    let frames = emulator.stats().frames;
+    assert!(emulator.run_frame());
+    assert!(!emulator.run_frame());
+    assert_eq!(emulator.stats().frames, frames + 1);
+    assert_eq!(emulator.run_state(), RunState::Paused(PauseReason::User));
+
+    emulator.queue_step(StepSize::Instruction);
+    let cycles = emulator.cpu_cycles();
+    assert!(emulator.run_frame());
+    assert!(emulator.cpu_cycles() > cycles);
+    assert_eq!(emulator.stats().frames, frames + 1);
+
+    //Resuming drops a step that didn't run
+    emulator.queue_step(StepSize::Scanline);
+    emulator.resume();
+    emulator.pause(PauseReason::User);
+    assert_eq!(emulator.run_state(), RunState::Paused(PauseReason::User));
+}
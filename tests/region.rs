@@ -0,0 +1,24 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::{Emulator, Region};
+
+#[test]
+fn switching_region_power_cycles_with_the_new_timing() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::COUNTER)).unwrap();
+    emulator.step_frame();
+    assert_ne!(emulator.peek(0x0010), 0x00);
+
+    emulator.switch_region(Region::Pal);
+    assert_eq!(emulator.region(), Region::Pal);
+    assert_eq!(emulator.peek(0x0010), 0x00);
+    assert_eq!(emulator.get_program_counter(), 0xC000);
+
+    //The second frame is a whole one: 341 * 312 / 3.2 CPU cycles
+    emulator.step_frame();
+    let start = emulator.cpu_cycles();
+    emulator.step_frame();
+    assert!((33247..=33248).contains(&(emulator.cpu_cycles() - start)));
+}
@@ -0,0 +1,32 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::Emulator;
+
+#[test]
+fn rewind_restores_an_earlier_frame() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::COUNTER)).unwrap();
+
+    assert_eq!(emulator.rewind(1), 0);
+    emulator.enable_rewind(2, 16);
+
+    let mut states = vec![emulator.save_state().unwrap()];
+    for _ in 0..10 {
+        emulator.step_frame();
+        states.push(emulator.save_state().unwrap());
+    }
+
+    //Snapshots are taken at frames 2, 4, ... so going back 3 frames from 10 lands on 6
+    assert_eq!(emulator.rewind(3), 4);
+    assert!(emulator.save_state().unwrap() == states[6]);
+
+    //Playing on from there repeats the same frames
+    emulator.step_frame();
+    assert!(emulator.save_state().unwrap() == states[7]);
+
+    assert_eq!(emulator.rewind(1), 3);
+    assert!(emulator.save_state().unwrap() == states[4]);
+    assert_eq!(emulator.rewind(100), 0);
+}
@@ -0,0 +1,42 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::{CycleStop, CyclesRun, DebugHit, Emulator};
+
+#[test]
+fn stops_at_the_budget_and_the_end_of_frames() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::COUNTER)).unwrap();
+    emulator.step_frame();
+
+    let run = emulator.run_cycles(1000);
+    assert_eq!(run, CyclesRun { cycles: 1000, stop: CycleStop::Budget });
+
+    //The rest of the frame: 262 scanlines of 341 dots with rendering off
+    let run = emulator.run_cycles(1_000_000);
+    assert_eq!(run, CyclesRun { cycles: 262 * 341 - 1000, stop: CycleStop::FrameComplete });
+
+    assert_eq!(emulator.run_cycles(0).cycles, 0);
+    assert_eq!(emulator.run_cycles(1_000_000).cycles, 262 * 341);
+}
+
+#[test]
+fn stops_before_breakpoints_and_jams() {
+    //C000 INC $10, C002 JAM, C003 JMP $C000
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(&[0xE6, 0x10, 0x02, 0x4C, 0x00, 0xC0])).unwrap();
+    emulator.step_instruction();
+
+    let id = emulator.add_breakpoint(0xC000);
+
+    let run = emulator.run_cycles(1_000_000);
+    assert_eq!(run.stop, CycleStop::Jam { opcode: 0x02, address: 0xC002 });
+    assert_eq!(run.cycles, 5 * 3); //INC zero page
+    assert_eq!(emulator.peek(0x0010), 1);
+
+    //The JAM runs as a placeholder when resumed, then the JMP lands on the breakpoint
+    let run = emulator.run_cycles(1_000_000);
+    assert_eq!(run.stop, CycleStop::Debug(DebugHit::Breakpoint { id, address: 0xC000 }));
+    assert_eq!(run.cycles, (2 + 3) * 3);
+}
@@ -0,0 +1,89 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use rnes::{cheats::CheatFormat, Emulator};
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::COUNTER)).unwrap();
+
+    return emulator;
+}
+
+#[test]
+fn states_record_active_cheats() {
+    let mut emulator = emulator();
+    emulator.run_frame();
+
+    let clean = emulator.save_state().unwrap();
+    assert!(!Emulator::state_has_cheats(&clean).unwrap());
+
+    let id = emulator.freeze(0x0010, 0x42);
+    emulator.run_frame();
+    assert_eq!(emulator.peek(0x0010), 0x42);
+
+    let cheated = emulator.save_state().unwrap();
+    assert!(Emulator::state_has_cheats(&cheated).unwrap());
+
+    //Removing the cheat lets the game count again
+    assert!(emulator.remove_write_interceptor(id));
+    emulator.run_frame();
+    assert_ne!(emulator.peek(0x0010), 0x42);
+    assert!(!Emulator::state_has_cheats(&emulator.save_state().unwrap()).unwrap());
+}
+
+#[test]
+fn frozen_addresses_survive_loading_a_state() {
+    let mut emulator = emulator();
+    emulator.run_frame();
+    let state = emulator.save_state().unwrap();
+
+    emulator.freeze(0x0010, 0x42);
+    emulator.load_state(&state).unwrap();
+
+    //Written again by the load, then still held while the game runs
+    assert_eq!(emulator.peek(0x0010), 0x42);
+    emulator.step_instruction();
+    emulator.step_instruction();
+    assert_eq!(emulator.peek(0x0010), 0x42);
+}
+
+#[test]
+fn cheat_codes_patch_reads_and_mark_states() {
+    let mut emulator = emulator();
+
+    //$C001 holds the operand of INC $10, patched to INC $11
+    let id = emulator.add_cheat("C001:11").unwrap();
+    emulator.run_frame();
+    assert_eq!(emulator.peek(0x0010), 0x00);
+    assert_ne!(emulator.peek(0x0011), 0x00);
+    assert!(Emulator::state_has_cheats(&emulator.save_state().unwrap()).unwrap());
+
+    assert!(emulator.set_cheat_enabled(id, false));
+    emulator.run_frame();
+    assert_ne!(emulator.peek(0x0010), 0x00);
+    assert!(!Emulator::state_has_cheats(&emulator.save_state().unwrap()).unwrap());
+
+    assert_eq!(emulator.export_cheats(CheatFormat::Rnes), "!C001:11\n");
+    assert!(emulator.remove_cheat(id));
+    assert!(emulator.cheats().is_empty());
+}
+
+#[test]
+fn random_generator_is_part_of_the_state() {
+    let mut emulator = emulator();
+    emulator.set_rng_seed(0x1234);
+    let state = emulator.save_state().unwrap();
+
+    emulator.randomize_power_on_state();
+    let memory: Vec<u8> = (0..0x0800).map(|address| emulator.peek(address)).collect();
+
+    //Reloading the state rewinds the generator, so the same values come out again
+    emulator.load_state(&state).unwrap();
+    emulator.randomize_power_on_state();
+    assert!((0..0x0800).all(|address| emulator.peek(address) == memory[address as usize]));
+
+    emulator.randomize_power_on_state();
+    assert!((0..0x0800).any(|address| emulator.peek(address) != memory[address as usize]));
+}
@@ -0,0 +1,50 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use rnes::{Emulator, PALETTE_2C02, SCREEN_WIDTH};
+
+#[test]
+fn callback_runs_on_every_scanline() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::COUNTER)).unwrap();
+    emulator.step_frame();
+
+    let scanlines = Rc::new(RefCell::new(Vec::new()));
+    let seen = scanlines.clone();
+    emulator.set_scanline_callback(0, move |scanline, _| seen.borrow_mut().push(scanline));
+
+    emulator.step_frame();
+
+    //A frame ends when the PPU wraps around to the pre-render scanline
+    let expected: Vec<i16> = (0..=260).chain([-1]).collect();
+    assert_eq!(*scanlines.borrow(), expected);
+
+    emulator.clear_scanline_callback();
+    emulator.step_frame();
+    assert_eq!(scanlines.borrow().len(), expected.len());
+}
+
+#[test]
+fn callback_writes_take_effect_mid_frame() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&common::rom(common::BLUE_BACKGROUND)).unwrap();
+    emulator.step_frame();
+
+    //Red from scanline 120 on, back to blue for the next frame
+    emulator.set_scanline_callback(0, |scanline, emulator| match scanline {
+        120 => emulator.ppu_poke(0x3F00, 0x16),
+        -1 => emulator.ppu_poke(0x3F00, 0x21),
+        _ => {}
+    });
+
+    emulator.step_frame();
+    emulator.step_frame();
+
+    let frame = emulator.frame_buffer();
+    assert_eq!(frame[119 * SCREEN_WIDTH], PALETTE_2C02[0x21]);
+    assert_eq!(frame[120 * SCREEN_WIDTH], PALETTE_2C02[0x16]);
+    assert_eq!(frame[239 * SCREEN_WIDTH + 255], PALETTE_2C02[0x16]);
+}
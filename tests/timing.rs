@@ -0,0 +1,41 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::time::Instant;
+
+use common::rom;
+use rnes::{timing::PRESENT_SPAN, Emulator};
+
+//C000 JMP $C000
+const PROGRAM: [u8; 3] = [0x4C, 0x00, 0xC0];
+
+#[test]
+fn records_frames_and_subsystems_while_running() {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&rom(&PROGRAM)).unwrap();
+
+    //Nothing is recorded before the trace starts
+    emulator.step_frame();
+    emulator.record_span(PRESENT_SPAN, Instant::now(), Instant::now());
+
+    emulator.start_timing_trace();
+    emulator.step_frame();
+    emulator.run_frame();
+    emulator.record_span(PRESENT_SPAN, Instant::now(), Instant::now());
+
+    let trace = emulator.stop_timing_trace().unwrap();
+    assert!(emulator.stop_timing_trace().is_none());
+
+    //Two frames with their CPU, PPU and APU spans, then the host span
+    assert_eq!(trace.span_count(), 2 * 4 + 1);
+
+    let mut json = Vec::new();
+    trace.write_json(&mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+
+    for name in ["frame", "cpu", "ppu", "apu"] {
+        assert_eq!(json.matches(&format!("\"name\":\"{}\"", name)).count(), 2, "{}", name);
+    }
+    assert_eq!(json.matches("\"name\":\"present\",\"cat\":\"host\"").count(), 1);
+}
@@ -0,0 +1,111 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use std::{cell::RefCell, io, io::Write, rc::Rc};
+
+use common::rom;
+use rnes::Emulator;
+
+///Writer the test keeps a handle to after giving it to the emulator
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        return Ok(data.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl SharedBuffer {
+    fn lines(&self) -> Vec<String> {
+        return String::from_utf8(self.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+    }
+}
+
+//C000 LDA #$05
+//C002 STA $0010
+//C005 JMP $C005
+const PROGRAM: [u8; 8] = [0xA9, 0x05, 0x8D, 0x10, 0x00, 0x4C, 0x05, 0xC0];
+
+fn emulator() -> Emulator {
+    let mut emulator = Emulator::new();
+    emulator.load_rom_bytes(&rom(&PROGRAM)).unwrap();
+
+    //Finish the reset sequence
+    emulator.step_instruction();
+
+    return emulator;
+}
+
+///CYC: field of a trace line
+fn cycles(line: &str) -> u64 {
+    return line.rsplit("CYC:").next().unwrap().parse().unwrap();
+}
+
+#[test]
+fn logs_every_instruction_before_it_runs() {
+    let mut emulator = emulator();
+    let buffer = SharedBuffer::default();
+
+    let start = emulator.cpu_cycles();
+    emulator.start_trace(buffer.clone()).unwrap();
+    assert!(emulator.is_tracing());
+
+    for _ in 0..3 {
+        emulator.step_instruction();
+    }
+
+    emulator.stop_trace().unwrap();
+    assert!(!emulator.is_tracing());
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 3, "{:#?}", lines);
+
+    assert!(lines[0].starts_with("C000  A9 05     LDA #$05"), "{}", lines[0]);
+    assert!(lines[1].starts_with("C002  8D 10 00  STA $0010"), "{}", lines[1]);
+    assert!(lines[2].starts_with("C005  4C 05 C0  JMP $C005"), "{}", lines[2]);
+
+    //Registers before the instruction: A only holds 5 once LDA ran
+    assert!(lines[0].contains(" A:00 "), "{}", lines[0]);
+    assert!(lines[1].contains(" A:05 "), "{}", lines[1]);
+    assert!(lines[0].contains(" PPU:"), "{}", lines[0]);
+
+    //LDA # takes 2 cycles, STA absolute 4
+    assert_eq!(cycles(&lines[0]), start);
+    assert_eq!(cycles(&lines[1]) - cycles(&lines[0]), 2);
+    assert_eq!(cycles(&lines[2]) - cycles(&lines[1]), 4);
+}
+
+#[test]
+fn can_be_toggled_while_running() {
+    let mut emulator = emulator();
+    let buffer = SharedBuffer::default();
+
+    emulator.start_trace(buffer.clone()).unwrap();
+    emulator.step_instruction();
+    emulator.stop_trace().unwrap();
+
+    //The frame can end in the middle of an instruction, finish it before tracing again
+    emulator.step_frame();
+    emulator.step_instruction();
+    assert_eq!(buffer.lines().len(), 1);
+
+    //Tracing again appends to the same writer
+    emulator.start_trace(buffer.clone()).unwrap();
+    emulator.step_instruction();
+    emulator.stop_trace().unwrap();
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with("C005  4C 05 C0  JMP $C005"), "{}", lines[1]);
+}